@@ -0,0 +1,9 @@
+//! Runs arbitrary bytes as a program with a fuel limit.
+
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|data: &[u8]| {
+    rustyvm::fuzz::machine(data);
+});
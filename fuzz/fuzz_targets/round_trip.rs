@@ -0,0 +1,9 @@
+//! Disassembles arbitrary bytes and reassembles the result.
+
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|data: &[u8]| {
+    rustyvm::fuzz::round_trip(data);
+});
@@ -1,5 +1,7 @@
 ; Demo program to test the ADDR instruction
 
+.include "prologue.inc"
+
 add_stack:
     push %10            ; push 10 onto the stack
     push %24            ; push 24 onto the stack
@@ -45,4 +47,4 @@ test_newer_registers:
 
 ; Now both A and B should contain the sum of their original values
 
-sig $09             ; signal to the monitor that the program is done
+.include "exit.inc"  ; signal to the monitor that the program is done
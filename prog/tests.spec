@@ -0,0 +1,12 @@
+# Regression suite for the programs in prog/, run with `make check-progs`
+
+[test.asm]
+program = test.asm
+include = runtime
+max_steps = 1000
+A = 30
+B = 30
+C = 27
+R0 = 30
+R4 = 30
+mem 0x1000 = 20 00
@@ -0,0 +1,58 @@
+//! Command-line arguments for VM programs.
+//!
+//! The runner copies the arguments given after `--` into a fixed region
+//! below the devices, laid out like C's `argv`:
+//!
+//! | Address            | Contents                                          |
+//! | ------------------ | ------------------------------------------------- |
+//! | `0x1A00`           | `argc` words, each the address of one argument    |
+//! | after the table    | The arguments, each followed by a 0 byte          |
+//!
+//! At the entry point `R0` holds the number of arguments and `R1` the
+//! address of the table, or 0 when there are no arguments. Nothing else is
+//! touched, so programs that ignore their arguments run as before.
+
+use crate::{Machine, Register, devices};
+
+/// Address of the argument table.
+pub const ARGS_ADDR: u16 = 0x1A00;
+
+/// End of the argument region (exclusive), where the devices start.
+pub const ARGS_END: u16 = devices::MAILBOX_OUT_ADDR;
+
+/// Copies the arguments into memory and points `R0` and `R1` at them, as
+/// described in the module docs.
+pub fn pass_args(vm: &mut Machine, args: &[&str]) -> Result<(), String> {
+    if let Some(arg) = args.iter().find(|arg| arg.contains('\0')) {
+        return Err(format!("argument {:?} contains a NUL byte", arg));
+    }
+    let size = args.len() * 2 + args.iter().map(|arg| arg.len() + 1).sum::<usize>();
+    if size > (ARGS_END - ARGS_ADDR) as usize {
+        return Err(format!(
+            "arguments take {} bytes, but only {} fit at 0x{:04X}",
+            size,
+            ARGS_END - ARGS_ADDR,
+            ARGS_ADDR
+        ));
+    }
+
+    let mut string = ARGS_ADDR + args.len() as u16 * 2;
+    for (i, arg) in args.iter().enumerate() {
+        let pointer = ARGS_ADDR + i as u16 * 2;
+        let mut ok = vm.memory.write2(pointer, string);
+        for (offset, byte) in arg.bytes().chain([0]).enumerate() {
+            ok &= vm.memory.write(string + offset as u16, byte);
+        }
+        if !ok {
+            return Err(format!(
+                "memory write fault - arguments do not fit in {} bytes of memory",
+                vm.memory.size()
+            ));
+        }
+        string += arg.len() as u16 + 1;
+    }
+
+    vm.registers[Register::R0 as usize] = args.len() as u16;
+    vm.registers[Register::R1 as usize] = if args.is_empty() { 0 } else { ARGS_ADDR };
+    Ok(())
+}
@@ -0,0 +1,63 @@
+//! Unit tests for passing command-line arguments to programs.
+//!
+//! This file checks the argument table layout, the registers set at entry
+//! and the errors for arguments that cannot be passed.
+
+#[cfg(test)]
+mod tests {
+    use crate::args::{ARGS_ADDR, ARGS_END, pass_args};
+    use crate::{Machine, Register};
+
+    /// Reads the 0-terminated string at `addr`.
+    fn read_string(vm: &Machine, mut addr: u16) -> String {
+        let mut bytes = Vec::new();
+        while let Some(byte) = vm.memory.read(addr).filter(|b| *b != 0) {
+            bytes.push(byte);
+            addr += 1;
+        }
+        String::from_utf8(bytes).unwrap()
+    }
+
+    #[test]
+    fn test_pass_args_layout() {
+        let mut vm = Machine::new();
+        pass_args(&mut vm, &["prog", "42", ""]).unwrap();
+
+        assert_eq!(vm.get_register(Register::R0), 3);
+        assert_eq!(vm.get_register(Register::R1), ARGS_ADDR);
+
+        let pointers: Vec<u16> = (0..3)
+            .map(|i| vm.memory.read2(ARGS_ADDR + i * 2).unwrap())
+            .collect();
+        // The strings follow the table back to back
+        assert_eq!(
+            pointers,
+            vec![ARGS_ADDR + 6, ARGS_ADDR + 11, ARGS_ADDR + 14]
+        );
+        assert_eq!(read_string(&vm, pointers[0]), "prog");
+        assert_eq!(read_string(&vm, pointers[1]), "42");
+        assert_eq!(read_string(&vm, pointers[2]), "");
+    }
+
+    #[test]
+    fn test_pass_no_args() {
+        let mut vm = Machine::new();
+        vm.registers[Register::R1 as usize] = 0x1234;
+        pass_args(&mut vm, &[]).unwrap();
+        assert_eq!(vm.get_register(Register::R0), 0);
+        assert_eq!(vm.get_register(Register::R1), 0);
+    }
+
+    #[test]
+    fn test_pass_args_errors() {
+        let mut vm = Machine::new();
+        let long = "x".repeat((ARGS_END - ARGS_ADDR) as usize);
+        let err = pass_args(&mut vm, &[&long]).unwrap_err();
+        assert!(err.contains("only 500 fit"), "{}", err);
+
+        let err = pass_args(&mut vm, &["a\0b"]).unwrap_err();
+        assert!(err.contains("NUL"), "{}", err);
+        // A rejected call leaves the registers alone
+        assert_eq!(vm.get_register(Register::R0), 0);
+    }
+}
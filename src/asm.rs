@@ -0,0 +1,428 @@
+//! Assembler for the Rusty 16-bit VM.
+//!
+//! Turns assembly source text into bytecode that can be loaded with
+//! [`Machine::load_program`](crate::Machine::load_program):
+//!
+//! ```
+//! use rustyvm::{Machine, Register, asm};
+//!
+//! let bytecode = asm::assemble_str("push %42\npop A\n").unwrap();
+//! let mut vm = Machine::new();
+//! vm.load_program(&bytecode).unwrap();
+//!
+//! vm.step().unwrap();
+//! vm.step().unwrap();
+//! assert_eq!(vm.get_register(Register::A), 42);
+//! ```
+
+pub mod builder;
+pub mod codegen;
+pub mod disassembler;
+pub mod expr;
+pub mod include;
+pub mod ir;
+pub mod lexer;
+pub mod optimizer;
+pub mod parser;
+pub mod pseudo;
+pub mod stack_depth;
+pub mod warnings;
+
+#[cfg(test)]
+mod builder_test;
+#[cfg(test)]
+mod disassembler_test;
+#[cfg(test)]
+mod expr_test;
+#[cfg(test)]
+mod optimizer_test;
+#[cfg(test)]
+mod parser_test;
+#[cfg(test)]
+mod stack_depth_test;
+#[cfg(test)]
+mod warnings_test;
+
+use std::{
+    collections::HashSet,
+    fmt, fs,
+    path::{Path, PathBuf},
+};
+
+pub use crate::asm::builder::ProgramBuilder;
+pub use crate::asm::codegen::{AliasTable, CodegenError, SymbolTable};
+pub use crate::asm::disassembler::disassemble;
+pub use crate::asm::parser::Constants;
+use crate::asm::{
+    lexer::{LexError, Token},
+    parser::ParseError,
+    warnings::Warning,
+};
+
+/// Errors that can stop a program from being assembled.
+#[derive(Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub enum AsmError {
+    /// A source or `.include` file could not be read or found
+    Io(String),
+    /// A line could not be split into tokens (e.g. an unterminated string)
+    Lex(LexError),
+    /// The token stream is not a valid program
+    Parse(ParseError),
+    /// The program parsed but could not be encoded (e.g. an undefined label)
+    Codegen {
+        /// 1-based source line of the offending instruction, when known
+        line: Option<usize>,
+        /// Source file of `line`, when assembling from files
+        file: Option<String>,
+        message: String,
+    },
+}
+
+impl AsmError {
+    /// 1-based source line of the error, when known.
+    pub fn line(&self) -> Option<usize> {
+        match self {
+            AsmError::Lex(e) => Some(e.line),
+            AsmError::Parse(e) => e.line,
+            AsmError::Codegen { line, .. } => *line,
+            AsmError::Io(_) => None,
+        }
+    }
+
+    /// Source file the error's line belongs to, when assembling from files.
+    pub fn file(&self) -> Option<&str> {
+        match self {
+            AsmError::Lex(e) => e.file.as_deref(),
+            AsmError::Parse(e) => e.file.as_deref(),
+            AsmError::Codegen { file, .. } => file.as_deref(),
+            AsmError::Io(_) => None,
+        }
+    }
+}
+
+impl fmt::Display for AsmError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AsmError::Io(e) => write!(f, "cannot read the file due to - {}", e),
+            AsmError::Lex(e) => write!(f, "Error reading tokens: {}", e),
+            AsmError::Parse(e) => write!(f, "Error parsing tokens: {}", e),
+            AsmError::Codegen {
+                line: Some(line),
+                file: Some(file),
+                message,
+            } => write!(
+                f,
+                "Error generating bytecode in {} at line {}: {}",
+                file, line, message
+            ),
+            AsmError::Codegen {
+                line: Some(line),
+                message,
+                ..
+            } => write!(f, "Error generating bytecode at line {}: {}", line, message),
+            AsmError::Codegen { message, .. } => {
+                write!(f, "Error generating bytecode: {}", message)
+            }
+        }
+    }
+}
+
+impl std::error::Error for AsmError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            AsmError::Lex(e) => Some(e),
+            AsmError::Parse(e) => Some(e),
+            AsmError::Io(_) | AsmError::Codegen { .. } => None,
+        }
+    }
+}
+
+impl From<LexError> for AsmError {
+    fn from(e: LexError) -> Self {
+        AsmError::Lex(e)
+    }
+}
+
+impl From<ParseError> for AsmError {
+    fn from(e: ParseError) -> Self {
+        AsmError::Parse(e)
+    }
+}
+
+/// For IR that did not come from source text, so has no lines.
+impl From<CodegenError> for AsmError {
+    fn from(e: CodegenError) -> Self {
+        AsmError::Codegen {
+            line: None,
+            file: None,
+            message: e.message,
+        }
+    }
+}
+
+/// Options controlling how source is assembled.
+#[derive(Debug, Clone, Default)]
+pub struct AsmOptions {
+    /// Run the peephole optimizer before encoding
+    pub optimize: bool,
+    /// Run the static stack-depth analysis and report its findings as warnings
+    pub check_stack: bool,
+    /// Directory of the source file, searched first for `.include` files
+    pub source_dir: Option<PathBuf>,
+    /// Further directories searched for `.include` files, in order (`-I`)
+    pub include_dirs: Vec<PathBuf>,
+    /// Constants usable in operands and `.if` conditions (`--define`)
+    pub defines: Constants,
+}
+
+/// The result of assembling a program.
+#[derive(Debug)]
+pub struct Assembly {
+    /// Encoded program, ready for `Machine::load_program`
+    pub bytecode: Vec<u8>,
+    /// Byte offset of every label in the program
+    pub symbols: SymbolTable,
+    /// Register aliases by the byte offset they take effect at
+    pub aliases: AliasTable,
+    /// Non-fatal problems found in the source
+    pub warnings: Vec<Warning>,
+    /// Bytes removed by the optimizer (0 when it did not run)
+    pub bytes_saved: usize,
+}
+
+/// Assembles source text with the given options.
+pub fn assemble(source: &str, options: &AsmOptions) -> Result<Assembly, AsmError> {
+    let (tokens, lines) = include::tokenize_with_includes(
+        source,
+        options.source_dir.as_deref(),
+        &options.include_dirs,
+    )?;
+    assemble_tokens(&tokens, &lines, options)
+}
+
+/// Assembles several source files as one program, in order.
+///
+/// The files are concatenated, so labels declared in one file can be used in
+/// any other and the result has a single symbol table. Each file looks for
+/// its `.include` files in its own directory first; `options.source_dir` is
+/// not used.
+pub fn assemble_files<P: AsRef<Path>>(
+    paths: &[P],
+    options: &AsmOptions,
+) -> Result<Assembly, AsmError> {
+    let mut tokens = Vec::new();
+    let mut lines = Vec::new();
+    // First line of every file in the combined line numbering
+    let mut files: Vec<(usize, &Path)> = Vec::new();
+    let mut line_base = 0;
+
+    for path in paths {
+        let path = path.as_ref();
+        let source = fs::read_to_string(path)
+            .map_err(|e| AsmError::Io(format!("{}: {}", path.display(), e)))?;
+        let (file_tokens, file_lines) =
+            include::tokenize_with_includes(&source, path.parent(), &options.include_dirs)
+                .map_err(|e| match e {
+                    AsmError::Io(e) => AsmError::Io(format!("{}: {}", path.display(), e)),
+                    AsmError::Lex(mut e) if e.file.is_none() => {
+                        e.file = Some(path.display().to_string());
+                        AsmError::Lex(e)
+                    }
+                    other => other,
+                })?;
+
+        files.push((line_base, path));
+        tokens.extend(file_tokens);
+        lines.extend(file_lines.into_iter().map(|line| line + line_base));
+        line_base += source.lines().count();
+    }
+
+    // Turns a line number in the combined sources back into a file and a
+    // line in it
+    let locate = |line: Option<usize>| {
+        let line = line?;
+        let (base, path) = files.iter().rev().find(|(base, _)| *base < line)?;
+        Some((line - base, path.display().to_string()))
+    };
+    assemble_tokens(&tokens, &lines, options).map_err(|e| match e {
+        AsmError::Parse(mut e) => {
+            if let Some((line, file)) = locate(e.line) {
+                e.line = Some(line);
+                e.file = Some(file);
+            }
+            AsmError::Parse(e)
+        }
+        AsmError::Codegen {
+            line,
+            file: None,
+            message,
+        } => match locate(line) {
+            Some((line, file)) => AsmError::Codegen {
+                line: Some(line),
+                file: Some(file),
+                message,
+            },
+            None => AsmError::Codegen {
+                line,
+                file: None,
+                message,
+            },
+        },
+        other => other,
+    })
+}
+
+fn assemble_tokens(
+    tokens: &[Token],
+    lines: &[usize],
+    options: &AsmOptions,
+) -> Result<Assembly, AsmError> {
+    let (mut ir, mut ir_lines) = parser::parse_tokens_located(tokens, lines, &options.defines)?;
+    let mut warnings = warnings::check(&ir);
+    warnings.extend(warnings::check_shadowed_constants(&ir, &options.defines));
+
+    // Expressions are evaluated by the parser, so the IR no longer shows
+    // which labels they used; don't report those as unused
+    let referenced: HashSet<&str> = tokens
+        .iter()
+        .filter_map(|token| match token {
+            Token::Expr(text) => Some(expr::symbols(text)),
+            _ => None,
+        })
+        .flatten()
+        .collect();
+    warnings.retain(|warning| match warning {
+        Warning::UnusedLabel(name) => {
+            let local = name
+                .find(parser::LOCAL_LABEL_PREFIX)
+                .map(|idx| &name[idx..]);
+            !referenced.contains(name.as_str()) && !local.is_some_and(|l| referenced.contains(l))
+        }
+        _ => true,
+    });
+    if options.check_stack {
+        warnings.extend(stack_depth::check(&ir));
+    }
+
+    let mut bytes_saved = 0;
+    if options.optimize {
+        let (optimized, optimized_lines) = optimizer::optimize_with_lines(&ir, &ir_lines);
+        bytes_saved = optimizer::code_size(&ir) - optimizer::code_size(&optimized);
+        ir = optimized;
+        ir_lines = optimized_lines;
+    }
+
+    let codegen_error = |e: CodegenError| AsmError::Codegen {
+        line: ir_lines.get(e.index).copied().flatten(),
+        file: None,
+        message: e.message,
+    };
+    let symbols = codegen::resolve_labels(&ir).map_err(codegen_error)?;
+    let bytecode = codegen::generate_bytecode(&ir).map_err(codegen_error)?;
+
+    Ok(Assembly {
+        bytecode,
+        symbols,
+        aliases: codegen::resolve_aliases(&ir),
+        warnings,
+        bytes_saved,
+    })
+}
+
+/// Assembles source text into bytecode with default options.
+pub fn assemble_str(source: &str) -> Result<Vec<u8>, AsmError> {
+    assemble(source, &AsmOptions::default()).map(|assembly| assembly.bytecode)
+}
+
+/// Assembles a source file, returning the bytecode together with its symbol table.
+pub fn assemble_file(path: impl AsRef<Path>) -> Result<Assembly, AsmError> {
+    assemble_files(&[path], &AsmOptions::default())
+}
+
+/// Renders a symbol table as text, one `ADDR name` line per label with the
+/// address in hex, sorted by name.
+pub fn format_symbols(symbols: &SymbolTable) -> String {
+    symbols
+        .iter()
+        .map(|(name, address)| format!("{:04X} {}\n", address, name))
+        .collect()
+}
+
+/// Renders register aliases as text to append to a symbol table, one
+/// `ADDR .alias name, reg` line per declaration, sorted by address.
+pub fn format_aliases(aliases: &AliasTable) -> String {
+    aliases
+        .iter()
+        .flat_map(|(address, names)| {
+            names
+                .iter()
+                .map(move |(name, reg)| format!("{:04X} .alias {}, {}\n", address, name, reg))
+        })
+        .collect()
+}
+
+/// Splits the `ADDR rest` lines of a symbol table file, skipping blank ones.
+fn symbol_lines(text: &str) -> impl Iterator<Item = Result<(usize, u16, &str), String>> {
+    text.lines()
+        .enumerate()
+        .filter(|(_, line)| !line.trim().is_empty())
+        .map(|(idx, line)| {
+            let (address, rest) = line
+                .trim()
+                .split_once(char::is_whitespace)
+                .ok_or_else(|| format!("line {}: expected `ADDR name`", idx + 1))?;
+            let address = u16::from_str_radix(address, 16)
+                .map_err(|_| format!("line {}: invalid address `{}`", idx + 1, address))?;
+            Ok((idx + 1, address, rest.trim()))
+        })
+}
+
+/// Reads a symbol table written by [`format_symbols`], skipping the lines
+/// of [`format_aliases`].
+pub fn parse_symbols(text: &str) -> Result<SymbolTable, String> {
+    let mut symbols = SymbolTable::new();
+    for line in symbol_lines(text) {
+        let (_, address, name) = line?;
+        if !name.starts_with(".alias") {
+            symbols.insert(name.to_string(), address);
+        }
+    }
+    Ok(symbols)
+}
+
+/// Reads the register aliases written by [`format_aliases`] from a symbol
+/// table file.
+pub fn parse_aliases(text: &str) -> Result<AliasTable, String> {
+    let mut aliases = AliasTable::new();
+    for line in symbol_lines(text) {
+        let (number, address, rest) = line?;
+        let Some(alias) = rest.strip_prefix(".alias") else {
+            continue;
+        };
+        let (name, reg) = alias
+            .split_once(',')
+            .ok_or_else(|| format!("line {}: expected `ADDR .alias name, reg`", number))?;
+        aliases
+            .entry(address)
+            .or_default()
+            .push((name.trim().to_string(), reg.trim().to_string()));
+    }
+    Ok(aliases)
+}
+
+/// Parses a `NAME=VALUE` definition. The value may be an expression using
+/// earlier definitions, and defaults to 1 when omitted. Used for the
+/// `--define` option of the binaries.
+pub fn parse_define(define: &str, defines: &Constants) -> Result<(String, u16), String> {
+    let (name, value) = define.split_once('=').unwrap_or((define, "1"));
+    if name.is_empty() || name.starts_with(|c: char| c.is_ascii_digit()) {
+        return Err(format!("invalid --define name in `{}`", define));
+    }
+
+    let lookup = |n: &str| defines.get(n).map(|v| *v as i64);
+    let value = expr::eval(value, &lookup).map_err(|e| format!("--define {}: {}", name, e))?;
+    let value = u16::try_from(value)
+        .map_err(|_| format!("--define {}: {} does not fit in 16 bits", name, value))?;
+    Ok((name.to_string(), value))
+}
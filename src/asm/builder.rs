@@ -0,0 +1,344 @@
+//! Building programs from Rust instead of source text.
+//!
+//! [`ProgramBuilder`] emits the same instructions the parser would, with
+//! registers as [`Register`] values and operands sized to what the encoding
+//! holds, so mistakes that source text only reveals when assembled are
+//! compile errors here. Labels are resolved when the program is assembled,
+//! so they can be used before they are placed.
+//!
+//! ```
+//! use rustyvm::{Machine, Register, asm::ProgramBuilder};
+//!
+//! let bytecode = ProgramBuilder::new()
+//!     .push(10)
+//!     .push(20)
+//!     .adds()
+//!     .pop(Register::A)
+//!     .halt()
+//!     .assemble()
+//!     .unwrap();
+//!
+//! let mut vm = Machine::new();
+//! vm.load_program(&bytecode).unwrap();
+//! for _ in 0..4 {
+//!     vm.step().unwrap();
+//! }
+//! assert_eq!(vm.get_register(Register::A), 30);
+//! ```
+
+use crate::Register;
+use crate::asm::{
+    AsmError,
+    ir::{Condition, Instruction},
+    pseudo,
+};
+
+/// Builds a program one instruction at a time.
+#[derive(Debug, Clone, Default)]
+pub struct ProgramBuilder {
+    instructions: Vec<Instruction>,
+}
+
+/// Name of a register as the IR spells it.
+fn name(reg: Register) -> String {
+    format!("{:?}", reg)
+}
+
+impl ProgramBuilder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn with(mut self, instructions: impl IntoIterator<Item = Instruction>) -> Self {
+        self.instructions.extend(instructions);
+        self
+    }
+
+    /// `NOP`
+    pub fn nop(self) -> Self {
+        self.with([Instruction::Nop])
+    }
+
+    /// `PUSH value`
+    pub fn push(self, value: u8) -> Self {
+        self.with([Instruction::PushImmediate(value as u16)])
+    }
+
+    /// `PUSH16 value`, which clobbers M
+    pub fn push16(self, value: u16) -> Self {
+        self.with(pseudo::push_wide(value))
+    }
+
+    /// `PUSH16 label`, pushing the label's address and clobbering M
+    pub fn push_address(self, label: &str) -> Self {
+        self.with(pseudo::push_address(label))
+    }
+
+    /// `PUSHR reg`
+    pub fn push_register(self, reg: Register) -> Self {
+        self.with([Instruction::PushRegister(name(reg))])
+    }
+
+    /// `POP reg`
+    pub fn pop(self, reg: Register) -> Self {
+        self.with([Instruction::Pop(name(reg))])
+    }
+
+    /// `ADDS`
+    pub fn adds(self) -> Self {
+        self.with([Instruction::AddStack])
+    }
+
+    /// `ADDR dst src`
+    pub fn add(self, dst: Register, src: Register) -> Self {
+        self.with([Instruction::AddRegister(name(dst), name(src))])
+    }
+
+    /// `SUBS`
+    pub fn subs(self) -> Self {
+        self.with([Instruction::SubStack])
+    }
+
+    /// `SUBR dst src`
+    pub fn sub(self, dst: Register, src: Register) -> Self {
+        self.with([Instruction::SubRegister(name(dst), name(src))])
+    }
+
+    /// `ANDS`
+    pub fn ands(self) -> Self {
+        self.with([Instruction::AndStack])
+    }
+
+    /// `ORS`
+    pub fn ors(self) -> Self {
+        self.with([Instruction::OrStack])
+    }
+
+    /// `XORS`
+    pub fn xors(self) -> Self {
+        self.with([Instruction::XorStack])
+    }
+
+    /// `CMP r1 r2`
+    pub fn cmp(self, r1: Register, r2: Register) -> Self {
+        self.with([Instruction::Compare(name(r1), name(r2))])
+    }
+
+    /// `MULW r1 r2`, the high word of the product going to M
+    pub fn mulw(self, r1: Register, r2: Register) -> Self {
+        self.with([Instruction::MulWide(name(r1), name(r2))])
+    }
+
+    /// `NOTS`
+    pub fn nots(self) -> Self {
+        self.with([Instruction::NotStack])
+    }
+
+    /// `DUP`
+    pub fn dup(self) -> Self {
+        self.with([Instruction::Dup])
+    }
+
+    /// `SWAP`
+    pub fn swap(self) -> Self {
+        self.with([Instruction::Swap])
+    }
+
+    /// `DROP`
+    pub fn drop(self) -> Self {
+        self.with([Instruction::Drop])
+    }
+
+    /// `OVER`
+    pub fn over(self) -> Self {
+        self.with([Instruction::Over])
+    }
+
+    /// `PUSHF`
+    pub fn pushf(self) -> Self {
+        self.with([Instruction::PushFlags])
+    }
+
+    /// `POPF`
+    pub fn popf(self) -> Self {
+        self.with([Instruction::PopFlags])
+    }
+
+    /// `ANDR dst src`
+    pub fn and(self, dst: Register, src: Register) -> Self {
+        self.with([Instruction::AndRegister(name(dst), name(src))])
+    }
+
+    /// `ORR dst src`
+    pub fn or(self, dst: Register, src: Register) -> Self {
+        self.with([Instruction::OrRegister(name(dst), name(src))])
+    }
+
+    /// `XORR dst src`
+    pub fn xor(self, dst: Register, src: Register) -> Self {
+        self.with([Instruction::XorRegister(name(dst), name(src))])
+    }
+
+    /// `NOTR reg`
+    pub fn not(self, reg: Register) -> Self {
+        self.with([Instruction::NotRegister(name(reg))])
+    }
+
+    /// `MOV dst src`
+    pub fn mov(self, dst: Register, src: Register) -> Self {
+        self.with([Instruction::Move(name(dst), name(src))])
+    }
+
+    /// `CMOVZ dst src`
+    pub fn cmovz(self, dst: Register, src: Register) -> Self {
+        self.with([Instruction::MoveIfZero(name(dst), name(src))])
+    }
+
+    /// `CMOVNZ dst src`
+    pub fn cmovnz(self, dst: Register, src: Register) -> Self {
+        self.with([Instruction::MoveIfNotZero(name(dst), name(src))])
+    }
+
+    /// `LOAD reg`, reading the word at the address in M
+    pub fn load_word(self, reg: Register) -> Self {
+        self.with([Instruction::Load(name(reg))])
+    }
+
+    /// `STORE reg`, writing the register at the address in M
+    pub fn store_word(self, reg: Register) -> Self {
+        self.with([Instruction::Store(name(reg))])
+    }
+
+    /// `LOAD reg, [base]`, reading the word at the address in `base`
+    pub fn load_indirect(self, reg: Register, base: Register) -> Self {
+        self.with([Instruction::LoadIndirect(name(reg), name(base))])
+    }
+
+    /// `STORE reg, [base]`, writing the register at the address in `base`
+    pub fn store_indirect(self, reg: Register, base: Register) -> Self {
+        self.with([Instruction::StoreIndirect(name(reg), name(base))])
+    }
+
+    /// `LOAD reg, [base+offset]`; the address wraps, so `offset` can be
+    /// a negative `i16` cast to `u16`
+    pub fn load_offset(self, reg: Register, base: Register, offset: u16) -> Self {
+        self.with([Instruction::LoadOffset(name(reg), name(base), offset)])
+    }
+
+    /// `STORE reg, [base+offset]`, wrapping like [`load_offset`](Self::load_offset)
+    pub fn store_offset(self, reg: Register, base: Register, offset: u16) -> Self {
+        self.with([Instruction::StoreOffset(name(reg), name(base), offset)])
+    }
+
+    /// `LDI reg, value`
+    pub fn ldi(self, reg: Register, value: u16) -> Self {
+        self.with([Instruction::LoadImmediate(name(reg), value)])
+    }
+
+    /// `MOV reg, value`, loading a 16-bit value through the stack
+    pub fn load(self, reg: Register, value: u16) -> Self {
+        self.with(pseudo::load_immediate(&name(reg), value))
+    }
+
+    /// `MOVI reg, label`
+    pub fn load_address(self, reg: Register, label: &str) -> Self {
+        self.with([Instruction::LoadAddress(name(reg), label.to_string())])
+    }
+
+    /// `TEST r1 r2`
+    pub fn test(self, r1: Register, r2: Register) -> Self {
+        self.with([Instruction::Test(name(r1), name(r2))])
+    }
+
+    /// `MEMCPY dst src`, copying M bytes
+    pub fn memcpy(self, dst: Register, src: Register) -> Self {
+        self.with([Instruction::MemCopy(name(dst), name(src))])
+    }
+
+    /// `MEMSET dst value`, filling M bytes
+    pub fn memset(self, dst: Register, value: Register) -> Self {
+        self.with([Instruction::MemSet(name(dst), name(value))])
+    }
+
+    /// `JMP label`, which must be in the first 256 bytes; see [`jmpl`](Self::jmpl)
+    pub fn jmp(self, label: &str) -> Self {
+        self.with([Instruction::Jump(label.to_string())])
+    }
+
+    /// `JMPL label`, which reaches any address
+    pub fn jmpl(self, label: &str) -> Self {
+        self.with([Instruction::JumpLong(label.to_string())])
+    }
+
+    /// `JMPR reg`
+    pub fn jmpr(self, reg: Register) -> Self {
+        self.with([Instruction::JumpRegister(name(reg))])
+    }
+
+    /// `JZ label`, `JNZ label` and the other conditional jumps, which must
+    /// be in the first 256 bytes
+    pub fn jump_if(self, condition: Condition, label: &str) -> Self {
+        self.with([Instruction::JumpIf(condition, label.to_string())])
+    }
+
+    /// `BRA label`, which must be within -128 to +127 bytes of the next
+    /// instruction
+    pub fn bra(self, label: &str) -> Self {
+        self.with([Instruction::Branch(label.to_string())])
+    }
+
+    /// `BZ label`, `BNZ label` and the other conditional branches, with the
+    /// same reach as `BRA`
+    pub fn branch_if(self, condition: Condition, label: &str) -> Self {
+        self.with([Instruction::BranchIf(condition, label.to_string())])
+    }
+
+    /// `SIG code`
+    pub fn sig(self, code: u8) -> Self {
+        self.with([Instruction::Signal(code as u16)])
+    }
+
+    /// `SYSCALL number`
+    pub fn syscall(self, number: u8) -> Self {
+        self.with([Instruction::Syscall(number as u16)])
+    }
+
+    /// `HALT`
+    pub fn halt(self) -> Self {
+        self.with(pseudo::halt())
+    }
+
+    /// Places a label at the current address.
+    pub fn label(self, name: &str) -> Self {
+        self.with([Instruction::Label(name.to_string())])
+    }
+
+    /// `.entry label`, starting execution at a label instead of address 0
+    pub fn entry(self, label: &str) -> Self {
+        self.with([Instruction::Entry(label.to_string())])
+    }
+
+    /// `.db`, raw data bytes
+    pub fn bytes(self, bytes: &[u8]) -> Self {
+        self.with([Instruction::Bytes(bytes.to_vec())])
+    }
+
+    /// `.fill count, value`
+    pub fn fill(self, count: u16, value: u8) -> Self {
+        self.with([Instruction::Fill {
+            count,
+            value: value as u16,
+        }])
+    }
+
+    /// The instructions emitted so far, pseudo-instructions expanded.
+    pub fn instructions(&self) -> &[Instruction] {
+        &self.instructions
+    }
+
+    /// Encodes the program, ready for `Machine::load_program`. Fails on a
+    /// duplicate or undefined label.
+    pub fn assemble(&self) -> Result<Vec<u8>, AsmError> {
+        Ok(crate::asm::codegen::generate_bytecode(&self.instructions)?)
+    }
+}
@@ -0,0 +1,69 @@
+//! Unit tests for the program builder.
+
+#[cfg(test)]
+mod tests {
+    use crate::asm::{AsmError, ProgramBuilder, assemble_str};
+    use crate::test_support::halting_machine;
+    use crate::{Machine, Register};
+
+    fn run(bytecode: &[u8]) -> Machine {
+        let mut vm = halting_machine(bytecode);
+        vm.run().unwrap();
+        vm
+    }
+
+    #[test]
+    fn test_matches_the_assembler() {
+        let built = ProgramBuilder::new()
+            .push(10)
+            .push(20)
+            .adds()
+            .pop(Register::A)
+            .mov(Register::B, Register::A)
+            .add(Register::B, Register::A)
+            .load(Register::C, 0x1234)
+            .sig(0x09)
+            .assemble()
+            .unwrap();
+        let source = "push %10\npush %20\nadds\npop A\nmov B A\naddr B A\nmov C, $1234\nsig $09\n";
+        assert_eq!(built, assemble_str(source).unwrap());
+
+        let vm = run(&built);
+        assert_eq!(vm.get_register(Register::A), 30);
+        assert_eq!(vm.get_register(Register::B), 60);
+        assert_eq!(vm.get_register(Register::C), 0x1234);
+    }
+
+    #[test]
+    fn test_labels_resolve_forwards() {
+        let bytecode = ProgramBuilder::new()
+            .entry("start")
+            .label("data")
+            .bytes(b"hi")
+            .label("start")
+            .load_address(Register::A, "end")
+            .push_address("data")
+            .pop(Register::B)
+            .halt()
+            .label("end")
+            .assemble()
+            .unwrap();
+        let vm = run(&bytecode);
+        // 6 header bytes come before the code, but addresses are load offsets
+        assert_eq!(vm.get_register(Register::B), 0);
+        assert_eq!(vm.get_register(Register::A), (bytecode.len() - 6) as u16);
+    }
+
+    #[test]
+    fn test_label_errors() {
+        let undefined = ProgramBuilder::new().load_address(Register::A, "nowhere");
+        assert!(
+            matches!(undefined.assemble(), Err(AsmError::Codegen { message, .. }) if message.contains("nowhere"))
+        );
+
+        let duplicate = ProgramBuilder::new().label("here").nop().label("here");
+        assert!(
+            matches!(duplicate.assemble(), Err(AsmError::Codegen { message, .. }) if message.contains("Duplicate"))
+        );
+    }
+}
@@ -0,0 +1,293 @@
+use crate::asm::ir::Instruction;
+use crate::asm::pseudo;
+use crate::{Op, Program, Register};
+use std::collections::BTreeMap;
+use std::fmt;
+
+/// Maps label names to the byte offsets they resolve to.
+pub type SymbolTable = BTreeMap<String, u16>;
+
+/// Register aliases by the byte offset they take effect at, as
+/// `(name, register)` pairs in the order they were declared.
+pub type AliasTable = BTreeMap<u16, Vec<(String, String)>>;
+
+/// An instruction that could not be encoded.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CodegenError {
+    /// Index of the offending instruction in the IR
+    pub index: usize,
+    pub message: String,
+}
+
+impl CodegenError {
+    fn new(index: usize, message: String) -> Self {
+        Self { index, message }
+    }
+}
+
+impl fmt::Display for CodegenError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.message)
+    }
+}
+
+impl std::error::Error for CodegenError {}
+
+/// First pass: maps labels to byte offsets.
+pub fn resolve_labels(instrs: &[Instruction]) -> Result<SymbolTable, CodegenError> {
+    let mut labels = SymbolTable::new();
+
+    // Wider than an address, so code or a label past 64 KiB is caught
+    let mut pc: usize = 0;
+    for (index, instr) in instrs.iter().enumerate() {
+        let too_large = || {
+            CodegenError::new(
+                index,
+                "program exceeds the 64 KiB address space".to_string(),
+            )
+        };
+        if pc + instr.size() > 0x10000 {
+            return Err(too_large());
+        }
+        let defined = match instr {
+            Instruction::Label(name) => Some((name, u16::try_from(pc).map_err(|_| too_large())?)),
+            Instruction::Equate(name, address) => Some((name, *address)),
+            _ => None,
+        };
+        if let Some((name, address)) = defined
+            && labels.insert(name.clone(), address).is_some()
+        {
+            return Err(CodegenError::new(
+                index,
+                format!("Duplicate label: {}", name),
+            ));
+        }
+        pc += instr.size();
+    }
+
+    Ok(labels)
+}
+
+/// Maps `.alias` declarations to the byte offset of the code that follows.
+pub fn resolve_aliases(instrs: &[Instruction]) -> AliasTable {
+    let mut aliases = AliasTable::new();
+    let mut pc: u16 = 0;
+    for instr in instrs {
+        if let Instruction::Alias(name, reg) = instr {
+            aliases
+                .entry(pc)
+                .or_default()
+                .push((name.clone(), reg.clone()));
+        }
+        // `resolve_labels` reports programs too large to address
+        let Some(next) = instr.next_address(pc) else {
+            break;
+        };
+        pc = next;
+    }
+    aliases
+}
+
+pub fn generate_bytecode(instrs: &[Instruction]) -> Result<Vec<u8>, CodegenError> {
+    let mut bytecode = Vec::new();
+    let labels = resolve_labels(instrs)?;
+
+    let mut entry = None;
+    for (index, instr) in instrs.iter().enumerate() {
+        if let Instruction::Entry(label) = instr
+            && entry.replace((index, label)).is_some()
+        {
+            return Err(CodegenError::new(
+                index,
+                "Duplicate .entry directive".to_string(),
+            ));
+        }
+    }
+
+    // Second pass: encode instructions
+    for (index, instr) in instrs.iter().enumerate() {
+        encode_instruction(instr, &labels, &mut bytecode)
+            .map_err(|message| CodegenError::new(index, message))?;
+    }
+
+    // Programs without an entry point stay raw binaries starting at address 0
+    match entry {
+        Some((index, label)) => {
+            let address = labels.get(label).ok_or_else(|| {
+                CodegenError::new(index, format!("Undefined entry label: {}", label))
+            })?;
+            Ok(Program::encode(*address, &bytecode))
+        }
+        None => Ok(bytecode),
+    }
+}
+
+/// Resolves the label a jump goes to. Jumps hold an 8-bit address, so the
+/// label must be in the first 256 bytes; `JMPL` holds a full 16-bit one.
+fn jump_target(label: &str, labels: &SymbolTable, mnemonic: &str) -> Result<u8, String> {
+    let address = labels
+        .get(label)
+        .ok_or_else(|| format!("Undefined label: {}", label))?;
+    u8::try_from(*address).map_err(|_| {
+        format!(
+            "Jump target out of range: {} is at 0x{:04X}, {} reaches 0x00FF - use JMPL {}",
+            label, address, mnemonic, label
+        )
+    })
+}
+
+/// Resolves the label a branch goes to as an offset from the instruction
+/// after the branch at `address`. The offset is a signed byte, so the label
+/// must be within -128 to +127 bytes of it.
+fn branch_offset(
+    label: &str,
+    labels: &SymbolTable,
+    address: u16,
+    mnemonic: &str,
+) -> Result<u8, String> {
+    let target = labels
+        .get(label)
+        .ok_or_else(|| format!("Undefined label: {}", label))?;
+    let offset = *target as i32 - (address as i32 + 2);
+    i8::try_from(offset).map(|o| o as u8).map_err(|_| {
+        format!(
+            "Branch target out of range: {} is {} bytes away, {} reaches -128 to +127 - use JMPL {}",
+            label, offset, mnemonic, label
+        )
+    })
+}
+
+/// Encodes one instruction, resolving label operands against `labels`.
+/// Branches are encoded relative to the end of `bytecode`, where the
+/// instruction goes.
+fn encode_instruction(
+    instr: &Instruction,
+    labels: &SymbolTable,
+    bytecode: &mut Vec<u8>,
+) -> Result<(), String> {
+    let register = |r: &str| Register::from_str(r).map_err(|_| format!("Invalid register: {}", r));
+    match instr {
+        Instruction::Nop => bytecode.extend(Op::Nop.to_bytes()),
+        // Operands wider than 8 bits are truncated, `warnings::check` reports them
+        Instruction::PushImmediate(n) | Instruction::PushHex(n) => {
+            bytecode.extend(Op::Push(*n as u8).to_bytes());
+        }
+        Instruction::PushRegister(r) => {
+            bytecode.extend(Op::PushRegister(register(r)?).to_bytes());
+        }
+        Instruction::Pop(r) => bytecode.extend(Op::PopRegister(register(r)?).to_bytes()),
+        Instruction::AddStack => bytecode.extend(Op::AddStack.to_bytes()),
+        Instruction::AddRegister(r1, r2) => {
+            bytecode.extend(Op::AddRegister(register(r1)?, register(r2)?).to_bytes());
+        }
+        Instruction::SubStack => bytecode.extend(Op::SubStack.to_bytes()),
+        Instruction::SubRegister(r1, r2) => {
+            bytecode.extend(Op::SubRegister(register(r1)?, register(r2)?).to_bytes());
+        }
+        Instruction::Compare(r1, r2) => {
+            bytecode.extend(Op::CompareRegister(register(r1)?, register(r2)?).to_bytes());
+        }
+        Instruction::MulWide(r1, r2) => {
+            bytecode.extend(Op::MulWide(register(r1)?, register(r2)?).to_bytes());
+        }
+        Instruction::AndStack => bytecode.extend(Op::AndStack.to_bytes()),
+        Instruction::OrStack => bytecode.extend(Op::OrStack.to_bytes()),
+        Instruction::XorStack => bytecode.extend(Op::XorStack.to_bytes()),
+        Instruction::NotStack => bytecode.extend(Op::NotStack.to_bytes()),
+        Instruction::Dup => bytecode.extend(Op::Dup.to_bytes()),
+        Instruction::Swap => bytecode.extend(Op::Swap.to_bytes()),
+        Instruction::Drop => bytecode.extend(Op::Drop.to_bytes()),
+        Instruction::Over => bytecode.extend(Op::Over.to_bytes()),
+        Instruction::PushFlags => bytecode.extend(Op::PushFlags.to_bytes()),
+        Instruction::PopFlags => bytecode.extend(Op::PopFlags.to_bytes()),
+        Instruction::AndRegister(r1, r2) => {
+            bytecode.extend(Op::AndRegister(register(r1)?, register(r2)?).to_bytes());
+        }
+        Instruction::OrRegister(r1, r2) => {
+            bytecode.extend(Op::OrRegister(register(r1)?, register(r2)?).to_bytes());
+        }
+        Instruction::XorRegister(r1, r2) => {
+            bytecode.extend(Op::XorRegister(register(r1)?, register(r2)?).to_bytes());
+        }
+        Instruction::NotRegister(r) => bytecode.extend(Op::NotRegister(register(r)?).to_bytes()),
+        Instruction::Move(r1, r2) => {
+            bytecode.extend(Op::MoveRegister(register(r1)?, register(r2)?).to_bytes());
+        }
+        Instruction::Load(r) => bytecode.extend(Op::Load(register(r)?).to_bytes()),
+        Instruction::Store(r) => bytecode.extend(Op::Store(register(r)?).to_bytes()),
+        Instruction::LoadIndirect(r, base) => {
+            bytecode.extend(Op::LoadIndirect(register(r)?, register(base)?).to_bytes());
+        }
+        Instruction::StoreIndirect(r, base) => {
+            bytecode.extend(Op::StoreIndirect(register(r)?, register(base)?).to_bytes());
+        }
+        Instruction::LoadOffset(r, base, offset) => {
+            bytecode.extend(Op::LoadOffset(register(r)?, register(base)?, *offset).to_bytes());
+        }
+        Instruction::StoreOffset(r, base, offset) => {
+            bytecode.extend(Op::StoreOffset(register(r)?, register(base)?, *offset).to_bytes());
+        }
+        Instruction::MoveIfZero(r1, r2) => {
+            bytecode.extend(Op::MoveIfZero(register(r1)?, register(r2)?).to_bytes());
+        }
+        Instruction::MoveIfNotZero(r1, r2) => {
+            bytecode.extend(Op::MoveIfNotZero(register(r1)?, register(r2)?).to_bytes());
+        }
+        Instruction::Test(r1, r2) => {
+            bytecode.extend(Op::Test(register(r1)?, register(r2)?).to_bytes());
+        }
+        Instruction::MemCopy(r1, r2) => {
+            bytecode.extend(Op::MemCopy(register(r1)?, register(r2)?).to_bytes());
+        }
+        Instruction::MemSet(r1, r2) => {
+            bytecode.extend(Op::MemSet(register(r1)?, register(r2)?).to_bytes());
+        }
+        Instruction::LoadImmediate(r, n) => {
+            bytecode.extend(Op::LoadImmediate(register(r)?, *n).to_bytes());
+        }
+        Instruction::Signal(n) => bytecode.extend(Op::Signal(*n as u8).to_bytes()),
+        Instruction::Syscall(n) => bytecode.extend(Op::Syscall(*n as u8).to_bytes()),
+        Instruction::Jump(label) => {
+            bytecode.extend(Op::Jump(jump_target(label, labels, "JMP")?).to_bytes());
+        }
+        Instruction::JumpLong(label) => {
+            let address = labels
+                .get(label)
+                .ok_or_else(|| format!("Undefined label: {}", label))?;
+            bytecode.extend(Op::JumpLong(*address).to_bytes());
+        }
+        Instruction::JumpRegister(r) => {
+            bytecode.extend(Op::JumpRegister(register(r)?).to_bytes());
+        }
+        Instruction::JumpIf(condition, label) => {
+            let target = jump_target(label, labels, condition.mnemonic())?;
+            bytecode.extend(condition.jump(target).to_bytes());
+        }
+        Instruction::Branch(label) => {
+            let offset = branch_offset(label, labels, bytecode.len() as u16, "BRA")?;
+            bytecode.extend(Op::Branch(offset).to_bytes());
+        }
+        Instruction::BranchIf(condition, label) => {
+            let mnemonic = condition.branch_mnemonic();
+            let offset = branch_offset(label, labels, bytecode.len() as u16, mnemonic)?;
+            bytecode.extend(condition.branch(offset).to_bytes());
+        }
+        Instruction::Label(_) => {}   // Skip label in final bytecode
+        Instruction::Entry(_) => {}   // Recorded in the program header
+        Instruction::Equate(..) => {} // Resolved in the first pass
+        Instruction::Alias(..) => {}  // Already substituted by the parser
+        Instruction::Fill { count, value } => {
+            bytecode.extend(std::iter::repeat_n(*value as u8, *count as usize));
+        }
+        Instruction::Bytes(bytes) => bytecode.extend(bytes),
+        Instruction::LoadAddress(reg, label) => {
+            let address = labels
+                .get(label)
+                .ok_or_else(|| format!("Undefined label: {}", label))?;
+            for expanded in pseudo::load_immediate_wide(reg, *address) {
+                encode_instruction(&expanded, labels, bytecode)?;
+            }
+        }
+    }
+    Ok(())
+}
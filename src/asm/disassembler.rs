@@ -0,0 +1,350 @@
+//! Disassembler turning bytecode back into assembler IR.
+//!
+//! Addresses that are referenced by the program (the entry point and jump
+//! and branch targets) get synthesized labels named after their address, e.g. `L0004`,
+//! so the output can be fed back to the assembler. Targets that don't start a
+//! decoded instruction, such as ones past the end of the image or in the
+//! middle of an instruction, are defined with `.equ` instead.
+
+use std::collections::{BTreeMap, BTreeSet};
+
+use crate::asm::{
+    codegen::{AliasTable, SymbolTable},
+    ir::{Condition, Instruction},
+};
+use crate::{Op, Program};
+
+/// Name of the label synthesized for an address.
+pub fn label_for(address: u16) -> String {
+    format!("L{:04X}", address)
+}
+
+/// Address a jump or branch continues at, for operations that jump.
+/// `address` is where the operation itself sits, which branches count from.
+pub fn jump_target(op: &Op, address: u16) -> Option<u16> {
+    let branch = |offset: u8| {
+        address
+            .wrapping_add(op.size())
+            .wrapping_add_signed(offset as i8 as i16)
+    };
+    match op {
+        Op::Jump(target) => Some(*target as u16),
+        Op::JumpLong(target) => Some(*target),
+        Op::Branch(offset) => Some(branch(*offset)),
+        _ => Condition::of(op)
+            .map(|(_, target)| target as u16)
+            .or_else(|| Condition::of_branch(op).map(|(_, offset)| branch(offset))),
+    }
+}
+
+/// Converts a decoded operation at `address` into its IR form. Jumps and
+/// branches refer to the synthesized label of their target.
+pub fn instruction_for(op: &Op, address: u16) -> Instruction {
+    let target = || label_for(jump_target(op, address).unwrap_or_default());
+    match op {
+        Op::Nop => Instruction::Nop,
+        Op::Push(v) => Instruction::PushImmediate(*v as u16),
+        Op::PopRegister(r) => Instruction::Pop(format!("{:?}", r)),
+        Op::PushRegister(r) => Instruction::PushRegister(format!("{:?}", r)),
+        Op::AddStack => Instruction::AddStack,
+        Op::AddRegister(r1, r2) => {
+            Instruction::AddRegister(format!("{:?}", r1), format!("{:?}", r2))
+        }
+        Op::SubStack => Instruction::SubStack,
+        Op::SubRegister(r1, r2) => {
+            Instruction::SubRegister(format!("{:?}", r1), format!("{:?}", r2))
+        }
+        Op::CompareRegister(r1, r2) => {
+            Instruction::Compare(format!("{:?}", r1), format!("{:?}", r2))
+        }
+        Op::Test(r1, r2) => Instruction::Test(format!("{:?}", r1), format!("{:?}", r2)),
+        Op::MulWide(r1, r2) => Instruction::MulWide(format!("{:?}", r1), format!("{:?}", r2)),
+        Op::AndStack => Instruction::AndStack,
+        Op::OrStack => Instruction::OrStack,
+        Op::XorStack => Instruction::XorStack,
+        Op::NotStack => Instruction::NotStack,
+        Op::Dup => Instruction::Dup,
+        Op::Swap => Instruction::Swap,
+        Op::Drop => Instruction::Drop,
+        Op::Over => Instruction::Over,
+        Op::PushFlags => Instruction::PushFlags,
+        Op::PopFlags => Instruction::PopFlags,
+        Op::AndRegister(r1, r2) => {
+            Instruction::AndRegister(format!("{:?}", r1), format!("{:?}", r2))
+        }
+        Op::OrRegister(r1, r2) => Instruction::OrRegister(format!("{:?}", r1), format!("{:?}", r2)),
+        Op::XorRegister(r1, r2) => {
+            Instruction::XorRegister(format!("{:?}", r1), format!("{:?}", r2))
+        }
+        Op::NotRegister(r) => Instruction::NotRegister(format!("{:?}", r)),
+        Op::MoveRegister(r1, r2) => Instruction::Move(format!("{:?}", r1), format!("{:?}", r2)),
+        Op::MoveIfZero(r1, r2) => Instruction::MoveIfZero(format!("{:?}", r1), format!("{:?}", r2)),
+        Op::MoveIfNotZero(r1, r2) => {
+            Instruction::MoveIfNotZero(format!("{:?}", r1), format!("{:?}", r2))
+        }
+        Op::Load(r) => Instruction::Load(format!("{:?}", r)),
+        Op::Store(r) => Instruction::Store(format!("{:?}", r)),
+        Op::LoadIndirect(r, base) => {
+            Instruction::LoadIndirect(format!("{:?}", r), format!("{:?}", base))
+        }
+        Op::StoreIndirect(r, base) => {
+            Instruction::StoreIndirect(format!("{:?}", r), format!("{:?}", base))
+        }
+        Op::LoadOffset(r, base, offset) => {
+            Instruction::LoadOffset(format!("{:?}", r), format!("{:?}", base), *offset)
+        }
+        Op::StoreOffset(r, base, offset) => {
+            Instruction::StoreOffset(format!("{:?}", r), format!("{:?}", base), *offset)
+        }
+        Op::MemCopy(r1, r2) => Instruction::MemCopy(format!("{:?}", r1), format!("{:?}", r2)),
+        Op::MemSet(r1, r2) => Instruction::MemSet(format!("{:?}", r1), format!("{:?}", r2)),
+        Op::LoadImmediate(r, value) => Instruction::LoadImmediate(format!("{:?}", r), *value),
+        Op::Jump(address) => Instruction::Jump(label_for(*address as u16)),
+        Op::JumpLong(address) => Instruction::JumpLong(label_for(*address)),
+        Op::JumpRegister(r) => Instruction::JumpRegister(format!("{:?}", r)),
+        Op::JumpZero(address) => Instruction::JumpIf(Condition::Zero, label_for(*address as u16)),
+        Op::JumpNotZero(address) => {
+            Instruction::JumpIf(Condition::NotZero, label_for(*address as u16))
+        }
+        Op::JumpCarry(address) => Instruction::JumpIf(Condition::Carry, label_for(*address as u16)),
+        Op::JumpNotCarry(address) => {
+            Instruction::JumpIf(Condition::NotCarry, label_for(*address as u16))
+        }
+        Op::JumpLess(address) => Instruction::JumpIf(Condition::Less, label_for(*address as u16)),
+        Op::JumpGreater(address) => {
+            Instruction::JumpIf(Condition::Greater, label_for(*address as u16))
+        }
+        Op::JumpLessEqual(address) => {
+            Instruction::JumpIf(Condition::LessEqual, label_for(*address as u16))
+        }
+        Op::JumpGreaterEqual(address) => {
+            Instruction::JumpIf(Condition::GreaterEqual, label_for(*address as u16))
+        }
+        Op::Branch(_) => Instruction::Branch(target()),
+        Op::BranchZero(_) => Instruction::BranchIf(Condition::Zero, target()),
+        Op::BranchNotZero(_) => Instruction::BranchIf(Condition::NotZero, target()),
+        Op::BranchCarry(_) => Instruction::BranchIf(Condition::Carry, target()),
+        Op::BranchNotCarry(_) => Instruction::BranchIf(Condition::NotCarry, target()),
+        Op::BranchLess(_) => Instruction::BranchIf(Condition::Less, target()),
+        Op::BranchGreater(_) => Instruction::BranchIf(Condition::Greater, target()),
+        Op::BranchLessEqual(_) => Instruction::BranchIf(Condition::LessEqual, target()),
+        Op::BranchGreaterEqual(_) => Instruction::BranchIf(Condition::GreaterEqual, target()),
+        Op::Signal(s) => Instruction::Signal(*s as u16),
+        Op::Syscall(n) => Instruction::Syscall(*n as u16),
+    }
+}
+
+/// Decodes a program image (raw or with a header) into assembler IR.
+///
+/// Fails on the first word that is not a valid instruction.
+pub fn disassemble(bytes: &[u8]) -> Result<Vec<Instruction>, String> {
+    let program = Program::parse(bytes)?;
+    let code = program.code;
+
+    if code.len() % 2 != 0 {
+        return Err(format!(
+            "truncated instruction at 0x{:04X} - program has an odd number of bytes",
+            code.len() - 1
+        ));
+    }
+
+    let mut ops = Vec::with_capacity(code.len() / 2);
+    let mut address = 0;
+    while address < code.len() {
+        let op =
+            Op::from_bytes(&code[address..]).map_err(|e| format!("{} at 0x{:04X}", e, address))?;
+        let size = op.size() as usize;
+        ops.push((address as u16, op));
+        address += size;
+    }
+
+    // Only a headered image has an explicit entry point worth naming
+    let mut targets = BTreeSet::new();
+    let has_header = code.len() != bytes.len();
+    if has_header {
+        targets.insert(program.entry);
+    }
+    targets.extend(
+        ops.iter()
+            .filter_map(|(address, op)| jump_target(op, *address)),
+    );
+
+    let mut instructions = Vec::new();
+    if has_header {
+        instructions.push(Instruction::Entry(label_for(program.entry)));
+    }
+
+    // Targets with no instruction to put a label on still need defining
+    let end = code.len() as u16;
+    let starts: BTreeSet<u16> = ops.iter().map(|(address, _)| *address).collect();
+    for target in &targets {
+        if !starts.contains(target) && *target != end {
+            instructions.push(Instruction::Equate(label_for(*target), *target));
+        }
+    }
+
+    for (address, op) in &ops {
+        if targets.contains(address) {
+            instructions.push(Instruction::Label(label_for(*address)));
+        }
+        instructions.push(instruction_for(op, *address));
+    }
+
+    // A target just past the last instruction still needs its label
+    if targets.contains(&end) {
+        instructions.push(Instruction::Label(label_for(end)));
+    }
+
+    Ok(instructions)
+}
+
+/// One instruction, or one word that doesn't decode, of a disassembly listing.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ListingLine {
+    /// Address of the first byte, relative to the start of the code
+    pub address: u16,
+    /// Raw bytes the line was decoded from
+    pub bytes: Vec<u8>,
+    /// Decoded instruction, or `Instruction::Bytes` for words that don't decode
+    pub instruction: Instruction,
+    /// Where the instruction jumps to, if it is a jump
+    pub target: Option<u16>,
+}
+
+impl ListingLine {
+    /// The instruction with its jump target named by the first of `labels`
+    /// at the target, so it matches the label a listing prints there.
+    pub fn labelled(&self, labels: &BTreeMap<u16, Vec<String>>) -> Instruction {
+        let Some(name) = self
+            .target
+            .and_then(|target| labels.get(&target))
+            .map(|names| names[0].clone())
+        else {
+            return self.instruction.clone();
+        };
+        match &self.instruction {
+            Instruction::Jump(_) => Instruction::Jump(name),
+            Instruction::JumpLong(_) => Instruction::JumpLong(name),
+            Instruction::JumpIf(condition, _) => Instruction::JumpIf(*condition, name),
+            Instruction::Branch(_) => Instruction::Branch(name),
+            Instruction::BranchIf(condition, _) => Instruction::BranchIf(*condition, name),
+            other => other.clone(),
+        }
+    }
+
+    /// Like [`labelled`](Self::labelled), with every register that has an
+    /// alias at this line shown with its names, as in `C (counter)`.
+    pub fn annotated(
+        &self,
+        labels: &BTreeMap<u16, Vec<String>>,
+        aliases: &AliasTable,
+    ) -> Instruction {
+        let mut instruction = self.labelled(labels);
+        let names = register_names(aliases, self.address);
+        for reg in instruction.registers_mut() {
+            if let Some(names) = names.get(reg.as_str()) {
+                *reg = format!("{} ({})", reg, names.join(", "));
+            }
+        }
+        instruction
+    }
+}
+
+/// The alias names of each register at `address`, from the aliases declared
+/// at or before it. Declaring a name again moves it to the new register.
+fn register_names(aliases: &AliasTable, address: u16) -> BTreeMap<&str, Vec<&str>> {
+    let mut registers: BTreeMap<&str, &str> = BTreeMap::new();
+    for (name, reg) in aliases.range(..=address).flat_map(|(_, names)| names) {
+        registers.insert(name, reg);
+    }
+    let mut names: BTreeMap<&str, Vec<&str>> = BTreeMap::new();
+    for (name, reg) in registers {
+        names.entry(reg).or_default().push(name);
+    }
+    names
+}
+
+/// A program decoded word by word, for display.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Listing {
+    /// Entry point recorded in the program header, if there is one
+    pub entry: Option<u16>,
+    /// Addresses that other code refers to and that should get a label
+    pub targets: BTreeSet<u16>,
+    pub lines: Vec<ListingLine>,
+}
+
+impl Listing {
+    /// Label names by address: names from `symbols` first, then synthesized
+    /// labels for targets that have no symbol.
+    pub fn labels(&self, symbols: &SymbolTable) -> BTreeMap<u16, Vec<String>> {
+        let mut labels: BTreeMap<u16, Vec<String>> = BTreeMap::new();
+        for (name, address) in symbols {
+            labels.entry(*address).or_default().push(name.clone());
+        }
+        for target in &self.targets {
+            labels
+                .entry(*target)
+                .or_insert_with(|| vec![label_for(*target)]);
+        }
+        labels
+    }
+
+    /// Addresses of the lines that hold data rather than code. A region runs
+    /// from one label to the next and counts as data if any of its words fails
+    /// to decode, so data that happens to look like instructions is still
+    /// marked when it shares a label with data that doesn't.
+    pub fn data_addresses(&self, labels: &BTreeMap<u16, Vec<String>>) -> BTreeSet<u16> {
+        let region_of = |address: u16| labels.range(..=address).next_back().map(|(a, _)| *a);
+
+        let data_regions: BTreeSet<Option<u16>> = self
+            .lines
+            .iter()
+            .filter(|line| matches!(line.instruction, Instruction::Bytes(_)))
+            .map(|line| region_of(line.address))
+            .collect();
+
+        self.lines
+            .iter()
+            .map(|line| line.address)
+            .filter(|address| data_regions.contains(&region_of(*address)))
+            .collect()
+    }
+}
+
+/// Decodes a program image for display. Unlike [`disassemble`], words that
+/// are not valid instructions (usually data) are kept as raw bytes.
+pub fn listing(bytes: &[u8]) -> Result<Listing, String> {
+    let program = Program::parse(bytes)?;
+    let has_header = program.code.len() != bytes.len();
+    let entry = has_header.then_some(program.entry);
+
+    let code = &program.code;
+    let mut lines = Vec::new();
+    let mut address = 0;
+    while address < code.len() {
+        let op = Op::from_bytes(&code[address..]).ok();
+        let size = op.as_ref().map_or(2, |op| op.size() as usize);
+        let bytes = code[address..(address + size).min(code.len())].to_vec();
+        lines.push(ListingLine {
+            address: address as u16,
+            instruction: op.as_ref().map_or_else(
+                || Instruction::Bytes(bytes.clone()),
+                |op| instruction_for(op, address as u16),
+            ),
+            bytes,
+            target: op.as_ref().and_then(|op| jump_target(op, address as u16)),
+        });
+        address += size;
+    }
+
+    let targets = entry
+        .into_iter()
+        .chain(lines.iter().filter_map(|line| line.target))
+        .collect();
+    Ok(Listing {
+        entry,
+        targets,
+        lines,
+    })
+}
@@ -0,0 +1,270 @@
+//! Unit tests for the disassembler.
+
+#[cfg(test)]
+mod tests {
+    use crate::asm::codegen::generate_bytecode;
+    use crate::asm::disassembler::{ListingLine, disassemble, listing};
+    use crate::asm::ir::{Condition, Instruction};
+    use crate::asm::{AsmOptions, assemble, assemble_str, lexer::Token, parser::parse_tokens};
+
+    const SOURCE: &str = "
+        .entry main
+        helper:
+            push %10
+            pushr R0
+            adds
+            addr A B
+        main:
+            mov C A
+            pop R4
+            nop
+            sig $09
+    ";
+
+    #[test]
+    fn test_disassemble_raw_program() {
+        let bytecode = assemble_str("push %7\npop A\nsig $09").expect("Failed to assemble");
+
+        assert_eq!(
+            disassemble(&bytecode).expect("Failed to disassemble"),
+            vec![
+                Instruction::PushImmediate(7),
+                Instruction::Pop("A".to_string()),
+                Instruction::Signal(0x09),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_disassemble_synthesizes_entry_label() {
+        let bytecode = assemble_str(SOURCE).expect("Failed to assemble");
+        let ir = disassemble(&bytecode).expect("Failed to disassemble");
+
+        assert_eq!(ir[0], Instruction::Entry("L0008".to_string()));
+        assert_eq!(ir[5], Instruction::Label("L0008".to_string()));
+    }
+
+    #[test]
+    fn test_round_trip_through_ir() {
+        let bytecode = assemble_str(SOURCE).expect("Failed to assemble");
+        let ir = disassemble(&bytecode).expect("Failed to disassemble");
+
+        assert_eq!(generate_bytecode(&ir).expect("Failed to encode"), bytecode);
+    }
+
+    #[test]
+    fn test_round_trip_through_source_text() {
+        let bytecode = assemble_str(SOURCE).expect("Failed to assemble");
+        let text: String = disassemble(&bytecode)
+            .expect("Failed to disassemble")
+            .iter()
+            .map(|instr| format!("{}\n", instr))
+            .collect();
+
+        let ir = parse_tokens(&Token::tokenize_source(&text).expect("Failed to tokenize"))
+            .expect("Failed to parse");
+        assert_eq!(generate_bytecode(&ir).expect("Failed to encode"), bytecode);
+    }
+
+    #[test]
+    fn test_disassemble_invalid_input() {
+        // Odd length
+        assert!(disassemble(&[0x00]).is_err());
+        // Unknown opcode
+        assert!(disassemble(&[0xFF, 0x00]).is_err());
+    }
+
+    #[test]
+    fn test_listing_keeps_data_words() {
+        let bytecode = assemble_str(".entry main\nmain:\n  nop\n  .db $FF, $FF, $07")
+            .expect("Failed to assemble");
+
+        let listing = listing(&bytecode).expect("Failed to build listing");
+        assert_eq!(listing.entry, Some(0));
+        assert!(listing.targets.contains(&0));
+        assert_eq!(
+            listing.lines,
+            vec![
+                ListingLine {
+                    address: 0,
+                    bytes: vec![0x00, 0x00],
+                    instruction: Instruction::Nop,
+                    target: None,
+                },
+                ListingLine {
+                    address: 2,
+                    bytes: vec![0xFF, 0xFF],
+                    instruction: Instruction::Bytes(vec![0xFF, 0xFF]),
+                    target: None,
+                },
+                ListingLine {
+                    address: 4,
+                    bytes: vec![0x07],
+                    instruction: Instruction::Bytes(vec![0x07]),
+                    target: None,
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_jump_targets_get_labels() {
+        let bytecode = assemble_str("start:\n  nop\nback:\n  push %1\n  jmp back\n")
+            .expect("Failed to assemble");
+
+        assert_eq!(
+            disassemble(&bytecode).expect("Failed to disassemble"),
+            vec![
+                Instruction::Nop,
+                Instruction::Label("L0002".to_string()),
+                Instruction::PushImmediate(1),
+                Instruction::Jump("L0002".to_string()),
+            ]
+        );
+
+        let assembly = assemble(
+            "start:\n  nop\nback:\n  push %1\n  jmp back\n",
+            &AsmOptions::default(),
+        )
+        .expect("Failed to assemble");
+        let listing = listing(&assembly.bytecode).expect("Failed to build listing");
+        assert!(listing.targets.contains(&2));
+        assert_eq!(listing.lines[2].target, Some(2));
+        let labels = listing.labels(&assembly.symbols);
+        assert_eq!(
+            listing.lines[2].labelled(&labels),
+            Instruction::Jump("back".to_string())
+        );
+    }
+
+    #[test]
+    fn test_listing_shows_register_aliases() {
+        let assembly = assemble(
+            "pop C\n.alias counter, C\n.alias total, C\nmov counter, A\n.alias total, B\naddr total counter\n",
+            &AsmOptions::default(),
+        )
+        .expect("Failed to assemble");
+        let listing = listing(&assembly.bytecode).expect("Failed to build listing");
+        let labels = listing.labels(&assembly.symbols);
+        let shown: Vec<String> = listing
+            .lines
+            .iter()
+            .map(|line| line.annotated(&labels, &assembly.aliases).to_string())
+            .collect();
+        assert_eq!(
+            shown,
+            vec![
+                "POP C",
+                "MOV C (counter, total) A",
+                "ADDR B (total) C (counter)",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_listing_data_regions() {
+        let assembly = assemble(
+            ".entry main\nmsg:\n  .db $FF, $FF, $01, $02\nmain:\n  nop\n",
+            &AsmOptions::default(),
+        )
+        .expect("Failed to assemble");
+        let listing = listing(&assembly.bytecode).expect("Failed to build listing");
+
+        let labels = listing.labels(&assembly.symbols);
+        assert_eq!(labels[&0], vec!["msg".to_string()]);
+        assert_eq!(labels[&4], vec!["main".to_string()]);
+
+        // `$01, $02` decodes as PUSH but shares its region with undecodable data
+        let data = listing.data_addresses(&labels);
+        assert_eq!(data.into_iter().collect::<Vec<_>>(), vec![0, 2]);
+
+        // Without symbols the code before the entry point is a single region
+        let labels = listing.labels(&Default::default());
+        assert_eq!(labels[&4], vec!["L0004".to_string()]);
+        let data = listing.data_addresses(&labels);
+        assert_eq!(data.into_iter().collect::<Vec<_>>(), vec![0, 2]);
+    }
+
+    #[test]
+    fn test_wide_instructions_take_their_whole_length() {
+        let bytecode = assemble_str("ldi A #1000\npop B").expect("Failed to assemble");
+
+        assert_eq!(
+            disassemble(&bytecode).expect("Failed to disassemble"),
+            vec![
+                Instruction::LoadImmediate("A".to_string(), 1000),
+                Instruction::Pop("B".to_string()),
+            ]
+        );
+        let lines = listing(&bytecode).expect("Failed to list").lines;
+        assert_eq!(lines[0].bytes, [0x18, 0x00, 0xE8, 0x03]);
+        assert_eq!(lines[1].address, 4);
+        assert_eq!(
+            disassemble(&bytecode[..2]),
+            Err("truncated instruction - 0x18 takes 4 bytes at 0x0000".to_string())
+        );
+    }
+
+    #[test]
+    fn test_addressing_forms_round_trip_through_source_text() {
+        let bytecode = assemble_str("load A, [R0]\nstore B, [M-2]\nstoreo C, [SP]")
+            .expect("Failed to assemble");
+        let text: Vec<String> = disassemble(&bytecode)
+            .expect("Failed to disassemble")
+            .iter()
+            .map(ToString::to_string)
+            .collect();
+
+        assert_eq!(
+            text,
+            ["LOADR A, [R0]", "STOREO B, [M-2]", "STOREO C, [SP+0]"]
+        );
+        assert_eq!(
+            assemble_str(&text.join("\n")).expect("Failed to reassemble"),
+            bytecode
+        );
+    }
+
+    #[test]
+    fn test_branch_targets_count_from_the_next_instruction() {
+        let source = "back:\n  nop\n  bnz back\n  bra ahead\n  nop\nahead:\n  nop\n";
+        let bytecode = assemble_str(source).expect("Failed to assemble");
+        assert_eq!(&bytecode[2..6], &[0x42, 0xFC, 0x40, 0x02]);
+
+        let ir = disassemble(&bytecode).expect("Failed to disassemble");
+        assert_eq!(
+            ir,
+            vec![
+                Instruction::Label("L0000".to_string()),
+                Instruction::Nop,
+                Instruction::BranchIf(Condition::NotZero, "L0000".to_string()),
+                Instruction::Branch("L0008".to_string()),
+                Instruction::Nop,
+                Instruction::Label("L0008".to_string()),
+                Instruction::Nop,
+            ]
+        );
+        assert_eq!(generate_bytecode(&ir).expect("Failed to encode"), bytecode);
+    }
+
+    #[test]
+    fn test_targets_without_an_instruction_are_equated() {
+        // BRA into the middle of the JNZ, JNZ past the end of the image
+        let bytecode = [0x40, 0x01, 0x22, 0x40];
+        let ir = disassemble(&bytecode).expect("Failed to disassemble");
+        assert_eq!(
+            ir,
+            vec![
+                Instruction::Equate("L0003".to_string(), 0x0003),
+                Instruction::Equate("L0040".to_string(), 0x0040),
+                Instruction::Branch("L0003".to_string()),
+                Instruction::JumpIf(Condition::NotZero, "L0040".to_string()),
+            ]
+        );
+        assert_eq!(generate_bytecode(&ir).expect("Failed to encode"), bytecode);
+
+        let source: String = ir.iter().map(|instr| format!("{}\n", instr)).collect();
+        assert!(source.starts_with(".equ L0003, $0003\n"));
+        assert_eq!(assemble_str(&source).expect("Failed to assemble"), bytecode);
+    }
+}
@@ -0,0 +1,150 @@
+//! Assembly-time integer expressions such as `BASE+4` or `(SIZE*2)|1`.
+//!
+//! Expressions are written without spaces, since whitespace separates
+//! operands. Numbers take the same prefixes as operands (`%`, `#`, `$`, `0x`)
+//! and may also be plain decimal. Names, including `$` and `.` for the current
+//! address, are looked up through a callback, so the caller decides which
+//! symbols are known.
+//!
+//! Operators, from lowest to highest precedence: `|`, `^`, `&`, `<<` `>>`,
+//! `+` `-`, `*` `/`, then unary `-` and `~`.
+
+/// Evaluates an expression, resolving names with `lookup`.
+pub fn eval(text: &str, lookup: &dyn Fn(&str) -> Option<i64>) -> Result<i64, String> {
+    let mut parser = Parser {
+        text,
+        pos: 0,
+        lookup,
+    };
+    let value = parser.binary(0)?;
+    match parser.peek() {
+        None => Ok(value),
+        Some(c) => Err(format!("unexpected `{}` in `{}`", c, text)),
+    }
+}
+
+/// Returns the names an expression refers to, excluding the current address.
+pub fn symbols(text: &str) -> Vec<&str> {
+    text.split(|c: char| !(c.is_alphanumeric() || matches!(c, '_' | '.' | '%' | '#' | '$')))
+        .filter(|word| {
+            !word.is_empty()
+                && *word != "."
+                && !word.starts_with(|c: char| c.is_ascii_digit() || matches!(c, '%' | '#' | '$'))
+        })
+        .collect()
+}
+
+/// Binary operators grouped by precedence level, lowest first.
+const LEVELS: &[&[&str]] = &[
+    &["|"],
+    &["^"],
+    &["&"],
+    &["<<", ">>"],
+    &["+", "-"],
+    &["*", "/"],
+];
+
+struct Parser<'a> {
+    text: &'a str,
+    pos: usize,
+    lookup: &'a dyn Fn(&str) -> Option<i64>,
+}
+
+impl Parser<'_> {
+    fn rest(&self) -> &str {
+        &self.text[self.pos..]
+    }
+
+    fn peek(&self) -> Option<char> {
+        self.rest().chars().next()
+    }
+
+    fn binary(&mut self, level: usize) -> Result<i64, String> {
+        if level == LEVELS.len() {
+            return self.unary();
+        }
+
+        let mut value = self.binary(level + 1)?;
+        while let Some(op) = LEVELS[level]
+            .iter()
+            .find(|op| self.rest().starts_with(**op))
+        {
+            self.pos += op.len();
+            let rhs = self.binary(level + 1)?;
+            value = match *op {
+                "|" => value | rhs,
+                "^" => value ^ rhs,
+                "&" => value & rhs,
+                "<<" => value.checked_shl(rhs as u32).unwrap_or(0),
+                ">>" => value.checked_shr(rhs as u32).unwrap_or(0),
+                "+" => value.wrapping_add(rhs),
+                "-" => value.wrapping_sub(rhs),
+                "*" => value.wrapping_mul(rhs),
+                _ if rhs == 0 => return Err(format!("division by zero in `{}`", self.text)),
+                _ => value / rhs,
+            };
+        }
+        Ok(value)
+    }
+
+    fn unary(&mut self) -> Result<i64, String> {
+        match self.peek() {
+            Some('-') => {
+                self.pos += 1;
+                Ok(self.unary()?.wrapping_neg())
+            }
+            Some('~') => {
+                self.pos += 1;
+                Ok(!self.unary()?)
+            }
+            Some('(') => {
+                self.pos += 1;
+                let value = self.binary(0)?;
+                if self.peek() != Some(')') {
+                    return Err(format!("missing `)` in `{}`", self.text));
+                }
+                self.pos += 1;
+                Ok(value)
+            }
+            Some(_) => self.atom(),
+            None => Err(format!("unexpected end of `{}`", self.text)),
+        }
+    }
+
+    fn atom(&mut self) -> Result<i64, String> {
+        let len = self
+            .rest()
+            .find(|c: char| !(c.is_alphanumeric() || matches!(c, '_' | '.' | '%' | '#' | '$')))
+            .unwrap_or(self.rest().len());
+        let text = self.text;
+        let word = &text[self.pos..self.pos + len];
+        self.pos += len;
+
+        if word.is_empty() {
+            return Err(format!("expected a value in `{}`", self.text));
+        }
+
+        let number = if word == "$" || word == "." {
+            // The current address, resolved by the caller
+            None
+        } else if let Some(digits) = word.strip_prefix('%').or(word.strip_prefix('#')) {
+            Some(digits.parse::<i64>().ok())
+        } else if let Some(digits) = word
+            .strip_prefix('$')
+            .or(word.strip_prefix("0x"))
+            .or(word.strip_prefix("0X"))
+        {
+            Some(i64::from_str_radix(digits, 16).ok())
+        } else if word.starts_with(|c: char| c.is_ascii_digit()) {
+            Some(word.parse::<i64>().ok())
+        } else {
+            None
+        };
+
+        match number {
+            Some(Some(value)) => Ok(value),
+            Some(None) => Err(format!("invalid number `{}`", word)),
+            None => (self.lookup)(word).ok_or_else(|| format!("undefined symbol `{}`", word)),
+        }
+    }
+}
@@ -0,0 +1,48 @@
+//! Unit tests for assembly-time expressions.
+
+#[cfg(test)]
+mod tests {
+    use crate::asm::expr::{eval, symbols};
+
+    fn lookup(name: &str) -> Option<i64> {
+        match name {
+            "BASE" => Some(0x1000),
+            "SIZE" => Some(16),
+            _ => None,
+        }
+    }
+
+    #[test]
+    fn test_eval_numbers_and_precedence() {
+        assert_eq!(eval("1+2*3", &lookup), Ok(7));
+        assert_eq!(eval("(1+2)*3", &lookup), Ok(9));
+        assert_eq!(eval("%10+$10+0x10+#1", &lookup), Ok(43));
+        assert_eq!(eval("1<<4|1", &lookup), Ok(17));
+        assert_eq!(eval("-SIZE+20", &lookup), Ok(4));
+        assert_eq!(eval("~0&$FF", &lookup), Ok(255));
+    }
+
+    #[test]
+    fn test_eval_symbols() {
+        assert_eq!(eval("BASE+SIZE*2", &lookup), Ok(0x1020));
+        assert_eq!(eval("BASE>>8", &lookup), Ok(0x10));
+    }
+
+    #[test]
+    fn test_eval_errors() {
+        assert!(eval("MISSING+1", &lookup).is_err());
+        assert!(eval("1/0", &lookup).is_err());
+        assert!(eval("(1+2", &lookup).is_err());
+        assert!(eval("1+", &lookup).is_err());
+        assert!(eval("0xZZ", &lookup).is_err());
+    }
+
+    #[test]
+    fn test_symbols() {
+        assert_eq!(
+            symbols("end-start+$10*.loop-$"),
+            vec!["end", "start", ".loop"]
+        );
+        assert!(symbols("$+%1+0x2-.").is_empty());
+    }
+}
@@ -0,0 +1,98 @@
+//! `.include "file"` handling.
+//!
+//! Included files are tokenized on their own and spliced into the token
+//! stream in place of the directive. A file is looked up next to the file
+//! that includes it first, then in each include directory in order.
+
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+use crate::asm::{AsmError, lexer::Token};
+
+/// Tokenizes `source` and splices in every file it includes.
+///
+/// `dir` is the directory of the file `source` came from, if any. Tokens from
+/// an included file report the line of the `.include` that pulled them in.
+pub fn tokenize_with_includes(
+    source: &str,
+    dir: Option<&Path>,
+    include_dirs: &[PathBuf],
+) -> Result<(Vec<Token>, Vec<usize>), AsmError> {
+    let mut stack = Vec::new();
+    expand(source, dir, include_dirs, &mut stack)
+}
+
+fn expand(
+    source: &str,
+    dir: Option<&Path>,
+    include_dirs: &[PathBuf],
+    stack: &mut Vec<PathBuf>,
+) -> Result<(Vec<Token>, Vec<usize>), AsmError> {
+    let (tokens, lines) = Token::tokenize_source_with_lines(source)?;
+    let mut out_tokens = Vec::with_capacity(tokens.len());
+    let mut out_lines = Vec::with_capacity(lines.len());
+
+    let mut i = 0;
+    while i < tokens.len() {
+        if !matches!(&tokens[i], Token::Directive(d) if d == "include") {
+            out_tokens.push(tokens[i].clone());
+            out_lines.push(lines[i]);
+            i += 1;
+            continue;
+        }
+
+        let Some(Token::Str(name)) = tokens.get(i + 1) else {
+            return Err(AsmError::Io(format!(
+                "line {}: .include expects a quoted file name",
+                lines[i]
+            )));
+        };
+        let path = find(name, dir, include_dirs).ok_or_else(|| {
+            AsmError::Io(format!(
+                "line {}: cannot find include file \"{}\"",
+                lines[i], name
+            ))
+        })?;
+        let canonical = path.canonicalize().unwrap_or_else(|_| path.clone());
+        if stack.contains(&canonical) {
+            return Err(AsmError::Io(format!(
+                "line {}: \"{}\" includes itself",
+                lines[i], name
+            )));
+        }
+
+        let included = fs::read_to_string(&path)
+            .map_err(|e| AsmError::Io(format!("{}: {}", path.display(), e)))?;
+        stack.push(canonical);
+        let (inner_tokens, _) =
+            expand(&included, path.parent(), include_dirs, stack).map_err(|e| match e {
+                // The line is in the included file, so name that file
+                AsmError::Lex(mut e) if e.file.is_none() => {
+                    e.file = Some(path.display().to_string());
+                    AsmError::Lex(e)
+                }
+                other => other,
+            })?;
+        stack.pop();
+
+        out_lines.extend(std::iter::repeat_n(lines[i], inner_tokens.len()));
+        out_tokens.extend(inner_tokens);
+        i += 2;
+    }
+
+    Ok((out_tokens, out_lines))
+}
+
+/// Resolves an include name against the including file's directory, then the
+/// include directories.
+fn find(name: &str, dir: Option<&Path>, include_dirs: &[PathBuf]) -> Option<PathBuf> {
+    if Path::new(name).is_absolute() {
+        return Some(PathBuf::from(name)).filter(|path| path.is_file());
+    }
+    dir.into_iter()
+        .chain(include_dirs.iter().map(PathBuf::as_path))
+        .map(|d| d.join(name))
+        .find(|path| path.is_file())
+}
@@ -0,0 +1,333 @@
+use crate::Op;
+use crate::asm::pseudo;
+use std::fmt;
+
+/// Flag test of a conditional jump or branch.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Condition {
+    Zero,
+    NotZero,
+    Carry,
+    NotCarry,
+    Less,
+    Greater,
+    LessEqual,
+    GreaterEqual,
+}
+
+impl Condition {
+    pub const ALL: [Condition; 8] = [
+        Condition::Zero,
+        Condition::NotZero,
+        Condition::Carry,
+        Condition::NotCarry,
+        Condition::Less,
+        Condition::Greater,
+        Condition::LessEqual,
+        Condition::GreaterEqual,
+    ];
+
+    /// The condition a conditional jump mnemonic tests.
+    pub fn from_mnemonic(mnemonic: &str) -> Option<Condition> {
+        Condition::ALL
+            .into_iter()
+            .find(|condition| condition.mnemonic() == mnemonic)
+    }
+
+    /// The conditional jump to `target` that tests this condition.
+    pub fn jump(self, target: u8) -> Op {
+        match self {
+            Condition::Zero => Op::JumpZero(target),
+            Condition::NotZero => Op::JumpNotZero(target),
+            Condition::Carry => Op::JumpCarry(target),
+            Condition::NotCarry => Op::JumpNotCarry(target),
+            Condition::Less => Op::JumpLess(target),
+            Condition::Greater => Op::JumpGreater(target),
+            Condition::LessEqual => Op::JumpLessEqual(target),
+            Condition::GreaterEqual => Op::JumpGreaterEqual(target),
+        }
+    }
+
+    /// The condition a conditional jump tests, and its target.
+    pub fn of(op: &Op) -> Option<(Condition, u8)> {
+        match op {
+            Op::JumpZero(target) => Some((Condition::Zero, *target)),
+            Op::JumpNotZero(target) => Some((Condition::NotZero, *target)),
+            Op::JumpCarry(target) => Some((Condition::Carry, *target)),
+            Op::JumpNotCarry(target) => Some((Condition::NotCarry, *target)),
+            Op::JumpLess(target) => Some((Condition::Less, *target)),
+            Op::JumpGreater(target) => Some((Condition::Greater, *target)),
+            Op::JumpLessEqual(target) => Some((Condition::LessEqual, *target)),
+            Op::JumpGreaterEqual(target) => Some((Condition::GreaterEqual, *target)),
+            _ => None,
+        }
+    }
+
+    /// The condition a conditional branch mnemonic tests.
+    pub fn from_branch_mnemonic(mnemonic: &str) -> Option<Condition> {
+        Condition::ALL
+            .into_iter()
+            .find(|condition| condition.branch_mnemonic() == mnemonic)
+    }
+
+    /// The conditional branch by `offset` that tests this condition.
+    pub fn branch(self, offset: u8) -> Op {
+        match self {
+            Condition::Zero => Op::BranchZero(offset),
+            Condition::NotZero => Op::BranchNotZero(offset),
+            Condition::Carry => Op::BranchCarry(offset),
+            Condition::NotCarry => Op::BranchNotCarry(offset),
+            Condition::Less => Op::BranchLess(offset),
+            Condition::Greater => Op::BranchGreater(offset),
+            Condition::LessEqual => Op::BranchLessEqual(offset),
+            Condition::GreaterEqual => Op::BranchGreaterEqual(offset),
+        }
+    }
+
+    /// The condition a conditional branch tests, and its offset.
+    pub fn of_branch(op: &Op) -> Option<(Condition, u8)> {
+        match op {
+            Op::BranchZero(offset) => Some((Condition::Zero, *offset)),
+            Op::BranchNotZero(offset) => Some((Condition::NotZero, *offset)),
+            Op::BranchCarry(offset) => Some((Condition::Carry, *offset)),
+            Op::BranchNotCarry(offset) => Some((Condition::NotCarry, *offset)),
+            Op::BranchLess(offset) => Some((Condition::Less, *offset)),
+            Op::BranchGreater(offset) => Some((Condition::Greater, *offset)),
+            Op::BranchLessEqual(offset) => Some((Condition::LessEqual, *offset)),
+            Op::BranchGreaterEqual(offset) => Some((Condition::GreaterEqual, *offset)),
+            _ => None,
+        }
+    }
+
+    pub fn mnemonic(self) -> &'static str {
+        self.jump(0).mnemonic()
+    }
+
+    pub fn branch_mnemonic(self) -> &'static str {
+        self.branch(0).mnemonic()
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Instruction {
+    Nop,
+    PushImmediate(u16),
+    PushHex(u16),
+    PushRegister(String),
+    Pop(String),
+    AddStack,
+    AddRegister(String, String),
+    SubStack,
+    SubRegister(String, String),
+    /// `CMP r1 r2` - sets the flags of `r1 - r2` without storing it
+    Compare(String, String),
+    /// `TEST r1 r2` - sets the flags of `r1 & r2` without storing it
+    Test(String, String),
+    /// `MULW r1 r2` - 32-bit product, low word in `r1` and high word in M
+    MulWide(String, String),
+    AndStack,
+    OrStack,
+    XorStack,
+    NotStack,
+    Dup,
+    Swap,
+    Drop,
+    Over,
+    /// `PUSHF` - pushes FLAGS
+    PushFlags,
+    /// `POPF` - pops into FLAGS
+    PopFlags,
+    AndRegister(String, String),
+    OrRegister(String, String),
+    XorRegister(String, String),
+    NotRegister(String),
+    Move(String, String),
+    /// `CMOVZ r1 r2` - copies `r2` into `r1` if the zero flag is set
+    MoveIfZero(String, String),
+    /// `CMOVNZ r1 r2` - copies `r2` into `r1` if the zero flag is clear
+    MoveIfNotZero(String, String),
+    /// `LOAD reg` - loads the word at the address in M
+    Load(String),
+    /// `STORE reg` - stores a register at the address in M
+    Store(String),
+    /// `LOAD reg, [base]` - loads the word at the address in `base`
+    LoadIndirect(String, String),
+    /// `STORE reg, [base]` - stores a register at the address in `base`
+    StoreIndirect(String, String),
+    /// `LOAD reg, [base+offset]` - loads the word at `base` plus a 16-bit offset
+    LoadOffset(String, String, u16),
+    /// `STORE reg, [base+offset]` - stores a register at `base` plus a 16-bit offset
+    StoreOffset(String, String, u16),
+    /// `MEMCPY dst src` - copies M bytes from the address in `src` to the address in `dst`
+    MemCopy(String, String),
+    /// `MEMSET dst value` - fills M bytes at the address in `dst` with the low byte of `value`
+    MemSet(String, String),
+    /// `LDI reg, value` - loads a 16-bit value into a register
+    LoadImmediate(String, u16),
+    Signal(u16),
+    /// `SYSCALL $n` - calls a syscall handler, arguments in A, B and C
+    Syscall(u16),
+    Label(String),
+    Jump(String),
+    /// `JMPL label` - jumps to a 16-bit address anywhere in memory
+    JumpLong(String),
+    /// `JMPR reg` - continues at the address in a register
+    JumpRegister(String),
+    /// `JZ label` and the other conditional jumps
+    JumpIf(Condition, String),
+    /// `BRA label` - jumps by a signed 8-bit offset from the next instruction
+    Branch(String),
+    /// `BZ label` and the other conditional branches
+    BranchIf(Condition, String),
+    /// `.entry label` - where execution starts
+    Entry(String),
+    /// `.equ name, address` - a label at a fixed address rather than where
+    /// it appears
+    Equate(String, u16),
+    /// `.alias name, reg` - `name` stands for `reg` from here on. Operands
+    /// already name the register, this only keeps the alias for listings
+    Alias(String, String),
+    /// `.fill count, value` - `count` copies of the byte `value`
+    Fill {
+        count: u16,
+        value: u16,
+    },
+    /// `.db` / `.ascii` - raw data bytes
+    Bytes(Vec<u8>),
+    /// `MOVI reg, label` - loads the address of a label into a register
+    LoadAddress(String, String),
+}
+
+impl Instruction {
+    /// Number of bytes the instruction occupies in the encoded program.
+    pub fn size(&self) -> usize {
+        match self {
+            Instruction::Label(_)
+            | Instruction::Entry(_)
+            | Instruction::Equate(..)
+            | Instruction::Alias(..) => 0,
+            Instruction::Fill { count, .. } => *count as usize,
+            Instruction::Bytes(bytes) => bytes.len(),
+            Instruction::LoadAddress(..) => pseudo::LOAD_ADDRESS_SIZE as usize,
+            Instruction::LoadImmediate(..)
+            | Instruction::LoadOffset(..)
+            | Instruction::StoreOffset(..)
+            | Instruction::JumpLong(_) => 4,
+            _ => 2,
+        }
+    }
+
+    /// Address just past the instruction when it is placed at `address`, or
+    /// `None` if it runs past the 64 KiB address space.
+    pub fn next_address(&self, address: u16) -> Option<u16> {
+        u16::try_from(address as usize + self.size()).ok()
+    }
+
+    /// The register operands, in source order.
+    pub fn registers_mut(&mut self) -> Vec<&mut String> {
+        match self {
+            Instruction::PushRegister(r)
+            | Instruction::Pop(r)
+            | Instruction::NotRegister(r)
+            | Instruction::Load(r)
+            | Instruction::Store(r)
+            | Instruction::LoadImmediate(r, _)
+            | Instruction::JumpRegister(r)
+            | Instruction::LoadAddress(r, _) => vec![r],
+            Instruction::AddRegister(r1, r2)
+            | Instruction::SubRegister(r1, r2)
+            | Instruction::Compare(r1, r2)
+            | Instruction::Test(r1, r2)
+            | Instruction::MulWide(r1, r2)
+            | Instruction::AndRegister(r1, r2)
+            | Instruction::OrRegister(r1, r2)
+            | Instruction::XorRegister(r1, r2)
+            | Instruction::Move(r1, r2)
+            | Instruction::MoveIfZero(r1, r2)
+            | Instruction::MoveIfNotZero(r1, r2)
+            | Instruction::LoadIndirect(r1, r2)
+            | Instruction::StoreIndirect(r1, r2)
+            | Instruction::LoadOffset(r1, r2, _)
+            | Instruction::StoreOffset(r1, r2, _)
+            | Instruction::MemCopy(r1, r2)
+            | Instruction::MemSet(r1, r2) => vec![r1, r2],
+            _ => Vec::new(),
+        }
+    }
+}
+
+/// Renders the instruction as assembly source the parser accepts.
+impl fmt::Display for Instruction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Instruction::Nop => write!(f, "NOP"),
+            Instruction::PushImmediate(n) => write!(f, "PUSH %{}", n),
+            Instruction::PushHex(n) => write!(f, "PUSH ${:02X}", n),
+            Instruction::PushRegister(r) => write!(f, "PUSHR {}", r),
+            Instruction::Pop(r) => write!(f, "POP {}", r),
+            Instruction::AddStack => write!(f, "ADDS"),
+            Instruction::AddRegister(r1, r2) => write!(f, "ADDR {} {}", r1, r2),
+            Instruction::SubStack => write!(f, "SUBS"),
+            Instruction::SubRegister(r1, r2) => write!(f, "SUBR {} {}", r1, r2),
+            Instruction::Compare(r1, r2) => write!(f, "CMP {} {}", r1, r2),
+            Instruction::Test(r1, r2) => write!(f, "TEST {} {}", r1, r2),
+            Instruction::MulWide(r1, r2) => write!(f, "MULW {} {}", r1, r2),
+            Instruction::AndStack => write!(f, "ANDS"),
+            Instruction::OrStack => write!(f, "ORS"),
+            Instruction::XorStack => write!(f, "XORS"),
+            Instruction::NotStack => write!(f, "NOTS"),
+            Instruction::Dup => write!(f, "DUP"),
+            Instruction::Swap => write!(f, "SWAP"),
+            Instruction::Drop => write!(f, "DROP"),
+            Instruction::Over => write!(f, "OVER"),
+            Instruction::PushFlags => write!(f, "PUSHF"),
+            Instruction::PopFlags => write!(f, "POPF"),
+            Instruction::AndRegister(r1, r2) => write!(f, "ANDR {} {}", r1, r2),
+            Instruction::OrRegister(r1, r2) => write!(f, "ORR {} {}", r1, r2),
+            Instruction::XorRegister(r1, r2) => write!(f, "XORR {} {}", r1, r2),
+            Instruction::NotRegister(r) => write!(f, "NOTR {}", r),
+            Instruction::Move(r1, r2) => write!(f, "MOV {} {}", r1, r2),
+            Instruction::MoveIfZero(r1, r2) => write!(f, "CMOVZ {} {}", r1, r2),
+            Instruction::MoveIfNotZero(r1, r2) => write!(f, "CMOVNZ {} {}", r1, r2),
+            Instruction::Load(r) => write!(f, "LOAD {}", r),
+            Instruction::Store(r) => write!(f, "STORE {}", r),
+            Instruction::LoadIndirect(r, base) => write!(f, "LOADR {}, [{}]", r, base),
+            Instruction::StoreIndirect(r, base) => write!(f, "STORER {}, [{}]", r, base),
+            Instruction::LoadOffset(r, base, offset) => {
+                write!(f, "LOADO {}, [{}{:+}]", r, base, *offset as i16)
+            }
+            Instruction::StoreOffset(r, base, offset) => {
+                write!(f, "STOREO {}, [{}{:+}]", r, base, *offset as i16)
+            }
+            Instruction::MemCopy(r1, r2) => write!(f, "MEMCPY {} {}", r1, r2),
+            Instruction::MemSet(r1, r2) => write!(f, "MEMSET {} {}", r1, r2),
+            Instruction::LoadImmediate(r, n) => write!(f, "LDI {} %{}", r, n),
+            Instruction::Signal(n) => write!(f, "SIG ${:02X}", n),
+            Instruction::Syscall(n) => write!(f, "SYSCALL ${:02X}", n),
+            Instruction::Label(name) => write!(f, "{}:", name),
+            Instruction::Equate(name, address) => write!(f, ".equ {}, ${:04X}", name, address),
+            Instruction::Alias(name, reg) => write!(f, ".alias {}, {}", name, reg),
+            Instruction::Jump(label) => write!(f, "JMP {}", label),
+            Instruction::JumpLong(label) => write!(f, "JMPL {}", label),
+            Instruction::JumpRegister(r) => write!(f, "JMPR {}", r),
+            Instruction::JumpIf(condition, label) => {
+                write!(f, "{} {}", condition.mnemonic(), label)
+            }
+            Instruction::Branch(label) => write!(f, "BRA {}", label),
+            Instruction::BranchIf(condition, label) => {
+                write!(f, "{} {}", condition.branch_mnemonic(), label)
+            }
+            Instruction::Entry(label) => write!(f, ".entry {}", label),
+            Instruction::Fill { count, value } => write!(f, ".fill %{}, %{}", count, value),
+            Instruction::Bytes(bytes) => {
+                write!(f, ".db")?;
+                for (idx, byte) in bytes.iter().enumerate() {
+                    let sep = if idx == 0 { " " } else { ", " };
+                    write!(f, "{}${:02X}", sep, byte)?;
+                }
+                Ok(())
+            }
+            Instruction::LoadAddress(reg, label) => write!(f, "MOVI {}, {}", reg, label),
+        }
+    }
+}
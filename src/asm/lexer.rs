@@ -0,0 +1,279 @@
+use std::fmt;
+
+use crate::Register;
+
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Token {
+    /// e.g. PUSH, POP, etc.
+    Keyword(String),
+    /// e.g. A, B, C, M, R0, R1 etc.
+    Register(String),
+    /// e.g. %42, #42 or a plain 42, range checked by the parser
+    Immediate(u32),
+    /// e.g. $2A or 0x2A, range checked by the parser
+    Hex(u32),
+    /// e.g. label: in the form of `label:`
+    LabelDecl(String),
+    /// e.g. `.entry`, stored lowercase without the leading dot
+    Directive(String),
+    /// e.g. a label reference such as `main` or `.loop`, case preserved
+    Identifier(String),
+    /// e.g. "hello\n", with escapes already resolved
+    Str(String),
+    /// e.g. `BASE+4`, evaluated by the parser
+    Expr(String),
+    /// e.g. `[M]` or `[M+4]`: a register, then the offset expression with
+    /// its sign, empty without one
+    Indirect(String, String),
+}
+
+/// A line that could not be split into tokens.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct LexError {
+    /// 1-based source line
+    pub line: usize,
+    /// Source file the line belongs to, when assembling from files
+    pub file: Option<String>,
+    pub message: String,
+}
+
+impl fmt::Display for LexError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.file {
+            Some(file) => write!(
+                f,
+                "Error in {} at line {}: {}",
+                file, self.line, self.message
+            ),
+            None => write!(f, "Error at line {}: {}", self.line, self.message),
+        }
+    }
+}
+
+impl std::error::Error for LexError {}
+
+impl Token {
+    /// Tokenizes a whole source file, skipping blank lines and `;` comments.
+    pub fn tokenize_source(source: &str) -> Result<Vec<Self>, LexError> {
+        Ok(Self::tokenize_source_with_lines(source)?.0)
+    }
+
+    /// Tokenizes a whole source file, also returning the 1-based source line
+    /// of every token.
+    pub fn tokenize_source_with_lines(source: &str) -> Result<(Vec<Self>, Vec<usize>), LexError> {
+        let mut all_tokens: Vec<Token> = Vec::new();
+        let mut lines: Vec<usize> = Vec::new();
+
+        for (line_idx, l) in source.lines().enumerate() {
+            // Cut the line at the first semicolon outside a string to drop comments
+            let code_part = Self::strip_comment(l).trim();
+
+            // If after removing comments the line is empty, skip it
+            if code_part.is_empty() {
+                continue;
+            }
+
+            let tokens = Self::tokenize_line(code_part).map_err(|message| LexError {
+                line: line_idx + 1,
+                file: None,
+                message,
+            })?;
+            lines.extend(std::iter::repeat_n(line_idx + 1, tokens.len()));
+            all_tokens.extend(tokens);
+        }
+
+        Ok((all_tokens, lines))
+    }
+
+    /// Tokenizes one line without its comment.
+    pub fn tokenize_line(line: &str) -> Result<Vec<Self>, String> {
+        let line = line.trim();
+        if line.ends_with(":") {
+            return Ok(vec![Token::LabelDecl(
+                line.trim_end_matches(":").to_string(),
+            )]);
+        }
+
+        let parts = Self::split_parts(line)?;
+        let mut tokens = Vec::new();
+
+        for (idx, part) in parts.into_iter().enumerate() {
+            if part.starts_with('"') {
+                tokens.push(Token::Str(Self::unescape(part)?));
+            } else if idx > 0 && part.starts_with('[') {
+                tokens.push(Self::indirect(part)?);
+            } else if let Some(expr) = Self::expression(idx, part) {
+                tokens.push(Token::Expr(expr.to_string()));
+            } else if idx == 0 && part.len() > 1 && part.starts_with('.') {
+                tokens.push(Token::Directive(part[1..].to_lowercase()));
+            } else if part.starts_with("%") || part.starts_with('#') {
+                tokens.push(Token::Immediate(Self::number(part, &part[1..], 10)?));
+            } else if let Some(digits) = part.strip_prefix("0x").or(part.strip_prefix("0X")) {
+                tokens.push(Token::Hex(Self::number(part, digits, 16)?));
+            } else if let Some(digits) = part.strip_prefix('$') {
+                tokens.push(Token::Hex(Self::number(part, digits, 16)?));
+            } else if idx > 0 && part.chars().all(|c| c.is_ascii_digit()) {
+                tokens.push(Token::Immediate(Self::number(part, part, 10)?));
+            } else if idx > 0 && Register::from_str(part).is_ok() {
+                tokens.push(Token::Register(part.to_uppercase()));
+            } else if idx == 0 && part.chars().all(char::is_alphanumeric) {
+                tokens.push(Token::Keyword(part.to_uppercase()));
+            } else if idx > 0 && (Self::is_identifier(part) || Self::is_numeric_reference(part)) {
+                tokens.push(Token::Identifier(part.to_string()));
+            } else {
+                return Err(format!("unknown token `{}`", part));
+            }
+        }
+        Ok(tokens)
+    }
+
+    /// Parses the digits of a numeric literal, naming the whole literal in
+    /// the error.
+    fn number(part: &str, digits: &str, radix: u32) -> Result<u32, String> {
+        u32::from_str_radix(digits, radix).map_err(|_| format!("invalid number `{}`", part))
+    }
+
+    /// Returns the part of a line before its `;` comment, ignoring `;` inside strings.
+    fn strip_comment(line: &str) -> &str {
+        let mut in_string = false;
+        let mut escaped = false;
+        for (i, c) in line.char_indices() {
+            match c {
+                _ if escaped => escaped = false,
+                '\\' if in_string => escaped = true,
+                '"' => in_string = !in_string,
+                ';' if !in_string => return &line[..i],
+                _ => {}
+            }
+        }
+        line
+    }
+
+    /// Splits a line into parts separated by whitespace, commas, or both
+    /// (`MOV A, B`). A quoted string is kept as one part, quotes included,
+    /// and so is a bracketed address such as `[M + 4]`.
+    fn split_parts(line: &str) -> Result<Vec<&str>, String> {
+        let mut parts = Vec::new();
+        let mut start = None;
+        let mut in_string = false;
+        let mut in_brackets = false;
+        let mut escaped = false;
+
+        for (i, c) in line.char_indices() {
+            if in_string {
+                match c {
+                    _ if escaped => escaped = false,
+                    '\\' => escaped = true,
+                    '"' => {
+                        in_string = false;
+                        parts.push(&line[start.take().unwrap_or(i)..=i]);
+                    }
+                    _ => {}
+                }
+            } else if in_brackets {
+                in_brackets = c != ']';
+            } else if c == '[' && start.is_none() {
+                in_brackets = true;
+                start = Some(i);
+            } else if c.is_whitespace() || c == ',' {
+                if let Some(s) = start.take() {
+                    parts.push(&line[s..i]);
+                }
+            } else if c == '"' && start.is_none() {
+                in_string = true;
+                start = Some(i);
+            } else if start.is_none() {
+                start = Some(i);
+            }
+        }
+
+        if in_string {
+            return Err(format!(
+                "unterminated string `{}`",
+                &line[start.unwrap_or(0)..]
+            ));
+        }
+        if let Some(s) = start {
+            parts.push(&line[s..]);
+        }
+        Ok(parts)
+    }
+
+    /// Splits a bracketed address into its register and offset, e.g.
+    /// `[M + 4]` into `M` and `+4`.
+    fn indirect(part: &str) -> Result<Self, String> {
+        let inner = part
+            .strip_prefix('[')
+            .and_then(|rest| rest.strip_suffix(']'))
+            .ok_or_else(|| format!("unterminated address `{}`", part))?;
+        let inner: String = inner.chars().filter(|c| !c.is_whitespace()).collect();
+        let (register, offset) = inner.split_at(inner.find(['+', '-']).unwrap_or(inner.len()));
+        if Register::from_str(register).is_err() {
+            return Err(format!(
+                "address `{}` must start with a register, e.g. `[M+4]`",
+                part
+            ));
+        }
+        Ok(Token::Indirect(register.to_uppercase(), offset.to_string()))
+    }
+
+    /// Resolves the escapes in a quoted string part (`\n`, `\t`, `\0`, `\"`, `\\`).
+    fn unescape(part: &str) -> Result<String, String> {
+        let inner = &part[1..part.len() - 1];
+        let mut out = String::with_capacity(inner.len());
+        let mut chars = inner.chars();
+        while let Some(c) = chars.next() {
+            if c != '\\' {
+                out.push(c);
+                continue;
+            }
+            match chars.next() {
+                Some('n') => out.push('\n'),
+                Some('t') => out.push('\t'),
+                Some('0') => out.push('\0'),
+                Some(other) => out.push(other),
+                None => return Err(format!("unterminated escape in string `{}`", part)),
+            }
+        }
+        Ok(out)
+    }
+
+    /// Returns the expression text of an operand that is more than a single
+    /// literal, e.g. `BASE+4`, `#SIZE` (a `#` before a name marks a constant),
+    /// or `$` / `.` for the current address.
+    fn expression(idx: usize, part: &str) -> Option<&str> {
+        if idx == 0 {
+            return None;
+        }
+        match part.strip_prefix('#') {
+            _ if part == "$" || part == "." => Some(part),
+            Some(rest) if !rest.starts_with(|c: char| c.is_ascii_digit()) => Some(rest),
+            _ if part.contains(Self::is_operator) => Some(part),
+            _ => None,
+        }
+    }
+
+    /// Characters that make an operand an expression rather than a single value.
+    fn is_operator(c: char) -> bool {
+        matches!(
+            c,
+            '+' | '-' | '*' | '/' | '&' | '|' | '^' | '~' | '<' | '>' | '(' | ')'
+        )
+    }
+
+    /// Checks whether an operand refers to a numeric label (`1b`, `2f`).
+    fn is_numeric_reference(part: &str) -> bool {
+        part.strip_suffix(['b', 'f'])
+            .is_some_and(|n| !n.is_empty() && n.chars().all(|c| c.is_ascii_digit()))
+    }
+
+    /// Checks whether an operand can name a label (`main`, `add_stack`, `.loop`).
+    fn is_identifier(part: &str) -> bool {
+        let name = part.strip_prefix('.').unwrap_or(part);
+        !name.is_empty()
+            && !name.starts_with(|c: char| c.is_ascii_digit())
+            && name.chars().all(|c| c.is_alphanumeric() || c == '_')
+    }
+}
@@ -0,0 +1,66 @@
+//! Peephole optimizer over the assembler IR.
+//!
+//! Rewrites run on adjacent instructions only. A label between two
+//! instructions keeps them apart, since a jump may land in the middle.
+
+use crate::asm::ir::Instruction;
+
+/// Applies every peephole rewrite until none match any more.
+///
+/// - `PUSHR x` / `POP y` becomes `MOV y x`
+/// - `PUSHR x` / `POP x` is removed
+/// - `PUSH 0` / `ADDS` is removed
+/// - `NOP` is removed
+pub fn optimize(instrs: &[Instruction]) -> Vec<Instruction> {
+    optimize_with_lines(instrs, &[]).0
+}
+
+/// Like [`optimize`], keeping `lines`, the source line of each instruction,
+/// in step with the output. A rewritten pair takes the line of its second
+/// instruction.
+pub(crate) fn optimize_with_lines(
+    instrs: &[Instruction],
+    lines: &[Option<usize>],
+) -> (Vec<Instruction>, Vec<Option<usize>>) {
+    let mut out: Vec<Instruction> = Vec::with_capacity(instrs.len());
+    let mut out_lines: Vec<Option<usize>> = Vec::with_capacity(instrs.len());
+
+    for (idx, instr) in instrs.iter().enumerate() {
+        if let Instruction::Nop = instr {
+            continue;
+        }
+        let line = lines.get(idx).copied().flatten();
+        out.push(instr.clone());
+        out_lines.push(line);
+
+        // Rewriting the tail of the output lets one rewrite expose the next,
+        // e.g. `PUSHR A, PUSHR B, POP B, POP C` collapses to `MOV C A`
+        let n = out.len();
+        if n < 2 {
+            continue;
+        }
+        match (&out[n - 2], &out[n - 1]) {
+            (Instruction::PushRegister(src), Instruction::Pop(dst)) => {
+                let mov = (src != dst).then(|| Instruction::Move(dst.clone(), src.clone()));
+                out.truncate(n - 2);
+                out_lines.truncate(n - 2);
+                if let Some(mov) = mov {
+                    out.push(mov);
+                    out_lines.push(line);
+                }
+            }
+            (Instruction::PushImmediate(0) | Instruction::PushHex(0), Instruction::AddStack) => {
+                out.truncate(n - 2);
+                out_lines.truncate(n - 2);
+            }
+            _ => {}
+        }
+    }
+
+    (out, out_lines)
+}
+
+/// Total number of bytes the instructions encode to.
+pub fn code_size(instrs: &[Instruction]) -> usize {
+    instrs.iter().map(|instr| instr.size()).sum()
+}
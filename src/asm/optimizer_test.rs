@@ -0,0 +1,84 @@
+//! Unit tests for the peephole optimizer.
+
+#[cfg(test)]
+mod tests {
+    use crate::asm::ir::Instruction;
+    use crate::asm::optimizer::{code_size, optimize};
+
+    fn reg(name: &str) -> String {
+        name.to_string()
+    }
+
+    #[test]
+    fn test_push_pop_becomes_move() {
+        let program = vec![
+            Instruction::PushRegister(reg("A")),
+            Instruction::Pop(reg("B")),
+        ];
+
+        let optimized = optimize(&program);
+        assert!(matches!(
+            optimized.as_slice(),
+            [Instruction::Move(dst, src)] if dst == "B" && src == "A"
+        ));
+        assert_eq!(code_size(&program) - code_size(&optimized), 2);
+    }
+
+    #[test]
+    fn test_push_pop_same_register_removed() {
+        let program = vec![
+            Instruction::PushRegister(reg("C")),
+            Instruction::Pop(reg("C")),
+        ];
+
+        assert!(optimize(&program).is_empty());
+    }
+
+    #[test]
+    fn test_push_zero_adds_and_nops_removed() {
+        let program = vec![
+            Instruction::Nop,
+            Instruction::PushImmediate(5),
+            Instruction::PushImmediate(0),
+            Instruction::AddStack,
+            Instruction::Nop,
+            Instruction::PushHex(0),
+            Instruction::AddStack,
+        ];
+
+        let optimized = optimize(&program);
+        assert!(matches!(
+            optimized.as_slice(),
+            [Instruction::PushImmediate(5)]
+        ));
+        assert_eq!(code_size(&program) - code_size(&optimized), 12);
+    }
+
+    #[test]
+    fn test_rewrites_cascade() {
+        let program = vec![
+            Instruction::PushRegister(reg("A")),
+            Instruction::PushRegister(reg("B")),
+            Instruction::Nop,
+            Instruction::Pop(reg("B")),
+            Instruction::Pop(reg("C")),
+        ];
+
+        let optimized = optimize(&program);
+        assert!(matches!(
+            optimized.as_slice(),
+            [Instruction::Move(dst, src)] if dst == "C" && src == "A"
+        ));
+    }
+
+    #[test]
+    fn test_labels_block_rewrites() {
+        let program = vec![
+            Instruction::PushRegister(reg("A")),
+            Instruction::Label(reg("target")),
+            Instruction::Pop(reg("B")),
+        ];
+
+        assert_eq!(optimize(&program).len(), 3);
+    }
+}
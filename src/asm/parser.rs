@@ -0,0 +1,1563 @@
+use crate::asm::expr;
+use crate::asm::ir::{Condition, Instruction};
+use crate::asm::lexer::Token;
+use crate::asm::pseudo;
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+
+#[derive(Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub enum ParseErrorKind {
+    UnexpectedToken(Token),
+    MissingOperand(&'static str, &'static str),
+    InvalidOperand(&'static str, Token),
+    InsufficientTokens(usize, usize),
+    JumpToInvalidTarget(Token),
+    ValueOutOfRange(u32, u8),
+    InvalidExpression(String),
+}
+
+#[derive(Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct ParseError {
+    /// Boxed to keep `Result<_, ParseError>` small
+    pub kind: Box<ParseErrorKind>,
+    pub position: usize,
+    /// 1-based source line of the offending token, when known
+    pub line: Option<usize>,
+    /// Source file the line belongs to, when assembling from files
+    pub file: Option<String>,
+    pub tokens_snapshot: Vec<Token>,
+    pub context: String,
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let position_info = match (&self.file, self.line) {
+            (Some(file), Some(line)) => format!(
+                "Error in {} at line {} (token position {})",
+                file, line, self.position
+            ),
+            (None, Some(line)) => {
+                format!("Error at line {} (token position {})", line, self.position)
+            }
+            _ => format!("Error at token position {}", self.position),
+        };
+
+        let error_details = match self.kind.as_ref() {
+            ParseErrorKind::UnexpectedToken(token) => format!("Unexpected token: {:?}", token),
+            ParseErrorKind::MissingOperand(instr, expected) => {
+                format!("Missing operand for {}. Expected {}", instr, expected)
+            }
+            ParseErrorKind::InvalidOperand(instr, token) => {
+                format!("Invalid operand for {}: {:?}", instr, token)
+            }
+            ParseErrorKind::InsufficientTokens(expected, actual) => format!(
+                "Expected at least {} more tokens, but found only {}",
+                expected, actual
+            ),
+            ParseErrorKind::JumpToInvalidTarget(token) => {
+                format!("Invalid jump target: {:?}", token)
+            }
+            ParseErrorKind::ValueOutOfRange(value, bits) => {
+                format!("value {} does not fit in {} bits", value, bits)
+            }
+            ParseErrorKind::InvalidExpression(e) => format!("Invalid expression: {}", e),
+        };
+
+        let context = if !self.context.is_empty() {
+            format!(" - {}", self.context)
+        } else {
+            String::new()
+        };
+
+        let token_context = self.format_token_context();
+
+        write!(
+            f,
+            "{}\n{}{}\n\n{}",
+            position_info, error_details, context, token_context
+        )
+    }
+}
+
+impl std::error::Error for ParseError {}
+
+impl ParseError {
+    fn format_token_context(&self) -> String {
+        // The snapshot starts up to 3 tokens before the error position
+        let snapshot_start = self.position.saturating_sub(3);
+
+        let mut result = String::from("Token context:\n");
+
+        for (idx, token) in self.tokens_snapshot.iter().enumerate() {
+            let pos = snapshot_start + idx;
+            let marker = if pos == self.position { "→ " } else { "  " };
+            result.push_str(&format!("{}{}: {:?}\n", marker, pos, token));
+        }
+
+        result
+    }
+
+    fn new(kind: ParseErrorKind, position: usize, tokens: &[Token]) -> Self {
+        // Create a smaller snapshot of the tokens for context
+        let snapshot_start = position.saturating_sub(3);
+        let snapshot_end = (position + 4).min(tokens.len());
+        let tokens_snapshot = tokens[snapshot_start..snapshot_end].to_vec();
+
+        ParseError {
+            kind: Box::new(kind),
+            position,
+            line: None,
+            file: None,
+            tokens_snapshot,
+            context: String::new(),
+        }
+    }
+
+    /// Checks that a numeric operand fits in `bits` bits.
+    fn check_range(
+        value: u32,
+        bits: u8,
+        position: usize,
+        tokens: &[Token],
+        suggestion: &str,
+    ) -> Result<u16, ParseError> {
+        if value < (1 << bits) {
+            Ok(value as u16)
+        } else {
+            Err(ParseError::new(
+                ParseErrorKind::ValueOutOfRange(value, bits),
+                position,
+                tokens,
+            )
+            .with_context(suggestion.to_string()))
+        }
+    }
+
+    fn with_context(mut self, context: String) -> Self {
+        self.context = context;
+        self
+    }
+}
+
+pub type ParseResult = Result<Vec<Instruction>, ParseError>;
+
+/// Assembly-time constants, e.g. from `--define NAME=VALUE`.
+pub type Constants = BTreeMap<String, u16>;
+
+/// Suggestion shown when a PUSH operand does not fit in the 8-bit argument.
+fn push_suggestion(value: u32) -> String {
+    format!(
+        "PUSH takes 8-bit values (0-255); use `MOV <reg>, #{}` to load it into a register, or push the high and low bytes separately",
+        value
+    )
+}
+
+/// Reads the `N` register operands following the instruction at `tokens[i]`.
+fn register_operands<const N: usize>(
+    tokens: &[Token],
+    i: usize,
+    mnemonic: &'static str,
+) -> Result<[String; N], ParseError> {
+    if i + N >= tokens.len() {
+        return Err(ParseError::new(
+            ParseErrorKind::InsufficientTokens(N, tokens.len() - i - 1),
+            i,
+            tokens,
+        )
+        .with_context(format!(
+            "{} instruction requires {} register operand(s)",
+            mnemonic, N
+        )));
+    }
+    let mut registers: [String; N] = std::array::from_fn(|_| String::new());
+    for (n, register) in registers.iter_mut().enumerate() {
+        match &tokens[i + 1 + n] {
+            Token::Register(r) => *register = r.clone(),
+            invalid => {
+                return Err(ParseError::new(
+                    ParseErrorKind::InvalidOperand(mnemonic, invalid.clone()),
+                    i + 1 + n,
+                    tokens,
+                )
+                .with_context(format!("{} expects {} register name(s)", mnemonic, N)));
+            }
+        }
+    }
+    Ok(registers)
+}
+
+/// Memory instructions. `LOAD` and `STORE` pick their form from the
+/// operand: none for the address in M, `[reg]` for `LOADR`/`STORER` and
+/// `[reg+offset]` for `LOADO`/`STOREO`.
+const MEMORY_MNEMONICS: [&str; 6] = ["LOAD", "STORE", "LOADR", "STORER", "LOADO", "STOREO"];
+
+/// Bitwise instructions: the bare mnemonic, which picks the register form
+/// when a register follows and the stack form otherwise, then the stack
+/// and register mnemonics.
+const BITWISE_MNEMONICS: [(&str, &str, &str); 4] = [
+    ("AND", "ANDS", "ANDR"),
+    ("OR", "ORS", "ORR"),
+    ("XOR", "XORS", "XORR"),
+    ("NOT", "NOTS", "NOTR"),
+];
+
+/// The entry of [`BITWISE_MNEMONICS`] that spells `keyword` in any form.
+fn bitwise_mnemonics(keyword: &str) -> Option<(&'static str, &'static str, &'static str)> {
+    BITWISE_MNEMONICS
+        .into_iter()
+        .find(|(bare, stack, reg)| [*bare, *stack, *reg].contains(&keyword))
+}
+
+/// Prefix marking a label as local to the nearest preceding global label.
+pub const LOCAL_LABEL_PREFIX: char = '.';
+
+/// Rewrites local labels (`.loop`) and jumps to them into names qualified by
+/// their enclosing global label (`main.loop`), so every routine can reuse the
+/// same short local names while codegen only ever sees unique ones.
+///
+/// Local labels that appear before the first global label keep their name as is.
+pub fn qualify_local_labels(instructions: Vec<Instruction>) -> Vec<Instruction> {
+    let mut scope = String::new();
+
+    instructions
+        .into_iter()
+        .map(|instr| match instr {
+            Instruction::Label(name) if name.starts_with(LOCAL_LABEL_PREFIX) => {
+                Instruction::Label(format!("{}{}", scope, name))
+            }
+            Instruction::Label(name) => {
+                if starts_scope(&name) {
+                    scope = name.clone();
+                }
+                Instruction::Label(name)
+            }
+            Instruction::Jump(target) if target.starts_with(LOCAL_LABEL_PREFIX) => {
+                Instruction::Jump(format!("{}{}", scope, target))
+            }
+            Instruction::JumpIf(condition, target) if target.starts_with(LOCAL_LABEL_PREFIX) => {
+                Instruction::JumpIf(condition, format!("{}{}", scope, target))
+            }
+            Instruction::JumpLong(target) if target.starts_with(LOCAL_LABEL_PREFIX) => {
+                Instruction::JumpLong(format!("{}{}", scope, target))
+            }
+            Instruction::Branch(target) if target.starts_with(LOCAL_LABEL_PREFIX) => {
+                Instruction::Branch(format!("{}{}", scope, target))
+            }
+            Instruction::BranchIf(condition, target) if target.starts_with(LOCAL_LABEL_PREFIX) => {
+                Instruction::BranchIf(condition, format!("{}{}", scope, target))
+            }
+            Instruction::Entry(target) if target.starts_with(LOCAL_LABEL_PREFIX) => {
+                Instruction::Entry(format!("{}{}", scope, target))
+            }
+            Instruction::LoadAddress(reg, target) if target.starts_with(LOCAL_LABEL_PREFIX) => {
+                Instruction::LoadAddress(reg, format!("{}{}", scope, target))
+            }
+            other => other,
+        })
+        .collect()
+}
+
+/// Separates the number of a numeric label from the token position that
+/// makes it unique. Source labels can't contain it.
+pub const NUMERIC_LABEL_SEPARATOR: char = '@';
+
+/// Whether a label starts a new scope for local labels. Local and numeric
+/// labels don't.
+fn starts_scope(name: &str) -> bool {
+    !name.starts_with(LOCAL_LABEL_PREFIX) && !name.contains(NUMERIC_LABEL_SEPARATOR)
+}
+
+/// Renames numeric labels (`1:`) to unique names and points every `1b` / `1f`
+/// reference at the nearest such label before / after it. Runs after `.rept`
+/// expansion, so each repetition gets its own labels.
+pub fn resolve_numeric_labels(tokens: &mut [Token]) -> Result<(), ParseError> {
+    let is_numeric = |name: &str| !name.is_empty() && name.chars().all(|c| c.is_ascii_digit());
+
+    // Positions of every numeric label declaration, in order
+    let declarations: Vec<(usize, String)> = tokens
+        .iter()
+        .enumerate()
+        .filter_map(|(i, token)| match token {
+            Token::LabelDecl(name) if is_numeric(name) => Some((i, name.clone())),
+            _ => None,
+        })
+        .collect();
+    let unique = |i: usize, number: &str| format!("{}{}{}", number, NUMERIC_LABEL_SEPARATOR, i);
+
+    for i in 0..tokens.len() {
+        let target = match &tokens[i] {
+            Token::LabelDecl(name) if is_numeric(name) => {
+                tokens[i] = Token::LabelDecl(unique(i, name));
+                continue;
+            }
+            Token::Identifier(name) => {
+                let (number, direction) = match (name.strip_suffix('b'), name.strip_suffix('f')) {
+                    (Some(number), _) if is_numeric(number) => (number, "b"),
+                    (_, Some(number)) if is_numeric(number) => (number, "f"),
+                    _ => continue,
+                };
+                let mut candidates = declarations.iter().filter(|(_, n)| n == number);
+                let found = if direction == "b" {
+                    candidates.rfind(|(pos, _)| *pos < i)
+                } else {
+                    candidates.find(|(pos, _)| *pos > i)
+                };
+                found.map(|(pos, n)| unique(*pos, n)).ok_or_else(|| {
+                    ParseError::new(
+                        ParseErrorKind::InvalidOperand("numeric label", tokens[i].clone()),
+                        i,
+                        tokens,
+                    )
+                    .with_context(format!(
+                        "no label `{}:` {} this reference",
+                        number,
+                        if direction == "b" { "before" } else { "after" }
+                    ))
+                })?
+            }
+            _ => continue,
+        };
+        tokens[i] = Token::Identifier(target);
+    }
+
+    Ok(())
+}
+
+/// Replaces names declared with `.alias name, reg` by the register they stand
+/// for, from the declaration onwards. The `.alias` tokens themselves are kept,
+/// naming a register even when declared from another alias, so the parser
+/// can record them and token positions stay the same.
+pub fn resolve_aliases(tokens: &mut [Token]) -> Result<(), ParseError> {
+    let mut aliases: HashMap<String, String> = HashMap::new();
+    let mut i = 0;
+
+    while i < tokens.len() {
+        match &tokens[i] {
+            Token::Directive(d) if d == "alias" => {
+                match (tokens.get(i + 1), tokens.get(i + 2)) {
+                    (Some(Token::Identifier(name)), Some(Token::Register(reg))) => {
+                        aliases.insert(name.clone(), reg.clone());
+                    }
+                    (Some(Token::Identifier(name)), Some(Token::Identifier(other)))
+                        if aliases.contains_key(other) =>
+                    {
+                        let reg = aliases[other].clone();
+                        aliases.insert(name.clone(), reg.clone());
+                        tokens[i + 2] = Token::Register(reg);
+                    }
+                    (Some(Token::Identifier(_)), Some(invalid)) => {
+                        return Err(ParseError::new(
+                            ParseErrorKind::InvalidOperand(".alias (register)", invalid.clone()),
+                            i + 2,
+                            tokens,
+                        )
+                        .with_context(".alias expects a register to name".into()));
+                    }
+                    (Some(invalid), _) => {
+                        return Err(ParseError::new(
+                            ParseErrorKind::InvalidOperand(".alias (name)", invalid.clone()),
+                            i + 1,
+                            tokens,
+                        )
+                        .with_context(".alias names can't be register names".into()));
+                    }
+                    _ => {
+                        return Err(ParseError::new(
+                            ParseErrorKind::InsufficientTokens(2, tokens.len() - i - 1),
+                            i,
+                            tokens,
+                        )
+                        .with_context(".alias directive requires a name and a register".into()));
+                    }
+                }
+                i += 3;
+            }
+            Token::Identifier(name) => {
+                if let Some(reg) = aliases.get(name) {
+                    tokens[i] = Token::Register(reg.clone());
+                }
+                i += 1;
+            }
+            _ => i += 1,
+        }
+    }
+
+    Ok(())
+}
+
+/// Unrolls `.rept n ... .endr` blocks by repeating their tokens `n` times.
+/// Blocks may be nested.
+pub fn expand_repeats(tokens: &[Token]) -> Result<Vec<Token>, ParseError> {
+    let indices = expand_repeat_indices(tokens, 0, tokens.len())?;
+    Ok(indices.into_iter().map(|idx| tokens[idx].clone()).collect())
+}
+
+/// Unrolls `.rept` blocks within `tokens[start..end]`, returning the indices
+/// of the original tokens in expanded order. Keeping indices lets errors in
+/// the expanded stream be traced back to a source line.
+fn expand_repeat_indices(
+    tokens: &[Token],
+    start: usize,
+    end: usize,
+) -> Result<Vec<usize>, ParseError> {
+    let mut expanded = Vec::with_capacity(end - start);
+    let mut i = start;
+
+    while i < end {
+        match &tokens[i] {
+            Token::Directive(d) if d == "rept" => {
+                let count = match tokens[..end].get(i + 1) {
+                    Some(Token::Immediate(n) | Token::Hex(n)) => ParseError::check_range(
+                        *n,
+                        16,
+                        i + 1,
+                        tokens,
+                        ".rept counts are 16-bit values (0-65535)",
+                    )?,
+                    Some(invalid) => {
+                        return Err(ParseError::new(
+                            ParseErrorKind::InvalidOperand(".rept", invalid.clone()),
+                            i + 1,
+                            tokens,
+                        )
+                        .with_context(".rept expects a repeat count".into()));
+                    }
+                    None => {
+                        return Err(ParseError::new(
+                            ParseErrorKind::InsufficientTokens(1, 0),
+                            i,
+                            tokens,
+                        )
+                        .with_context(".rept directive requires a repeat count".into()));
+                    }
+                };
+
+                // Find the matching `.endr`, skipping over nested blocks
+                let body_start = i + 2;
+                let mut depth = 1;
+                let mut body_end = body_start;
+                while body_end < end {
+                    match &tokens[body_end] {
+                        Token::Directive(d) if d == "rept" => depth += 1,
+                        Token::Directive(d) if d == "endr" => {
+                            depth -= 1;
+                            if depth == 0 {
+                                break;
+                            }
+                        }
+                        _ => {}
+                    }
+                    body_end += 1;
+                }
+                if depth != 0 {
+                    return Err(ParseError::new(
+                        ParseErrorKind::UnexpectedToken(tokens[i].clone()),
+                        i,
+                        tokens,
+                    )
+                    .with_context(".rept block is missing its .endr".into()));
+                }
+
+                let body = expand_repeat_indices(tokens, body_start, body_end)?;
+                for _ in 0..count {
+                    expanded.extend(body.iter().copied());
+                }
+                i = body_end + 1;
+            }
+            Token::Directive(d) if d == "endr" => {
+                return Err(ParseError::new(
+                    ParseErrorKind::UnexpectedToken(tokens[i].clone()),
+                    i,
+                    tokens,
+                )
+                .with_context(".endr without a matching .rept".into()));
+            }
+            _ => {
+                expanded.push(i);
+                i += 1;
+            }
+        }
+    }
+
+    Ok(expanded)
+}
+
+pub fn parse_tokens(tokens: &[Token]) -> ParseResult {
+    parse_tokens_with_lines(tokens, &[], &Constants::new())
+}
+
+/// Parses tokens produced by `Token::tokenize_source_with_lines`, attaching
+/// the source line of the offending token to any error.
+pub fn parse_tokens_with_lines(
+    tokens: &[Token],
+    lines: &[usize],
+    constants: &Constants,
+) -> ParseResult {
+    parse_tokens_located(tokens, lines, constants).map(|(instructions, _)| instructions)
+}
+
+/// Like [`parse_tokens_with_lines`], also returning the source line of each
+/// instruction, for errors found after parsing.
+pub(crate) fn parse_tokens_located(
+    tokens: &[Token],
+    lines: &[usize],
+    constants: &Constants,
+) -> Result<(Vec<Instruction>, Vec<Option<usize>>), ParseError> {
+    let attach_line = |mut e: ParseError, indices: &[usize]| {
+        // Errors past the last token point at the end of the input
+        let original = indices
+            .get(e.position)
+            .or(indices.last())
+            .copied()
+            .unwrap_or(e.position);
+        e.line = lines.get(original).copied();
+        e
+    };
+
+    // `.rept` blocks are unrolled up front so the parser never sees them
+    let indices =
+        expand_repeat_indices(tokens, 0, tokens.len()).map_err(|e| attach_line(e, &[]))?;
+    let expanded: Vec<Token> = indices.iter().map(|&idx| tokens[idx].clone()).collect();
+
+    // Then `.if` blocks whose condition is false are dropped
+    let kept = select_conditionals(&expanded, constants).map_err(|e| attach_line(e, &indices))?;
+    let indices: Vec<usize> = kept.iter().map(|&k| indices[k]).collect();
+    let mut expanded: Vec<Token> = kept.into_iter().map(|k| expanded[k].clone()).collect();
+
+    let (instructions, positions) = resolve_numeric_labels(&mut expanded)
+        .and_then(|_| resolve_aliases(&mut expanded))
+        .and_then(|_| parse_expanded(&mut expanded, constants))
+        .map_err(|e| attach_line(e, &indices))?;
+    let instruction_lines = positions
+        .iter()
+        .map(|position| lines.get(indices[*position]).copied())
+        .collect();
+    Ok((instructions, instruction_lines))
+}
+
+/// Evaluates the condition operand of an `.if` directive.
+fn eval_condition(
+    tokens: &[Token],
+    position: usize,
+    constants: &Constants,
+) -> Result<bool, ParseError> {
+    let lookup = |name: &str| constants.get(name).map(|v| *v as i64);
+    let value = match tokens.get(position) {
+        Some(Token::Immediate(n) | Token::Hex(n)) => Ok(*n as i64),
+        Some(Token::Identifier(text) | Token::Expr(text)) => expr::eval(text, &lookup),
+        Some(invalid) => {
+            return Err(ParseError::new(
+                ParseErrorKind::InvalidOperand(".if", invalid.clone()),
+                position,
+                tokens,
+            )
+            .with_context(".if expects a number, constant or expression".into()));
+        }
+        None => {
+            return Err(ParseError::new(
+                ParseErrorKind::InsufficientTokens(1, 0),
+                position - 1,
+                tokens,
+            )
+            .with_context(".if directive requires a condition".into()));
+        }
+    };
+    value
+        .map(|v| v != 0)
+        .map_err(|e| ParseError::new(ParseErrorKind::InvalidExpression(e), position, tokens))
+}
+
+/// Evaluates `.if cond` / `.ifdef NAME` / `.ifndef NAME` ... `.else` ... `.endif`
+/// blocks, returning the positions of the tokens that stay in the program.
+/// Blocks may be nested.
+fn select_conditionals(tokens: &[Token], constants: &Constants) -> Result<Vec<usize>, ParseError> {
+    // One entry per open block: whether its current branch is taken, and
+    // whether it has seen its `.else` yet
+    let mut blocks: Vec<(bool, bool)> = Vec::new();
+    let mut kept = Vec::with_capacity(tokens.len());
+    let mut i = 0;
+
+    while i < tokens.len() {
+        let active = blocks.iter().all(|(taken, _)| *taken);
+        match &tokens[i] {
+            Token::Directive(d) if d == "if" || d == "ifdef" || d == "ifndef" => {
+                // Conditions inside a skipped block are never evaluated
+                let taken = match d.as_str() {
+                    _ if !active => false,
+                    "if" => eval_condition(tokens, i + 1, constants)?,
+                    _ => match tokens.get(i + 1) {
+                        Some(Token::Identifier(name)) => {
+                            constants.contains_key(name) == (d == "ifdef")
+                        }
+                        other => {
+                            return Err(ParseError::new(
+                                ParseErrorKind::MissingOperand(".ifdef", "a constant name"),
+                                if other.is_some() { i + 1 } else { i },
+                                tokens,
+                            ));
+                        }
+                    },
+                };
+                blocks.push((taken, false));
+                i += 2;
+            }
+            Token::Directive(d) if d == "else" => {
+                let parent_active =
+                    blocks.len() < 2 || blocks[..blocks.len() - 1].iter().all(|b| b.0);
+                match blocks.last_mut() {
+                    Some((taken, seen_else @ false)) => {
+                        *taken = parent_active && !*taken;
+                        *seen_else = true;
+                    }
+                    _ => {
+                        return Err(ParseError::new(
+                            ParseErrorKind::UnexpectedToken(tokens[i].clone()),
+                            i,
+                            tokens,
+                        )
+                        .with_context(".else without a matching .if".into()));
+                    }
+                }
+                i += 1;
+            }
+            Token::Directive(d) if d == "endif" => {
+                if blocks.pop().is_none() {
+                    return Err(ParseError::new(
+                        ParseErrorKind::UnexpectedToken(tokens[i].clone()),
+                        i,
+                        tokens,
+                    )
+                    .with_context(".endif without a matching .if".into()));
+                }
+                i += 1;
+            }
+            _ => {
+                if active {
+                    kept.push(i);
+                }
+                i += 1;
+            }
+        }
+    }
+
+    if !blocks.is_empty() {
+        return Err(ParseError::new(
+            ParseErrorKind::InsufficientTokens(1, 0),
+            tokens.len(),
+            tokens,
+        )
+        .with_context(".if block is missing its .endif".into()));
+    }
+
+    Ok(kept)
+}
+
+/// Name of the current address in expressions (`.` is accepted too).
+pub const CURRENT_ADDRESS: &str = "$";
+
+/// Replaces constant names and expressions in the operands that start at
+/// `start` by their values, stopping at the next instruction or label.
+/// With `signed`, negative values down to -32768 become their 16-bit two's
+/// complement; otherwise, as for counts and addresses, they are an error.
+fn resolve_operands(
+    tokens: &mut [Token],
+    start: usize,
+    constants: &Constants,
+    lookup: &dyn Fn(&str) -> Option<i64>,
+    signed: bool,
+) -> Result<(), ParseError> {
+    let mut i = start;
+
+    while let Some(token) = tokens.get(i) {
+        match token {
+            Token::Keyword(_) | Token::Directive(_) | Token::LabelDecl(_) => break,
+            Token::Identifier(name) => {
+                if let Some(value) = constants.get(name) {
+                    tokens[i] = Token::Immediate(*value as u32);
+                }
+            }
+            Token::Expr(text) => {
+                let value = expr::eval(text, lookup)
+                    .and_then(|v| match v {
+                        -0x8000..0 if signed => Ok(v as u16 as u32),
+                        _ if signed => u32::try_from(v).map_err(|_| {
+                            format!(
+                                "`{}` evaluates to {}, operands can't be below -32768",
+                                text, v
+                            )
+                        }),
+                        _ => u32::try_from(v).map_err(|_| {
+                            format!("`{}` evaluates to {}, which can't be negative", text, v)
+                        }),
+                    })
+                    .map_err(|e| {
+                        ParseError::new(ParseErrorKind::InvalidExpression(e), i, tokens)
+                            .with_context(
+                                "expressions can use constants and labels declared earlier".into(),
+                            )
+                    })?;
+                tokens[i] = Token::Immediate(value);
+            }
+            Token::Indirect(register, offset) if !offset.is_empty() => {
+                // The sign is part of the offset, so it reads as `0+4` or `0-2`
+                let value = expr::eval(&format!("0{}", offset), lookup)
+                    .and_then(|v| {
+                        if (-0x8000..=0xFFFF).contains(&v) {
+                            Ok(v)
+                        } else {
+                            Err(format!(
+                                "offset `{}` evaluates to {}, which does not fit in 16 bits",
+                                offset, v
+                            ))
+                        }
+                    })
+                    .map_err(|e| {
+                        ParseError::new(ParseErrorKind::InvalidExpression(e), i, tokens)
+                            .with_context(
+                                "expressions can use constants and labels declared earlier".into(),
+                            )
+                    })?;
+                tokens[i] = Token::Indirect(register.clone(), format!("{:+}", value));
+            }
+            _ => {}
+        }
+        i += 1;
+    }
+
+    Ok(())
+}
+
+/// Parses the expanded tokens, returning the instructions and the position
+/// of the token each one was parsed from.
+fn parse_expanded(
+    tokens: &mut [Token],
+    constants: &Constants,
+) -> Result<(Vec<Instruction>, Vec<usize>), ParseError> {
+    let mut i = 0;
+    let mut instructions = Vec::new();
+    let mut positions = Vec::new();
+    // Address of the next instruction and of every label so far, so that
+    // expressions can refer to them
+    let mut address: u16 = 0;
+    let mut labels: HashMap<String, u16> = HashMap::new();
+    let mut scope = String::new();
+
+    while i < tokens.len() {
+        let is_alias = matches!(&tokens[i], Token::Directive(d) if d == "alias");
+        if matches!(tokens[i], Token::Keyword(_) | Token::Directive(_)) && !is_alias {
+            let lookup = |name: &str| {
+                let value = match name {
+                    CURRENT_ADDRESS | "." => Some(address),
+                    local if local.starts_with(LOCAL_LABEL_PREFIX) => {
+                        labels.get(&format!("{}{}", scope, local)).copied()
+                    }
+                    name => constants.get(name).or(labels.get(name)).copied(),
+                };
+                value.map(i64::from)
+            };
+            // Instructions take signed values, directives counts and addresses
+            let signed = matches!(&tokens[i], Token::Keyword(k) if k != "INCSP");
+            resolve_operands(tokens, i + 1, constants, &lookup, signed)?;
+        }
+        let parsed = instructions.len();
+        let position = i;
+        let tokens: &[Token] = tokens;
+
+        match &tokens[i] {
+            Token::LabelDecl(name) => {
+                instructions.push(Instruction::Label(name.clone()));
+                i += 1;
+            }
+            Token::Keyword(k) if k == "NOP" => {
+                instructions.push(Instruction::Nop);
+                i += 1;
+            }
+            Token::Keyword(k) if k == "PUSH" => {
+                // Check if we have enough tokens
+                if i + 1 >= tokens.len() {
+                    return Err(ParseError::new(
+                        ParseErrorKind::InsufficientTokens(1, 0),
+                        i,
+                        tokens,
+                    )
+                    .with_context("PUSH instruction requires an operand".into()));
+                }
+
+                match &tokens[i + 1] {
+                    Token::Immediate(n) => {
+                        let n =
+                            ParseError::check_range(*n, 8, i + 1, tokens, &push_suggestion(*n))?;
+                        instructions.push(Instruction::PushImmediate(n));
+                    }
+                    Token::Hex(n) => {
+                        let n =
+                            ParseError::check_range(*n, 8, i + 1, tokens, &push_suggestion(*n))?;
+                        instructions.push(Instruction::PushHex(n));
+                    }
+                    Token::Register(r) => {
+                        instructions.push(Instruction::PushRegister(r.clone()));
+                    }
+                    invalid => {
+                        return Err(ParseError::new(
+                            ParseErrorKind::InvalidOperand("PUSH", invalid.clone()),
+                            i + 1,
+                            tokens,
+                        )
+                        .with_context(
+                            "PUSH expects an immediate value, hex value, or register".into(),
+                        ));
+                    }
+                }
+                i += 2;
+            }
+            Token::Keyword(k) if k == "PUSHR" => {
+                // Check if we have enough tokens
+                if i + 1 >= tokens.len() {
+                    return Err(ParseError::new(
+                        ParseErrorKind::InsufficientTokens(1, 0),
+                        i,
+                        tokens,
+                    )
+                    .with_context("PUSHR instruction requires a register operand".into()));
+                }
+
+                match &tokens[i + 1] {
+                    Token::Register(r) => {
+                        instructions.push(Instruction::PushRegister(r.clone()));
+                        i += 2;
+                    }
+                    invalid => {
+                        return Err(ParseError::new(
+                            ParseErrorKind::InvalidOperand("PUSHR", invalid.clone()),
+                            i + 1,
+                            tokens,
+                        )
+                        .with_context("PUSHR expects a register name".into()));
+                    }
+                }
+            }
+            Token::Keyword(k) if k == "POP" => {
+                // Check if we have enough tokens
+                if i + 1 >= tokens.len() {
+                    return Err(ParseError::new(
+                        ParseErrorKind::InsufficientTokens(1, 0),
+                        i,
+                        tokens,
+                    )
+                    .with_context("POP instruction requires a register operand".into()));
+                }
+
+                match &tokens[i + 1] {
+                    Token::Register(r) => {
+                        instructions.push(Instruction::Pop(r.clone()));
+                        i += 2;
+                    }
+                    invalid => {
+                        return Err(ParseError::new(
+                            ParseErrorKind::InvalidOperand("POP", invalid.clone()),
+                            i + 1,
+                            tokens,
+                        )
+                        .with_context("POP expects a register name".into()));
+                    }
+                }
+            }
+            Token::Keyword(k) if k == "JMPR" => {
+                // Check if we have enough tokens
+                if i + 1 >= tokens.len() {
+                    return Err(ParseError::new(
+                        ParseErrorKind::InsufficientTokens(1, 0),
+                        i,
+                        tokens,
+                    )
+                    .with_context("JMPR instruction requires a register operand".into()));
+                }
+
+                match &tokens[i + 1] {
+                    Token::Register(r) => {
+                        instructions.push(Instruction::JumpRegister(r.clone()));
+                        i += 2;
+                    }
+                    invalid => {
+                        return Err(ParseError::new(
+                            ParseErrorKind::InvalidOperand("JMPR", invalid.clone()),
+                            i + 1,
+                            tokens,
+                        )
+                        .with_context("JMPR expects a register holding the address".into()));
+                    }
+                }
+            }
+            Token::Keyword(k) if MEMORY_MNEMONICS.contains(&k.as_str()) => {
+                let mnemonic =
+                    MEMORY_MNEMONICS[MEMORY_MNEMONICS.iter().position(|m| m == k).unwrap()];
+                let [r] = register_operands(tokens, i, mnemonic)?;
+                let load = mnemonic.starts_with("LOAD");
+                match tokens.get(i + 2) {
+                    Some(Token::Indirect(base, offset)) => {
+                        // Offsets were resolved to signed numbers with the operands
+                        let offset = match offset.as_str() {
+                            "" => None,
+                            n => Some(n.parse::<i64>().map_err(|_| {
+                                ParseError::new(
+                                    ParseErrorKind::InvalidOperand(mnemonic, tokens[i + 2].clone()),
+                                    i + 2,
+                                    tokens,
+                                )
+                            })? as u16),
+                        };
+                        let base = base.clone();
+                        instructions.push(match (mnemonic, offset) {
+                            ("LOADR" | "STORER", Some(_)) => {
+                                return Err(ParseError::new(
+                                    ParseErrorKind::InvalidOperand(mnemonic, tokens[i + 2].clone()),
+                                    i + 2,
+                                    tokens,
+                                )
+                                .with_context(format!(
+                                    "{} takes no offset, use LOADO or STOREO",
+                                    mnemonic
+                                )));
+                            }
+                            ("LOADO" | "STOREO", offset) | (_, offset @ Some(_)) => {
+                                let offset = offset.unwrap_or(0);
+                                if load {
+                                    Instruction::LoadOffset(r, base, offset)
+                                } else {
+                                    Instruction::StoreOffset(r, base, offset)
+                                }
+                            }
+                            _ if load => Instruction::LoadIndirect(r, base),
+                            _ => Instruction::StoreIndirect(r, base),
+                        });
+                        i += 3;
+                    }
+                    _ if mnemonic == "LOAD" || mnemonic == "STORE" => {
+                        instructions.push(if load {
+                            Instruction::Load(r)
+                        } else {
+                            Instruction::Store(r)
+                        });
+                        i += 2;
+                    }
+                    _ => {
+                        return Err(ParseError::new(
+                            ParseErrorKind::MissingOperand(mnemonic, "an address like [M+4]"),
+                            i,
+                            tokens,
+                        ));
+                    }
+                }
+            }
+            Token::Keyword(k) if k == "ADDS" => {
+                instructions.push(Instruction::AddStack);
+                i += 1;
+            }
+            Token::Keyword(k) if k == "SUBS" => {
+                instructions.push(Instruction::SubStack);
+                i += 1;
+            }
+            Token::Keyword(k)
+                if matches!(
+                    k.as_str(),
+                    "DUP" | "SWAP" | "DROP" | "OVER" | "PUSHF" | "POPF"
+                ) =>
+            {
+                instructions.push(match k.as_str() {
+                    "DUP" => Instruction::Dup,
+                    "SWAP" => Instruction::Swap,
+                    "DROP" => Instruction::Drop,
+                    "OVER" => Instruction::Over,
+                    "PUSHF" => Instruction::PushFlags,
+                    _ => Instruction::PopFlags,
+                });
+                i += 1;
+            }
+            Token::Keyword(k)
+                if matches!(
+                    k.as_str(),
+                    "ADDR"
+                        | "SUBR"
+                        | "CMP"
+                        | "TEST"
+                        | "MULW"
+                        | "MEMCPY"
+                        | "MEMSET"
+                        | "CMOVZ"
+                        | "CMOVNZ"
+                ) =>
+            {
+                let [r1, r2] = match k.as_str() {
+                    "ADDR" => register_operands(tokens, i, "ADDR")?,
+                    "SUBR" => register_operands(tokens, i, "SUBR")?,
+                    "CMP" => register_operands(tokens, i, "CMP")?,
+                    "TEST" => register_operands(tokens, i, "TEST")?,
+                    "MULW" => register_operands(tokens, i, "MULW")?,
+                    "MEMCPY" => register_operands(tokens, i, "MEMCPY")?,
+                    "CMOVZ" => register_operands(tokens, i, "CMOVZ")?,
+                    "CMOVNZ" => register_operands(tokens, i, "CMOVNZ")?,
+                    _ => register_operands(tokens, i, "MEMSET")?,
+                };
+                instructions.push(match k.as_str() {
+                    "ADDR" => Instruction::AddRegister(r1, r2),
+                    "SUBR" => Instruction::SubRegister(r1, r2),
+                    "CMP" => Instruction::Compare(r1, r2),
+                    "TEST" => Instruction::Test(r1, r2),
+                    "MULW" => Instruction::MulWide(r1, r2),
+                    "MEMCPY" => Instruction::MemCopy(r1, r2),
+                    "CMOVZ" => Instruction::MoveIfZero(r1, r2),
+                    "CMOVNZ" => Instruction::MoveIfNotZero(r1, r2),
+                    _ => Instruction::MemSet(r1, r2),
+                });
+                i += 3;
+            }
+            Token::Keyword(k) if bitwise_mnemonics(k).is_some() => {
+                let (bare, stack, reg) = bitwise_mnemonics(k).unwrap();
+                let register_form = k == reg
+                    || (k == bare && matches!(tokens.get(i + 1), Some(Token::Register(_))));
+                if !register_form {
+                    instructions.push(match stack {
+                        "ANDS" => Instruction::AndStack,
+                        "ORS" => Instruction::OrStack,
+                        "XORS" => Instruction::XorStack,
+                        _ => Instruction::NotStack,
+                    });
+                    i += 1;
+                } else if reg == "NOTR" {
+                    let [r] = register_operands(tokens, i, reg)?;
+                    instructions.push(Instruction::NotRegister(r));
+                    i += 2;
+                } else {
+                    let [r1, r2] = register_operands(tokens, i, reg)?;
+                    instructions.push(match reg {
+                        "ANDR" => Instruction::AndRegister(r1, r2),
+                        "ORR" => Instruction::OrRegister(r1, r2),
+                        _ => Instruction::XorRegister(r1, r2),
+                    });
+                    i += 3;
+                }
+            }
+            Token::Keyword(k) if k == "MOV" || k == "MOVI" => {
+                // Check if we have enough tokens
+                if i + 2 >= tokens.len() {
+                    return Err(ParseError::new(
+                        ParseErrorKind::InsufficientTokens(2, tokens.len() - i - 1),
+                        i,
+                        tokens,
+                    )
+                    .with_context("MOV instruction requires two operands".into()));
+                }
+
+                match (&tokens[i + 1], &tokens[i + 2]) {
+                    (Token::Register(r1), Token::Register(r2)) => {
+                        instructions.push(Instruction::Move(r1.clone(), r2.clone()));
+                        i += 3;
+                    }
+                    // Pseudo-instruction: load an immediate into a register
+                    (Token::Register(r), Token::Immediate(n) | Token::Hex(n)) => {
+                        let n = ParseError::check_range(
+                            *n,
+                            16,
+                            i + 2,
+                            tokens,
+                            "registers hold 16-bit values (0-65535)",
+                        )?;
+                        instructions.extend(pseudo::load_immediate(r, n));
+                        i += 3;
+                    }
+                    // Pseudo-instruction: load the address of a label into a register
+                    (Token::Register(r), Token::Identifier(label)) => {
+                        instructions.push(Instruction::LoadAddress(r.clone(), label.clone()));
+                        i += 3;
+                    }
+                    (Token::Register(_), invalid) => {
+                        return Err(ParseError::new(
+                            ParseErrorKind::InvalidOperand("MOV (second operand)", invalid.clone()),
+                            i + 2,
+                            tokens,
+                        )
+                        .with_context(
+                            "MOV expects a register, an immediate value or a label".into(),
+                        ));
+                    }
+                    (invalid, _) => {
+                        return Err(ParseError::new(
+                            ParseErrorKind::InvalidOperand("MOV (first operand)", invalid.clone()),
+                            i + 1,
+                            tokens,
+                        )
+                        .with_context("MOV expects two register names".into()));
+                    }
+                }
+            }
+            Token::Keyword(k) if k == "LDI" => {
+                // Check if we have enough tokens
+                if i + 2 >= tokens.len() {
+                    return Err(ParseError::new(
+                        ParseErrorKind::InsufficientTokens(2, tokens.len() - i - 1),
+                        i,
+                        tokens,
+                    )
+                    .with_context("LDI instruction requires a register and a value".into()));
+                }
+
+                match (&tokens[i + 1], &tokens[i + 2]) {
+                    (Token::Register(r), Token::Immediate(n) | Token::Hex(n)) => {
+                        let n = ParseError::check_range(
+                            *n,
+                            16,
+                            i + 2,
+                            tokens,
+                            "registers hold 16-bit values (0-65535)",
+                        )?;
+                        instructions.push(Instruction::LoadImmediate(r.clone(), n));
+                        i += 3;
+                    }
+                    (Token::Register(_), invalid) => {
+                        return Err(ParseError::new(
+                            ParseErrorKind::InvalidOperand("LDI (second operand)", invalid.clone()),
+                            i + 2,
+                            tokens,
+                        )
+                        .with_context("LDI expects an immediate value".into()));
+                    }
+                    (invalid, _) => {
+                        return Err(ParseError::new(
+                            ParseErrorKind::InvalidOperand("LDI (first operand)", invalid.clone()),
+                            i + 1,
+                            tokens,
+                        )
+                        .with_context("LDI expects a register name first".into()));
+                    }
+                }
+            }
+            Token::Keyword(k) if k == "PUSH16" => {
+                // Check if we have enough tokens
+                if i + 1 >= tokens.len() {
+                    return Err(ParseError::new(
+                        ParseErrorKind::InsufficientTokens(1, 0),
+                        i,
+                        tokens,
+                    )
+                    .with_context("PUSH16 instruction requires an operand".into()));
+                }
+
+                match &tokens[i + 1] {
+                    Token::Immediate(n) | Token::Hex(n) => {
+                        let n = ParseError::check_range(
+                            *n,
+                            16,
+                            i + 1,
+                            tokens,
+                            "PUSH16 takes 16-bit values (0-65535)",
+                        )?;
+                        instructions.extend(pseudo::push_wide(n));
+                    }
+                    Token::Identifier(label) => {
+                        instructions.extend(pseudo::push_address(label));
+                    }
+                    invalid => {
+                        return Err(ParseError::new(
+                            ParseErrorKind::InvalidOperand("PUSH16", invalid.clone()),
+                            i + 1,
+                            tokens,
+                        )
+                        .with_context("PUSH16 expects an immediate value or a label".into()));
+                    }
+                }
+                i += 2;
+            }
+            Token::Keyword(k) if k == "CLR" => {
+                // Check if we have enough tokens
+                if i + 1 >= tokens.len() {
+                    return Err(ParseError::new(
+                        ParseErrorKind::InsufficientTokens(1, 0),
+                        i,
+                        tokens,
+                    )
+                    .with_context("CLR instruction requires a register operand".into()));
+                }
+
+                match &tokens[i + 1] {
+                    Token::Register(r) => {
+                        instructions.extend(pseudo::clear(r));
+                        i += 2;
+                    }
+                    invalid => {
+                        return Err(ParseError::new(
+                            ParseErrorKind::InvalidOperand("CLR", invalid.clone()),
+                            i + 1,
+                            tokens,
+                        )
+                        .with_context("CLR expects a register name".into()));
+                    }
+                }
+            }
+            Token::Keyword(k) if k == "INCSP" => {
+                // Check if we have enough tokens
+                if i + 1 >= tokens.len() {
+                    return Err(ParseError::new(
+                        ParseErrorKind::InsufficientTokens(1, 0),
+                        i,
+                        tokens,
+                    )
+                    .with_context("INCSP instruction requires a byte count".into()));
+                }
+
+                match &tokens[i + 1] {
+                    Token::Immediate(n) | Token::Hex(n) => {
+                        let n = ParseError::check_range(
+                            *n,
+                            8,
+                            i + 1,
+                            tokens,
+                            "INCSP moves SP by at most 255 bytes; use several INCSP for more",
+                        )?;
+                        instructions.extend(pseudo::increment_sp(n));
+                        i += 2;
+                    }
+                    invalid => {
+                        return Err(ParseError::new(
+                            ParseErrorKind::InvalidOperand("INCSP", invalid.clone()),
+                            i + 1,
+                            tokens,
+                        )
+                        .with_context("INCSP expects a byte count".into()));
+                    }
+                }
+            }
+            Token::Keyword(k) if k == "HALT" => {
+                instructions.extend(pseudo::halt());
+                i += 1;
+            }
+            Token::Keyword(k) if k == "SIG" => {
+                // Check if we have enough tokens
+                if i + 1 >= tokens.len() {
+                    return Err(ParseError::new(
+                        ParseErrorKind::InsufficientTokens(1, 0),
+                        i,
+                        tokens,
+                    )
+                    .with_context("SIG instruction requires a hex value operand".into()));
+                }
+
+                match &tokens[i + 1] {
+                    Token::Hex(n) => {
+                        let n = ParseError::check_range(
+                            *n,
+                            8,
+                            i + 1,
+                            tokens,
+                            "signal codes are 8-bit values ($00-$FF)",
+                        )?;
+                        instructions.push(Instruction::Signal(n));
+                        i += 2;
+                    }
+                    invalid => {
+                        return Err(ParseError::new(
+                            ParseErrorKind::InvalidOperand("SIG", invalid.clone()),
+                            i + 1,
+                            tokens,
+                        )
+                        .with_context("SIG expects a hex value".into()));
+                    }
+                }
+            }
+            Token::Keyword(k) if k == "SYSCALL" => match tokens.get(i + 1) {
+                Some(Token::Immediate(n) | Token::Hex(n)) => {
+                    let n = ParseError::check_range(
+                        *n,
+                        8,
+                        i + 1,
+                        tokens,
+                        "syscall numbers are 8-bit values (0-255)",
+                    )?;
+                    instructions.push(Instruction::Syscall(n));
+                    i += 2;
+                }
+                Some(invalid) => {
+                    return Err(ParseError::new(
+                        ParseErrorKind::InvalidOperand("SYSCALL", invalid.clone()),
+                        i + 1,
+                        tokens,
+                    )
+                    .with_context("SYSCALL expects a syscall number".into()));
+                }
+                None => {
+                    return Err(ParseError::new(
+                        ParseErrorKind::InsufficientTokens(1, 0),
+                        i,
+                        tokens,
+                    )
+                    .with_context("SYSCALL instruction requires a syscall number".into()));
+                }
+            },
+            Token::Directive(d) if d == "fill" => {
+                // Check if we have enough tokens
+                if i + 2 >= tokens.len() {
+                    return Err(ParseError::new(
+                        ParseErrorKind::InsufficientTokens(2, tokens.len() - i - 1),
+                        i,
+                        tokens,
+                    )
+                    .with_context(".fill directive requires a count and a value".into()));
+                }
+
+                match (&tokens[i + 1], &tokens[i + 2]) {
+                    (
+                        Token::Immediate(count) | Token::Hex(count),
+                        Token::Immediate(value) | Token::Hex(value),
+                    ) => {
+                        let count = ParseError::check_range(
+                            *count,
+                            16,
+                            i + 1,
+                            tokens,
+                            ".fill counts are 16-bit values (0-65535)",
+                        )?;
+                        // Values up to 16 bits are truncated with a warning
+                        let value = ParseError::check_range(
+                            *value,
+                            16,
+                            i + 2,
+                            tokens,
+                            ".fill emits single bytes (0-255)",
+                        )?;
+                        instructions.push(Instruction::Fill { count, value });
+                        i += 3;
+                    }
+                    (Token::Immediate(_) | Token::Hex(_), invalid) => {
+                        return Err(ParseError::new(
+                            ParseErrorKind::InvalidOperand(".fill (value)", invalid.clone()),
+                            i + 2,
+                            tokens,
+                        )
+                        .with_context(".fill expects a numeric byte value".into()));
+                    }
+                    (invalid, _) => {
+                        return Err(ParseError::new(
+                            ParseErrorKind::InvalidOperand(".fill (count)", invalid.clone()),
+                            i + 1,
+                            tokens,
+                        )
+                        .with_context(".fill expects a numeric count".into()));
+                    }
+                }
+            }
+            Token::Directive(d) if d == "db" || d == "byte" => {
+                let directive = if d == "db" { ".db" } else { ".byte" };
+                // Every value up to the next instruction belongs to the directive
+                let mut bytes = Vec::new();
+                let mut j = i + 1;
+                while let Some(token) = tokens.get(j) {
+                    match token {
+                        Token::Immediate(n) | Token::Hex(n) => {
+                            let n = ParseError::check_range(
+                                *n,
+                                8,
+                                j,
+                                tokens,
+                                "data directives emit single bytes (0-255)",
+                            )?;
+                            bytes.push(n as u8);
+                        }
+                        Token::Str(text) => bytes.extend(text.bytes()),
+                        _ => break,
+                    }
+                    j += 1;
+                }
+                if bytes.is_empty() {
+                    return Err(ParseError::new(
+                        ParseErrorKind::MissingOperand(directive, "byte values or a string"),
+                        i,
+                        tokens,
+                    ));
+                }
+                instructions.push(Instruction::Bytes(bytes));
+                i = j;
+            }
+            Token::Directive(d) if d == "ascii" => match tokens.get(i + 1) {
+                Some(Token::Str(text)) => {
+                    instructions.push(Instruction::Bytes(text.as_bytes().to_vec()));
+                    i += 2;
+                }
+                Some(invalid) => {
+                    return Err(ParseError::new(
+                        ParseErrorKind::InvalidOperand(".ascii", invalid.clone()),
+                        i + 1,
+                        tokens,
+                    )
+                    .with_context(".ascii expects a quoted string".into()));
+                }
+                None => {
+                    return Err(ParseError::new(
+                        ParseErrorKind::InsufficientTokens(1, 0),
+                        i,
+                        tokens,
+                    )
+                    .with_context(".ascii directive requires a string operand".into()));
+                }
+            },
+            // Already applied by `resolve_aliases`, kept for listings
+            Token::Directive(d) if d == "alias" => {
+                if let (Some(Token::Identifier(name)), Some(Token::Register(reg))) =
+                    (tokens.get(i + 1), tokens.get(i + 2))
+                {
+                    instructions.push(Instruction::Alias(name.clone(), reg.clone()));
+                }
+                i += 3;
+            }
+            Token::Directive(d) if d == "equ" => match (tokens.get(i + 1), tokens.get(i + 2)) {
+                (Some(Token::Identifier(name)), Some(Token::Immediate(n) | Token::Hex(n))) => {
+                    let address = ParseError::check_range(
+                        *n,
+                        16,
+                        i + 2,
+                        tokens,
+                        ".equ addresses are 16-bit values (0-65535)",
+                    )?;
+                    instructions.push(Instruction::Equate(name.clone(), address));
+                    i += 3;
+                }
+                (Some(Token::Identifier(_)), Some(invalid)) => {
+                    return Err(ParseError::new(
+                        ParseErrorKind::InvalidOperand(".equ (address)", invalid.clone()),
+                        i + 2,
+                        tokens,
+                    )
+                    .with_context(".equ expects a numeric address".into()));
+                }
+                (Some(Token::Identifier(_)), None) | (None, _) => {
+                    return Err(ParseError::new(
+                        ParseErrorKind::InsufficientTokens(2, tokens.len() - i - 1),
+                        i,
+                        tokens,
+                    )
+                    .with_context(".equ directive requires a name and an address".into()));
+                }
+                (Some(invalid), _) => {
+                    return Err(ParseError::new(
+                        ParseErrorKind::InvalidOperand(".equ (name)", invalid.clone()),
+                        i + 1,
+                        tokens,
+                    )
+                    .with_context(".equ expects a label identifier".into()));
+                }
+            },
+            Token::Directive(d) if d == "entry" => {
+                // Check if we have enough tokens
+                if i + 1 >= tokens.len() {
+                    return Err(ParseError::new(
+                        ParseErrorKind::InsufficientTokens(1, 0),
+                        i,
+                        tokens,
+                    )
+                    .with_context(".entry directive requires a label operand".into()));
+                }
+
+                match &tokens[i + 1] {
+                    Token::Identifier(label) => {
+                        instructions.push(Instruction::Entry(label.clone()));
+                        i += 2;
+                    }
+                    invalid => {
+                        return Err(ParseError::new(
+                            ParseErrorKind::InvalidOperand(".entry", invalid.clone()),
+                            i + 1,
+                            tokens,
+                        )
+                        .with_context(".entry expects a label identifier".into()));
+                    }
+                }
+            }
+            Token::Keyword(k)
+                if k == "JMP"
+                    || k == "JUMP"
+                    || k == "JMPL"
+                    || k == "BRA"
+                    || Condition::from_mnemonic(k).is_some()
+                    || Condition::from_branch_mnemonic(k).is_some() =>
+            {
+                let branch = k == "BRA" || Condition::from_branch_mnemonic(k).is_some();
+                let (condition, mnemonic) = if branch {
+                    let condition = Condition::from_branch_mnemonic(k);
+                    (
+                        condition,
+                        condition.map_or("BRA", Condition::branch_mnemonic),
+                    )
+                } else {
+                    let condition = Condition::from_mnemonic(k);
+                    let jump = if k == "JMPL" { "JMPL" } else { "JMP" };
+                    (condition, condition.map_or(jump, Condition::mnemonic))
+                };
+
+                // Check if we have enough tokens
+                if i + 1 >= tokens.len() {
+                    return Err(ParseError::new(
+                        ParseErrorKind::InsufficientTokens(1, 0),
+                        i,
+                        tokens,
+                    )
+                    .with_context(format!("{} instruction requires a label operand", mnemonic)));
+                }
+
+                match &tokens[i + 1] {
+                    Token::Identifier(label) => {
+                        instructions.push(match (branch, condition) {
+                            (false, Some(condition)) => {
+                                Instruction::JumpIf(condition, label.clone())
+                            }
+                            (false, None) if k == "JMPL" => Instruction::JumpLong(label.clone()),
+                            (false, None) => Instruction::Jump(label.clone()),
+                            (true, Some(condition)) => {
+                                Instruction::BranchIf(condition, label.clone())
+                            }
+                            (true, None) => Instruction::Branch(label.clone()),
+                        });
+                        i += 2;
+                    }
+                    invalid => {
+                        return Err(ParseError::new(
+                            ParseErrorKind::JumpToInvalidTarget(invalid.clone()),
+                            i + 1,
+                            tokens,
+                        )
+                        .with_context(format!("{} expects a label identifier", mnemonic)));
+                    }
+                }
+            }
+            unexpected => {
+                return Err(ParseError::new(
+                    ParseErrorKind::UnexpectedToken(unexpected.clone()),
+                    i,
+                    tokens,
+                )
+                .with_context(format!(
+                    "Unrecognized token in instruction position: {:?}",
+                    unexpected
+                )));
+            }
+        }
+
+        for instr in &instructions[parsed..] {
+            if let Instruction::Label(name) = instr {
+                let name = if name.starts_with(LOCAL_LABEL_PREFIX) {
+                    format!("{}{}", scope, name)
+                } else {
+                    if starts_scope(name) {
+                        scope = name.clone();
+                    }
+                    name.clone()
+                };
+                labels.insert(name, address);
+            }
+            // Code generation reports programs too large to address
+            address = address.wrapping_add(instr.size() as u16);
+        }
+        positions.resize(instructions.len(), position);
+    }
+
+    Ok((qualify_local_labels(instructions), positions))
+}
@@ -0,0 +1,298 @@
+//! Unit tests for the assembler parser.
+
+#[cfg(test)]
+mod tests {
+    use crate::asm::ir::Instruction;
+    use crate::asm::lexer::Token;
+    use crate::asm::parser::{
+        Constants, expand_repeats, parse_tokens, parse_tokens_with_lines, qualify_local_labels,
+        resolve_numeric_labels,
+    };
+
+    fn label(name: &str) -> Instruction {
+        Instruction::Label(name.to_string())
+    }
+
+    fn jump(target: &str) -> Instruction {
+        Instruction::Jump(target.to_string())
+    }
+
+    #[test]
+    fn test_parse_local_label_declaration() {
+        let tokens: Vec<Token> = ["main:", ".loop:", "nop"]
+            .iter()
+            .flat_map(|line| Token::tokenize_line(line).expect("Failed to tokenize"))
+            .collect();
+
+        let ir = parse_tokens(&tokens).expect("Failed to parse tokens");
+        assert!(matches!(&ir[1], Instruction::Label(name) if name == "main.loop"));
+    }
+
+    #[test]
+    fn test_local_labels_are_scoped_to_global_label() {
+        let program = vec![
+            label("first"),
+            label(".loop"),
+            jump(".loop"),
+            label("second"),
+            label(".loop"),
+            jump(".loop"),
+            jump("first"),
+        ];
+
+        let qualified = qualify_local_labels(program);
+        let names: Vec<String> = qualified
+            .iter()
+            .map(|instr| match instr {
+                Instruction::Label(name) | Instruction::Jump(name) => name.clone(),
+                other => panic!("Unexpected instruction: {:?}", other),
+            })
+            .collect();
+
+        assert_eq!(
+            names,
+            [
+                "first",
+                "first.loop",
+                "first.loop",
+                "second",
+                "second.loop",
+                "second.loop",
+                "first"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_local_label_before_any_global_label() {
+        let qualified = qualify_local_labels(vec![label(".top"), jump(".top")]);
+        assert!(matches!(&qualified[0], Instruction::Label(name) if name == ".top"));
+        assert!(matches!(&qualified[1], Instruction::Jump(name) if name == ".top"));
+    }
+
+    #[test]
+    fn test_parse_entry_directive() {
+        let tokens: Vec<Token> = ["main:", ".entry .start", ".start:", "nop"]
+            .iter()
+            .flat_map(|line| Token::tokenize_line(line).expect("Failed to tokenize"))
+            .collect();
+
+        assert_eq!(tokens[1], Token::Directive("entry".to_string()));
+        assert_eq!(tokens[2], Token::Identifier(".start".to_string()));
+
+        let ir = parse_tokens(&tokens).expect("Failed to parse tokens");
+        assert!(matches!(&ir[1], Instruction::Entry(name) if name == "main.start"));
+    }
+
+    fn tokenize(lines: &[&str]) -> Vec<Token> {
+        lines
+            .iter()
+            .flat_map(|line| Token::tokenize_line(line).expect("Failed to tokenize"))
+            .collect()
+    }
+
+    #[test]
+    fn test_expand_nested_repeats() {
+        let tokens = tokenize(&[".rept %2", "nop", ".rept %3", "adds", ".endr", ".endr"]);
+
+        let expanded = expand_repeats(&tokens).expect("Failed to expand");
+        let adds = Token::Keyword("ADDS".to_string());
+        let nop = Token::Keyword("NOP".to_string());
+        assert_eq!(
+            expanded,
+            vec![
+                nop.clone(),
+                adds.clone(),
+                adds.clone(),
+                adds.clone(),
+                nop,
+                adds.clone(),
+                adds.clone(),
+                adds
+            ]
+        );
+    }
+
+    #[test]
+    fn test_unbalanced_repeats() {
+        assert!(expand_repeats(&tokenize(&[".rept %2", "nop"])).is_err());
+        assert!(expand_repeats(&tokenize(&["nop", ".endr"])).is_err());
+        assert!(expand_repeats(&tokenize(&[".rept A", "nop", ".endr"])).is_err());
+    }
+
+    #[test]
+    fn test_parse_fill() {
+        let ir = parse_tokens(&tokenize(&[".fill %4, $FF"])).expect("Failed to parse");
+        assert_eq!(
+            ir,
+            vec![Instruction::Fill {
+                count: 4,
+                value: 0xFF
+            }]
+        );
+        assert!(parse_tokens(&tokenize(&[".fill %4, A"])).is_err());
+
+        // Counts are never two's complement, unlike instruction operands
+        let error = parse_tokens(&tokenize(&["nop", ".fill -1, 0"])).unwrap_err();
+        assert!(error.to_string().contains("can't be negative"), "{}", error);
+        // Padding to an address the code is already past
+        let error = parse_tokens(&tokenize(&["nop", "nop", ".fill 2-$, 0"])).unwrap_err();
+        assert!(
+            error
+                .to_string()
+                .contains("evaluates to -2, which can't be negative"),
+            "{}",
+            error
+        );
+        assert_eq!(
+            parse_tokens(&tokenize(&["ldi A, -1"])).expect("Failed to parse"),
+            vec![Instruction::LoadImmediate("A".to_string(), 0xFFFF)]
+        );
+    }
+
+    #[test]
+    fn test_parse_data_directives() {
+        let ir = parse_tokens(&tokenize(&[
+            "msg:",
+            ".ascii \"Hi; \\\"x\\\"\\n\"",
+            ".db %1, $FF \"ok\"",
+            "movi A, msg",
+        ]))
+        .expect("Failed to parse");
+        assert_eq!(
+            ir,
+            vec![
+                label("msg"),
+                Instruction::Bytes(b"Hi; \"x\"\n".to_vec()),
+                Instruction::Bytes(vec![1, 0xFF, b'o', b'k']),
+                Instruction::LoadAddress("A".to_string(), "msg".to_string()),
+            ]
+        );
+        assert!(parse_tokens(&tokenize(&[".db %256"])).is_err());
+        assert!(parse_tokens(&tokenize(&[".db", "nop"])).is_err());
+    }
+
+    #[test]
+    fn test_register_aliases() {
+        let ir = parse_tokens(&tokenize(&[
+            ".alias counter, C",
+            ".alias total counter",
+            "mov counter, #1",
+            "addr total counter",
+        ]))
+        .expect("Failed to parse");
+        assert_eq!(
+            ir,
+            vec![
+                Instruction::Alias("counter".to_string(), "C".to_string()),
+                Instruction::Alias("total".to_string(), "C".to_string()),
+                Instruction::PushImmediate(1),
+                Instruction::Pop("C".to_string()),
+                Instruction::AddRegister("C".to_string(), "C".to_string()),
+            ]
+        );
+        assert!(parse_tokens(&tokenize(&[".alias A, B"])).is_err());
+        assert!(parse_tokens(&tokenize(&[".alias x, %1"])).is_err());
+        assert!(parse_tokens(&tokenize(&["pop counter", ".alias counter, C"])).is_err());
+    }
+
+    #[test]
+    fn test_conditionals_and_constants() {
+        let constants = Constants::from([("DEBUG".to_string(), 1), ("BASE".to_string(), 0x20)]);
+        let tokens = tokenize(&[
+            ".if DEBUG",
+            ".ifdef RELEASE",
+            "push %1",
+            ".else",
+            "push BASE",
+            ".endif",
+            ".else",
+            "push %3",
+            ".endif",
+            ".if BASE-$20",
+            "nop",
+            ".endif",
+            "push BASE+1",
+        ]);
+
+        let ir = parse_tokens_with_lines(&tokens, &[], &constants).expect("Failed to parse");
+        assert_eq!(
+            ir,
+            vec![
+                Instruction::PushImmediate(0x20),
+                Instruction::PushImmediate(0x21)
+            ]
+        );
+    }
+
+    #[test]
+    fn test_conditional_errors() {
+        let constants = Constants::new();
+        for lines in [
+            &[".if %1", "nop"][..],
+            &["nop", ".endif"],
+            &[".else"],
+            &[".if UNDEFINED", "nop", ".endif"],
+            &["push UNDEFINED+1"],
+            &["push %1-%2"],
+        ] {
+            assert!(parse_tokens_with_lines(&tokenize(lines), &[], &constants).is_err());
+        }
+    }
+
+    #[test]
+    fn test_current_address_and_label_expressions() {
+        let ir = parse_tokens(&tokenize(&[
+            "start:",
+            "nop",
+            ".fill 8-$, 0",
+            "here:",
+            "push here-start",
+            "push .+1",
+            "main:",
+            ".loop:",
+            "push $-.loop",
+        ]))
+        .expect("Failed to parse");
+        assert_eq!(
+            &ir[2..],
+            &[
+                Instruction::Fill { count: 6, value: 0 },
+                label("here"),
+                Instruction::PushImmediate(8),
+                Instruction::PushImmediate(11),
+                label("main"),
+                label("main.loop"),
+                Instruction::PushImmediate(0),
+            ]
+        );
+
+        // Labels can only be used once they are declared
+        assert!(parse_tokens(&tokenize(&["push later-$", "later:"])).is_err());
+    }
+
+    #[test]
+    fn test_numeric_labels() {
+        let mut tokens = tokenize(&[
+            "main:",
+            "1:",
+            "movi A, 1f",
+            "movi A, 1b",
+            "1:",
+            "movi B, 1b",
+        ]);
+        resolve_numeric_labels(&mut tokens).expect("Failed to resolve numeric labels");
+        assert_eq!(tokens[1], Token::LabelDecl("1@1".to_string()));
+        assert_eq!(tokens[4], Token::Identifier("1@8".to_string()));
+        assert_eq!(tokens[7], Token::Identifier("1@1".to_string()));
+        assert_eq!(tokens[8], Token::LabelDecl("1@8".to_string()));
+        assert_eq!(tokens[11], Token::Identifier("1@8".to_string()));
+
+        // Numeric labels don't open a scope for local labels
+        let ir = parse_tokens(&tokenize(&["main:", "1:", ".loop:"])).expect("Failed to parse");
+        assert_eq!(ir[2], label("main.loop"));
+
+        assert!(parse_tokens(&tokenize(&["movi A, 2b", "2:"])).is_err());
+        assert!(parse_tokens(&tokenize(&["2:", "movi A, 2f"])).is_err());
+    }
+}
@@ -0,0 +1,109 @@
+//! Pseudo-instructions expanded by the parser into real instruction sequences.
+//!
+//! They keep source readable while the ISA itself stays small. Expansions only
+//! use the stack and the destination register, so no other register is clobbered
+//! (except by `PUSH16`, which needs M as scratch).
+
+use crate::asm::ir::Instruction;
+
+/// Signal code the VM runner treats as halt.
+pub const HALT_SIGNAL: u16 = 0x09;
+
+/// `MOV reg, #value` - loads a 16-bit value into a register.
+///
+/// Values that fit in 8 bits are a plain push/pop pair. Wider values push the
+/// high byte, shift it into place by doubling the register eight times, then
+/// add the low byte on the stack.
+pub fn load_immediate(reg: &str, value: u16) -> Vec<Instruction> {
+    let hi = value >> 8;
+    let lo = value & 0xFF;
+
+    if hi == 0 {
+        return vec![
+            Instruction::PushImmediate(lo),
+            Instruction::Pop(reg.to_string()),
+        ];
+    }
+
+    let mut instrs = vec![
+        Instruction::PushImmediate(hi),
+        Instruction::Pop(reg.to_string()),
+    ];
+    instrs.extend((0..8).map(|_| Instruction::AddRegister(reg.to_string(), reg.to_string())));
+    if lo != 0 {
+        instrs.extend([
+            Instruction::PushRegister(reg.to_string()),
+            Instruction::PushImmediate(lo),
+            Instruction::AddStack,
+            Instruction::Pop(reg.to_string()),
+        ]);
+    }
+    instrs
+}
+
+/// Encoded size of `MOVI reg, label`, which is the same for every address.
+pub const LOAD_ADDRESS_SIZE: u16 = 14 * 2;
+
+/// Like `load_immediate`, but always emits the full-width sequence.
+///
+/// Label addresses are only known after the first codegen pass, so the
+/// expansion of `MOVI reg, label` must not depend on the value it loads.
+pub fn load_immediate_wide(reg: &str, value: u16) -> Vec<Instruction> {
+    let mut instrs = vec![
+        Instruction::PushImmediate(value >> 8),
+        Instruction::Pop(reg.to_string()),
+    ];
+    instrs.extend((0..8).map(|_| Instruction::AddRegister(reg.to_string(), reg.to_string())));
+    instrs.extend([
+        Instruction::PushRegister(reg.to_string()),
+        Instruction::PushImmediate(value & 0xFF),
+        Instruction::AddStack,
+        Instruction::Pop(reg.to_string()),
+    ]);
+    instrs
+}
+
+/// Register `PUSH16` builds its value in before pushing it.
+pub const PUSH_WIDE_SCRATCH: &str = "M";
+
+/// `PUSH16 #value` - pushes a 16-bit value.
+///
+/// The argument of `PUSH` is a single byte, so the value is loaded into M
+/// first and M is pushed. M is left holding the value.
+pub fn push_wide(value: u16) -> Vec<Instruction> {
+    let mut instrs = load_immediate(PUSH_WIDE_SCRATCH, value);
+    instrs.push(Instruction::PushRegister(PUSH_WIDE_SCRATCH.to_string()));
+    instrs
+}
+
+/// `PUSH16 label` - pushes the address of a label, clobbering M like `push_wide`.
+pub fn push_address(label: &str) -> Vec<Instruction> {
+    vec![
+        Instruction::LoadAddress(PUSH_WIDE_SCRATCH.to_string(), label.to_string()),
+        Instruction::PushRegister(PUSH_WIDE_SCRATCH.to_string()),
+    ]
+}
+
+/// `CLR reg` - sets a register to zero.
+pub fn clear(reg: &str) -> Vec<Instruction> {
+    load_immediate(reg, 0)
+}
+
+/// `INCSP n` - moves SP up by `n` bytes, reserving stack space.
+///
+/// SP is pushed, the byte count added to it on the stack, and the sum popped
+/// straight back into SP. The pop happens before SP is written, so the
+/// sequence leaves nothing behind on the stack.
+pub fn increment_sp(bytes: u16) -> Vec<Instruction> {
+    vec![
+        Instruction::PushRegister("SP".to_string()),
+        Instruction::PushImmediate(bytes),
+        Instruction::AddStack,
+        Instruction::Pop("SP".to_string()),
+    ]
+}
+
+/// `HALT` - raises the halt signal.
+pub fn halt() -> Vec<Instruction> {
+    vec![Instruction::Signal(HALT_SIGNAL)]
+}
@@ -0,0 +1,115 @@
+//! Static stack-depth analysis over the assembler IR.
+//!
+//! Walks the program once, tracking how many values each instruction leaves
+//! on the stack. A label is a join point: the depth on the fall-through path
+//! must match the depth of every jump to it. A label that nothing reaches yet
+//! starts a new routine at depth 0.
+
+use std::collections::HashMap;
+
+use crate::asm::{ir::Instruction, warnings::Warning};
+
+/// Stack values an instruction needs, and how many it leaves behind.
+fn stack_effect(instr: &Instruction) -> (i32, i32) {
+    match instr {
+        Instruction::PushImmediate(_)
+        | Instruction::PushHex(_)
+        | Instruction::PushRegister(_)
+        | Instruction::PushFlags => (0, 1),
+        Instruction::Pop(_) | Instruction::PopFlags => (1, -1),
+        Instruction::AddStack
+        | Instruction::SubStack
+        | Instruction::AndStack
+        | Instruction::OrStack
+        | Instruction::XorStack => (2, -1),
+        Instruction::NotStack => (1, 0),
+        Instruction::Swap => (2, 0),
+        Instruction::Dup => (1, 1),
+        Instruction::Drop => (1, -1),
+        Instruction::Over => (2, 1),
+        _ => (0, 0),
+    }
+}
+
+/// Reports stack underflows and inconsistent depths at join points.
+pub fn check(instrs: &[Instruction]) -> Vec<Warning> {
+    let mut warnings = Vec::new();
+    // Depth recorded for each label by the first path that reached it
+    let mut label_depths: HashMap<&str, i32> = HashMap::new();
+    // `None` while the current code is unreachable (right after a jump)
+    let mut depth: Option<i32> = Some(0);
+    let mut address: u16 = 0;
+
+    for instr in instrs {
+        match instr {
+            Instruction::Label(name) => {
+                depth = match (depth, label_depths.get(name.as_str())) {
+                    (Some(fallthrough), Some(&jumped)) if fallthrough != jumped => {
+                        warnings.push(Warning::InconsistentStackDepth {
+                            label: name.clone(),
+                            first: jumped,
+                            second: fallthrough,
+                        });
+                        Some(jumped)
+                    }
+                    (Some(d), _) | (None, Some(&d)) => Some(d),
+                    // Nothing reaches this label yet, so it starts a routine
+                    (None, None) => Some(0),
+                };
+                label_depths.insert(name, depth.unwrap_or(0));
+            }
+            Instruction::Jump(target)
+            | Instruction::JumpLong(target)
+            | Instruction::JumpIf(_, target)
+            | Instruction::Branch(target)
+            | Instruction::BranchIf(_, target) => {
+                if let Some(d) = depth {
+                    match label_depths.get(target.as_str()) {
+                        Some(&expected) if expected != d => {
+                            warnings.push(Warning::InconsistentStackDepth {
+                                label: target.clone(),
+                                first: expected,
+                                second: d,
+                            });
+                        }
+                        Some(_) => {}
+                        None => {
+                            label_depths.insert(target, d);
+                        }
+                    }
+                }
+                // A conditional jump also falls through with the same depth
+                if let Instruction::Jump(_) | Instruction::JumpLong(_) | Instruction::Branch(_) =
+                    instr
+                {
+                    depth = None;
+                }
+            }
+            // A computed jump can't be followed, and the code after it is only reached through a label
+            Instruction::JumpRegister(_) => depth = None,
+            _ => {
+                if let Some(d) = depth {
+                    let (needs, net) = stack_effect(instr);
+                    if d < needs {
+                        warnings.push(Warning::StackUnderflow {
+                            address,
+                            needed: needs as u16,
+                            available: d as u16,
+                        });
+                        // Carry on as if the missing values had been there to avoid cascades
+                        depth = Some(needs + net);
+                    } else {
+                        depth = Some(d + net);
+                    }
+                }
+            }
+        }
+        // Code generation reports programs too large to address
+        match instr.next_address(address) {
+            Some(next) => address = next,
+            None => break,
+        }
+    }
+
+    warnings
+}
@@ -0,0 +1,146 @@
+//! Unit tests for the stack-depth analysis.
+
+#[cfg(test)]
+mod tests {
+    use crate::asm::ir::{Condition, Instruction};
+    use crate::asm::stack_depth::check;
+    use crate::asm::warnings::Warning;
+
+    fn label(name: &str) -> Instruction {
+        Instruction::Label(name.to_string())
+    }
+
+    fn jump(target: &str) -> Instruction {
+        Instruction::Jump(target.to_string())
+    }
+
+    fn pop(reg: &str) -> Instruction {
+        Instruction::Pop(reg.to_string())
+    }
+
+    #[test]
+    fn test_balanced_program() {
+        let program = vec![
+            Instruction::PushImmediate(1),
+            Instruction::PushImmediate(2),
+            Instruction::AddStack,
+            pop("A"),
+            Instruction::Signal(0x09),
+        ];
+
+        assert!(check(&program).is_empty());
+    }
+
+    #[test]
+    fn test_pop_more_than_pushed() {
+        let program = vec![
+            label("routine"),
+            Instruction::PushImmediate(1),
+            Instruction::AddStack,
+            pop("A"),
+        ];
+
+        assert_eq!(
+            check(&program),
+            vec![Warning::StackUnderflow {
+                address: 0x0002,
+                needed: 2,
+                available: 1
+            }]
+        );
+    }
+
+    #[test]
+    fn test_stack_manipulation_effects() {
+        let program = vec![
+            label("routine"),
+            Instruction::PushImmediate(1),
+            Instruction::Dup,
+            Instruction::Over,
+            Instruction::Swap,
+            Instruction::Drop,
+            Instruction::Drop,
+            Instruction::Drop,
+            Instruction::Swap,
+        ];
+
+        assert_eq!(
+            check(&program),
+            vec![Warning::StackUnderflow {
+                address: 0x000E,
+                needed: 2,
+                available: 0
+            }]
+        );
+    }
+
+    #[test]
+    fn test_inconsistent_depth_at_join() {
+        let program = vec![
+            Instruction::PushImmediate(1),
+            jump("join"),
+            label("other"),
+            label("join"),
+            pop("A"),
+        ];
+
+        // `other` starts a fresh routine at depth 0, then falls into `join`,
+        // which the jump reached with one value on the stack
+        assert_eq!(
+            check(&program),
+            vec![Warning::InconsistentStackDepth {
+                label: "join".to_string(),
+                first: 1,
+                second: 0
+            }]
+        );
+    }
+
+    #[test]
+    fn test_backward_jump_with_growing_stack() {
+        let program = vec![label("loop"), Instruction::PushImmediate(1), jump("loop")];
+
+        assert_eq!(
+            check(&program),
+            vec![Warning::InconsistentStackDepth {
+                label: "loop".to_string(),
+                first: 0,
+                second: 1
+            }]
+        );
+    }
+
+    #[test]
+    fn test_consistent_forward_jump() {
+        let program = vec![
+            Instruction::PushImmediate(1),
+            jump("done"),
+            Instruction::Nop,
+            label("done"),
+            pop("A"),
+        ];
+
+        assert!(check(&program).is_empty());
+    }
+
+    #[test]
+    fn test_conditional_jump_falls_through() {
+        let program = vec![
+            Instruction::PushImmediate(1),
+            Instruction::JumpIf(Condition::Zero, "done".to_string()),
+            pop("A"),
+            label("done"),
+        ];
+
+        // The pop runs on the fall-through path with the pushed value, and
+        // `done` is reached with depth 1 by the jump but 0 by falling through
+        assert_eq!(
+            check(&program),
+            vec![Warning::InconsistentStackDepth {
+                label: "done".to_string(),
+                first: 1,
+                second: 0
+            }]
+        );
+    }
+}
@@ -0,0 +1,187 @@
+//! Non-fatal diagnostics for assembled programs.
+//!
+//! Warnings never stop code generation on their own; the assembler prints them
+//! to stderr and only fails when invoked with `--deny-warnings`.
+
+use std::{collections::HashSet, fmt};
+
+use crate::asm::{ir::Instruction, parser::Constants};
+
+/// A non-fatal problem found in an assembled program.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Warning {
+    /// A label is declared but no instruction refers to it.
+    UnusedLabel(String),
+    /// An immediate operand does not fit in 8 bits and only its low byte is emitted.
+    TruncatedImmediate { value: u16, truncated: u8 },
+    /// An instruction follows an unconditional jump with no label in between,
+    /// so nothing can ever reach it.
+    UnreachableCode { address: u16 },
+    /// An instruction pops more values than the routine has pushed.
+    StackUnderflow {
+        address: u16,
+        needed: u16,
+        available: u16,
+    },
+    /// Two paths reach the same label with different stack depths.
+    InconsistentStackDepth {
+        label: String,
+        first: i32,
+        second: i32,
+    },
+    /// A label has the same name as a constant, so operands naming it get the constant.
+    LabelShadowsConstant(String),
+}
+
+impl fmt::Display for Warning {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Warning::UnusedLabel(name) => write!(f, "label `{}` is never used", name),
+            Warning::TruncatedImmediate { value, truncated } => write!(
+                f,
+                "immediate value {} (0x{:X}) truncated to 8 bits ({})",
+                value, value, truncated
+            ),
+            Warning::UnreachableCode { address } => write!(
+                f,
+                "unreachable code at 0x{:04X} after unconditional JMP",
+                address
+            ),
+            Warning::StackUnderflow {
+                address,
+                needed,
+                available,
+            } => write!(
+                f,
+                "stack underflow at 0x{:04X}: needs {} value(s) but only {} pushed",
+                address, needed, available
+            ),
+            Warning::InconsistentStackDepth {
+                label,
+                first,
+                second,
+            } => write!(
+                f,
+                "label `{}` is reached with stack depth {} on one path and {} on another",
+                label, first, second
+            ),
+            Warning::LabelShadowsConstant(name) => write!(
+                f,
+                "label `{}` has the same name as a constant; operands `{}` refer to the constant",
+                name, name
+            ),
+        }
+    }
+}
+
+/// Runs every warning check over the parsed program.
+pub fn check(instrs: &[Instruction]) -> Vec<Warning> {
+    let mut warnings = Vec::new();
+    warnings.extend(check_truncated_immediates(instrs));
+    warnings.extend(check_unreachable_code(instrs));
+    warnings.extend(check_unused_labels(instrs));
+    warnings
+}
+
+/// Reports numeric operands that will lose their upper byte during encoding.
+fn check_truncated_immediates(instrs: &[Instruction]) -> Vec<Warning> {
+    instrs
+        .iter()
+        .filter_map(|instr| match instr {
+            Instruction::PushImmediate(n)
+            | Instruction::PushHex(n)
+            | Instruction::Signal(n)
+            | Instruction::Syscall(n)
+            | Instruction::Fill { value: n, .. }
+                if *n > 0xFF =>
+            {
+                Some(Warning::TruncatedImmediate {
+                    value: *n,
+                    truncated: *n as u8,
+                })
+            }
+            _ => None,
+        })
+        .collect()
+}
+
+/// Reports the first instruction of each block that directly follows a `JMP`.
+fn check_unreachable_code(instrs: &[Instruction]) -> Vec<Warning> {
+    let mut warnings = Vec::new();
+    let mut address: u16 = 0;
+    let mut after_jump = false;
+
+    for instr in instrs {
+        // Code generation reports programs too large to address
+        let Some(next) = instr.next_address(address) else {
+            break;
+        };
+        match instr {
+            // A label makes the following code reachable again
+            Instruction::Label(_) => after_jump = false,
+            // Directives emit no code, so they can't be unreachable
+            Instruction::Entry(_) | Instruction::Equate(..) => {}
+            // Data is never executed, so it can't be unreachable code either
+            Instruction::Fill { .. } | Instruction::Bytes(_) => address = next,
+            _ => {
+                if after_jump {
+                    warnings.push(Warning::UnreachableCode { address });
+                    // Only report the start of each unreachable block
+                    after_jump = false;
+                }
+                if let Instruction::Jump(_)
+                | Instruction::JumpLong(_)
+                | Instruction::Branch(_)
+                | Instruction::JumpRegister(_) = instr
+                {
+                    after_jump = true;
+                }
+                address = next;
+            }
+        }
+    }
+
+    warnings
+}
+
+/// Reports labels that are declared but never used as a jump target, entry
+/// point or data address.
+fn check_unused_labels(instrs: &[Instruction]) -> Vec<Warning> {
+    let referenced: HashSet<&str> = instrs
+        .iter()
+        .filter_map(|instr| match instr {
+            Instruction::Jump(label)
+            | Instruction::JumpLong(label)
+            | Instruction::JumpIf(_, label)
+            | Instruction::Branch(label)
+            | Instruction::BranchIf(_, label)
+            | Instruction::Entry(label)
+            | Instruction::LoadAddress(_, label) => Some(label.as_str()),
+            _ => None,
+        })
+        .collect();
+
+    instrs
+        .iter()
+        .filter_map(|instr| match instr {
+            Instruction::Label(name) if !referenced.contains(name.as_str()) => {
+                Some(Warning::UnusedLabel(name.clone()))
+            }
+            _ => None,
+        })
+        .collect()
+}
+
+/// Reports labels whose name is also defined as a constant.
+pub fn check_shadowed_constants(instrs: &[Instruction], constants: &Constants) -> Vec<Warning> {
+    instrs
+        .iter()
+        .filter_map(|instr| match instr {
+            Instruction::Label(name) if constants.contains_key(name) => {
+                Some(Warning::LabelShadowsConstant(name.clone()))
+            }
+            _ => None,
+        })
+        .collect()
+}
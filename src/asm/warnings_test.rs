@@ -0,0 +1,73 @@
+//! Unit tests for the assembler warnings pass.
+
+#[cfg(test)]
+mod tests {
+    use crate::asm::ir::Instruction;
+    use crate::asm::warnings::{Warning, check};
+
+    #[test]
+    fn test_truncated_immediate() {
+        let program = vec![
+            Instruction::PushImmediate(300),
+            Instruction::PushHex(0xFF),
+            Instruction::Signal(0x109),
+        ];
+
+        assert_eq!(
+            check(&program),
+            vec![
+                Warning::TruncatedImmediate {
+                    value: 300,
+                    truncated: 44
+                },
+                Warning::TruncatedImmediate {
+                    value: 0x109,
+                    truncated: 0x09
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_unused_label() {
+        let program = vec![
+            Instruction::Label("start".to_string()),
+            Instruction::Nop,
+            Instruction::Label("end".to_string()),
+            Instruction::Jump("start".to_string()),
+        ];
+
+        assert_eq!(
+            check(&program),
+            vec![Warning::UnusedLabel("end".to_string())]
+        );
+    }
+
+    #[test]
+    fn test_unreachable_after_jump() {
+        let program = vec![
+            Instruction::Label("start".to_string()),
+            Instruction::Jump("start".to_string()),
+            Instruction::Nop,
+            Instruction::Nop,
+        ];
+
+        // Only the first instruction of the dead block is reported
+        assert_eq!(
+            check(&program),
+            vec![Warning::UnreachableCode { address: 0x0002 }]
+        );
+    }
+
+    #[test]
+    fn test_label_after_jump_is_reachable() {
+        let program = vec![
+            Instruction::Label("start".to_string()),
+            Instruction::Jump("next".to_string()),
+            Instruction::Label("next".to_string()),
+            Instruction::Jump("start".to_string()),
+        ];
+
+        assert!(check(&program).is_empty());
+    }
+}
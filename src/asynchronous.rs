@@ -0,0 +1,99 @@
+//! Running a machine inside an async application.
+//!
+//! [`Machine::run_async`] steps the machine like a plain loop but hands
+//! control back to the executor every so many steps, so a web server or an
+//! async TUI can run programs on the same thread as everything else.
+//! [`Machine::run_async_with`] also awaits a future at each of those pauses,
+//! which is where the host waits on devices or input without blocking.
+//!
+//! Nothing here depends on a particular runtime. The futures are not `Send`,
+//! because the machine's output and input are not, so on a multi-threaded
+//! runtime they go on a local task set.
+//!
+//! ```
+//! use rustyvm::{Machine, asynchronous::yield_now, devices::KeyQueue};
+//!
+//! async fn run(vm: &mut Machine, keys: &mut KeyQueue) -> Result<u64, String> {
+//!     // Pause every 1000 steps to press the next key and let other tasks run
+//!     vm.run_async_with(1000, |vm| {
+//!         keys.pump(vm);
+//!         async {
+//!             yield_now().await;
+//!             Ok(())
+//!         }
+//!     })
+//!     .await
+//! }
+//! ```
+
+use std::{
+    future::Future,
+    pin::Pin,
+    task::{Context, Poll},
+};
+
+use crate::Machine;
+
+/// A future that is pending once, waking its task straight away, so the
+/// executor can run other tasks before it resumes.
+#[derive(Debug, Default)]
+pub struct YieldNow {
+    yielded: bool,
+}
+
+impl Future for YieldNow {
+    type Output = ();
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<()> {
+        if self.yielded {
+            return Poll::Ready(());
+        }
+        self.yielded = true;
+        cx.waker().wake_by_ref();
+        Poll::Pending
+    }
+}
+
+/// Hands control back to the executor once.
+pub fn yield_now() -> YieldNow {
+    YieldNow::default()
+}
+
+impl Machine {
+    /// Runs until the machine halts, yielding to the executor after every
+    /// `steps_per_yield` steps. Returns the number of steps executed.
+    pub async fn run_async(&mut self, steps_per_yield: u64) -> Result<u64, String> {
+        self.run_async_with(steps_per_yield, |_| async {
+            yield_now().await;
+            Ok(())
+        })
+        .await
+    }
+
+    /// Runs until the machine halts, calling `pause` after every
+    /// `steps_per_yield` steps and awaiting the future it returns before
+    /// stepping on. `pause` gets the machine first, to pump keys or sync
+    /// devices; the future is where the host awaits anything it needs, and
+    /// should yield at least once so other tasks get a turn. An error from
+    /// the future stops the run. Returns the number of steps executed.
+    pub async fn run_async_with<F, Fut>(
+        &mut self,
+        steps_per_yield: u64,
+        mut pause: F,
+    ) -> Result<u64, String>
+    where
+        F: FnMut(&mut Machine) -> Fut,
+        Fut: Future<Output = Result<(), String>>,
+    {
+        let steps_per_yield = steps_per_yield.max(1);
+        let mut steps = 0;
+        while !self.halt {
+            self.step()?;
+            steps += 1;
+            if steps % steps_per_yield == 0 && !self.halt {
+                pause(self).await?;
+            }
+        }
+        Ok(steps)
+    }
+}
@@ -0,0 +1,76 @@
+//! Unit tests for async execution.
+//!
+//! This file drives the futures by hand with a no-op waker, counting how
+//! often the run hands control back.
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        future::Future,
+        pin::pin,
+        task::{Context, Poll, Waker},
+    };
+
+    use crate::asm::assemble_str;
+    use crate::test_support::halting_machine;
+    use crate::{Machine, Register};
+
+    /// Polls a future to completion, returning its output and how many
+    /// times it was pending.
+    fn block_on<F: Future>(future: F) -> (F::Output, usize) {
+        let mut future = pin!(future);
+        let mut cx = Context::from_waker(Waker::noop());
+        let mut pending = 0;
+        loop {
+            match future.as_mut().poll(&mut cx) {
+                Poll::Ready(output) => return (output, pending),
+                Poll::Pending => pending += 1,
+            }
+        }
+    }
+
+    /// A machine that counts to `n` in A, two steps per count, and halts.
+    fn counting_machine(n: u8) -> Machine {
+        let mut source: String = (1..=n).map(|k| format!("push %{}\npop A\n", k)).collect();
+        source.push_str("sig $09\n");
+        halting_machine(&assemble_str(&source).unwrap())
+    }
+
+    #[test]
+    fn test_run_async_yields_every_n_steps() {
+        let mut vm = counting_machine(4);
+        let (steps, pending) = block_on(vm.run_async(4));
+        assert_eq!(steps, Ok(9));
+        // After steps 4 and 8; the run ends at step 9
+        assert_eq!(pending, 2);
+        assert_eq!(vm.get_register(Register::A), 4);
+    }
+
+    #[test]
+    fn test_pause_sees_the_machine_and_can_stop_the_run() {
+        let mut vm = counting_machine(9);
+        let mut seen = Vec::new();
+        let (result, _) = block_on(vm.run_async_with(2, |vm| {
+            seen.push(vm.get_register(Register::A));
+            let done = vm.get_register(Register::A) >= 3;
+            async move {
+                if done {
+                    Err("stopped".to_string())
+                } else {
+                    Ok(())
+                }
+            }
+        }));
+        assert_eq!(result, Err("stopped".to_string()));
+        assert_eq!(seen, vec![1, 2, 3]);
+    }
+
+    #[test]
+    fn test_errors_end_the_run() {
+        let mut vm = Machine::new();
+        vm.load_program(&assemble_str("sig $42\n").unwrap())
+            .unwrap();
+        let (result, _) = block_on(vm.run_async(10));
+        assert!(result.is_err());
+    }
+}
@@ -0,0 +1,148 @@
+//! Assembles and runs a program in one go, optionally rerunning it whenever
+//! the source changes.
+
+use std::{
+    env, fs, panic,
+    path::PathBuf,
+    process, thread,
+    time::{Duration, SystemTime},
+};
+
+use rustyvm::{
+    HaltReason, Machine, Register,
+    asm::{self, AsmOptions},
+    halt, logging,
+};
+
+/// How often `--watch` checks the sources for changes.
+const POLL_INTERVAL: Duration = Duration::from_millis(300);
+
+/// Step budget in watch mode, so a program that never halts doesn't stall the loop.
+const WATCH_MAX_STEPS: u64 = 1_000_000;
+
+/// Main function for the assemble-and-run binary.
+fn main() {
+    if let Err(e) = run() {
+        eprintln!("Error: {}", e);
+        process::exit(1);
+    }
+}
+
+/// Parses the options, then assembles and runs once or keeps watching.
+fn run() -> Result<(), String> {
+    let args: Vec<_> = env::args().collect();
+    let usage = format!(
+        "usage: {} <input>... [-I <dir>]... [--max-steps N] [--watch] [-v|-vv|-vvv]",
+        args[0]
+    );
+
+    let mut inputs: Vec<PathBuf> = Vec::new();
+    let mut options = AsmOptions::default();
+    let mut max_steps: Option<u64> = None;
+    let mut watch = false;
+    let mut verbosity = 0;
+
+    let mut args_iter = args[1..].iter();
+    while let Some(arg) = args_iter.next() {
+        match arg.as_str() {
+            "-I" => {
+                let dir = args_iter.next().ok_or_else(|| usage.clone())?;
+                options.include_dirs.push(dir.into());
+            }
+            option if option.starts_with("-I") => options.include_dirs.push(option[2..].into()),
+            "--max-steps" => {
+                let value = args_iter.next().ok_or_else(|| usage.clone())?;
+                let steps = value
+                    .parse()
+                    .map_err(|_| format!("invalid --max-steps value: {}", value))?;
+                max_steps = Some(steps);
+            }
+            "--watch" => watch = true,
+            "-v" | "-vv" | "-vvv" => verbosity = arg.len() as u8 - 1,
+            option if option.starts_with('-') => {
+                return Err(format!("Unknown option: {}\n{}", option, usage));
+            }
+            path => inputs.push(path.into()),
+        }
+    }
+
+    if inputs.is_empty() {
+        return Err(usage);
+    }
+    logging::init(verbosity)?;
+
+    if !watch {
+        return assemble_and_run(&inputs, &options, max_steps);
+    }
+
+    let max_steps = Some(max_steps.unwrap_or(WATCH_MAX_STEPS));
+    let mut last_seen = None;
+    loop {
+        let modified = modified_times(&inputs);
+        if last_seen.as_ref() != Some(&modified) {
+            last_seen = Some(modified);
+            println!("=== {} ===", inputs_label(&inputs));
+
+            // A broken source must not end the watch, even if the assembler panics on it
+            let result = panic::catch_unwind(|| assemble_and_run(&inputs, &options, max_steps))
+                .unwrap_or_else(|_| Err("the assembler crashed on this source".to_string()));
+            if let Err(e) = result {
+                println!("Error: {}", e);
+            }
+            println!("Watching for changes, press Ctrl+C to stop...");
+        }
+        thread::sleep(POLL_INTERVAL);
+    }
+}
+
+/// Names the watched files for the banner printed before each run.
+fn inputs_label(inputs: &[PathBuf]) -> String {
+    let names: Vec<String> = inputs.iter().map(|p| p.display().to_string()).collect();
+    names.join(", ")
+}
+
+/// Modification times of the inputs; files that can't be read count as `None`.
+fn modified_times(inputs: &[PathBuf]) -> Vec<Option<SystemTime>> {
+    inputs
+        .iter()
+        .map(|path| fs::metadata(path).and_then(|m| m.modified()).ok())
+        .collect()
+}
+
+/// Assembles the inputs into a fresh machine and runs it until it halts.
+fn assemble_and_run(
+    inputs: &[PathBuf],
+    options: &AsmOptions,
+    max_steps: Option<u64>,
+) -> Result<(), String> {
+    let assembly = asm::assemble_files(inputs, options).map_err(|e| e.to_string())?;
+    for warning in &assembly.warnings {
+        eprintln!("warning: {}", warning);
+    }
+
+    let mut vm = Machine::builder()
+        .handler(0x09, halt)
+        .program(&assembly.bytecode)
+        .build()?;
+
+    let _run = logging::run_span(&inputs_label(inputs));
+    let result = match max_steps {
+        Some(max) => vm.run_with_fuel(max),
+        None => vm.run(),
+    };
+    match result.inspect_err(|e| logging::run_finished(e.steps, "error"))? {
+        HaltReason::Halted { steps } => {
+            logging::run_finished(steps, "halted");
+            vm.print_final_state().map_err(|e| e.to_string())
+        }
+        HaltReason::OutOfFuel { steps } => {
+            logging::run_finished(steps, "step_limit");
+            vm.print_final_state().map_err(|e| e.to_string())?;
+            Err(format!(
+                "step budget exhausted after {} steps (PC=0x{:04X}) - the program never raised the halt signal",
+                steps,
+                vm.get_register(Register::PC)
+            ))
+        }
+    }
+}
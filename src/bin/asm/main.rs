@@ -1,75 +1,147 @@
 //! Assembler binary for the Rusty 16-bit VM.
 
-pub mod codegen;
-pub mod ir;
-pub mod lexer;
-pub mod parser;
-
 use std::{
-    env,
-    fs::File,
-    io::{self, BufRead, BufReader, Write},
-    path::Path,
+    env, fs,
+    io::{self, Write},
+    process,
 };
 
-use crate::lexer::Token;
+use rustyvm::{
+    Executable, Program, Section,
+    asm::{self, AsmOptions},
+    ihex, isa,
+};
 
 /// Main function for the assembler binary.
-/// Reads an assembly source file, converts to bytecode, outputs to stdout.
-fn main() -> Result<(), String> {
-    let args: Vec<_> = env::args().collect();
-    if args.len() != 2 {
-        return Err(format!("usage: {} <input>", args[0]));
+/// Prints errors as plain text (not `Debug`-quoted) so multi-line messages stay readable.
+fn main() {
+    if let Err(e) = run() {
+        eprintln!("Error: {}", e);
+        process::exit(1);
     }
+}
 
-    let file: File = match File::open(Path::new(&args[1])) {
-        Err(e) => {
-            return Err(format!("failed to open the file, err - {}", e));
-        }
-        Ok(f) => f,
-    };
-
-    // Process the file line by line and convert to bytecode
+/// Output formats selected with `--format`.
+enum Format {
+    /// Raw bytecode, with a header when the program has an entry point
+    Bin,
+    /// Intel HEX text
+    Ihex,
+    /// Executable container with sections and symbols
+    Exe,
+}
 
-    let lines: Vec<String> = match BufReader::new(file).lines().collect() {
-        Ok(lines) => lines,
-        Err(e) => {
-            return Err(format!("cannot read the file due to - {}", e));
+/// Reads assembly source files, converts them to one program, outputs the bytecode to stdout.
+fn run() -> Result<(), String> {
+    let args: Vec<_> = env::args().collect();
+    let usage = format!(
+        "usage: {} <input>... | --dump-isa [--format bin|ihex|exe] [--strip] [--symbols <file>] [-I <dir>]... [--define NAME[=VALUE]]... [--deny-warnings] [-O|--optimize] [--check-stack]",
+        args[0]
+    );
+
+    let mut inputs: Vec<&str> = Vec::new();
+    let mut symbols_path: Option<&str> = None;
+    let mut deny_warnings = false;
+    let mut format = Format::Bin;
+    let mut strip = false;
+    let mut options = AsmOptions::default();
+
+    let mut args_iter = args[1..].iter();
+    while let Some(arg) = args_iter.next() {
+        match arg.as_str() {
+            "-I" => {
+                let dir = args_iter.next().ok_or_else(|| usage.clone())?;
+                options.include_dirs.push(dir.into());
+            }
+            option if option.starts_with("-I") => options.include_dirs.push(option[2..].into()),
+            "--define" => {
+                let define = args_iter.next().ok_or_else(|| usage.clone())?;
+                let (name, value) = asm::parse_define(define, &options.defines)?;
+                options.defines.insert(name, value);
+            }
+            "--symbols" => {
+                let path = args_iter.next().ok_or_else(|| usage.clone())?;
+                symbols_path = Some(path);
+            }
+            "--format" => match args_iter.next().map(String::as_str) {
+                Some("bin") => format = Format::Bin,
+                Some("ihex") => format = Format::Ihex,
+                Some("exe") => format = Format::Exe,
+                _ => {
+                    return Err(format!(
+                        "--format expects `bin`, `ihex` or `exe`\n{}",
+                        usage
+                    ));
+                }
+            },
+            "--strip" => strip = true,
+            "--deny-warnings" => deny_warnings = true,
+            "-O" | "--optimize" => options.optimize = true,
+            "--check-stack" => options.check_stack = true,
+            "--dump-isa" => {
+                print!("{}", isa::to_json());
+                return Ok(());
+            }
+            option if option.starts_with('-') => {
+                return Err(format!("Unknown option: {}\n{}", option, usage));
+            }
+            path => inputs.push(path),
         }
-    };
-
-    // Parse the tokens into bytecode
-    // let mut outputs: Vec<u8> = Vec::new();
-
-    let mut all_tokens: Vec<Token> = Vec::new();
+    }
 
-    for l in lines {
-        // Skip empty lines or handle full-line comments (lines that start with semicolon)
-        if l.trim().is_empty() || l.trim_start().starts_with(';') {
-            continue;
-        }
+    if inputs.is_empty() {
+        return Err(usage);
+    }
 
-        // Split the line at the first semicolon to handle inline comments
-        let code_part = l.split(';').next().unwrap_or("").trim();
+    let assembly = asm::assemble_files(&inputs, &options).map_err(|e| e.to_string())?;
 
-        // If after removing comments the line is empty, skip it
-        if code_part.is_empty() {
-            continue;
-        }
+    // Warnings go to stderr so they never end up mixed into the bytecode
+    for warning in &assembly.warnings {
+        eprintln!("warning: {}", warning);
+    }
+    if deny_warnings && !assembly.warnings.is_empty() {
+        return Err(format!(
+            "{} warning(s) treated as errors (--deny-warnings)",
+            assembly.warnings.len()
+        ));
+    }
 
-        // Tokenize the code part into instruction parts
-        let tokens = Token::tokenize_line(code_part);
-        all_tokens.extend(tokens);
+    if options.optimize {
+        eprintln!("optimizer: saved {} bytes", assembly.bytes_saved);
     }
 
-    let ir =
-        parser::parse_tokens(&all_tokens).map_err(|e| format!("Error parsing tokens: {}", e))?;
-    let byte_code =
-        codegen::generate_bytecode(&ir).map_err(|e| format!("Error generating bytecode: {}", e))?;
+    if let Some(path) = symbols_path {
+        let text = asm::format_symbols(&assembly.symbols) + &asm::format_aliases(&assembly.aliases);
+        fs::write(path, text).map_err(|e| format!("failed to write symbols to {}: {}", path, e))?;
+    }
 
     // Write the generated bytecode to stdout
     let mut out = io::stdout().lock();
-    out.write_all(&byte_code).map_err(|x| format!("{}", x))?;
+    let program = Program::parse(&assembly.bytecode)?;
+    match format {
+        Format::Bin => out.write_all(&assembly.bytecode),
+        Format::Ihex => {
+            // The entry point moves from the program header into a start address record
+            let entry = (assembly.bytecode.len() != program.code.len()).then_some(program.entry);
+            out.write_all(ihex::encode(0, program.code, entry).as_bytes())
+        }
+        Format::Exe => {
+            let executable = Executable {
+                entry: program.entry,
+                sections: vec![Section {
+                    address: 0,
+                    data: program.code.to_vec(),
+                }],
+                symbols: if strip {
+                    Default::default()
+                } else {
+                    assembly.symbols.clone()
+                },
+            };
+            out.write_all(&executable.encode()?)
+        }
+    }
+    .map_err(|x| format!("{}", x))?;
 
     Ok(())
 }
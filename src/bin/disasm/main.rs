@@ -0,0 +1,234 @@
+//! Disassembler binary for the Rusty 16-bit VM.
+
+use std::{
+    collections::BTreeMap,
+    env, fs,
+    io::{self, Read, Write},
+    process,
+};
+
+use rustyvm::{
+    Executable, HEADER_SIZE, Profile,
+    asm::{
+        self, AliasTable,
+        disassembler::{self, Listing},
+    },
+};
+
+/// Main function for the disassembler binary.
+fn main() {
+    if let Err(e) = run() {
+        eprintln!("Error: {}", e);
+        process::exit(1);
+    }
+}
+
+/// Reads a bytecode file and prints one line per word: address, raw bytes
+/// and the decoded instruction. With `--to-hex` it prints the file as
+/// space-separated hex text instead. With `--coverage` each instruction is
+/// prefixed with how often it ran.
+fn run() -> Result<(), String> {
+    let args: Vec<_> = env::args().collect();
+    let usage = format!(
+        "usage: {} <input> [--symbols <file>] [--coverage <file>] [--to-hex [--group]]",
+        args[0]
+    );
+
+    let mut input: Option<&str> = None;
+    let mut symbols_path: Option<&str> = None;
+    let mut to_hex = false;
+    let mut group = false;
+    let mut coverage_path: Option<&str> = None;
+
+    let mut args_iter = args[1..].iter();
+    while let Some(arg) = args_iter.next() {
+        match arg.as_str() {
+            "--symbols" => {
+                let path = args_iter.next().ok_or_else(|| usage.clone())?;
+                symbols_path = Some(path);
+            }
+            "--coverage" => {
+                let path = args_iter.next().ok_or_else(|| usage.clone())?;
+                coverage_path = Some(path);
+            }
+            "--to-hex" => to_hex = true,
+            "--group" => group = true,
+            option if option.starts_with('-') && option != "-" => {
+                return Err(format!("Unknown option: {}\n{}", option, usage));
+            }
+            path if input.is_none() => input = Some(path),
+            _ => return Err(usage),
+        }
+    }
+
+    let input = input.ok_or(usage)?;
+    // `-` reads the program from stdin
+    let mut bytes = Vec::new();
+    if input == "-" {
+        io::stdin()
+            .read_to_end(&mut bytes)
+            .map_err(|e| format!("failed to read stdin, err - {}", e))?;
+    } else {
+        bytes = fs::read(input).map_err(|e| format!("failed to open the file, err - {}", e))?;
+    }
+
+    // Executables are shown as one flat image, using their own symbols unless overridden
+    let mut embedded_symbols = None;
+    if Executable::is_executable(&bytes) {
+        let executable = Executable::parse(&bytes)?;
+        let (start, image) = executable.flatten();
+        if start != 0 {
+            eprintln!(
+                "note: image starts at 0x{:04X}, addresses are relative to it",
+                start
+            );
+        }
+        bytes = image;
+        let symbols = executable.symbols.into_iter();
+        embedded_symbols = Some(
+            symbols
+                .map(|(name, a)| (name, a.wrapping_sub(start)))
+                .collect(),
+        );
+    }
+    let listing = disassembler::listing(&bytes)?;
+
+    if to_hex {
+        let mut out = io::stdout().lock();
+        return print_hex(&mut out, &bytes, group.then_some(&listing)).map_err(|e| e.to_string());
+    }
+
+    // Label names by address: symbols from the file first, then synthesized
+    // ones. Register aliases are only kept in symbol files
+    let (symbols, aliases) = match symbols_path {
+        Some(path) => {
+            let text = fs::read_to_string(path)
+                .map_err(|e| format!("failed to read symbols from {}: {}", path, e))?;
+            (asm::parse_symbols(&text)?, asm::parse_aliases(&text)?)
+        }
+        None => (embedded_symbols.unwrap_or_default(), Default::default()),
+    };
+    let labels = listing.labels(&symbols);
+
+    let coverage = match coverage_path {
+        Some(path) => {
+            let text = fs::read_to_string(path)
+                .map_err(|e| format!("failed to read coverage from {}: {}", path, e))?;
+            Some(Profile::parse_coverage(&text)?)
+        }
+        None => None,
+    };
+
+    let mut out = io::stdout().lock();
+    print_listing(&mut out, &listing, &labels, &aliases, coverage.as_ref())
+        .map_err(|e| e.to_string())
+}
+
+/// Writes the listing, with label lines before the addresses they name and
+/// registers shown with their aliases.
+/// With coverage, every line starts with an execution count column in the
+/// style of gcov: `#####` marks instructions that never ran.
+fn print_listing(
+    out: &mut impl Write,
+    listing: &Listing,
+    labels: &BTreeMap<u16, Vec<String>>,
+    aliases: &AliasTable,
+    coverage: Option<&BTreeMap<u16, u64>>,
+) -> io::Result<()> {
+    let blank = if coverage.is_some() { "        | " } else { "" };
+    let data = listing.data_addresses(labels);
+    // Wide enough for the longest instruction's bytes
+    let raw_width = listing
+        .lines
+        .iter()
+        .map(|line| line.bytes.len() * 3 - 1)
+        .fold(6, usize::max);
+
+    if let Some(entry) = listing.entry {
+        let name = &labels[&entry][0];
+        writeln!(out, "{}            .entry {}", blank, name)?;
+    }
+
+    let mut executed = 0;
+    let mut instructions = 0;
+    for line in &listing.lines {
+        for name in labels.get(&line.address).into_iter().flatten() {
+            writeln!(out, "{}{}:", blank, name)?;
+        }
+
+        let column = match coverage {
+            // Data is not expected to run, so it doesn't count against coverage
+            Some(_) if data.contains(&line.address) => blank.to_string(),
+            Some(coverage) => {
+                instructions += 1;
+                match coverage.get(&line.address) {
+                    Some(count) => {
+                        executed += 1;
+                        format!("{:>7} | ", count)
+                    }
+                    None => "  ##### | ".to_string(),
+                }
+            }
+            None => String::new(),
+        };
+        let raw: Vec<String> = line.bytes.iter().map(|b| format!("{:02X}", b)).collect();
+        writeln!(
+            out,
+            "{}{:04X}: {:<width$} {}",
+            column,
+            line.address,
+            raw.join(" "),
+            line.annotated(labels, aliases),
+            width = raw_width
+        )?;
+    }
+
+    // Labels at or past the end of the code still get printed
+    let end = listing
+        .lines
+        .last()
+        .map_or(0, |l| l.address + l.bytes.len() as u16);
+    for name in labels.range(end..).flat_map(|(_, names)| names) {
+        writeln!(out, "{}{}:", blank, name)?;
+    }
+
+    if coverage.is_some() {
+        writeln!(
+            out,
+            "; coverage: {} of {} instructions executed ({:.1}%)",
+            executed,
+            instructions,
+            executed as f64 * 100.0 / instructions.max(1) as f64
+        )?;
+    }
+    Ok(())
+}
+
+/// Writes the file as space-separated hex bytes, 16 per line. With a listing,
+/// each instruction gets its own line with the decoded instruction as a comment.
+fn print_hex(out: &mut impl Write, bytes: &[u8], listing: Option<&Listing>) -> io::Result<()> {
+    let hex = |bytes: &[u8]| -> String {
+        let hex: Vec<String> = bytes.iter().map(|b| format!("{:02X}", b)).collect();
+        hex.join(" ")
+    };
+
+    let Some(listing) = listing else {
+        for chunk in bytes.chunks(16) {
+            writeln!(out, "{}", hex(chunk))?;
+        }
+        return Ok(());
+    };
+
+    if let Some(entry) = listing.entry {
+        writeln!(
+            out,
+            "{:<17} ; header, entry {:04X}",
+            hex(&bytes[..HEADER_SIZE]),
+            entry
+        )?;
+    }
+    for line in &listing.lines {
+        writeln!(out, "{:<17} ; {}", hex(&line.bytes), line.instruction)?;
+    }
+    Ok(())
+}
@@ -0,0 +1,48 @@
+//! Speaker front-end for the beeper device, built with the `audio` feature.
+//!
+//! Beeps are queued on a sink and play in the background while the program
+//! keeps running, one after another in the order they were taken. The run
+//! only waits for the queue to drain once the program has stopped.
+
+use rodio::{OutputStream, OutputStreamHandle, Sink, buffer::SamplesBuffer};
+use rustyvm::devices::Beep;
+
+/// Sample rate the beeps are rendered at.
+const SAMPLE_RATE: u32 = 44_100;
+
+/// The host audio output, with the queue of beeps still to play.
+pub struct Beeper {
+    // The stream stops playing once dropped, so it is kept alongside the sink
+    _stream: OutputStream,
+    _handle: OutputStreamHandle,
+    sink: Sink,
+}
+
+impl Beeper {
+    /// Opens the default audio output.
+    pub fn open() -> Result<Self, String> {
+        let (stream, handle) = OutputStream::try_default()
+            .map_err(|e| format!("failed to open the audio output - {}", e))?;
+        let sink = Sink::try_new(&handle)
+            .map_err(|e| format!("failed to open the audio output - {}", e))?;
+        Ok(Self {
+            _stream: stream,
+            _handle: handle,
+            sink,
+        })
+    }
+
+    /// Queues a beep without waiting for it to play.
+    pub fn play(&mut self, beep: Beep) {
+        self.sink.append(SamplesBuffer::new(
+            1,
+            SAMPLE_RATE,
+            beep.samples(SAMPLE_RATE),
+        ));
+    }
+
+    /// Waits until every queued beep has played.
+    pub fn finish(&mut self) {
+        self.sink.sleep_until_end();
+    }
+}
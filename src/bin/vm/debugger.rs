@@ -0,0 +1,211 @@
+//! Command prompt for `--manual` mode.
+
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    io::{self, BufRead, Write},
+};
+
+use rustyvm::{
+    Machine, Op, Register,
+    asm::{disassembler, expr},
+};
+
+use crate::{hexdump, symbolize, write_backtrace};
+
+/// Instructions shown on each side of PC by `d`.
+const DISASSEMBLY_CONTEXT: u16 = 4;
+
+/// Help text printed by `h`.
+const HELP: &str = "\
+Commands:
+  <Enter>, n         execute the next instruction
+  c                  continue until a breakpoint or the end of the program
+  b <addr>           set a breakpoint, or list them with no address
+  del <addr>         remove a breakpoint
+  p <reg>            print a register
+  set <reg> <value>  change a register
+  x <addr> <len>     examine memory
+  d                  disassemble around PC
+  s                  print the machine state
+  bt                 list the calls in progress
+  exit, q            stop the program
+Addresses and values are expressions, and may use symbol names.";
+
+/// What the run loop should do after the prompt.
+pub enum Action {
+    /// Execute one instruction and prompt again
+    Step,
+    /// Run until a breakpoint
+    Continue,
+    /// Stop the program
+    Exit,
+}
+
+/// Breakpoints and the state of a `c` in progress.
+pub struct Debugger<'a> {
+    breakpoints: BTreeSet<u16>,
+    continuing: bool,
+    /// Set when examining a core dump, where nothing can run
+    post_mortem: bool,
+    /// Names for addresses, from `--symbols` or `--asm`
+    symbols: &'a BTreeMap<u16, String>,
+}
+
+impl<'a> Debugger<'a> {
+    pub fn new(symbols: &'a BTreeMap<u16, String>) -> Self {
+        Self {
+            breakpoints: BTreeSet::new(),
+            continuing: false,
+            post_mortem: false,
+            symbols,
+        }
+    }
+
+    /// A debugger for a core dump: every command works except those that
+    /// run instructions.
+    pub fn post_mortem(symbols: &'a BTreeMap<u16, String>) -> Self {
+        Self {
+            post_mortem: true,
+            ..Self::new(symbols)
+        }
+    }
+
+    /// Prompts before the instruction at PC unless a `c` is running past it.
+    pub fn before_step(&mut self, vm: &mut Machine) -> Result<Action, String> {
+        let pc = vm.get_register(Register::PC);
+        if self.continuing {
+            if !self.breakpoints.contains(&pc) {
+                return Ok(Action::Continue);
+            }
+            let line = format!("Breakpoint at 0x{:04X} {}", pc, symbolize(pc, self.symbols));
+            println!("{}", line.trim_end());
+            self.continuing = false;
+        }
+
+        let stdin = io::stdin();
+        loop {
+            print!("(0x{:04X}) ", pc);
+            io::stdout().flush().map_err(|e| e.to_string())?;
+            let mut line = String::new();
+            let read = stdin
+                .lock()
+                .read_line(&mut line)
+                .map_err(|e| format!("failed to read command - {}", e))?;
+            // End of input leaves the debugger like `exit` does
+            if read == 0 {
+                return Ok(Action::Exit);
+            }
+
+            match self.command(vm, &line) {
+                Ok(Some(action)) => return Ok(action),
+                Ok(None) => {}
+                Err(e) => println!("Error: {}", e),
+            }
+        }
+    }
+
+    /// Runs one command, returning the action when it resumes execution.
+    fn command(&mut self, vm: &mut Machine, line: &str) -> Result<Option<Action>, String> {
+        let words: Vec<&str> = line.split_whitespace().collect();
+        match words.as_slice() {
+            [] | ["n"] | ["c"] if self.post_mortem => {
+                return Err("the program has crashed, so nothing can run - q to quit".to_string());
+            }
+            [] | ["n"] => return Ok(Some(Action::Step)),
+            ["c"] => {
+                self.continuing = true;
+                return Ok(Some(Action::Continue));
+            }
+            ["exit"] | ["q"] => {
+                println!("Exiting manual mode.");
+                return Ok(Some(Action::Exit));
+            }
+            ["b"] if self.breakpoints.is_empty() => println!("No breakpoints"),
+            ["b"] => {
+                for address in &self.breakpoints {
+                    let line = format!("\t0x{:04X} {}", address, symbolize(*address, self.symbols));
+                    println!("{}", line.trim_end());
+                }
+            }
+            ["b", address] => {
+                let address = self.value(address)?;
+                self.breakpoints.insert(address);
+                println!("Breakpoint set at 0x{:04X}", address);
+            }
+            ["del", address] => {
+                let address = self.value(address)?;
+                if !self.breakpoints.remove(&address) {
+                    return Err(format!("no breakpoint at 0x{:04X}", address));
+                }
+            }
+            ["p", register] => {
+                let register = parse_register(register)?;
+                let value = vm.get_register(register);
+                println!("{:?} = 0x{:04X} ({})", register, value, value);
+            }
+            ["set", register, value] => {
+                let register = parse_register(register)?;
+                vm.registers[register as usize] = self.value(value)?;
+            }
+            ["x", address, len] => {
+                let start = self.value(address)?;
+                let end = start.saturating_add(self.value(len)?);
+                hexdump(&mut io::stdout().lock(), vm, start, end).map_err(|e| e.to_string())?;
+            }
+            ["d"] => self.disassemble(vm),
+            ["s"] => vm.print_intermediate_state().map_err(|e| e.to_string())?,
+            ["bt"] => write_backtrace(&mut io::stdout().lock(), vm, self.symbols)
+                .map_err(|e| e.to_string())?,
+            ["h"] | ["help"] => println!("{}", HELP),
+            _ => return Err(format!("unknown command `{}`, h for help", line.trim())),
+        }
+        Ok(None)
+    }
+
+    /// Evaluates an address or value, looking up symbol names.
+    fn value(&self, text: &str) -> Result<u16, String> {
+        let lookup = |name: &str| {
+            self.symbols
+                .iter()
+                .find(|(_, symbol)| *symbol == name)
+                .map(|(address, _)| *address as i64)
+        };
+        let value = expr::eval(text, &lookup)?;
+        u16::try_from(value).map_err(|_| format!("{} does not fit in 16 bits", text))
+    }
+
+    /// Prints the instructions around PC, marking PC and breakpoints.
+    fn disassemble(&self, vm: &Machine) {
+        let pc = vm.get_register(Register::PC);
+        let first = pc.saturating_sub(DISASSEMBLY_CONTEXT * 2);
+        let last = pc.saturating_add(DISASSEMBLY_CONTEXT * 2);
+        let mut address = first;
+        while address <= last {
+            if vm.memory.read(address).is_none() {
+                break;
+            }
+            let op = vm.decode_at(address);
+            let size = op.as_ref().map_or(2, Op::size);
+            let instruction = op
+                .map(|op| disassembler::instruction_for(&op, address).to_string())
+                .unwrap_or_else(|e| format!("<{}>", e));
+            let marker = match (address == pc, self.breakpoints.contains(&address)) {
+                (true, _) => "=>",
+                (false, true) => " *",
+                (false, false) => "  ",
+            };
+            if let Some(name) = self.symbols.get(&address) {
+                println!("{}:", name);
+            }
+            println!("{} {:04X}: {}", marker, address, instruction);
+            address = match address.checked_add(size) {
+                Some(next) => next,
+                None => break,
+            };
+        }
+    }
+}
+
+fn parse_register(name: &str) -> Result<Register, String> {
+    Register::from_str(&name.to_uppercase()).map_err(|_| format!("unknown register `{}`", name))
+}
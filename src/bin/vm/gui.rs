@@ -0,0 +1,132 @@
+//! Window front-end for the memory-mapped devices, built with the `gui` feature.
+//!
+//! The window shows the framebuffer and forwards key presses to the
+//! keyboard byte. It is pumped between instructions: once per frame it
+//! copies the framebuffer to the screen and collects the keys pressed since
+//! the last frame, so the program runs at full speed in between.
+
+use std::time::{Duration, Instant};
+
+use minifb::{Key, KeyRepeat, Scale, Window, WindowOptions};
+use rustyvm::{
+    Machine,
+    devices::{self, FRAMEBUFFER_HEIGHT, FRAMEBUFFER_WIDTH},
+};
+
+/// A window on the framebuffer, pumped between instructions.
+pub struct Gui {
+    window: Window,
+    frame: Duration,
+    next_frame: Instant,
+}
+
+impl Gui {
+    /// Opens the window, drawing `fps` frames per second.
+    pub fn open(fps: u32) -> Result<Self, String> {
+        let options = WindowOptions {
+            scale: Scale::X16,
+            ..WindowOptions::default()
+        };
+        let window = Window::new(
+            "Rusty 16-bit VM",
+            FRAMEBUFFER_WIDTH,
+            FRAMEBUFFER_HEIGHT,
+            options,
+        )
+        .map_err(|e| format!("failed to open the window - {}", e))?;
+        Ok(Self {
+            window,
+            frame: Duration::from_secs(1) / fps.max(1),
+            next_frame: Instant::now(),
+        })
+    }
+
+    /// Draws a frame and delivers key presses if one is due. Returns false
+    /// once the window has been closed.
+    pub fn pump(&mut self, vm: &mut Machine) -> Result<bool, String> {
+        let now = Instant::now();
+        if now < self.next_frame {
+            return Ok(true);
+        }
+        self.next_frame = now + self.frame;
+        self.draw(vm)
+    }
+
+    /// Keeps showing the final frame until the window is closed.
+    pub fn wait_for_close(&mut self, vm: &mut Machine) -> Result<(), String> {
+        while self.draw(vm)? {
+            std::thread::sleep(self.frame);
+        }
+        Ok(())
+    }
+
+    fn draw(&mut self, vm: &mut Machine) -> Result<bool, String> {
+        for key in self.window.get_keys_pressed(KeyRepeat::Yes) {
+            if let Some(code) = key_code(key) {
+                devices::press_key(vm, code);
+            }
+        }
+        self.window
+            .update_with_buffer(
+                &devices::framebuffer(vm),
+                FRAMEBUFFER_WIDTH,
+                FRAMEBUFFER_HEIGHT,
+            )
+            .map_err(|e| format!("failed to update the window - {}", e))?;
+        Ok(self.window.is_open())
+    }
+}
+
+/// Maps a key to the code stored in the keyboard byte: ASCII where there is
+/// one, the `devices::KEY_*` codes for arrows.
+fn key_code(key: Key) -> Option<u8> {
+    let code = match key {
+        Key::A => b'a',
+        Key::B => b'b',
+        Key::C => b'c',
+        Key::D => b'd',
+        Key::E => b'e',
+        Key::F => b'f',
+        Key::G => b'g',
+        Key::H => b'h',
+        Key::I => b'i',
+        Key::J => b'j',
+        Key::K => b'k',
+        Key::L => b'l',
+        Key::M => b'm',
+        Key::N => b'n',
+        Key::O => b'o',
+        Key::P => b'p',
+        Key::Q => b'q',
+        Key::R => b'r',
+        Key::S => b's',
+        Key::T => b't',
+        Key::U => b'u',
+        Key::V => b'v',
+        Key::W => b'w',
+        Key::X => b'x',
+        Key::Y => b'y',
+        Key::Z => b'z',
+        Key::Key0 => b'0',
+        Key::Key1 => b'1',
+        Key::Key2 => b'2',
+        Key::Key3 => b'3',
+        Key::Key4 => b'4',
+        Key::Key5 => b'5',
+        Key::Key6 => b'6',
+        Key::Key7 => b'7',
+        Key::Key8 => b'8',
+        Key::Key9 => b'9',
+        Key::Space => b' ',
+        Key::Enter => b'\n',
+        Key::Backspace => 0x08,
+        Key::Tab => b'\t',
+        Key::Escape => 0x1B,
+        Key::Up => devices::KEY_UP,
+        Key::Down => devices::KEY_DOWN,
+        Key::Left => devices::KEY_LEFT,
+        Key::Right => devices::KEY_RIGHT,
+        _ => return None,
+    };
+    Some(code)
+}
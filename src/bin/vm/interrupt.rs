@@ -0,0 +1,38 @@
+//! Ctrl-C handling, so an interrupted run can still save its snapshot.
+//!
+//! The standard library has no signal API, so on Unix the handler is
+//! installed through the C library's `signal` function. Elsewhere Ctrl-C
+//! keeps its default behaviour and ends the process.
+
+use std::sync::atomic::{AtomicBool, Ordering};
+
+/// Set by the handler, polled by the run loop between instructions.
+static INTERRUPTED: AtomicBool = AtomicBool::new(false);
+
+/// Returns true once Ctrl-C has been pressed.
+pub fn interrupted() -> bool {
+    INTERRUPTED.load(Ordering::Relaxed)
+}
+
+#[cfg(unix)]
+pub fn install() {
+    use std::ffi::c_int;
+
+    const SIGINT: c_int = 2;
+
+    unsafe extern "C" {
+        fn signal(signum: c_int, handler: extern "C" fn(c_int)) -> usize;
+    }
+
+    extern "C" fn on_interrupt(_: c_int) {
+        INTERRUPTED.store(true, Ordering::Relaxed);
+    }
+
+    // SAFETY: the handler only stores to an atomic, which is async-signal-safe
+    unsafe {
+        signal(SIGINT, on_interrupt);
+    }
+}
+
+#[cfg(not(unix))]
+pub fn install() {}
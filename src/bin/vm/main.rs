@@ -1,116 +1,1091 @@
 //! The main executable for the Rusty 16-bit VM.
 
+#[cfg(feature = "audio")]
+mod audio;
+mod debugger;
+#[cfg(feature = "gui")]
+mod gui;
+mod interrupt;
+mod trace_filter;
+
 use std::{
-    env,
+    cell::RefCell,
+    collections::BTreeMap,
+    env, fs,
     fs::File,
-    io::{BufReader, Read},
+    io::{self, BufReader, BufWriter, Read, Write},
     path::Path,
+    process,
+    rc::Rc,
+};
+
+use rustyvm::{
+    Executable, Machine, Op, PagedMemory, Profile, Program, Register,
+    args::pass_args,
+    asm::{self, AsmOptions, disassembler, expr},
+    coredump::{self, CoreDump},
+    devices, diff, halt, ihex,
+    isa::IsaProfile,
+    logging,
+    snapshot::Snapshot,
+    syscalls::{self, Permissions},
+    timing::{Pacer, Timing},
+    trace::{Recorder, json_string},
+    tracer::TextTracer,
 };
 
-use rustyvm::Machine;
+use crate::{
+    debugger::{Action, Debugger},
+    trace_filter::{FilteredTracer, TraceFilter},
+};
+
+/// Stand-in for the window front-end when built without the `gui` feature,
+/// so `--gui` explains how to get it.
+#[cfg(not(feature = "gui"))]
+mod gui {
+    use rustyvm::Machine;
+
+    pub struct Gui;
+
+    impl Gui {
+        pub fn open(_fps: u32) -> Result<Self, String> {
+            Err(
+                "this vm was built without the `gui` feature - rebuild with `--features gui`"
+                    .to_string(),
+            )
+        }
+
+        pub fn pump(&mut self, _vm: &mut Machine) -> Result<bool, String> {
+            Ok(true)
+        }
+
+        pub fn wait_for_close(&mut self, _vm: &mut Machine) -> Result<(), String> {
+            Ok(())
+        }
+    }
+}
+
+/// Stand-in for the speaker front-end when built without the `audio`
+/// feature, so `--audio` explains how to get it.
+#[cfg(not(feature = "audio"))]
+mod audio {
+    use rustyvm::devices::Beep;
+
+    pub struct Beeper;
+
+    impl Beeper {
+        pub fn open() -> Result<Self, String> {
+            Err(
+                "this vm was built without the `audio` feature - rebuild with `--features audio`"
+                    .to_string(),
+            )
+        }
+
+        pub fn play(&mut self, _beep: Beep) {}
+
+        pub fn finish(&mut self) {}
+    }
+}
+
+/// Window refresh rate unless `--fps` says otherwise.
+const DEFAULT_FPS: u32 = 60;
+
+/// Number of addresses listed in the `--profile` report.
+const HOTTEST_ADDRESSES: usize = 10;
+
+/// Parses a 16-bit address given as an option value, e.g. `0x0100` or `256`.
+fn parse_address(option: &str, value: Option<&String>) -> Result<u16, String> {
+    let value = value.ok_or_else(|| format!("{} requires an address", option))?;
+    let address = expr::eval(value, &|_| None).map_err(|e| format!("{}: {}", option, e))?;
+    u16::try_from(address).map_err(|_| format!("{}: {} is not a 16-bit address", option, value))
+}
+
+/// Opens the `--input` file for the `read` syscall, if one was given.
+fn open_input(path: Option<&str>) -> Result<Option<Box<dyn Read>>, String> {
+    let Some(path) = path else {
+        return Ok(None);
+    };
+    let file =
+        File::open(path).map_err(|e| format!("failed to open input file {} - {}", path, e))?;
+    Ok(Some(Box::new(BufReader::new(file))))
+}
+
+/// Parses a memory region given as `START..END`, with END exclusive.
+fn parse_range(option: &str, value: Option<&String>) -> Result<(u16, u16), String> {
+    let value = value.ok_or_else(|| format!("{} requires a range START..END", option))?;
+    let (start, end) = value
+        .split_once("..")
+        .ok_or_else(|| format!("{}: expected START..END, found {}", option, value))?;
+    let start = parse_address(option, Some(&start.to_string()))?;
+    let end = parse_address(option, Some(&end.to_string()))?;
+    if start > end {
+        return Err(format!("{}: range {} is reversed", option, value));
+    }
+    Ok((start, end))
+}
+
+/// Parses a clock rate in Hz, with an optional `k` or `M` suffix.
+fn parse_clock(value: Option<&String>) -> Result<u64, String> {
+    let value = value.ok_or("--clock requires a rate in Hz")?;
+    let (digits, scale) = match value.strip_suffix(['k', 'K']) {
+        Some(digits) => (digits, 1_000),
+        None => match value.strip_suffix('M') {
+            Some(digits) => (digits, 1_000_000),
+            None => (value.as_str(), 1),
+        },
+    };
+    digits
+        .parse::<u64>()
+        .ok()
+        .and_then(|hz| hz.checked_mul(scale))
+        .filter(|hz| *hz > 0)
+        .ok_or_else(|| format!("invalid --clock rate: {}", value))
+}
+
+/// Why execution stopped.
+enum Outcome {
+    /// The program raised the halt signal
+    Halted,
+    /// The user left manual mode
+    Exited,
+    /// `--max-steps` ran out before the program halted
+    StepLimit,
+    /// Ctrl-C stopped the run, with `--snapshot-out` set
+    Interrupted,
+    /// An instruction failed to decode or execute
+    Error(Fault, String),
+}
+
+/// Exit status when the arguments are wrong or the program cannot be loaded.
+const EXIT_USAGE: i32 = 1;
+/// Exit status when an instruction fails for a reason other than the ones below,
+/// such as an unknown signal.
+const EXIT_RUNTIME_ERROR: i32 = 122;
+/// Exit status when the program touches memory outside the address space.
+const EXIT_MEMORY_FAULT: i32 = 123;
+/// Exit status when `--max-steps` runs out, matching `timeout(1)`.
+const EXIT_STEP_LIMIT: i32 = 124;
+/// Exit status when the word at PC is not a valid instruction.
+const EXIT_DECODE_ERROR: i32 = 125;
+/// Exit status when Ctrl-C stopped the run, as for a shell's SIGINT.
+const EXIT_INTERRUPTED: i32 = 130;
+
+/// What kind of failure ended the run, for the exit status.
+#[derive(Clone, Copy)]
+enum Fault {
+    Decode,
+    Memory,
+    Runtime,
+}
+
+impl Fault {
+    /// Classifies the error from a failed step at `pc`. A failed decode
+    /// leaves the machine untouched, so decoding again tells the cases apart.
+    fn classify(vm: &Machine, pc: u16, error: &str) -> Self {
+        if vm.memory.read2(pc).is_none() {
+            Fault::Memory
+        } else if vm.decode_at(pc).is_err() {
+            Fault::Decode
+        } else if error.contains("fault")
+            || error.contains("stack underflow")
+            || error.contains("stack overflow")
+            || error.contains("PC overflow")
+        {
+            Fault::Memory
+        } else {
+            Fault::Runtime
+        }
+    }
+
+    fn exit_code(self) -> i32 {
+        match self {
+            Fault::Decode => EXIT_DECODE_ERROR,
+            Fault::Memory => EXIT_MEMORY_FAULT,
+            Fault::Runtime => EXIT_RUNTIME_ERROR,
+        }
+    }
+}
+
+impl Outcome {
+    /// Short name used for the `halt_reason` field of the JSON report.
+    fn reason(&self) -> &'static str {
+        match self {
+            Outcome::Halted => "halted",
+            Outcome::Exited => "exited",
+            Outcome::StepLimit => "step_limit",
+            Outcome::Interrupted => "interrupted",
+            Outcome::Error(..) => "error",
+        }
+    }
+}
+
+/// Writes a hexdump of `start..end`, 16 bytes per line with an ASCII column.
+/// Addresses past the end of memory are shown as `--`.
+fn hexdump(out: &mut dyn Write, vm: &Machine, start: u16, end: u16) -> io::Result<()> {
+    writeln!(out, "Memory 0x{:04X}..0x{:04X}:", start, end)?;
+    for line in (start as usize..end as usize).step_by(16) {
+        let addresses = line as u16..(line + 16).min(end as usize) as u16;
+        let bytes: Vec<Option<u8>> = addresses.map(|addr| vm.memory.read(addr)).collect();
+
+        let hex: Vec<String> = bytes
+            .iter()
+            .map(|byte| match byte {
+                Some(byte) => format!("{:02X}", byte),
+                None => "--".to_string(),
+            })
+            .collect();
+        let ascii: String = bytes
+            .iter()
+            .map(|byte| match byte {
+                Some(byte) if byte.is_ascii_graphic() || *byte == b' ' => *byte as char,
+                _ => '.',
+            })
+            .collect();
+        writeln!(out, "\t{:04X}: {:<47}  |{}|", line, hex.join(" "), ascii)?;
+    }
+    Ok(())
+}
+
+/// Writes the `--disassemble` listing of a program image loaded at
+/// `load_addr`: one line per word with its address, raw bytes and
+/// instruction, symbol names as label lines, and `=>` marking PC.
+fn disassemble(
+    out: &mut dyn Write,
+    image: &[u8],
+    load_addr: u16,
+    pc: u16,
+    symbols: &BTreeMap<u16, String>,
+) -> Result<(), String> {
+    // Executables are listed as one flat image from their lowest section
+    let (base, listing) = if Executable::is_executable(image) {
+        let (start, flat) = Executable::parse(image)?.flatten();
+        (start, disassembler::listing(&flat)?)
+    } else {
+        (0, disassembler::listing(image)?)
+    };
+
+    let write = |out: &mut dyn Write| -> io::Result<()> {
+        writeln!(out, "Disassembly:")?;
+        for line in &listing.lines {
+            let address = load_addr.wrapping_add(base).wrapping_add(line.address);
+            if let Some(name) = symbols.get(&address) {
+                writeln!(out, "{}:", name)?;
+            }
+            let marker = if address == pc { "=>" } else { "  " };
+            let raw: Vec<String> = line.bytes.iter().map(|b| format!("{:02X}", b)).collect();
+            writeln!(
+                out,
+                "{} {:04X}: {:<6} {}",
+                marker,
+                address,
+                raw.join(" "),
+                line.instruction
+            )?;
+        }
+        Ok(())
+    };
+    write(out).map_err(|e| format!("failed to write output - {}", e))
+}
+
+/// Writes the calls in progress, innermost first, named after their symbols.
+fn write_backtrace(
+    out: &mut dyn Write,
+    vm: &Machine,
+    symbols: &BTreeMap<u16, String>,
+) -> io::Result<()> {
+    writeln!(out, "Backtrace:")?;
+    for (depth, frame) in coredump::backtrace(vm).iter().enumerate() {
+        let line = format!(
+            "\t#{} 0x{:04X} {}",
+            depth,
+            frame,
+            symbolize(*frame, symbols)
+        );
+        writeln!(out, "{}", line.trim_end())?;
+    }
+    Ok(())
+}
+
+/// Names an address after the closest symbol at or below it, e.g. `loop+4`.
+fn symbolize(address: u16, symbols: &BTreeMap<u16, String>) -> String {
+    match symbols.range(..=address).next_back() {
+        Some((base, name)) if *base == address => name.clone(),
+        Some((base, name)) => format!("{}+{}", name, address - base),
+        None => String::new(),
+    }
+}
 
-/// Signal handler for the halt operation (signal code 0x09).
-/// Sets the VM's halt flag when executed.
-fn signal_halt(vm: &mut Machine) -> Result<(), String> {
-    // This function is called when the VM halts
-    // It can be used to perform any cleanup or final operations
-    vm.halt = true;
+/// Writes the `--profile` report: total instructions, the most executed addresses
+/// and how often each opcode ran.
+fn write_profile(
+    out: &mut dyn Write,
+    profile: &Profile,
+    symbols: &BTreeMap<u16, String>,
+) -> io::Result<()> {
+    let percent = |count: u64| count as f64 * 100.0 / profile.steps.max(1) as f64;
+
+    writeln!(out, "Profile: {} instructions", profile.steps)?;
+
+    let mut hottest: Vec<(&u16, &u64)> = profile.addresses.iter().collect();
+    hottest.sort_by(|a, b| b.1.cmp(a.1).then(a.0.cmp(b.0)));
+    writeln!(out, "Hottest addresses:")?;
+    for (address, count) in hottest.into_iter().take(HOTTEST_ADDRESSES) {
+        writeln!(
+            out,
+            "\t{:04X} {:<20} {:>8} ({:5.1}%)",
+            address,
+            symbolize(*address, symbols),
+            count,
+            percent(*count)
+        )?;
+    }
+
+    let mut opcodes: Vec<(&u8, &u64)> = profile.opcodes.iter().collect();
+    opcodes.sort_by(|a, b| b.1.cmp(a.1).then(a.0.cmp(b.0)));
+    writeln!(out, "Opcodes:")?;
+    for (opcode, count) in opcodes {
+        // Operands of 0 decode for every opcode, which is enough to get its mnemonic
+        let mnemonic = Op::from_bytes(&[*opcode, 0, 0, 0])
+            .map(|op| disassembler::instruction_for(&op, 0).to_string())
+            .ok()
+            .and_then(|text| text.split_whitespace().next().map(str::to_string))
+            .unwrap_or_else(|| format!("0x{:02X}", opcode));
+        writeln!(
+            out,
+            "\t{:<20} {:>8} ({:5.1}%)",
+            mnemonic,
+            count,
+            percent(*count)
+        )?;
+    }
     Ok(())
 }
 
+/// Writes the final machine state as a single JSON object.
+fn write_json(
+    out: &mut dyn Write,
+    vm: &Machine,
+    outcome: &Outcome,
+    steps: u64,
+    regions: &[(u16, u16)],
+) -> io::Result<()> {
+    let registers: Vec<String> = Register::ALL
+        .iter()
+        .map(|reg| format!("\"{}\": {}", reg, vm.registers[*reg as usize]))
+        .collect();
+    let flags = vm.get_register(Register::FLAGS);
+    let error = match outcome {
+        Outcome::Error(_, e) => json_string(e),
+        _ => "null".to_string(),
+    };
+
+    writeln!(out, "{{")?;
+    writeln!(out, "  \"halt_reason\": \"{}\",", outcome.reason())?;
+    writeln!(out, "  \"error\": {},", error)?;
+    writeln!(out, "  \"steps\": {},", steps)?;
+    writeln!(out, "  \"cycles\": {},", vm.cycles)?;
+    writeln!(out, "  \"registers\": {{ {} }},", registers.join(", "))?;
+    write!(
+        out,
+        "  \"flags\": {{ \"value\": {}, \"bits\": \"{:08b}\" }}",
+        flags, flags
+    )?;
+    if !regions.is_empty() {
+        let regions: Vec<String> = regions
+            .iter()
+            .map(|&(start, end)| {
+                let bytes: Vec<String> = (start..end)
+                    // Addresses past the end of memory read as null
+                    .map(|addr| match vm.memory.read(addr) {
+                        Some(byte) => byte.to_string(),
+                        None => "null".to_string(),
+                    })
+                    .collect();
+                format!(
+                    "{{ \"start\": {}, \"bytes\": [{}] }}",
+                    start,
+                    bytes.join(", ")
+                )
+            })
+            .collect();
+        write!(out, ",\n  \"memory\": [{}]", regions.join(", "))?;
+    }
+    writeln!(out, "\n}}")
+}
+
 /// The main entry point for the VM runner application.
+/// Exits with the program's exit code when it halts, and with one of the
+/// `EXIT_*` codes when it fails.
+fn main() {
+    match run() {
+        Ok(code) => process::exit(code),
+        Err(e) => {
+            eprintln!("Error: {}", e);
+            process::exit(EXIT_USAGE);
+        }
+    }
+}
+
+/// Opens a core dump written by `--core-dump`: shows why the program
+/// failed and where, then hands over to the debugger prompt so memory and
+/// registers can be examined. Nothing can be executed.
+fn post_mortem(
+    vm: &mut Machine,
+    path: &str,
+    symbols: &BTreeMap<u16, String>,
+) -> Result<i32, String> {
+    let bytes =
+        fs::read(path).map_err(|e| format!("failed to read core dump from {} - {}", path, e))?;
+    let core = CoreDump::parse(&bytes)?;
+    vm.restore(&core.snapshot)?;
+
+    let pc = vm.get_register(Register::PC);
+    let instruction = match (vm.memory.read2(pc), vm.decode_at(pc)) {
+        (Some(_), Ok(op)) => disassembler::instruction_for(&op, pc).to_string(),
+        (Some(word), Err(_)) => format!("<invalid word 0x{:04X}>", word),
+        (None, _) => "<outside memory>".to_string(),
+    };
+    println!("Core dump: {}", core.error);
+    let line = format!(
+        "Failed at 0x{:04X}: {} {}",
+        pc,
+        instruction,
+        symbolize(pc, symbols)
+    );
+    println!("{}", line.trim_end());
+    vm.print_intermediate_state()
+        .map_err(|e| format!("failed to write output - {}", e))?;
+    write_backtrace(&mut io::stdout().lock(), vm, symbols)
+        .map_err(|e| format!("failed to write output - {}", e))?;
+
+    println!("Post-mortem mode: type h for the list of commands, q to quit.");
+    let mut debugger = Debugger::post_mortem(symbols);
+    while !matches!(debugger.before_step(vm)?, Action::Exit) {}
+    Ok(0)
+}
+
 /// Creates VM, loads program, executes until completion, and displays state.
-fn main() -> Result<(), String> {
+/// Returns the process exit status.
+fn run() -> Result<i32, String> {
     let mut vm = Machine::new();
     // Register the halt signal handler for signal code 0x09
-    vm.define_handler(0x09, signal_halt);
+    vm.define_handler(0x09, halt);
 
     let mut manual_mode = false;
+    // `-v`, `-vv` and `-vvv` log more and more of the run to stderr
+    let mut verbosity = 0;
+    // `--trace` prints to stdout, `--trace=file` writes to a file
+    let mut trace: Option<Box<dyn Write>> = None;
+    // `--trace-op`, `--trace-range` and `--trace-reg` narrow down the trace
+    let mut trace_filter = TraceFilter::default();
+    let mut trace_filtered = false;
+    // Number of instructions to run before giving up on a missing halt
+    let mut max_steps: Option<u64> = None;
+    // Where the image is placed in memory, and an optional PC override
+    let mut load_addr: Option<u16> = None;
+    let mut entry: Option<u16> = None;
+    // `--output json` replaces the printed state with a JSON report on stdout
+    let mut json = false;
+    // `--diff` runs the program on linear and paged memory side by side
+    let mut differential = false;
+    // `--verify-determinism` runs the program twice and compares the runs
+    let mut verify_determinism = false;
+    let mut trace_to_stdout = false;
+    // Memory regions shown after the run, from `--dump-memory START..END`
+    let mut regions: Vec<(u16, u16)> = Vec::new();
+    // `--profile` prints execution counters, named after `--symbols` when given
+    let mut show_profile = false;
+    // `--coverage file` writes the executed addresses after the run
+    let mut coverage_path: Option<&str> = None;
+    // `--record file` writes a binary trace of every step for `vmtrace`
+    let mut record_path: Option<&str> = None;
+    let mut symbols: BTreeMap<u16, String> = BTreeMap::new();
+    // `--asm` assembles the input in memory first, searching `-I` directories for
+    // includes; `.asm` and `.s` files are assembled without it
+    let mut assemble = false;
+    let mut asm_options = AsmOptions::default();
+    // `--snapshot-in` resumes a saved state instead of loading a program,
+    // `--snapshot-out` saves the state when the run stops
+    let mut snapshot_in: Option<&str> = None;
+    let mut snapshot_out: Option<&str> = None;
+    // `--core-dump` saves the machine if an instruction fails, `--core`
+    // opens such a dump in the debugger
+    let mut core_dump_path: Option<&str> = None;
+    let mut core_path: Option<&str> = None;
+    // `--gui` opens a window on the framebuffer, refreshed `--fps` times a second
+    let mut show_window = false;
+    let mut fps = DEFAULT_FPS;
+    // `--audio` plays the beeper on the host's speakers
+    let mut play_audio = false;
+    // `--keys TEXT` types ahead on the keyboard, one key per acknowledgement
+    let mut keys = devices::KeyQueue::default();
+    // `--clock HZ` paces the run to a clock rate in host time
+    let mut clock: Option<u64> = None;
+    // `--input FILE` feeds the `read` syscall from a file instead of stdin
+    let mut input_path: Option<&str> = None;
+    // `--disassemble` lists the loaded program before running it
+    let mut show_disassembly = false;
+    // Everything after `--` is passed to the program, see `rustyvm::args`
+    let mut program_args: &[String] = &[];
+    // `--allow-env` and `--allow-time` open up the host syscalls, and
+    // `--allow-path` the file syscalls
+    let mut permissions = Permissions::default();
 
     // ----------------------------------------------------------------
     // Load program from the specified file
 
     let args: Vec<_> = env::args().collect();
     if args.len() < 2 {
-        return Err(format!("Usage: {} <input> [options...]", args[0]));
+        return Err(format!(
+            "Usage: {0} <input> [options...] [-- <args...>]\n       {0} --snapshot-in <file> [options...]\n       {0} --core <file> [--symbols <file>]",
+            args[0]
+        ));
     }
 
-    // Check for manual mode option
-    if args.len() > 2 {
-        for arg in &args[2..] {
-            match arg.as_str() {
-                "-m" | "--manual" => {
-                    manual_mode = true;
-                }
-                _ => {
-                    return Err(format!("Unknown option: {}", arg));
+    // The input comes first, unless the run resumes from a snapshot or core dump
+    let (input, options) = match args[1].as_str() {
+        "--snapshot-in" | "--core" => (None, &args[1..]),
+        _ => (Some(&args[1]), &args[2..]),
+    };
+
+    // Check for options
+    let mut args_iter = options.iter();
+    while let Some(arg) = args_iter.next() {
+        match arg.as_str() {
+            "--" => {
+                program_args = args_iter.as_slice();
+                break;
+            }
+            "-v" | "-vv" | "-vvv" => verbosity = arg.len() as u8 - 1,
+            "-m" | "--manual" => {
+                manual_mode = true;
+            }
+            "--trace" => {
+                trace = Some(Box::new(io::stdout()));
+                trace_to_stdout = true;
+            }
+            option if option.starts_with("--trace=") => {
+                let path = &option["--trace=".len()..];
+                let file = File::create(path)
+                    .map_err(|e| format!("failed to create trace file {} - {}", path, e))?;
+                trace = Some(Box::new(BufWriter::new(file)));
+            }
+            "--trace-op" => {
+                let classes = args_iter
+                    .next()
+                    .ok_or("--trace-op requires an instruction class")?;
+                trace_filter.add_classes(classes)?;
+                trace_filtered = true;
+            }
+            "--trace-range" => {
+                let (start, end) = parse_range(arg, args_iter.next())?;
+                trace_filter.add_range(start, end);
+                trace_filtered = true;
+            }
+            "--trace-reg" => {
+                let register = args_iter.next().ok_or("--trace-reg requires a register")?;
+                trace_filter.add_register(register)?;
+                trace_filtered = true;
+            }
+            "--max-steps" => {
+                let value = args_iter
+                    .next()
+                    .ok_or("--max-steps requires a step count")?;
+                let steps = value
+                    .parse()
+                    .map_err(|_| format!("invalid --max-steps value: {}", value))?;
+                max_steps = Some(steps);
+            }
+            "--load-addr" => load_addr = Some(parse_address(arg, args_iter.next())?),
+            "--entry" => entry = Some(parse_address(arg, args_iter.next())?),
+            "--output" => match args_iter.next().map(String::as_str) {
+                Some("json") => json = true,
+                Some("text") => json = false,
+                _ => return Err("--output expects `text` or `json`".to_string()),
+            },
+            "--dump-memory" => regions.push(parse_range(arg, args_iter.next())?),
+            "--profile" => show_profile = true,
+            "--disassemble" => show_disassembly = true,
+            "--allow-env" => permissions.env = true,
+            "--allow-time" => permissions.time = true,
+            "--allow-path" => {
+                let dir = args_iter
+                    .next()
+                    .ok_or("--allow-path requires a directory")?;
+                vm.files
+                    .allow(dir)
+                    .map_err(|e| format!("--allow-path: {}", e))?;
+            }
+            "--clock" => clock = Some(parse_clock(args_iter.next())?),
+            "--timing" => {
+                let timing = args_iter.next().ok_or("--timing requires cycle costs")?;
+                vm.timing = Timing::parse(timing).map_err(|e| format!("--timing: {}", e))?;
+            }
+            "--isa" => {
+                let profile = args_iter.next().ok_or("--isa requires a profile")?;
+                vm.isa = IsaProfile::parse(profile)?;
+            }
+            "--diff" => differential = true,
+            "--verify-determinism" => verify_determinism = true,
+            "--asm" => assemble = true,
+            "-I" => {
+                let dir = args_iter.next().ok_or("-I requires a directory")?;
+                asm_options.include_dirs.push(dir.into());
+            }
+            option if option.starts_with("-I") => asm_options.include_dirs.push(option[2..].into()),
+            "--define" => {
+                let define = args_iter.next().ok_or("--define requires NAME[=VALUE]")?;
+                let (name, value) = asm::parse_define(define, &asm_options.defines)?;
+                asm_options.defines.insert(name, value);
+            }
+            "--coverage" => {
+                let path = args_iter.next().ok_or("--coverage requires a file")?;
+                coverage_path = Some(path);
+            }
+            "--gui" => show_window = true,
+            "--keys" => keys.push_str(args_iter.next().ok_or("--keys requires the keys to type")?),
+            "--input" => {
+                let path = args_iter.next().ok_or("--input requires a file")?;
+                input_path = Some(path);
+            }
+            "--audio" => play_audio = true,
+            "--fps" => {
+                let value = args_iter.next().ok_or("--fps requires a frame rate")?;
+                fps = value
+                    .parse()
+                    .ok()
+                    .filter(|fps| *fps > 0)
+                    .ok_or_else(|| format!("invalid --fps value: {}", value))?;
+            }
+            "--snapshot-in" => {
+                let path = args_iter.next().ok_or("--snapshot-in requires a file")?;
+                snapshot_in = Some(path);
+            }
+            "--snapshot-out" => {
+                let path = args_iter.next().ok_or("--snapshot-out requires a file")?;
+                snapshot_out = Some(path);
+            }
+            "--core-dump" => {
+                let path = args_iter.next().ok_or("--core-dump requires a file")?;
+                core_dump_path = Some(path);
+            }
+            "--core" => {
+                let path = args_iter.next().ok_or("--core requires a file")?;
+                core_path = Some(path);
+            }
+            "--record" => {
+                let path = args_iter.next().ok_or("--record requires a file")?;
+                record_path = Some(path);
+            }
+            "--symbols" => {
+                let path = args_iter.next().ok_or("--symbols requires a file")?;
+                let text = fs::read_to_string(path)
+                    .map_err(|e| format!("failed to read symbols from {} - {}", path, e))?;
+                for (name, address) in asm::parse_symbols(&text)? {
+                    symbols.entry(address).or_insert(name);
                 }
             }
+            _ => {
+                return Err(format!("Unknown option: {}", arg));
+            }
         }
     }
 
-    let file: File = match File::open(Path::new(&args[1])) {
-        Err(e) => {
-            return Err(format!("failed to open the file, err - {}", e));
+    logging::init(verbosity)?;
+    syscalls::install(&mut vm, permissions);
+
+    if let Some(path) = core_path {
+        let only_symbols = options
+            .chunks(2)
+            .all(|pair| matches!(pair[0].as_str(), "--core" | "--symbols"));
+        if input.is_some() || !only_symbols {
+            return Err(
+                "--core opens the dump in the debugger, so it can only be combined with --symbols"
+                    .to_string(),
+            );
         }
-        Ok(f) => f,
-    };
+        return post_mortem(&mut vm, path, &symbols);
+    }
 
-    let mut buffer: Vec<u8> = Vec::new();
-    let mut reader = BufReader::new(file);
+    if differential && (manual_mode || json) {
+        return Err("--diff cannot be combined with --manual or --output json".to_string());
+    }
+    if verify_determinism && (manual_mode || json || differential || show_window) {
+        return Err(
+            "--verify-determinism cannot be combined with --manual, --output json, --diff or --gui"
+                .to_string(),
+        );
+    }
+    if show_window && manual_mode {
+        return Err("--gui cannot be combined with --manual".to_string());
+    }
+    if json && (manual_mode || trace_to_stdout || show_disassembly) {
+        return Err(
+            "--output json cannot be combined with --manual, --disassemble or --trace to stdout"
+                .to_string(),
+        );
+    }
 
-    let r = reader.read_to_end(&mut buffer);
-    match r {
-        Ok(_) => println!("Program: read successfully!"),
-        Err(e) => panic!("Error: cannot read, err = {e}"),
+    // Keep stdout for the JSON report alone
+    if json {
+        vm.output = Box::new(io::stderr());
+    }
+    if let Some(input) = open_input(input_path)? {
+        vm.input = input;
     }
 
-    // Load the program into memory at address 0
-    if let Some((bytes, instructions)) = vm.memory.load_from_vec(&buffer, 0) {
-        println!(
-            "Program: loaded {} bytes ({} instructions)",
-            bytes, instructions
-        );
-        println!("Program: running loaded program...");
-    }
-
-    // Execute instructions until halted or error occurs
-    while !vm.halt {
-        match vm.step() {
-            Ok(_) => {
-                // get user input, if he or she in the manual mode
-                // then each iteration will wait for user input,
-                // if they pass y/Y/YES/yes then it will step another step
-                // if not then it will print state, then ask again, until use passes X/x/EXIT/exit
-                // then it will close the program or if it hit HALT then it will close with non-zero exit code
-                if manual_mode {
-                    println!(
-                        "Press Enter to step, enter 's' to print state, or type 'exit' to quit..."
-                    );
-                    let mut input = String::new();
-                    std::io::stdin().read_line(&mut input).unwrap();
-                    let trimmed_input = input.trim().to_lowercase();
-                    if trimmed_input == "exit" {
-                        println!("Exiting manual mode.");
-                        break;
-                    }
-                    if trimmed_input == "s" {
-                        vm.print_intermediate_state();
-                    }
-                    continue;
+    if trace_filtered && trace.is_none() && !manual_mode {
+        return Err("--trace-op, --trace-range and --trace-reg require --trace".to_string());
+    }
+
+    // Manual mode shows each instruction as it runs
+    if manual_mode && trace.is_none() {
+        trace = Some(Box::new(io::stdout()));
+    }
+
+    if let Some(path) = snapshot_in {
+        if input.is_some()
+            || assemble
+            || load_addr.is_some()
+            || entry.is_some()
+            || show_disassembly
+            || !program_args.is_empty()
+        {
+            return Err(
+                "--snapshot-in replaces the program, so it cannot be combined with an input, --asm, --disassemble, --load-addr, --entry or program arguments"
+                    .to_string(),
+            );
+        }
+        let bytes =
+            fs::read(path).map_err(|e| format!("failed to read snapshot from {} - {}", path, e))?;
+        vm.restore(&Snapshot::parse(&bytes)?)?;
+        if !json {
+            println!(
+                "Program: resumed from {} at PC=0x{:04X}",
+                path,
+                vm.get_register(Register::PC)
+            );
+        }
+    } else {
+        let input = input.ok_or("no program given")?;
+        // `-` reads the program from stdin, e.g. `asm prog.asm | vm -`
+        let from_stdin = input == "-";
+        let extension = Path::new(input).extension().and_then(|e| e.to_str());
+        if matches!(extension, Some("asm" | "s")) {
+            assemble = true;
+        }
+        if from_stdin && manual_mode {
+            return Err(
+                "manual mode reads its commands from stdin, so the program cannot come from stdin"
+                    .to_string(),
+            );
+        }
+        let file: Box<dyn Read> = if from_stdin {
+            Box::new(io::stdin())
+        } else {
+            match File::open(Path::new(&input)) {
+                Err(e) => {
+                    return Err(format!("failed to open the file, err - {}", e));
                 }
-                continue; // continue in automatic mode
+                Ok(f) => Box::new(f),
+            }
+        };
+
+        let mut buffer: Vec<u8> = Vec::new();
+        let mut reader = BufReader::new(file);
+
+        reader
+            .read_to_end(&mut buffer)
+            .map_err(|e| format!("cannot read the program - {}", e))?;
+
+        if assemble {
+            let source =
+                String::from_utf8(buffer).map_err(|_| "assembly source is not valid text")?;
+            if !from_stdin {
+                asm_options.source_dir = Path::new(&input).parent().map(Path::to_path_buf);
+            }
+            let assembly = asm::assemble(&source, &asm_options).map_err(|e| e.to_string())?;
+            for warning in &assembly.warnings {
+                eprintln!("warning: {}", warning);
             }
-            Err(e) => {
-                println!("Error during execution: {}", e);
-                return Err(e);
+            // Labels name the profile's addresses unless --symbols gave names already
+            for (name, address) in assembly.symbols {
+                symbols.entry(address).or_insert(name);
+            }
+            buffer = assembly.bytecode;
+        }
+
+        // Intel HEX files carry their own load address, which --load-addr overrides
+        if ihex::is_ihex(&buffer) {
+            let text = String::from_utf8(buffer).map_err(|_| "Intel HEX file is not valid text")?;
+            let image = ihex::decode(&text)?;
+            load_addr = load_addr.or(Some(image.address));
+            buffer = match image.entry {
+                Some(start) => Program::encode(start.wrapping_sub(image.address), &image.bytes),
+                None => image.bytes,
+            };
+        }
+        let load_addr = load_addr.unwrap_or(0);
+
+        // Load the program into memory, starting at its entry point
+        let (bytes, instructions) = vm.load_program_at(&buffer, load_addr, entry)?;
+        let program_args: Vec<&str> = program_args.iter().map(String::as_str).collect();
+        pass_args(&mut vm, &program_args)?;
+        if !json {
+            println!(
+                "Program: loaded {} bytes ({} instructions) at 0x{:04X}, entry 0x{:04X}",
+                bytes,
+                instructions,
+                load_addr,
+                vm.get_register(Register::PC)
+            );
+            if show_disassembly {
+                let pc = vm.get_register(Register::PC);
+                disassemble(&mut io::stdout().lock(), &buffer, load_addr, pc, &symbols)?;
             }
         }
     }
 
-    // Print the final state
-    vm.print_final_state();
+    if differential {
+        let mut paged = Machine::with_memory(Box::new(PagedMemory::new(vm.memory.size())));
+        paged.define_handler(0x09, halt);
+        syscalls::install(&mut paged, permissions);
+        paged.restore(&vm.snapshot())?;
+        paged.isa = vm.isa.clone();
+        for root in vm.files.roots() {
+            paged.files.allow(root)?;
+        }
+        // The program's output is shown once, from the linear run
+        paged.output = Box::new(io::sink());
+        // Both machines read the same input, and none from the terminal
+        vm.input = open_input(input_path)?.unwrap_or_else(|| Box::new(io::empty()));
+        paged.input = open_input(input_path)?.unwrap_or_else(|| Box::new(io::empty()));
+
+        let (divergence, steps) =
+            diff::lockstep(&mut vm, &mut paged, max_steps.unwrap_or(u64::MAX));
+        return match divergence {
+            None => {
+                println!("Diff: linear and paged memory agree after {} steps", steps);
+                Ok(0)
+            }
+            Some(divergence) => Err(format!("linear and paged memory diverge at {}", divergence)),
+        };
+    }
 
-    // Successful execution
-    Ok(())
+    if verify_determinism {
+        let initial = vm.snapshot();
+        let mut runs = Vec::new();
+        for _ in 0..2 {
+            let mut run = Machine::new();
+            run.define_handler(0x09, halt);
+            syscalls::install(&mut run, permissions);
+            run.restore(&initial)?;
+            run.isa = vm.isa.clone();
+            for root in vm.files.roots() {
+                run.files.allow(root)?;
+            }
+            run.output = Box::new(io::sink());
+            run.input = open_input(input_path)?.unwrap_or_else(|| Box::new(io::empty()));
+            runs.push(diff::record_run(&mut run, max_steps.unwrap_or(u64::MAX)));
+        }
+        let [(first, first_trace), (second, second_trace)] = <[_; 2]>::try_from(runs).unwrap();
+        if first == second {
+            println!(
+                "Determinism: 2 runs agree after {} steps (trace {:016x}, state {:016x})",
+                first.steps, first.trace_hash, first.state_hash
+            );
+            return Ok(0);
+        }
+        return Err(match diff::first_difference(&first_trace, &second_trace) {
+            Some(step) => format!(
+                "the program is not deterministic - runs diverge at step {}",
+                step
+            ),
+            None => "the program is not deterministic - runs end in different states".to_string(),
+        });
+    }
+
+    // Coverage is read from the same counters as the profile
+    if show_profile || coverage_path.is_some() {
+        vm.profile = Some(Profile::default());
+    }
+
+    let mut recorder = record_path.map(|_| Recorder::attach(&mut vm));
+    // Kept to flush the trace once the run is over
+    let tracer = trace.map(|out| {
+        let tracer = Rc::new(RefCell::new(TextTracer::new(out)));
+        vm.add_hook(FilteredTracer::new(trace_filter, tracer.clone()));
+        tracer
+    });
+    // Ctrl-C stops the run cleanly so the snapshot can still be written
+    if snapshot_out.is_some() {
+        interrupt::install();
+    }
+    let mut window = show_window.then(|| gui::Gui::open(fps)).transpose()?;
+    let mut beeper = play_audio.then(audio::Beeper::open).transpose()?;
+    let mut debugger = manual_mode.then(|| {
+        println!("Manual mode: press Enter to step, or type h for the list of commands.");
+        Debugger::new(&symbols)
+    });
+
+    let pacer = clock.map(|hz| Pacer::new(&vm, hz));
+
+    // Execute instructions until halted, the step budget runs out, or an error occurs
+    let _run = logging::run_span(input.map(String::as_str).or(snapshot_in).unwrap_or("-"));
+    let mut steps: u64 = 0;
+    let mut core_dump = None;
+    let outcome = loop {
+        if vm.halt {
+            break Outcome::Halted;
+        }
+        if max_steps.is_some_and(|max| steps >= max) {
+            break Outcome::StepLimit;
+        }
+        if interrupt::interrupted() {
+            break Outcome::Interrupted;
+        }
+        keys.pump(&mut vm);
+        if let Some(pacer) = &pacer {
+            pacer.pace(&vm);
+        }
+
+        // In manual mode the prompt comes before each instruction, except
+        // while a `c` runs towards the next breakpoint
+        if let Some(debugger) = debugger.as_mut()
+            && let Action::Exit = debugger.before_step(&mut vm)?
+        {
+            break Outcome::Exited;
+        }
+        steps += 1;
+
+        let pc = vm.get_register(Register::PC);
+        let result = vm.step();
+        if let Some(recorder) = recorder.as_mut() {
+            recorder.record(&vm, pc);
+        }
+
+        if let Err(e) = result {
+            if core_dump_path.is_some() {
+                core_dump = Some(CoreDump::capture(&vm, pc, &e));
+            }
+            break Outcome::Error(Fault::classify(&vm, pc, &e), e);
+        }
+
+        // Closing the window ends the run like leaving manual mode
+        if let Some(window) = window.as_mut()
+            && !window.pump(&mut vm)?
+        {
+            break Outcome::Exited;
+        }
+        if let Some(beeper) = beeper.as_mut()
+            && let Some(beep) = devices::take_beep(&mut vm)
+        {
+            beeper.play(beep);
+        }
+    };
+    logging::run_finished(steps, outcome.reason());
+
+    if let Some(tracer) = tracer {
+        let mut tracer = tracer.borrow_mut();
+        if let Some(e) = tracer.error() {
+            return Err(e.to_string());
+        }
+        tracer
+            .flush()
+            .map_err(|e| format!("failed to write trace - {}", e))?;
+    }
+
+    // Report the final state
+    if json {
+        write_json(&mut io::stdout().lock(), &vm, &outcome, steps, &regions)
+            .map_err(|e| format!("failed to write output - {}", e))?;
+    } else {
+        if let Outcome::Error(_, e) = &outcome {
+            println!("Error during execution: {}", e);
+        } else {
+            vm.print_final_state()
+                .map_err(|e| format!("failed to write output - {}", e))?;
+        }
+        for &(start, end) in &regions {
+            hexdump(&mut io::stdout().lock(), &vm, start, end)
+                .map_err(|e| format!("failed to write output - {}", e))?;
+        }
+    }
+
+    // Let the last beeps finish before the process exits
+    if let Some(beeper) = beeper.as_mut() {
+        beeper.finish();
+    }
+
+    if let Some(window) = window.as_mut()
+        && !matches!(outcome, Outcome::Exited)
+    {
+        println!("Close the window to exit.");
+        window.wait_for_close(&mut vm)?;
+    }
+
+    // A failed instruction leaves nothing worth resuming
+    if let Some(path) = snapshot_out
+        && !matches!(outcome, Outcome::Error(..))
+    {
+        fs::write(path, vm.snapshot().encode()?)
+            .map_err(|e| format!("failed to write snapshot to {} - {}", path, e))?;
+        if !json {
+            println!("Snapshot: saved to {}", path);
+        }
+    }
+
+    if let (Some(path), Some(core_dump)) = (core_dump_path, core_dump) {
+        fs::write(path, core_dump.encode()?)
+            .map_err(|e| format!("failed to write core dump to {} - {}", path, e))?;
+        if !json {
+            println!("Core dump: saved to {}, open it with --core", path);
+        }
+    }
+
+    if let (Some(path), Some(recorder)) = (record_path, recorder) {
+        fs::write(path, recorder.finish().encode())
+            .map_err(|e| format!("failed to write trace to {} - {}", path, e))?;
+    }
+
+    if let (Some(path), Some(counters)) = (coverage_path, &vm.profile) {
+        fs::write(path, counters.format_coverage())
+            .map_err(|e| format!("failed to write coverage to {} - {}", path, e))?;
+    }
+
+    // The profile goes to stderr in JSON mode so stdout stays a single object
+    if let Some(profile) = vm.profile.as_ref().filter(|_| show_profile) {
+        let result = if json {
+            write_profile(&mut io::stderr().lock(), profile, &symbols)
+        } else {
+            write_profile(&mut io::stdout().lock(), profile, &symbols)
+        };
+        result.map_err(|e| format!("failed to write output - {}", e))?;
+    }
+
+    // A halted program's exit code is register A, truncated to the 8 bits a process can return
+    match outcome {
+        Outcome::Halted => Ok((vm.get_register(Register::A) & 0xFF) as i32),
+        Outcome::Exited => Ok(0),
+        Outcome::StepLimit => {
+            eprintln!(
+                "Error: step budget exhausted after {} steps (PC=0x{:04X}) - the program never raised the halt signal",
+                steps,
+                vm.get_register(Register::PC)
+            );
+            Ok(EXIT_STEP_LIMIT)
+        }
+        Outcome::Interrupted => {
+            eprintln!(
+                "Interrupted after {} steps (PC=0x{:04X})",
+                steps,
+                vm.get_register(Register::PC)
+            );
+            Ok(EXIT_INTERRUPTED)
+        }
+        Outcome::Error(fault, e) => {
+            eprintln!("Error: {}", e);
+            Ok(fault.exit_code())
+        }
+    }
 } // end of main
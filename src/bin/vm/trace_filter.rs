@@ -0,0 +1,209 @@
+//! Filters for `--trace`, so traces of long runs only show the steps of
+//! interest.
+//!
+//! Options of the same kind widen the filter, options of different kinds
+//! narrow it: `--trace-op stack --trace-op jump --trace-reg A` shows stack
+//! operations and jumps, but only those that changed A.
+
+use rustyvm::{Hook, Machine, Op, Register, tracer::Tracer};
+
+/// Groups of instructions that `--trace-op` selects by name.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OpClass {
+    /// PUSH, PUSHR, POP, DUP, SWAP, DROP, OVER, PUSHF and POPF
+    Stack,
+    /// ADDS, ADDR, SUBS, SUBR, CMP, TEST and MULW, and the AND, OR, XOR and NOT forms
+    Arith,
+    /// MOV, CMOVZ, CMOVNZ and LDI into any register but PC
+    Move,
+    /// JMP, JMPL, JMPR, BRA, the conditional jumps and branches, and MOV, CMOVZ, CMOVNZ and LDI into PC
+    Jump,
+    /// LOAD and STORE, their LOADR, STORER, LOADO and STOREO forms, MEMCPY and MEMSET
+    Memory,
+    /// SIG and SYSCALL
+    Signal,
+    /// NOP
+    Nop,
+}
+
+impl OpClass {
+    const NAMES: [(&'static str, OpClass); 7] = [
+        ("stack", OpClass::Stack),
+        ("arith", OpClass::Arith),
+        ("move", OpClass::Move),
+        ("jump", OpClass::Jump),
+        ("memory", OpClass::Memory),
+        ("signal", OpClass::Signal),
+        ("nop", OpClass::Nop),
+    ];
+
+    fn parse(name: &str) -> Result<Self, String> {
+        Self::NAMES
+            .iter()
+            .find(|(known, _)| *known == name)
+            .map(|(_, class)| *class)
+            .ok_or_else(|| {
+                let names: Vec<&str> = Self::NAMES.iter().map(|(name, _)| *name).collect();
+                format!(
+                    "unknown instruction class `{}`, expected one of {}",
+                    name,
+                    names.join(", ")
+                )
+            })
+    }
+
+    fn of(op: &Op) -> Self {
+        match op {
+            Op::Nop => OpClass::Nop,
+            Op::Push(_)
+            | Op::PopRegister(_)
+            | Op::PushRegister(_)
+            | Op::Dup
+            | Op::Swap
+            | Op::Drop
+            | Op::Over
+            | Op::PushFlags
+            | Op::PopFlags => OpClass::Stack,
+            Op::AddStack
+            | Op::AddRegister(..)
+            | Op::SubStack
+            | Op::SubRegister(..)
+            | Op::CompareRegister(..)
+            | Op::Test(..)
+            | Op::MulWide(..)
+            | Op::AndStack
+            | Op::OrStack
+            | Op::XorStack
+            | Op::NotStack
+            | Op::AndRegister(..)
+            | Op::OrRegister(..)
+            | Op::XorRegister(..)
+            | Op::NotRegister(_) => OpClass::Arith,
+            Op::Jump(_)
+            | Op::JumpLong(_)
+            | Op::JumpRegister(_)
+            | Op::JumpZero(_)
+            | Op::JumpNotZero(_)
+            | Op::JumpCarry(_)
+            | Op::JumpNotCarry(_)
+            | Op::JumpLess(_)
+            | Op::JumpGreater(_)
+            | Op::JumpLessEqual(_)
+            | Op::JumpGreaterEqual(_)
+            | Op::Branch(_)
+            | Op::BranchZero(_)
+            | Op::BranchNotZero(_)
+            | Op::BranchCarry(_)
+            | Op::BranchNotCarry(_)
+            | Op::BranchLess(_)
+            | Op::BranchGreater(_)
+            | Op::BranchLessEqual(_)
+            | Op::BranchGreaterEqual(_)
+            | Op::MoveRegister(Register::PC, _)
+            | Op::MoveIfZero(Register::PC, _)
+            | Op::MoveIfNotZero(Register::PC, _)
+            | Op::LoadImmediate(Register::PC, _) => OpClass::Jump,
+            Op::MoveRegister(..)
+            | Op::MoveIfZero(..)
+            | Op::MoveIfNotZero(..)
+            | Op::LoadImmediate(..) => OpClass::Move,
+            Op::Load(_)
+            | Op::Store(_)
+            | Op::LoadIndirect(..)
+            | Op::StoreIndirect(..)
+            | Op::LoadOffset(..)
+            | Op::StoreOffset(..)
+            | Op::MemCopy(..)
+            | Op::MemSet(..) => OpClass::Memory,
+            Op::Signal(_) | Op::Syscall(_) => OpClass::Signal,
+        }
+    }
+}
+
+/// Which steps `--trace` shows. The default shows every step.
+#[derive(Debug, Default)]
+pub struct TraceFilter {
+    classes: Vec<OpClass>,
+    ranges: Vec<(u16, u16)>,
+    registers: Vec<Register>,
+}
+
+impl TraceFilter {
+    /// Adds the classes in a comma-separated list, for `--trace-op`.
+    pub fn add_classes(&mut self, list: &str) -> Result<(), String> {
+        for name in list.split(',') {
+            self.classes.push(OpClass::parse(name.trim())?);
+        }
+        Ok(())
+    }
+
+    /// Adds an address range, end exclusive, for `--trace-range`.
+    pub fn add_range(&mut self, start: u16, end: u16) {
+        self.ranges.push((start, end));
+    }
+
+    /// Adds a register whose changes are shown, for `--trace-reg`.
+    pub fn add_register(&mut self, name: &str) -> Result<(), String> {
+        let register = Register::from_str(&name.to_uppercase())
+            .map_err(|_| format!("unknown register `{}`", name))?;
+        self.registers.push(register);
+        Ok(())
+    }
+
+    /// Decides whether to show the step at `pc`, which executed `op` and
+    /// took the registers from `before` to `after`.
+    pub fn matches(
+        &self,
+        pc: u16,
+        op: &Op,
+        before: &[u16; Register::COUNT],
+        after: &[u16; Register::COUNT],
+    ) -> bool {
+        let class = self.classes.is_empty() || self.classes.contains(&OpClass::of(op));
+        let range = self.ranges.is_empty()
+            || self
+                .ranges
+                .iter()
+                .any(|(start, end)| (*start..*end).contains(&pc));
+        let register = self.registers.is_empty()
+            || self
+                .registers
+                .iter()
+                .any(|r| before[*r as usize] != after[*r as usize]);
+        class && range && register
+    }
+}
+
+/// Passes the steps a [`TraceFilter`] keeps on to a tracer. It runs as a
+/// hook rather than a tracer so it sees the registers before each step too.
+pub struct FilteredTracer<T> {
+    filter: TraceFilter,
+    tracer: T,
+    before: [u16; Register::COUNT],
+}
+
+impl<T: Tracer> FilteredTracer<T> {
+    pub fn new(filter: TraceFilter, tracer: T) -> Self {
+        Self {
+            filter,
+            tracer,
+            before: [0; Register::COUNT],
+        }
+    }
+}
+
+impl<T: Tracer> Hook for FilteredTracer<T> {
+    fn before(&mut self, machine: &Machine, pc: u16, op: &Op) {
+        self.before = machine.registers;
+        self.tracer.before_step(pc, op, &machine.registers);
+    }
+
+    fn after(&mut self, machine: &Machine, pc: u16, op: &Op, _: Option<&str>) {
+        if self
+            .filter
+            .matches(pc, op, &self.before, &machine.registers)
+        {
+            self.tracer.on_step(pc, op, &machine.registers);
+        }
+    }
+}
@@ -0,0 +1,168 @@
+//! Benchmarks for the VM's step loop.
+//!
+//! Runs each bundled workload for a fixed number of instructions on every
+//! memory backend and reports instructions per second. The workloads never
+//! halt, so every run executes exactly the requested number of steps.
+
+use std::{
+    env, process,
+    time::{Duration, Instant},
+};
+
+use rustyvm::{Addressable, LinearMemory, Machine, PagedMemory, asm};
+
+/// Instructions executed per workload and backend unless `--cycles` is given.
+const DEFAULT_CYCLES: u64 = 1_000_000;
+
+/// Memory size used by every backend, the same as `Machine::new`.
+const MEMORY_SIZE: usize = 8 * 1024;
+
+/// Adds two registers and jumps back, over and over.
+const TIGHT_LOOP: &str = "
+    push %1
+    pop B
+    movi R1, loop
+loop:
+    addr A, B
+    mov PC, R1
+";
+
+/// Copies 128 bytes from 0x1100 to 0x1800 one word at a time, moving SP
+/// to pop from the source and push to the destination, then starts over.
+const MEMCPY: &str = "
+    push %2
+    pop R2
+    movi R0, start
+start:
+    movi R4, $1102
+    movi C, $1800
+.rept 64
+    mov SP, R4
+    pop R3
+    addr R4, R2
+    mov SP, C
+    pushr R3
+    mov C, SP
+.endr
+    mov PC, R0
+";
+
+/// Calls a function that calls another, returning through the stack.
+/// A call pushes the address after the jump and moves the target into PC;
+/// a return pops it back into PC.
+const CALLS: &str = "
+    push %1
+    pop B
+    movi R0, start
+    movi R1, outer
+    movi R2, inner
+start:
+    pushr PC
+    push %6
+    adds
+    mov PC, R1
+    mov PC, R0
+outer:
+    pushr PC
+    push %6
+    adds
+    mov PC, R2
+    pop PC
+inner:
+    addr A, B
+    pop PC
+";
+
+/// Bundled workloads, by name.
+const WORKLOADS: [(&str, &str); 3] = [
+    ("tight-loop", TIGHT_LOOP),
+    ("memcpy", MEMCPY),
+    ("calls", CALLS),
+];
+
+/// Creates the memory a backend runs on.
+type Backend = fn() -> Box<dyn Addressable>;
+
+/// Execution backends, by name.
+const BACKENDS: [(&str, Backend); 2] = [
+    ("linear", || Box::new(LinearMemory::new(MEMORY_SIZE))),
+    ("paged", || Box::new(PagedMemory::new(MEMORY_SIZE))),
+];
+
+/// Main function for the benchmark binary.
+fn main() {
+    if let Err(e) = run() {
+        eprintln!("Error: {}", e);
+        process::exit(1);
+    }
+}
+
+/// Runs every workload on every backend and prints a results table.
+fn run() -> Result<(), String> {
+    let args: Vec<_> = env::args().collect();
+    let usage = format!("usage: {} [--cycles N] [<workload>...]", args[0]);
+
+    let mut cycles = DEFAULT_CYCLES;
+    let mut selected: Vec<&str> = Vec::new();
+
+    let mut args_iter = args[1..].iter();
+    while let Some(arg) = args_iter.next() {
+        match arg.as_str() {
+            "--cycles" => {
+                let value = args_iter.next().ok_or_else(|| usage.clone())?;
+                cycles = value
+                    .parse()
+                    .map_err(|_| format!("invalid --cycles value: {}", value))?;
+            }
+            option if option.starts_with('-') => {
+                return Err(format!("Unknown option: {}\n{}", option, usage));
+            }
+            name if WORKLOADS.iter().any(|(w, _)| *w == name) => selected.push(name),
+            name => {
+                let names: Vec<&str> = WORKLOADS.iter().map(|(w, _)| *w).collect();
+                return Err(format!(
+                    "unknown workload `{}`, expected one of {}",
+                    name,
+                    names.join(", ")
+                ));
+            }
+        }
+    }
+
+    println!(
+        "{:<12} {:<8} {:>12} {:>10} {:>14}",
+        "workload", "backend", "instructions", "time", "instr/s"
+    );
+    for (name, source) in WORKLOADS {
+        if !selected.is_empty() && !selected.contains(&name) {
+            continue;
+        }
+        let bytecode = asm::assemble_str(source).map_err(|e| format!("{}: {}", name, e))?;
+        for (backend, memory) in BACKENDS {
+            let elapsed = bench(&bytecode, memory(), cycles)
+                .map_err(|e| format!("{} on {}: {}", name, backend, e))?;
+            let rate = cycles as f64 / elapsed.as_secs_f64();
+            println!(
+                "{:<12} {:<8} {:>12} {:>8.1}ms {:>14.0}",
+                name,
+                backend,
+                cycles,
+                elapsed.as_secs_f64() * 1000.0,
+                rate
+            );
+        }
+    }
+    Ok(())
+}
+
+/// Loads a workload into a machine on the given memory and times `cycles` steps.
+fn bench(bytecode: &[u8], memory: Box<dyn Addressable>, cycles: u64) -> Result<Duration, String> {
+    let mut vm = Machine::with_memory(memory);
+    vm.load_program(bytecode)?;
+
+    let start = Instant::now();
+    for _ in 0..cycles {
+        vm.step()?;
+    }
+    Ok(start.elapsed())
+}
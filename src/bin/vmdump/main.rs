@@ -0,0 +1,182 @@
+//! Annotated hexdump for Rusty 16-bit VM program files.
+
+use std::{
+    collections::BTreeMap,
+    env, fs,
+    io::{self, Read, Write},
+    process,
+};
+
+use rustyvm::{
+    Executable,
+    asm::{
+        self,
+        disassembler::{Listing, ListingLine},
+    },
+};
+
+/// Number of bytes shown per hexdump row.
+const ROW_SIZE: usize = 16;
+
+/// Main function for the dump binary.
+fn main() {
+    if let Err(e) = run() {
+        eprintln!("Error: {}", e);
+        process::exit(1);
+    }
+}
+
+/// Reads a program file and prints it as hexdump rows, each followed by the
+/// instructions decoded from that row.
+fn run() -> Result<(), String> {
+    let args: Vec<_> = env::args().collect();
+    let usage = format!("usage: {} <input> [--symbols <file>]", args[0]);
+
+    let mut input: Option<&str> = None;
+    let mut symbols_path: Option<&str> = None;
+
+    let mut args_iter = args[1..].iter();
+    while let Some(arg) = args_iter.next() {
+        match arg.as_str() {
+            "--symbols" => {
+                let path = args_iter.next().ok_or_else(|| usage.clone())?;
+                symbols_path = Some(path);
+            }
+            option if option.starts_with('-') && option != "-" => {
+                return Err(format!("Unknown option: {}\n{}", option, usage));
+            }
+            path if input.is_none() => input = Some(path),
+            _ => return Err(usage),
+        }
+    }
+
+    let input = input.ok_or(usage)?;
+    // `-` reads the program from stdin
+    let mut bytes = Vec::new();
+    if input == "-" {
+        io::stdin()
+            .read_to_end(&mut bytes)
+            .map_err(|e| format!("failed to read stdin, err - {}", e))?;
+    } else {
+        bytes = fs::read(input).map_err(|e| format!("failed to open the file, err - {}", e))?;
+    }
+
+    // Executables are shown as one flat image, using their own symbols unless overridden
+    let mut embedded_symbols = None;
+    if Executable::is_executable(&bytes) {
+        let executable = Executable::parse(&bytes)?;
+        let (start, image) = executable.flatten();
+        if start != 0 {
+            eprintln!(
+                "note: image starts at 0x{:04X}, addresses are relative to it",
+                start
+            );
+        }
+        bytes = image;
+        let symbols = executable.symbols.into_iter();
+        embedded_symbols = Some(
+            symbols
+                .map(|(name, a)| (name, a.wrapping_sub(start)))
+                .collect(),
+        );
+    }
+    let listing = asm::disassembler::listing(&bytes)?;
+
+    let symbols = match symbols_path {
+        Some(path) => {
+            let text = fs::read_to_string(path)
+                .map_err(|e| format!("failed to read symbols from {}: {}", path, e))?;
+            asm::parse_symbols(&text)?
+        }
+        None => embedded_symbols.unwrap_or_default(),
+    };
+    let labels = listing.labels(&symbols);
+
+    let mut out = io::stdout().lock();
+    print_dump(&mut out, &listing, &labels).map_err(|e| e.to_string())
+}
+
+/// Splits the listing into rows of at most `ROW_SIZE` bytes, keeping each
+/// instruction on one row.
+fn rows(lines: &[ListingLine]) -> Vec<&[ListingLine]> {
+    let mut rows = Vec::new();
+    let (mut start, mut bytes) = (0, 0);
+    for (i, line) in lines.iter().enumerate() {
+        if bytes + line.bytes.len() > ROW_SIZE {
+            rows.push(&lines[start..i]);
+            (start, bytes) = (i, 0);
+        }
+        bytes += line.bytes.len();
+    }
+    if start < lines.len() {
+        rows.push(&lines[start..]);
+    }
+    rows
+}
+
+/// Writes the dump. Instruction boundaries are marked by the wider gaps
+/// between words, jump targets and the entry point by `>`, and words in
+/// data regions by a `; data` comment.
+fn print_dump(
+    out: &mut impl Write,
+    listing: &Listing,
+    labels: &BTreeMap<u16, Vec<String>>,
+) -> io::Result<()> {
+    if let Some(entry) = listing.entry {
+        writeln!(out, "; program header, entry 0x{:04X}", entry)?;
+    }
+    let data = listing.data_addresses(labels);
+
+    for row in rows(&listing.lines) {
+        let words: Vec<String> = row
+            .iter()
+            .map(|line| {
+                let hex: Vec<String> = line.bytes.iter().map(|b| format!("{:02X}", b)).collect();
+                hex.join(" ")
+            })
+            .collect();
+        let ascii: String = row
+            .iter()
+            .flat_map(|line| &line.bytes)
+            .map(|byte| match byte {
+                byte if byte.is_ascii_graphic() || *byte == b' ' => *byte as char,
+                _ => '.',
+            })
+            .collect();
+        writeln!(
+            out,
+            "{:04X}  {:<54}  |{}|",
+            row[0].address,
+            words.join("  "),
+            ascii
+        )?;
+
+        for line in row {
+            let mark = if listing.targets.contains(&line.address) {
+                '>'
+            } else {
+                ' '
+            };
+            let names = labels.get(&line.address).map_or(&[][..], Vec::as_slice);
+            let (last, others) = match names.split_last() {
+                Some((last, others)) => (format!("{}:", last), others),
+                None => (String::new(), &[][..]),
+            };
+            for name in others {
+                writeln!(out, "      {:04X}  {}{}:", line.address, mark, name)?;
+            }
+
+            let comment = if data.contains(&line.address) {
+                "  ; data"
+            } else {
+                ""
+            };
+            writeln!(
+                out,
+                "      {:04X}  {}{:<16} {}{}",
+                line.address, mark, last, line.instruction, comment
+            )?;
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,247 @@
+//! Spec-driven test runner for Rusty 16-bit VM programs.
+//!
+//! A spec file lists test cases, each starting with a `[name]` header:
+//!
+//! ```text
+//! # Comments start with `#`
+//! [add two numbers]
+//! program = add.asm      # .asm files are assembled, anything else is loaded as is
+//! include = runtime      # include directory for .asm programs, repeatable
+//! max_steps = 100        # step budget, 10000 by default
+//! halt = halted          # expected halt reason: halted, step_limit or error
+//! A = 30                 # expected register value
+//! mem 0x1000 = 14 00     # expected bytes starting at an address
+//! ```
+//!
+//! Paths are relative to the spec file. Numbers are assembler expressions,
+//! so `30`, `$1E`, `0x1E` and `%30` all mean the same.
+
+use std::{
+    env, fs,
+    path::{Path, PathBuf},
+    process,
+};
+
+use rustyvm::{
+    Machine, Register,
+    asm::{self, AsmOptions, expr},
+    diff::ExpectedState,
+    halt, logging,
+};
+
+/// Step budget used when a case doesn't set `max_steps`.
+const DEFAULT_MAX_STEPS: u64 = 10_000;
+
+/// One test case from a spec file.
+struct Case {
+    name: String,
+    /// Line of the `[name]` header, for error messages
+    line: usize,
+    program: Option<PathBuf>,
+    include_dirs: Vec<PathBuf>,
+    max_steps: u64,
+    halt: String,
+    expected: ExpectedState,
+}
+
+impl Case {
+    fn new(name: &str, line: usize) -> Self {
+        Self {
+            name: name.to_string(),
+            line,
+            program: None,
+            include_dirs: Vec::new(),
+            max_steps: DEFAULT_MAX_STEPS,
+            halt: "halted".to_string(),
+            expected: ExpectedState::new(),
+        }
+    }
+}
+
+/// Main function for the test runner binary.
+fn main() {
+    match run() {
+        Ok(true) => {}
+        Ok(false) => process::exit(1),
+        Err(e) => {
+            eprintln!("Error: {}", e);
+            process::exit(2);
+        }
+    }
+}
+
+/// Runs every case of every spec file given. Returns whether all passed.
+fn run() -> Result<bool, String> {
+    let args: Vec<_> = env::args().collect();
+    // `-v`, `-vv` and `-vvv` log the runs of the cases to stderr
+    let (flags, specs): (Vec<&String>, Vec<&String>) = args[1..]
+        .iter()
+        .partition(|arg| matches!(arg.as_str(), "-v" | "-vv" | "-vvv"));
+    if specs.is_empty() {
+        return Err(format!("usage: {} [-v|-vv|-vvv] <spec>...", args[0]));
+    }
+    let verbosity = flags
+        .iter()
+        .map(|flag| flag.len() as u8 - 1)
+        .max()
+        .unwrap_or(0);
+    logging::init(verbosity)?;
+
+    let mut passed = 0;
+    let mut failed = 0;
+    for path in specs {
+        let text =
+            fs::read_to_string(path).map_err(|e| format!("failed to read spec {}: {}", path, e))?;
+        let dir = Path::new(path).parent().unwrap_or(Path::new(""));
+        let cases = parse_spec(&text, dir).map_err(|e| format!("{}: {}", path, e))?;
+
+        for case in cases {
+            let failures = run_case(&case)?;
+            if failures.is_empty() {
+                println!("PASS {}", case.name);
+                passed += 1;
+            } else {
+                println!("FAIL {}", case.name);
+                for failure in failures {
+                    println!("    {}", failure);
+                }
+                failed += 1;
+            }
+        }
+    }
+
+    println!("{} passed, {} failed", passed, failed);
+    Ok(failed == 0)
+}
+
+/// Evaluates a number in a spec file.
+fn number(text: &str) -> Result<i64, String> {
+    expr::eval(text, &|_| None)
+}
+
+/// Evaluates a number that must fit in `T`, such as a register value or byte.
+fn number_as<T: TryFrom<i64>>(text: &str) -> Result<T, String> {
+    let value = number(text)?;
+    T::try_from(value).map_err(|_| format!("{} is out of range", text))
+}
+
+/// Parses a spec file into its test cases.
+fn parse_spec(text: &str, dir: &Path) -> Result<Vec<Case>, String> {
+    let mut cases: Vec<Case> = Vec::new();
+
+    for (idx, line) in text.lines().enumerate() {
+        let line_no = idx + 1;
+        let error = |message: String| format!("line {}: {}", line_no, message);
+        let line = line.split('#').next().unwrap_or("").trim();
+        if line.is_empty() {
+            continue;
+        }
+
+        if let Some(name) = line.strip_prefix('[').and_then(|l| l.strip_suffix(']')) {
+            cases.push(Case::new(name.trim(), line_no));
+            continue;
+        }
+
+        let case = cases
+            .last_mut()
+            .ok_or_else(|| error("expected a `[name]` header first".to_string()))?;
+        let (key, value) = line
+            .split_once('=')
+            .ok_or_else(|| error(format!("expected `key = value`, found `{}`", line)))?;
+        let (key, value) = (key.trim(), value.trim());
+
+        match key {
+            "program" => case.program = Some(dir.join(value)),
+            "include" => case.include_dirs.push(dir.join(value)),
+            "max_steps" => case.max_steps = number_as(value).map_err(error)?,
+            "halt" => match value {
+                "halted" | "step_limit" | "error" => case.halt = value.to_string(),
+                _ => return Err(error(format!("unknown halt reason `{}`", value))),
+            },
+            key if key.starts_with("mem ") => {
+                let address = number_as(key["mem ".len()..].trim()).map_err(error)?;
+                let bytes = value
+                    .split_whitespace()
+                    .map(number_as)
+                    .collect::<Result<Vec<u8>, _>>()
+                    .map_err(error)?;
+                case.expected.memory(address, &bytes);
+            }
+            key => {
+                let register = Register::from_str(key)
+                    .map_err(|_| error(format!("unknown key or register `{}`", key)))?;
+                let expected = number_as(value).map_err(error)?;
+                case.expected.register(register, expected);
+            }
+        }
+    }
+
+    Ok(cases)
+}
+
+/// Loads the program of a case, assembling it first if it is a source file.
+fn load(case: &Case) -> Result<Vec<u8>, String> {
+    let path = case
+        .program
+        .as_ref()
+        .ok_or_else(|| format!("case `{}` (line {}) has no `program`", case.name, case.line))?;
+
+    if path.extension().is_some_and(|ext| ext == "asm") {
+        let options = AsmOptions {
+            include_dirs: case.include_dirs.clone(),
+            ..Default::default()
+        };
+        let assembly = asm::assemble_files(&[path], &options)
+            .map_err(|e| format!("{}: {}", path.display(), e))?;
+        Ok(assembly.bytecode)
+    } else {
+        fs::read(path).map_err(|e| format!("failed to read {}: {}", path.display(), e))
+    }
+}
+
+/// Runs one case and describes every expectation it missed.
+fn run_case(case: &Case) -> Result<Vec<String>, String> {
+    let bytecode = load(case)?;
+
+    let mut vm = Machine::new();
+    vm.define_handler(0x09, halt);
+
+    let _run = logging::run_span(&case.name);
+    let mut failures = Vec::new();
+    let mut steps = 0;
+    let outcome = match vm.load_program(&bytecode) {
+        Err(e) => Err(e),
+        Ok(_) => loop {
+            if vm.halt {
+                break Ok("halted");
+            }
+            if steps >= case.max_steps {
+                break Ok("step_limit");
+            }
+            steps += 1;
+            if let Err(e) = vm.step() {
+                break Err(e);
+            }
+        },
+    };
+
+    let halt = match &outcome {
+        Ok(reason) => reason,
+        Err(_) => "error",
+    };
+    logging::run_finished(steps, halt);
+    if halt != case.halt {
+        let detail = match &outcome {
+            Err(e) => format!(" ({})", e),
+            Ok(_) => String::new(),
+        };
+        failures.push(format!(
+            "halt: expected {}, got {} after {} steps{}",
+            case.halt, halt, steps, detail
+        ));
+    }
+
+    failures.extend(case.expected.check(&vm));
+
+    Ok(failures)
+}
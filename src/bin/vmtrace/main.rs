@@ -0,0 +1,209 @@
+//! Viewer for execution traces recorded with `vm --record`.
+//!
+//! Shows the trace a page at a time and reads commands from stdin:
+//!
+//! ```text
+//! <Enter> or n     next page
+//! p                previous page
+//! g N              go to step N
+//! w ADDR           list the steps that wrote to ADDR
+//! r REG VALUE      go to the step after which REG first held VALUE
+//! s [N]            show the registers after step N (the current step by default)
+//! q                quit
+//! ```
+//!
+//! Steps are numbered from 1; step 0 is the state before the first instruction.
+//!
+//! With `--export chrome` or `--export jsonl` the trace is converted for
+//! other tools and written to stdout instead.
+
+use std::{
+    env, fs,
+    io::{self, BufRead, Write},
+    process,
+};
+
+use rustyvm::{
+    Register,
+    asm::expr,
+    trace::{Step, Trace},
+};
+
+/// Number of steps shown per page.
+const PAGE_SIZE: usize = 20;
+
+/// Main function for the trace viewer binary.
+fn main() {
+    if let Err(e) = run() {
+        eprintln!("Error: {}", e);
+        process::exit(1);
+    }
+}
+
+/// Loads the trace and runs the command loop until `q` or end of input, or
+/// exports it with `--export`.
+fn run() -> Result<(), String> {
+    let args: Vec<_> = env::args().collect();
+    let usage = format!("usage: {} <trace> [--export chrome|jsonl]", args[0]);
+
+    let mut input: Option<&str> = None;
+    let mut export: Option<&str> = None;
+    let mut args_iter = args[1..].iter();
+    while let Some(arg) = args_iter.next() {
+        match arg.as_str() {
+            "--export" => match args_iter.next().map(String::as_str) {
+                Some(format @ ("chrome" | "jsonl")) => export = Some(format),
+                _ => return Err("--export expects `chrome` or `jsonl`".to_string()),
+            },
+            option if option.starts_with('-') => {
+                return Err(format!("Unknown option: {}\n{}", option, usage));
+            }
+            path if input.is_none() => input = Some(path),
+            _ => return Err(usage),
+        }
+    }
+
+    let input = input.ok_or(usage)?;
+    let bytes = fs::read(input).map_err(|e| format!("failed to open the file, err - {}", e))?;
+    let trace = Trace::parse(&bytes)?;
+
+    let mut out = io::stdout().lock();
+    let write_error = |e: io::Error| format!("failed to write output - {}", e);
+
+    if let Some(format) = export {
+        let text = match format {
+            "chrome" => trace.to_chrome_trace(),
+            _ => trace.to_json_lines(),
+        };
+        return out.write_all(text.as_bytes()).map_err(write_error);
+    }
+    writeln!(
+        out,
+        "{} steps recorded. Enter for the next page, h for help.",
+        trace.steps.len()
+    )
+    .map_err(write_error)?;
+
+    // First step of the page shown next, numbered from 1
+    let mut current = 1;
+    print_page(&mut out, &trace, current).map_err(write_error)?;
+
+    for line in io::stdin().lock().lines() {
+        let line = line.map_err(|e| format!("failed to read command - {}", e))?;
+        let words: Vec<&str> = line.split_whitespace().collect();
+        let result = match words.as_slice() {
+            [] | ["n"] => {
+                current = (current + PAGE_SIZE).min(trace.steps.len() + 1);
+                print_page(&mut out, &trace, current).map_err(write_error)
+            }
+            ["p"] => {
+                current = current.saturating_sub(PAGE_SIZE).max(1);
+                print_page(&mut out, &trace, current).map_err(write_error)
+            }
+            ["g", step] => number(step).and_then(|step| {
+                current = (step as usize).clamp(1, trace.steps.len().max(1));
+                print_page(&mut out, &trace, current).map_err(write_error)
+            }),
+            ["w", address] => number(address).and_then(|address| {
+                let steps: Vec<String> = trace
+                    .writes_to(address)
+                    .map(|idx| format_step(idx + 1, &trace.steps[idx]))
+                    .collect();
+                if steps.is_empty() {
+                    writeln!(out, "no step wrote to 0x{:04X}", address)
+                } else {
+                    writeln!(out, "{}", steps.join("\n"))
+                }
+                .map_err(write_error)
+            }),
+            ["r", register, value] => Register::from_str(register)
+                .map_err(|_| format!("unknown register `{}`", register))
+                .and_then(|register| Ok((register, number(value)?)))
+                .and_then(
+                    |(register, value)| match trace.first_holding(register, value) {
+                        Some(step) => {
+                            writeln!(
+                                out,
+                                "{:?} first held 0x{:04X} after step {}",
+                                register, value, step
+                            )
+                            .map_err(write_error)?;
+                            current = step.max(1);
+                            print_page(&mut out, &trace, current).map_err(write_error)
+                        }
+                        None => writeln!(out, "{:?} never held 0x{:04X}", register, value)
+                            .map_err(write_error),
+                    },
+                ),
+            ["s"] => print_registers(&mut out, &trace, current).map_err(write_error),
+            ["s", step] => number(step).and_then(|step| {
+                print_registers(&mut out, &trace, step as usize).map_err(write_error)
+            }),
+            ["q"] => break,
+            ["h"] | ["?"] => writeln!(
+                out,
+                "commands: <Enter>/n next page, p previous page, g N go to step, \
+                 w ADDR writes to an address, r REG VALUE first step REG held VALUE, \
+                 s [N] registers after a step, q quit"
+            )
+            .map_err(write_error),
+            _ => Err(format!("unknown command `{}`, h for help", line.trim())),
+        };
+        // A bad command is reported without leaving the viewer
+        if let Err(e) = result {
+            writeln!(out, "Error: {}", e).map_err(write_error)?;
+        }
+    }
+    Ok(())
+}
+
+/// Evaluates a step number, address or register value.
+fn number(text: &str) -> Result<u16, String> {
+    let value = expr::eval(text, &|_| None)?;
+    u16::try_from(value).map_err(|_| format!("{} does not fit in 16 bits", text))
+}
+
+/// Describes one step: its number, address, instruction, and effects.
+fn format_step(number: usize, step: &Step) -> String {
+    let mut effects: Vec<String> = step
+        .registers
+        .iter()
+        .map(|(register, value)| format!("{:?}=0x{:04X}", register, value))
+        .collect();
+    effects.extend(
+        step.writes
+            .iter()
+            .map(|(address, value)| format!("[{:04X}]={:02X}", address, value)),
+    );
+    let line = format!(
+        "{:>6}  {:04X}  {:<16} {}",
+        number,
+        step.pc,
+        step.mnemonic(),
+        effects.join(" ")
+    );
+    line.trim_end().to_string()
+}
+
+/// Shows up to a page of steps, starting at step `first`.
+fn print_page(out: &mut impl Write, trace: &Trace, first: usize) -> io::Result<()> {
+    let start = first.saturating_sub(1);
+    if start >= trace.steps.len() {
+        return writeln!(out, "end of trace");
+    }
+    for (idx, step) in trace.steps.iter().enumerate().skip(start).take(PAGE_SIZE) {
+        writeln!(out, "{}", format_step(idx + 1, step))?;
+    }
+    Ok(())
+}
+
+/// Shows every register after the given step.
+fn print_registers(out: &mut impl Write, trace: &Trace, step: usize) -> io::Result<()> {
+    let step = step.min(trace.steps.len());
+    let values = trace.registers_after(step);
+    let registers: Vec<String> = Register::ALL
+        .iter()
+        .map(|reg| format!("{}=0x{:04X}", reg, values[*reg as usize]))
+        .collect();
+    writeln!(out, "after step {}: {}", step, registers.join(" "))
+}
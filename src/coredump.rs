@@ -0,0 +1,97 @@
+//! Core dumps: the state of a machine at the instruction that failed, for
+//! debugging a crash after the fact.
+//!
+//! A core dump is a [`Snapshot`] taken when a step fails, with PC put back
+//! on the failing instruction, plus the error it failed with:
+//!
+//! | Size | Contents                                  |
+//! | ---- | ----------------------------------------- |
+//! | 4    | Magic bytes `RVC\0`                       |
+//! | 1    | Format version, currently 1               |
+//! | 2    | Length of the error message (little-endian) |
+//! | ...  | The error message, UTF-8                  |
+//! | ...  | The snapshot, in the `RVS\0` format       |
+
+use crate::{Machine, Op, Register, program::Reader, snapshot::Snapshot};
+
+/// Magic bytes at the start of a core dump.
+pub const CORE_MAGIC: [u8; 4] = *b"RVC\0";
+
+/// Version of the core dump format written by [`CoreDump::encode`].
+pub const CORE_VERSION: u8 = 1;
+
+/// A machine that failed, and why.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct CoreDump {
+    /// The error the failing step returned
+    pub error: String,
+    /// The machine state, with PC on the failing instruction
+    pub snapshot: Snapshot,
+}
+
+impl CoreDump {
+    /// Captures a machine whose step from `pc` just failed with `error`.
+    pub fn capture(vm: &Machine, pc: u16, error: &str) -> Self {
+        let mut snapshot = vm.snapshot();
+        snapshot.registers[Register::PC as usize] = pc;
+        Self {
+            error: error.to_string(),
+            snapshot,
+        }
+    }
+
+    /// Returns true if the bytes start with the core dump magic.
+    pub fn is_core_dump(bytes: &[u8]) -> bool {
+        bytes.starts_with(&CORE_MAGIC)
+    }
+
+    /// Encodes the core dump in the format described in the module docs.
+    pub fn encode(&self) -> Result<Vec<u8>, String> {
+        let len = u16::try_from(self.error.len())
+            .map_err(|_| format!("error message is too long - {} bytes", self.error.len()))?;
+        let mut bytes = CORE_MAGIC.to_vec();
+        bytes.push(CORE_VERSION);
+        bytes.extend(len.to_le_bytes());
+        bytes.extend(self.error.as_bytes());
+        bytes.extend(self.snapshot.encode()?);
+        Ok(bytes)
+    }
+
+    /// Decodes a core dump file.
+    pub fn parse(bytes: &[u8]) -> Result<Self, String> {
+        let mut reader = Reader::new(bytes, "core dump");
+        if reader.take(CORE_MAGIC.len())? != CORE_MAGIC {
+            return Err("not a core dump - bad magic".to_string());
+        }
+        let version = reader.u8()?;
+        if version != CORE_VERSION {
+            return Err(format!("unsupported core dump version {}", version));
+        }
+        let len = reader.u16()? as usize;
+        let error = String::from_utf8(reader.take(len)?.to_vec())
+            .map_err(|_| "core dump error message is not valid UTF-8")?;
+        let snapshot = Snapshot::parse(reader.rest())?;
+        Ok(Self { error, snapshot })
+    }
+}
+
+/// Finds the calls in progress, innermost first, starting with PC.
+///
+/// The ISA has no call instruction: a call pushes its return address and
+/// jumps with `MOV PC, reg`. So every stack word that points just past a
+/// `MOV PC, reg` is taken to be a return address. Data that happens to look
+/// like one shows up too, so the result is a best guess.
+pub fn backtrace(vm: &Machine) -> Vec<u16> {
+    let mut frames = vec![vm.get_register(Register::PC)];
+    for word in vm.stack().rev() {
+        let after_jump = word
+            .checked_sub(2)
+            .and_then(|call| vm.decode_at(call).ok())
+            .is_some_and(|op| matches!(op, Op::MoveRegister(Register::PC, _)));
+        if after_jump {
+            frames.push(word);
+        }
+    }
+    frames
+}
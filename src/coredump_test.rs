@@ -0,0 +1,78 @@
+//! Unit tests for core dumps.
+//!
+//! This file crashes a program inside a nested call and checks the dump,
+//! its encoding and the backtrace recovered from the stack.
+
+#[cfg(test)]
+mod tests {
+    use crate::asm::assemble_str;
+    use crate::coredump::{CoreDump, backtrace};
+    use crate::{Machine, Op, Register};
+
+    /// Calls `outer`, which calls `inner`, which runs into an invalid word.
+    const CRASH: &str = "
+    movi R1, outer
+    movi R2, inner
+    pushr PC
+    push %6
+    adds
+    mov PC, R1
+    sig $09
+outer:
+    pushr PC
+    push %6
+    adds
+    mov PC, R2
+    pop PC
+inner:
+    nop
+    .db $FF, $FF
+";
+
+    /// Runs the program until it fails, returning the machine and the
+    /// address of the failing instruction.
+    fn crash() -> (Machine, u16, String) {
+        let mut vm = Machine::new();
+        vm.load_program(&assemble_str(CRASH).unwrap()).unwrap();
+        loop {
+            let pc = vm.get_register(Register::PC);
+            if let Err(e) = vm.step() {
+                return (vm, pc, e);
+            }
+        }
+    }
+
+    #[test]
+    fn test_capture_and_encode() {
+        let (vm, pc, error) = crash();
+        let core = CoreDump::capture(&vm, pc, &error);
+        assert_eq!(core.error, "unknown op - 0xFF");
+        assert_eq!(core.snapshot.registers[Register::PC as usize], pc);
+
+        let bytes = core.encode().unwrap();
+        assert!(CoreDump::is_core_dump(&bytes));
+        assert_eq!(CoreDump::parse(&bytes), Ok(core));
+        assert!(CoreDump::parse(&bytes[..bytes.len() - 1]).is_err());
+        assert!(CoreDump::parse(&bytes[..7]).is_err());
+    }
+
+    #[test]
+    fn test_backtrace() {
+        let (mut vm, pc, _) = crash();
+        vm.registers[Register::PC as usize] = pc;
+        let frames = backtrace(&vm);
+
+        // The failing word, then the returns into `outer` and the main program
+        assert_eq!(frames.len(), 3);
+        assert_eq!(frames[0], pc);
+        assert_eq!(vm.memory.read2(pc), Some(0xFFFF));
+        // Each return address follows the `mov PC, reg` that made the call
+        for frame in &frames[1..] {
+            assert!(matches!(
+                vm.decode_at(frame - 2),
+                Ok(Op::MoveRegister(Register::PC, _))
+            ));
+        }
+        assert!(frames[1] > frames[2]);
+    }
+}
@@ -0,0 +1,217 @@
+//! Memory-mapped devices.
+//!
+//! Devices are plain memory locations with an agreed meaning; programs use
+//! them with ordinary pushes and pops, and front-ends read or write the
+//! same locations between instructions.
+//!
+//! | Address           | Device                                           |
+//! | ----------------- | ------------------------------------------------ |
+//! | `0x1BF4`          | Mailbox: outgoing message (word)                 |
+//! | `0x1BF6`          | Mailbox: incoming message (word)                 |
+//! | `0x1BF8`          | Mailbox: send flag, nonzero while sending        |
+//! | `0x1BF9`          | Mailbox: receive flag, nonzero when a message waits |
+//! | `0x1BFA`          | Beeper frequency in Hz (word)                    |
+//! | `0x1BFC`          | Beeper duration in milliseconds (word)           |
+//! | `0x1BFE`          | Keyboard: code of the last key pressed, 0 if none |
+//! | `0x1C00..0x2000`  | Framebuffer: 32 x 32 pixels, one byte each       |
+//!
+//! Framebuffer bytes are RGB332 colours (`RRRGGGBB`), row by row from the
+//! top left. A program acknowledges a key by writing 0 back to the keyboard
+//! byte; a key pressed before that replaces the previous one.
+//!
+//! Hosts that want to type ahead, such as tests or scripted input, put keys
+//! in a [`KeyQueue`], which hands them over one at a time as the program
+//! acknowledges each.
+//!
+//! Two machines talk through their mailboxes once the host pairs them with
+//! [`exchange_mail`]. To send, a program stores the message and then sets
+//! the send flag; the flag clears when the message has been delivered. A
+//! delivered message lands in the peer's incoming word with its receive flag
+//! set, and the peer writes 0 to the flag to take it, which makes room for
+//! the next one.
+//!
+//! To beep, a program sets the frequency and then the duration. The
+//! front-end takes the beep and clears the duration, so the program can
+//! queue the next one once it reads 0 there. A frequency of 0 is a rest.
+
+use std::collections::VecDeque;
+
+use crate::{Machine, logging};
+
+/// Width of the framebuffer in pixels.
+pub const FRAMEBUFFER_WIDTH: usize = 32;
+
+/// Height of the framebuffer in pixels.
+pub const FRAMEBUFFER_HEIGHT: usize = 32;
+
+/// Address of the top left pixel.
+pub const FRAMEBUFFER_ADDR: u16 = 0x1C00;
+
+/// Address of the keyboard byte.
+pub const KEYBOARD_ADDR: u16 = 0x1BFE;
+
+/// Address of the outgoing mailbox word.
+pub const MAILBOX_OUT_ADDR: u16 = 0x1BF4;
+
+/// Address of the incoming mailbox word.
+pub const MAILBOX_IN_ADDR: u16 = 0x1BF6;
+
+/// Address of the mailbox send flag.
+pub const MAILBOX_SEND_ADDR: u16 = 0x1BF8;
+
+/// Address of the mailbox receive flag.
+pub const MAILBOX_RECEIVE_ADDR: u16 = 0x1BF9;
+
+/// Address of the beeper frequency word.
+pub const BEEPER_FREQUENCY_ADDR: u16 = 0x1BFA;
+
+/// Address of the beeper duration word.
+pub const BEEPER_DURATION_ADDR: u16 = 0x1BFC;
+
+/// Peak amplitude of beeper samples, well below full scale.
+const BEEPER_VOLUME: f32 = 0.25;
+
+/// Key codes for keys without an ASCII code.
+pub const KEY_UP: u8 = 0x80;
+pub const KEY_DOWN: u8 = 0x81;
+pub const KEY_LEFT: u8 = 0x82;
+pub const KEY_RIGHT: u8 = 0x83;
+
+/// Names the device at an address, if there is one.
+pub fn device_at(addr: u16) -> Option<&'static str> {
+    match addr {
+        FRAMEBUFFER_ADDR.. => Some("framebuffer"),
+        KEYBOARD_ADDR => Some("keyboard"),
+        BEEPER_FREQUENCY_ADDR..KEYBOARD_ADDR => Some("beeper"),
+        MAILBOX_OUT_ADDR..BEEPER_FREQUENCY_ADDR => Some("mailbox"),
+        _ => None,
+    }
+}
+
+/// Expands an RGB332 pixel to `0x00RRGGBB`.
+pub fn rgb332_to_rgb(pixel: u8) -> u32 {
+    let red = (pixel >> 5) as u32 * 255 / 7;
+    let green = ((pixel >> 2) & 0x07) as u32 * 255 / 7;
+    let blue = (pixel & 0x03) as u32 * 255 / 3;
+    (red << 16) | (green << 8) | blue
+}
+
+/// Reads the framebuffer as `0x00RRGGBB` pixels, row by row. Pixels outside
+/// the machine's memory read as black.
+pub fn framebuffer(vm: &Machine) -> Vec<u32> {
+    (0..FRAMEBUFFER_WIDTH * FRAMEBUFFER_HEIGHT)
+        .map(|offset| {
+            let pixel = vm
+                .memory
+                .read(FRAMEBUFFER_ADDR.wrapping_add(offset as u16))
+                .unwrap_or(0);
+            rgb332_to_rgb(pixel)
+        })
+        .collect()
+}
+
+/// Stores a key press in the keyboard byte. Returns false if the machine's
+/// memory does not reach the keyboard address.
+pub fn press_key(vm: &mut Machine, code: u8) -> bool {
+    logging::key_press(code);
+    vm.memory.write(KEYBOARD_ADDR, code)
+}
+
+/// Keys waiting to be pressed, delivered in order.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct KeyQueue {
+    keys: VecDeque<u8>,
+}
+
+impl KeyQueue {
+    /// Queues a key code.
+    pub fn push(&mut self, code: u8) {
+        self.keys.push_back(code);
+    }
+
+    /// Queues the bytes of a string as key codes, e.g. `"ls\n"`.
+    pub fn push_str(&mut self, keys: &str) {
+        self.keys.extend(keys.bytes());
+    }
+
+    /// Number of keys still waiting.
+    pub fn len(&self) -> usize {
+        self.keys.len()
+    }
+
+    /// Returns true once every key has been delivered.
+    pub fn is_empty(&self) -> bool {
+        self.keys.is_empty()
+    }
+
+    /// Presses the next key if the program has acknowledged the previous
+    /// one. Returns the key pressed, if any.
+    pub fn pump(&mut self, vm: &mut Machine) -> Option<u8> {
+        if vm.memory.read(KEYBOARD_ADDR)? != 0 {
+            return None;
+        }
+        let code = self.keys.pop_front()?;
+        press_key(vm, code);
+        Some(code)
+    }
+}
+
+/// A tone requested through the beeper.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Beep {
+    /// Frequency in Hz, 0 for silence
+    pub frequency: u16,
+    /// Length in milliseconds
+    pub duration_ms: u16,
+}
+
+impl Beep {
+    /// Renders the beep as a mono square wave at the given sample rate.
+    pub fn samples(&self, sample_rate: u32) -> Vec<f32> {
+        let count = sample_rate as u64 * self.duration_ms as u64 / 1000;
+        (0..count)
+            .map(|i| {
+                if self.frequency == 0 {
+                    return 0.0;
+                }
+                // Position within the current period, in half periods
+                let half_periods = i * 2 * self.frequency as u64 / sample_rate as u64;
+                if half_periods.is_multiple_of(2) {
+                    BEEPER_VOLUME
+                } else {
+                    -BEEPER_VOLUME
+                }
+            })
+            .collect()
+    }
+}
+
+/// Takes a pending beep, if the program has set a duration, and clears
+/// the duration to acknowledge it.
+pub fn take_beep(vm: &mut Machine) -> Option<Beep> {
+    let duration_ms = vm.memory.read2(BEEPER_DURATION_ADDR).filter(|d| *d != 0)?;
+    let frequency = vm.memory.read2(BEEPER_FREQUENCY_ADDR)?;
+    vm.memory.write2(BEEPER_DURATION_ADDR, 0);
+    let beep = Beep {
+        frequency,
+        duration_ms,
+    };
+    logging::beep(&beep);
+    Some(beep)
+}
+
+/// Delivers the message `from` is sending to `to`, if there is one and
+/// `to` has taken its last message. Returns the message delivered. Hosts
+/// call it between steps, once in each direction for a two-way pair.
+pub fn exchange_mail(from: &mut Machine, to: &mut Machine) -> Option<u16> {
+    if from.memory.read(MAILBOX_SEND_ADDR)? == 0 || to.memory.read(MAILBOX_RECEIVE_ADDR)? != 0 {
+        return None;
+    }
+    let message = from.memory.read2(MAILBOX_OUT_ADDR)?;
+    to.memory.write2(MAILBOX_IN_ADDR, message);
+    to.memory.write(MAILBOX_RECEIVE_ADDR, 1);
+    from.memory.write(MAILBOX_SEND_ADDR, 0);
+    logging::mail(message);
+    Some(message)
+}
@@ -0,0 +1,149 @@
+//! Unit tests for the memory-mapped devices.
+//!
+//! This file checks the RGB332 palette and that the framebuffer and
+//! keyboard map onto the documented addresses.
+
+#[cfg(test)]
+mod tests {
+    use crate::devices::{
+        BEEPER_DURATION_ADDR, BEEPER_FREQUENCY_ADDR, Beep, FRAMEBUFFER_ADDR, FRAMEBUFFER_HEIGHT,
+        FRAMEBUFFER_WIDTH, KEYBOARD_ADDR, KeyQueue, MAILBOX_IN_ADDR, MAILBOX_OUT_ADDR,
+        MAILBOX_RECEIVE_ADDR, MAILBOX_SEND_ADDR, device_at, exchange_mail, framebuffer, press_key,
+        rgb332_to_rgb, take_beep,
+    };
+    use crate::{LinearMemory, Machine};
+
+    #[test]
+    fn test_rgb332_palette() {
+        assert_eq!(rgb332_to_rgb(0x00), 0x000000);
+        assert_eq!(rgb332_to_rgb(0xFF), 0xFFFFFF);
+        assert_eq!(rgb332_to_rgb(0b1110_0000), 0xFF0000);
+        assert_eq!(rgb332_to_rgb(0b0001_1100), 0x00FF00);
+        assert_eq!(rgb332_to_rgb(0b0000_0011), 0x0000FF);
+    }
+
+    #[test]
+    fn test_framebuffer_layout() {
+        let mut vm = Machine::new();
+        // Second row, third column
+        vm.memory
+            .write(FRAMEBUFFER_ADDR + FRAMEBUFFER_WIDTH as u16 + 2, 0xE0);
+        vm.memory.write(0x1FFF, 0x03);
+
+        let pixels = framebuffer(&vm);
+        assert_eq!(pixels.len(), FRAMEBUFFER_WIDTH * FRAMEBUFFER_HEIGHT);
+        assert_eq!(pixels[FRAMEBUFFER_WIDTH + 2], 0xFF0000);
+        assert_eq!(pixels[pixels.len() - 1], 0x0000FF);
+        assert_eq!(pixels.iter().filter(|p| **p != 0).count(), 2);
+    }
+
+    #[test]
+    fn test_press_key() {
+        let mut vm = Machine::new();
+        assert!(press_key(&mut vm, b'a'));
+        assert_eq!(vm.memory.read(KEYBOARD_ADDR), Some(b'a'));
+
+        // Memory too small to hold the devices
+        let mut small = Machine::with_memory(Box::new(LinearMemory::new(0x100)));
+        assert!(!press_key(&mut small, b'a'));
+        assert!(framebuffer(&small).iter().all(|p| *p == 0));
+    }
+
+    #[test]
+    fn test_take_beep() {
+        let mut vm = Machine::new();
+        assert_eq!(take_beep(&mut vm), None);
+
+        vm.memory.write2(BEEPER_FREQUENCY_ADDR, 440);
+        vm.memory.write2(BEEPER_DURATION_ADDR, 250);
+        assert_eq!(
+            take_beep(&mut vm),
+            Some(Beep {
+                frequency: 440,
+                duration_ms: 250
+            })
+        );
+        // Taking a beep acknowledges it
+        assert_eq!(vm.memory.read2(BEEPER_DURATION_ADDR), Some(0));
+        assert_eq!(take_beep(&mut vm), None);
+    }
+
+    #[test]
+    fn test_beep_samples() {
+        let beep = Beep {
+            frequency: 1000,
+            duration_ms: 10,
+        };
+        let samples = beep.samples(8000);
+        assert_eq!(samples.len(), 80);
+        // 8 samples per period: 4 high, then 4 low
+        assert!(samples[..4].iter().all(|s| *s > 0.0));
+        assert!(samples[4..8].iter().all(|s| *s < 0.0));
+        assert!(samples[8] > 0.0);
+
+        let rest = Beep {
+            frequency: 0,
+            duration_ms: 10,
+        };
+        assert!(rest.samples(8000).iter().all(|s| *s == 0.0));
+    }
+
+    #[test]
+    fn test_device_at() {
+        assert_eq!(device_at(0x1FFF), Some("framebuffer"));
+        assert_eq!(device_at(FRAMEBUFFER_ADDR), Some("framebuffer"));
+        assert_eq!(device_at(KEYBOARD_ADDR), Some("keyboard"));
+        assert_eq!(device_at(KEYBOARD_ADDR + 1), None);
+        assert_eq!(device_at(BEEPER_FREQUENCY_ADDR), Some("beeper"));
+        assert_eq!(device_at(BEEPER_DURATION_ADDR + 1), Some("beeper"));
+        assert_eq!(device_at(MAILBOX_OUT_ADDR), Some("mailbox"));
+        assert_eq!(device_at(MAILBOX_RECEIVE_ADDR), Some("mailbox"));
+        assert_eq!(device_at(MAILBOX_OUT_ADDR - 1), None);
+        assert_eq!(device_at(0x1000), None);
+    }
+
+    #[test]
+    fn test_key_queue_waits_for_acknowledgement() {
+        let mut vm = Machine::new();
+        let mut keys = KeyQueue::default();
+        keys.push_str("hi");
+        keys.push(0x1B);
+
+        assert_eq!(keys.pump(&mut vm), Some(b'h'));
+        // Not acknowledged yet, so the next key waits
+        assert_eq!(keys.pump(&mut vm), None);
+        assert_eq!(vm.memory.read(KEYBOARD_ADDR), Some(b'h'));
+
+        vm.memory.write(KEYBOARD_ADDR, 0);
+        assert_eq!(keys.pump(&mut vm), Some(b'i'));
+        vm.memory.write(KEYBOARD_ADDR, 0);
+        assert_eq!(keys.pump(&mut vm), Some(0x1B));
+        vm.memory.write(KEYBOARD_ADDR, 0);
+        assert!(keys.is_empty());
+        assert_eq!(keys.pump(&mut vm), None);
+    }
+
+    #[test]
+    fn test_mail_waits_for_the_receiver() {
+        let mut sender = Machine::new();
+        let mut receiver = Machine::new();
+        // Nothing to send yet
+        assert_eq!(exchange_mail(&mut sender, &mut receiver), None);
+
+        sender.memory.write2(MAILBOX_OUT_ADDR, 0x1234);
+        sender.memory.write(MAILBOX_SEND_ADDR, 1);
+        assert_eq!(exchange_mail(&mut sender, &mut receiver), Some(0x1234));
+        assert_eq!(sender.memory.read(MAILBOX_SEND_ADDR), Some(0));
+        assert_eq!(receiver.memory.read2(MAILBOX_IN_ADDR), Some(0x1234));
+        assert_eq!(receiver.memory.read(MAILBOX_RECEIVE_ADDR), Some(1));
+
+        // The second message waits until the first has been taken
+        sender.memory.write2(MAILBOX_OUT_ADDR, 0x0042);
+        sender.memory.write(MAILBOX_SEND_ADDR, 1);
+        assert_eq!(exchange_mail(&mut sender, &mut receiver), None);
+        assert_eq!(sender.memory.read(MAILBOX_SEND_ADDR), Some(1));
+        receiver.memory.write(MAILBOX_RECEIVE_ADDR, 0);
+        assert_eq!(exchange_mail(&mut sender, &mut receiver), Some(0x0042));
+        assert_eq!(receiver.memory.read2(MAILBOX_IN_ADDR), Some(0x0042));
+    }
+}
@@ -0,0 +1,405 @@
+//! Differential execution: runs two machines in lockstep and reports the
+//! first point where their registers, memory or step results disagree.
+//!
+//! This is meant for checking a new backend (for example [`PagedMemory`]
+//! against [`LinearMemory`](crate::LinearMemory)) against a known-good one:
+//! load the same program into both, then call [`lockstep`].
+//!
+//! The same machinery checks determinism: [`record_run`] runs a machine to
+//! the end and fingerprints its trace and final state, so two runs from the
+//! same starting state can be compared, and [`first_difference`] finds the
+//! step where their traces part ways.
+//!
+//! For tests, [`Snapshot::diff`] lists every difference between two states,
+//! and [`ExpectedState`] (behind the [`assert_state!`](crate::assert_state)
+//! macro) checks a machine against the values a test expects, reporting
+//! each mismatch rather than the first.
+//!
+//! [`PagedMemory`]: crate::PagedMemory
+
+use std::{
+    fmt,
+    hash::{DefaultHasher, Hasher},
+};
+
+use crate::{
+    Machine, Register,
+    snapshot::Snapshot,
+    trace::{Recorder, Trace},
+};
+
+/// What differed between the two machines.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Difference {
+    /// A register holds different values
+    Register {
+        register: Register,
+        left: u16,
+        right: u16,
+    },
+    /// A memory byte differs; `None` means the address is out of range
+    Memory {
+        address: u16,
+        left: Option<u8>,
+        right: Option<u8>,
+    },
+    /// One machine halted, or failed, where the other didn't
+    Status {
+        left: Result<bool, String>,
+        right: Result<bool, String>,
+    },
+}
+
+/// The first divergence found by [`lockstep`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Divergence {
+    /// Number of instructions both machines executed, including the one that diverged
+    pub step: u64,
+    /// Address of the instruction that diverged
+    pub pc: u16,
+    pub difference: Difference,
+}
+
+impl fmt::Display for Divergence {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "step {} at PC=0x{:04X}: ", self.step, self.pc)?;
+        let byte = |b: &Option<u8>| b.map_or("--".to_string(), |b| format!("0x{:02X}", b));
+        let status = |s: &Result<bool, String>| match s {
+            Ok(true) => "halted".to_string(),
+            Ok(false) => "running".to_string(),
+            Err(e) => format!("error ({})", e),
+        };
+        match &self.difference {
+            Difference::Register {
+                register,
+                left,
+                right,
+            } => write!(f, "{:?} is 0x{:04X} vs 0x{:04X}", register, left, right),
+            Difference::Memory {
+                address,
+                left,
+                right,
+            } => write!(
+                f,
+                "memory 0x{:04X} is {} vs {}",
+                address,
+                byte(left),
+                byte(right)
+            ),
+            Difference::Status { left, right } => {
+                write!(f, "{} vs {}", status(left), status(right))
+            }
+        }
+    }
+}
+
+/// Finds the first difference between the current states of two machines.
+pub fn compare(left: &Machine, right: &Machine) -> Option<Difference> {
+    for register in Register::ALL {
+        let (l, r) = (
+            left.registers[register as usize],
+            right.registers[register as usize],
+        );
+        if l != r {
+            return Some(Difference::Register {
+                register,
+                left: l,
+                right: r,
+            });
+        }
+    }
+
+    let size = left.memory.size().max(right.memory.size());
+    (0..size).find_map(|address| {
+        let address = address as u16;
+        let (l, r) = (left.memory.read(address), right.memory.read(address));
+        (l != r).then_some(Difference::Memory {
+            address,
+            left: l,
+            right: r,
+        })
+    })
+}
+
+/// Steps both machines together until both halt, both fail the same way,
+/// `max_steps` run out, or they diverge. Returns the first divergence and the
+/// number of steps executed.
+pub fn lockstep(
+    left: &mut Machine,
+    right: &mut Machine,
+    max_steps: u64,
+) -> (Option<Divergence>, u64) {
+    let mut step = 0;
+    if let Some(difference) = compare(left, right) {
+        let pc = left.get_register(Register::PC);
+        return (
+            Some(Divergence {
+                step,
+                pc,
+                difference,
+            }),
+            step,
+        );
+    }
+
+    while step < max_steps && !(left.halt && right.halt) {
+        step += 1;
+        let pc = left.get_register(Register::PC);
+        let l = left.step().map(|_| left.halt);
+        let r = right.step().map(|_| right.halt);
+
+        let difference = if l != r {
+            Some(Difference::Status {
+                left: l.clone(),
+                right: r,
+            })
+        } else {
+            compare(left, right)
+        };
+        if let Some(difference) = difference {
+            return (
+                Some(Divergence {
+                    step,
+                    pc,
+                    difference,
+                }),
+                step,
+            );
+        }
+        if l.is_err() {
+            break;
+        }
+    }
+    (None, step)
+}
+
+/// A summary of a complete run, equal for two runs that behaved the same.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Fingerprint {
+    /// Number of instructions executed
+    pub steps: u64,
+    /// How the run ended: whether the machine halted, or the error of the
+    /// step that failed
+    pub status: Result<bool, String>,
+    /// Hash of the recorded trace
+    pub trace_hash: u64,
+    /// Hash of the final registers, halt flag and memory
+    pub state_hash: u64,
+}
+
+/// Runs a machine until it halts, fails or `max_steps` run out, recording
+/// every step. Returns the fingerprint of the run and its trace.
+pub fn record_run(vm: &mut Machine, max_steps: u64) -> (Fingerprint, Trace) {
+    let mut recorder = Recorder::attach(vm);
+    let mut steps = 0;
+    let mut status = Ok(vm.halt);
+    while steps < max_steps && !vm.halt {
+        steps += 1;
+        let pc = vm.get_register(Register::PC);
+        let result = vm.step();
+        recorder.record(vm, pc);
+        status = result.map(|_| vm.halt);
+        if status.is_err() {
+            break;
+        }
+    }
+
+    let trace = recorder.finish();
+    let fingerprint = Fingerprint {
+        steps,
+        status,
+        trace_hash: hash(&trace.encode()),
+        state_hash: hash(
+            &vm.snapshot()
+                .encode()
+                .expect("a machine's own snapshot has no devices to fail on"),
+        ),
+    };
+    (fingerprint, trace)
+}
+
+/// Number of the first step that differs between two traces, counted from
+/// 1, including a step that only one of them has; 0 if they start from
+/// different registers. `None` if the traces are identical.
+pub fn first_difference(left: &Trace, right: &Trace) -> Option<usize> {
+    if left.initial != right.initial {
+        return Some(0);
+    }
+    let common = left.steps.len().min(right.steps.len());
+    (0..common)
+        .find(|idx| left.steps[*idx] != right.steps[*idx])
+        .or((left.steps.len() != right.steps.len()).then_some(common))
+        .map(|idx| idx + 1)
+}
+
+/// Hashes bytes the same way in every run of the same build.
+fn hash(bytes: &[u8]) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    hasher.write(bytes);
+    hasher.finish()
+}
+
+/// A run of adjacent memory bytes that differ between two states.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct MemoryDifference {
+    pub start: u16,
+    /// The bytes on each side; `None` past the end of the smaller memory
+    pub left: Vec<Option<u8>>,
+    pub right: Vec<Option<u8>>,
+}
+
+/// Every difference between two machine states.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct StateDiff {
+    /// Registers that differ, with the left and right values
+    pub registers: Vec<(Register, u16, u16)>,
+    /// The halt flags, if they differ
+    pub halt: Option<(bool, bool)>,
+    /// Differing memory, one entry per run of adjacent bytes
+    pub memory: Vec<MemoryDifference>,
+}
+
+impl StateDiff {
+    /// Returns true if the states are the same.
+    pub fn is_empty(&self) -> bool {
+        self.registers.is_empty() && self.halt.is_none() && self.memory.is_empty()
+    }
+}
+
+/// Formats bytes as hex pairs, `--` for bytes outside memory.
+fn hex_bytes(bytes: &[Option<u8>]) -> String {
+    bytes
+        .iter()
+        .map(|b| b.map_or("--".to_string(), |b| format!("{:02X}", b)))
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// One line per difference, left value first.
+impl fmt::Display for StateDiff {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (register, left, right) in &self.registers {
+            writeln!(f, "{:?}: 0x{:04X} vs 0x{:04X}", register, left, right)?;
+        }
+        if let Some((left, right)) = self.halt {
+            writeln!(f, "halt: {} vs {}", left, right)?;
+        }
+        for range in &self.memory {
+            writeln!(
+                f,
+                "mem 0x{:04X}..0x{:04X}: {} vs {}",
+                range.start,
+                range.start as usize + range.left.len(),
+                hex_bytes(&range.left),
+                hex_bytes(&range.right)
+            )?;
+        }
+        Ok(())
+    }
+}
+
+impl Snapshot {
+    /// Lists every register, the halt flag and every run of memory that
+    /// differs from `other`.
+    pub fn diff(&self, other: &Snapshot) -> StateDiff {
+        let registers = Register::ALL
+            .into_iter()
+            .map(|reg| {
+                (
+                    reg,
+                    self.registers[reg as usize],
+                    other.registers[reg as usize],
+                )
+            })
+            .filter(|(_, left, right)| left != right)
+            .collect();
+        let halt = (self.halt != other.halt).then_some((self.halt, other.halt));
+
+        let mut memory: Vec<MemoryDifference> = Vec::new();
+        for address in 0..self.memory.len().max(other.memory.len()) {
+            let (left, right) = (self.memory.get(address), other.memory.get(address));
+            if left == right {
+                continue;
+            }
+            match memory.last_mut() {
+                Some(range) if range.start as usize + range.left.len() == address => {
+                    range.left.push(left.copied());
+                    range.right.push(right.copied());
+                }
+                _ => memory.push(MemoryDifference {
+                    start: address as u16,
+                    left: vec![left.copied()],
+                    right: vec![right.copied()],
+                }),
+            }
+        }
+
+        StateDiff {
+            registers,
+            halt,
+            memory,
+        }
+    }
+}
+
+/// Register values and memory contents a test expects a machine to have.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ExpectedState {
+    pub registers: Vec<(Register, u16)>,
+    /// Bytes expected from each address on
+    pub memory: Vec<(u16, Vec<u8>)>,
+}
+
+impl ExpectedState {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Expects a register to hold `value`.
+    pub fn register(&mut self, register: Register, value: u16) -> &mut Self {
+        self.registers.push((register, value));
+        self
+    }
+
+    /// Expects memory from `address` on to hold `bytes`.
+    pub fn memory(&mut self, address: u16, bytes: &[u8]) -> &mut Self {
+        self.memory.push((address, bytes.to_vec()));
+        self
+    }
+
+    /// Checks the machine, returning one line for each expectation it
+    /// does not meet, e.g. `A: expected 0x001F (31), got 0x001E (30)`.
+    pub fn check(&self, vm: &Machine) -> Vec<String> {
+        let mut failures = Vec::new();
+        for (register, expected) in &self.registers {
+            let actual = vm.get_register(*register);
+            if actual != *expected {
+                failures.push(format!(
+                    "{:?}: expected 0x{:04X} ({}), got 0x{:04X} ({})",
+                    register, expected, expected, actual, actual
+                ));
+            }
+        }
+
+        for (address, expected) in &self.memory {
+            let actual: Vec<Option<u8>> = (0..expected.len())
+                .map(|i| vm.memory.read(address.wrapping_add(i as u16)))
+                .collect();
+            if actual.iter().zip(expected).any(|(a, e)| *a != Some(*e)) {
+                let expected: Vec<Option<u8>> = expected.iter().copied().map(Some).collect();
+                failures.push(format!(
+                    "mem 0x{:04X}: expected {}, got {}",
+                    address,
+                    hex_bytes(&expected),
+                    hex_bytes(&actual)
+                ));
+            }
+        }
+        failures
+    }
+}
@@ -0,0 +1,188 @@
+//! Unit tests for differential execution.
+//!
+//! This file runs programs on linear and paged memory side by side and
+//! checks that real differences are reported at the right step.
+
+#[cfg(test)]
+mod tests {
+    use crate::diff::{
+        Difference, ExpectedState, MemoryDifference, first_difference, lockstep, record_run,
+    };
+    use std::sync::atomic::{AtomicU16, Ordering};
+
+    use crate::test_support::push_pop_program;
+    use crate::{Addressable, LinearMemory, Machine, PagedMemory, Register};
+
+    fn machine(memory: Box<dyn Addressable>) -> Machine {
+        let mut vm = Machine::with_memory(memory).with_halt_handler();
+        vm.load_program(&push_pop_program())
+            .expect("Failed to load program");
+        vm
+    }
+
+    #[test]
+    fn test_linear_and_paged_memory_agree() {
+        let mut left = machine(Box::new(LinearMemory::new(8 * 1024)));
+        let mut right = machine(Box::new(PagedMemory::new(8 * 1024)));
+
+        let (divergence, steps) = lockstep(&mut left, &mut right, 100);
+        assert_eq!(divergence, None);
+        assert_eq!(steps, 4);
+        assert!(left.halt && right.halt);
+    }
+
+    #[test]
+    fn test_divergence_in_registers() {
+        let mut left = machine(Box::new(LinearMemory::new(8 * 1024)));
+        let mut right = machine(Box::new(LinearMemory::new(8 * 1024)));
+        // The second machine's halt handler also touches a register
+        right.define_handler(0x09, |vm| {
+            vm.registers[Register::B as usize] = 1;
+            vm.halt = true;
+            Ok(())
+        });
+
+        let (divergence, steps) = lockstep(&mut left, &mut right, 100);
+        let divergence = divergence.expect("Machines should diverge");
+        assert_eq!((divergence.step, divergence.pc, steps), (4, 0x0006, 4));
+        assert_eq!(
+            divergence.difference,
+            Difference::Register {
+                register: Register::B,
+                left: 0,
+                right: 1,
+            }
+        );
+    }
+
+    #[test]
+    fn test_divergence_in_status() {
+        let mut left = machine(Box::new(LinearMemory::new(8 * 1024)));
+        let mut right = machine(Box::new(LinearMemory::new(8 * 1024)));
+        right.define_handler(0x09, |_| Err("no halt here".to_string()));
+
+        let (divergence, _) = lockstep(&mut left, &mut right, 100);
+        let divergence = divergence.expect("Machines should diverge");
+        assert_eq!(
+            divergence.to_string(),
+            "step 4 at PC=0x0006: halted vs error (no halt here)"
+        );
+    }
+
+    #[test]
+    fn test_divergence_in_memory_size() {
+        let mut left = machine(Box::new(LinearMemory::new(8 * 1024)));
+        let mut right = machine(Box::new(PagedMemory::new(0x1001)));
+
+        // Memories of different sizes differ before anything runs
+        let (divergence, _) = lockstep(&mut left, &mut right, 100);
+        let divergence = divergence.expect("Machines should diverge");
+        assert_eq!(divergence.step, 0);
+        assert_eq!(
+            divergence.to_string(),
+            "step 0 at PC=0x0000: memory 0x1001 is 0x00 vs --"
+        );
+    }
+
+    #[test]
+    fn test_repeated_runs_match() {
+        let mut first = machine(Box::new(LinearMemory::new(8 * 1024)));
+        let mut second = machine(Box::new(LinearMemory::new(8 * 1024)));
+
+        let (left, left_trace) = record_run(&mut first, 100);
+        let (right, right_trace) = record_run(&mut second, 100);
+        assert_eq!(left, right);
+        assert_eq!((left.steps, left.status), (4, Ok(true)));
+        assert_eq!(first_difference(&left_trace, &right_trace), None);
+    }
+
+    #[test]
+    fn test_nondeterministic_runs_differ() {
+        static CALLS: AtomicU16 = AtomicU16::new(0);
+        let nondeterministic = |memory: Box<dyn Addressable>| {
+            let mut vm = machine(memory);
+            vm.define_handler(0x09, |vm| {
+                vm.registers[Register::B as usize] = CALLS.fetch_add(1, Ordering::Relaxed);
+                vm.halt = true;
+                Ok(())
+            });
+            vm
+        };
+        let mut first = nondeterministic(Box::new(LinearMemory::new(8 * 1024)));
+        let mut second = nondeterministic(Box::new(LinearMemory::new(8 * 1024)));
+
+        let (left, left_trace) = record_run(&mut first, 100);
+        let (right, right_trace) = record_run(&mut second, 100);
+        assert_ne!(left.trace_hash, right.trace_hash);
+        assert_ne!(left.state_hash, right.state_hash);
+        // The signal is the fourth step
+        assert_eq!(first_difference(&left_trace, &right_trace), Some(4));
+    }
+
+    #[test]
+    fn test_state_diff_groups_memory() {
+        let left = Machine::new();
+        let mut right = Machine::new();
+        assert!(left.snapshot().diff(&right.snapshot()).is_empty());
+
+        right.registers[Register::B as usize] = 2;
+        right.halt = true;
+        right.memory.write(0x0100, 1);
+        right.memory.write(0x0101, 2);
+        right.memory.write(0x0200, 3);
+
+        let diff = left.snapshot().diff(&right.snapshot());
+        assert_eq!(diff.registers, vec![(Register::B, 0, 2)]);
+        assert_eq!(diff.halt, Some((false, true)));
+        assert_eq!(
+            diff.memory,
+            vec![
+                MemoryDifference {
+                    start: 0x0100,
+                    left: vec![Some(0), Some(0)],
+                    right: vec![Some(1), Some(2)],
+                },
+                MemoryDifference {
+                    start: 0x0200,
+                    left: vec![Some(0)],
+                    right: vec![Some(3)],
+                },
+            ]
+        );
+        assert_eq!(
+            diff.to_string(),
+            "B: 0x0000 vs 0x0002\nhalt: false vs true\n\
+             mem 0x0100..0x0102: 00 00 vs 01 02\nmem 0x0200..0x0201: 00 vs 03\n"
+        );
+    }
+
+    #[test]
+    fn test_expected_state_reports_every_mismatch() {
+        let mut vm = Machine::new();
+        vm.registers[Register::A as usize] = 30;
+        vm.memory.write2(0x1000, 20);
+
+        let mut expected = ExpectedState::new();
+        expected.register(Register::A, 30).memory(0x1000, &[20, 0]);
+        assert!(expected.check(&vm).is_empty());
+
+        expected.register(Register::B, 1).memory(0x1FFF, &[0, 0]);
+        assert_eq!(
+            expected.check(&vm),
+            vec![
+                "B: expected 0x0001 (1), got 0x0000 (0)".to_string(),
+                "mem 0x1FFF: expected 00 00, got 00 --".to_string(),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_assert_state_macro() {
+        let mut vm = Machine::new();
+        vm.registers[Register::R3 as usize] = 7;
+        vm.memory.write(0x0100, 0xAB);
+        crate::assert_state!(vm, R3 = 7, [0x0100] = [0xAB, 0], SP = 0x1000,);
+        let result = std::panic::catch_unwind(|| crate::assert_state!(Machine::new(), A = 1));
+        assert!(result.is_err());
+    }
+}
@@ -0,0 +1,45 @@
+//! Entry points for fuzzing.
+//!
+//! The targets under `fuzz/` are thin wrappers around these functions, so
+//! they can also be driven from ordinary tests. Each function must return
+//! normally for every input; a panic is a bug.
+
+use crate::{Machine, asm, halt};
+
+/// Instructions executed per input before [`machine`] gives up.
+pub const FUEL: u64 = 10_000;
+
+/// Loads arbitrary bytes as a program and runs it until it halts, fails or
+/// runs out of fuel. Returns the number of instructions executed.
+pub fn machine(data: &[u8]) -> u64 {
+    let mut vm = Machine::new();
+    vm.define_handler(0x09, halt);
+    if vm.load_program(data).is_err() {
+        return 0;
+    }
+
+    match vm.run_with_fuel(FUEL) {
+        Ok(reason) => reason.steps(),
+        Err(e) => e.steps,
+    }
+}
+
+/// Disassembles arbitrary bytes and, when they decode, assembles the result
+/// again. The reassembled program must decode to the same instructions; the
+/// bytes themselves can differ where the decoder ignores an argument byte.
+pub fn round_trip(data: &[u8]) {
+    let Ok(instructions) = asm::disassemble(data) else {
+        return;
+    };
+    let source: String = instructions.iter().map(|i| format!("{}\n", i)).collect();
+
+    let bytecode = asm::assemble_str(&source)
+        .unwrap_or_else(|e| panic!("disassembly does not assemble: {}\n{}", e, source));
+    let reassembled = asm::disassemble(&bytecode)
+        .unwrap_or_else(|e| panic!("reassembled program does not decode: {}\n{}", e, source));
+    assert_eq!(
+        reassembled, instructions,
+        "round trip changed the program:\n{}",
+        source
+    );
+}
@@ -0,0 +1,78 @@
+//! Unit tests for the fuzzing entry points.
+//!
+//! This file drives the fuzz targets with pseudo-random inputs, so the
+//! crashes fuzzing found stay fixed without needing cargo-fuzz.
+
+#[cfg(test)]
+mod tests {
+    use crate::{Op, Program, Register, fuzz};
+
+    /// Small xorshift generator, so the inputs are the same on every run.
+    fn inputs(count: usize) -> Vec<Vec<u8>> {
+        let mut state: u32 = 0x2545_F491;
+        let mut next = move || {
+            state ^= state << 13;
+            state ^= state >> 17;
+            state ^= state << 5;
+            state
+        };
+
+        (0..count)
+            .map(|_| {
+                let len = (next() % 64) as usize;
+                // Bias opcodes towards valid ones so execution gets somewhere
+                (0..len)
+                    .map(|i| match i % 2 {
+                        0 => [0x00, 0x01, 0x02, 0x03, 0x04, 0x05, 0x09, 0x0F][next() as usize % 8],
+                        _ => next() as u8,
+                    })
+                    .collect()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_machine_survives_random_programs() {
+        for input in inputs(2000) {
+            fuzz::machine(&input);
+        }
+    }
+
+    #[test]
+    fn test_machine_arithmetic_wraps() {
+        // 0xFF + 0xFF repeatedly overflowed the 16-bit addition
+        let mut program = Vec::new();
+        for _ in 0..300 {
+            program.extend(Op::Push(0xFF).to_bytes());
+            program.extend(Op::PushRegister(Register::A).to_bytes());
+            program.extend(Op::AddStack.to_bytes());
+            program.extend(Op::PopRegister(Register::A).to_bytes());
+            program.extend(Op::AddRegister(Register::A, Register::A).to_bytes());
+        }
+        fuzz::machine(&program);
+    }
+
+    #[test]
+    fn test_machine_stack_underflow() {
+        // Popping past the bottom of memory used to underflow SP
+        let mut program = Vec::new();
+        for _ in 0..3000 {
+            program.extend(Op::PopRegister(Register::A).to_bytes());
+        }
+        fuzz::machine(&program);
+    }
+
+    #[test]
+    fn test_round_trip_random_programs() {
+        for input in inputs(2000) {
+            fuzz::round_trip(&input);
+            fuzz::round_trip(&Program::encode(0, &input));
+        }
+    }
+
+    #[test]
+    fn test_round_trip_jump_outside_the_image() {
+        // A jump to an address no instruction starts at
+        fuzz::round_trip(&[0x22, 0x67]);
+    }
+}
@@ -0,0 +1,169 @@
+//! Intel HEX encoding for program images.
+//!
+//! Intel HEX is a text format understood by most EEPROM programmers and
+//! embedded tooling. Each line is a record:
+//!
+//! ```text
+//! :LLAAAATTDD...CC
+//! ```
+//!
+//! with a byte count `LL`, a 16-bit address `AAAA`, a record type `TT`, the
+//! data bytes and a checksum `CC` that makes all bytes of the record sum to 0.
+//!
+//! Supported record types:
+//!
+//! | Type | Meaning                                           |
+//! | ---- | ------------------------------------------------- |
+//! | `00` | Data bytes at the record address                  |
+//! | `01` | End of file                                       |
+//! | `02` | Extended segment address (must be 0)              |
+//! | `03` | Start segment address, used as the entry point    |
+//! | `04` | Extended linear address (must be 0)               |
+//! | `05` | Start linear address, used as the entry point     |
+
+/// Number of data bytes written per record.
+const BYTES_PER_RECORD: usize = 16;
+
+const DATA: u8 = 0x00;
+const END_OF_FILE: u8 = 0x01;
+const EXTENDED_SEGMENT_ADDRESS: u8 = 0x02;
+const START_SEGMENT_ADDRESS: u8 = 0x03;
+const EXTENDED_LINEAR_ADDRESS: u8 = 0x04;
+const START_LINEAR_ADDRESS: u8 = 0x05;
+
+/// A contiguous memory image decoded from Intel HEX records.
+#[derive(Debug, PartialEq, Eq)]
+pub struct HexImage {
+    /// Address of the first byte of the image
+    pub address: u16,
+    /// Image bytes, with gaps between records filled with zeros
+    pub bytes: Vec<u8>,
+    /// Entry point from a start address record, if any
+    pub entry: Option<u16>,
+}
+
+/// Returns true if the input looks like Intel HEX text rather than a binary.
+pub fn is_ihex(bytes: &[u8]) -> bool {
+    bytes.trim_ascii_start().starts_with(b":")
+}
+
+/// Encodes bytes placed at `address` as Intel HEX, with a start address
+/// record when an entry point is given.
+pub fn encode(address: u16, bytes: &[u8], entry: Option<u16>) -> String {
+    let mut text = String::new();
+    for (i, chunk) in bytes.chunks(BYTES_PER_RECORD).enumerate() {
+        let offset = address.wrapping_add((i * BYTES_PER_RECORD) as u16);
+        push_record(&mut text, offset, DATA, chunk);
+    }
+    if let Some(entry) = entry {
+        // CS:IP with CS = 0, so IP is the entry point itself
+        let [hi, lo] = entry.to_be_bytes();
+        push_record(&mut text, 0, START_SEGMENT_ADDRESS, &[0, 0, hi, lo]);
+    }
+    push_record(&mut text, 0, END_OF_FILE, &[]);
+    text
+}
+
+fn push_record(text: &mut String, address: u16, kind: u8, data: &[u8]) {
+    let [hi, lo] = address.to_be_bytes();
+    let mut record = vec![data.len() as u8, hi, lo, kind];
+    record.extend(data);
+    let checksum = record
+        .iter()
+        .fold(0u8, |sum, byte| sum.wrapping_add(*byte))
+        .wrapping_neg();
+    record.push(checksum);
+
+    text.push(':');
+    for byte in record {
+        text.push_str(&format!("{:02X}", byte));
+    }
+    text.push('\n');
+}
+
+/// Decodes Intel HEX text into a single memory image.
+pub fn decode(text: &str) -> Result<HexImage, String> {
+    let mut chunks: Vec<(u16, Vec<u8>)> = Vec::new();
+    let mut entry = None;
+    let mut finished = false;
+
+    for (idx, line) in text.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() {
+            continue;
+        }
+        let error = |message: &str| format!("Intel HEX line {}: {}", idx + 1, message);
+
+        if finished {
+            return Err(error("record after end of file"));
+        }
+        let digits = line
+            .strip_prefix(':')
+            .ok_or_else(|| error("record does not start with `:`"))?;
+        if digits.len() % 2 != 0 || !digits.is_ascii() {
+            return Err(error("malformed record"));
+        }
+        let record = (0..digits.len())
+            .step_by(2)
+            .map(|i| u8::from_str_radix(&digits[i..i + 2], 16))
+            .collect::<Result<Vec<u8>, _>>()
+            .map_err(|_| error("invalid hex digit"))?;
+
+        if record.len() < 5 || record.len() != record[0] as usize + 5 {
+            return Err(error("byte count does not match record length"));
+        }
+        if record.iter().fold(0u8, |sum, byte| sum.wrapping_add(*byte)) != 0 {
+            return Err(error("checksum mismatch"));
+        }
+
+        let address = u16::from_be_bytes([record[1], record[2]]);
+        let data = &record[4..record.len() - 1];
+        match record[3] {
+            DATA => {
+                if address as usize + data.len() > 0x10000 {
+                    return Err(error("data runs past address 0xFFFF"));
+                }
+                chunks.push((address, data.to_vec()));
+            }
+            END_OF_FILE => finished = true,
+            EXTENDED_SEGMENT_ADDRESS | EXTENDED_LINEAR_ADDRESS => {
+                if data.iter().any(|byte| *byte != 0) {
+                    return Err(error("addresses above 0xFFFF are not supported"));
+                }
+            }
+            START_SEGMENT_ADDRESS | START_LINEAR_ADDRESS if data.len() == 4 => {
+                if data[..2] != [0, 0] {
+                    return Err(error("start address above 0xFFFF is not supported"));
+                }
+                entry = Some(u16::from_be_bytes([data[2], data[3]]));
+            }
+            kind => return Err(error(&format!("unsupported record type {:02X}", kind))),
+        }
+    }
+
+    if !finished {
+        return Err("Intel HEX: missing end of file record".to_string());
+    }
+
+    let start = chunks
+        .iter()
+        .map(|(address, _)| *address)
+        .min()
+        .unwrap_or(0);
+    let end = chunks
+        .iter()
+        .map(|(address, data)| *address as usize + data.len())
+        .max()
+        .unwrap_or(start as usize);
+    let mut bytes = vec![0; end - start as usize];
+    for (address, data) in chunks {
+        let offset = (address - start) as usize;
+        bytes[offset..offset + data.len()].copy_from_slice(&data);
+    }
+
+    Ok(HexImage {
+        address: start,
+        bytes,
+        entry,
+    })
+}
@@ -0,0 +1,65 @@
+//! Unit tests for the Intel HEX module.
+//!
+//! This file checks that images round-trip through Intel HEX records and
+//! that malformed records are rejected.
+
+#[cfg(test)]
+mod tests {
+    use crate::ihex::{self, HexImage};
+
+    #[test]
+    fn test_encode_records() {
+        let text = ihex::encode(0x0100, &[0x01, 0x2A, 0x09, 0x09], Some(0x0102));
+        assert_eq!(
+            text,
+            ":04010000012A0909BE\n:0400000300000102F6\n:00000001FF\n"
+        );
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let bytes: Vec<u8> = (0..40).collect();
+        let text = ihex::encode(0x0200, &bytes, None);
+        assert_eq!(text.lines().count(), 4);
+
+        let image = ihex::decode(&text).expect("Failed to decode");
+        assert_eq!(
+            image,
+            HexImage {
+                address: 0x0200,
+                bytes,
+                entry: None,
+            }
+        );
+    }
+
+    #[test]
+    fn test_decode_fills_gaps() {
+        let text = ":0100100001EE\n:02000000AABB99\n:00000001FF\n";
+        let image = ihex::decode(text).expect("Failed to decode");
+        assert_eq!(image.address, 0);
+        assert_eq!(image.bytes.len(), 0x11);
+        assert_eq!(&image.bytes[..3], &[0xAA, 0xBB, 0]);
+        assert_eq!(image.bytes[0x10], 0x01);
+    }
+
+    #[test]
+    fn test_decode_errors() {
+        // Bad checksum
+        assert!(ihex::decode(":02000000AABB00\n:00000001FF\n").is_err());
+        // Byte count does not match the data
+        assert!(ihex::decode(":03000000AABB98\n:00000001FF\n").is_err());
+        // Missing end of file record
+        assert!(ihex::decode(":02000000AABB99\n").is_err());
+        // Address above 16 bits
+        assert!(ihex::decode(":020000040001F9\n:00000001FF\n").is_err());
+        // Not a record
+        assert!(ihex::decode("01 02\n").is_err());
+    }
+
+    #[test]
+    fn test_is_ihex() {
+        assert!(ihex::is_ihex(b"\n:00000001FF\n"));
+        assert!(!ihex::is_ihex(&[0x01, 0x2A]));
+    }
+}
@@ -0,0 +1,372 @@
+//! Machine-readable description of the instruction set.
+//!
+//! [`instructions`] describes every [`Op`], derived from the opcode enum
+//! itself: the mnemonic, opcode and operand come from its
+//! `define_instructions!` list, and adding a variant does not compile until
+//! its stack effect and summary are described here.
+//! External assemblers, syntax highlighters and the docs read the JSON form
+//! from `asm --dump-isa` instead of copying the tables by hand.
+//!
+//! A machine can also be limited to part of the set with an [`IsaProfile`],
+//! so a course can introduce instructions a few at a time, or a program can
+//! be checked against a smaller machine. Instructions outside the profile
+//! fault as illegal when executed.
+//!
+//! Instructions are two bytes, the opcode followed by its argument, except
+//! `LDI`, `LOADO`, `STOREO` and `JMPL`, which carry a 16-bit value in two
+//! more. Each takes one step.
+//! Additions, subtractions, `CMP` and `TEST` list the FLAGS bits they set; the flag list
+//! of every other instruction is empty.
+
+use std::collections::BTreeSet;
+
+use crate::{Op, Register};
+
+/// What the argument byte of an instruction holds.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Operand {
+    /// The argument is ignored and assembled as 0
+    None,
+    /// An 8-bit immediate value
+    Byte,
+    /// A register number
+    Register,
+    /// Two register numbers, the first in the upper 4 bits
+    RegisterPair,
+    /// Ignored and assembled as 0, followed by a 16-bit value in the next two bytes
+    Word,
+    /// A register number, followed by a 16-bit value in the next two bytes
+    RegisterWord,
+    /// Two register numbers, followed by a 16-bit value in the next two bytes
+    RegisterPairWord,
+}
+
+impl Operand {
+    /// Name used in the JSON description.
+    pub fn name(&self) -> &'static str {
+        match self {
+            Operand::None => "none",
+            Operand::Byte => "byte",
+            Operand::Register => "register",
+            Operand::RegisterPair => "register_pair",
+            Operand::Word => "word",
+            Operand::RegisterWord => "register_word",
+            Operand::RegisterPairWord => "register_pair_word",
+        }
+    }
+}
+
+/// One instruction of the ISA.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct InstructionSpec {
+    /// Mnemonic the assembler accepts and the disassembler prints
+    pub mnemonic: &'static str,
+    /// Value of the opcode byte
+    pub opcode: u8,
+    /// What the argument byte holds
+    pub operand: Operand,
+    /// Bytes the instruction takes in memory
+    pub bytes: u16,
+    /// Values popped from the stack
+    pub pops: u8,
+    /// Values pushed onto the stack
+    pub pushes: u8,
+    /// Flags the instruction may change
+    pub flags: &'static [&'static str],
+    /// Steps the instruction takes
+    pub cycles: u8,
+    /// One-line description
+    pub summary: &'static str,
+}
+
+/// Flags set by additions and subtractions, see [`Flag`](crate::Flag).
+const ARITHMETIC_FLAGS: [&str; 4] = ["zero", "carry", "negative", "overflow"];
+
+/// Describes one operation. The match is exhaustive, so a new opcode must
+/// be described before the crate builds.
+pub fn describe(op: &Op) -> InstructionSpec {
+    let (pops, pushes, summary) = match op {
+        Op::Nop => (0, 0, "Does nothing"),
+        Op::Push(_) => (0, 1, "Pushes the argument"),
+        Op::PopRegister(_) => (1, 0, "Pops into a register"),
+        Op::PushRegister(_) => (0, 1, "Pushes a register"),
+        Op::AddStack => (2, 1, "Pops two values and pushes their wrapping sum"),
+        Op::AddRegister(..) => (0, 0, "Adds the second register to the first, wrapping"),
+        Op::SubStack => (
+            2,
+            1,
+            "Pops two values and pushes the lower minus the top, wrapping",
+        ),
+        Op::SubRegister(..) => (
+            0,
+            0,
+            "Subtracts the second register from the first, wrapping",
+        ),
+        Op::MulWide(..) => (
+            0,
+            0,
+            "Multiplies two registers, unsigned, the low word into the first and the high word into M",
+        ),
+        Op::AndStack => (2, 1, "Pops two values and pushes their bitwise AND"),
+        Op::OrStack => (2, 1, "Pops two values and pushes their bitwise OR"),
+        Op::XorStack => (2, 1, "Pops two values and pushes their bitwise XOR"),
+        Op::NotStack => (1, 1, "Pops a value and pushes it with every bit inverted"),
+        Op::AndRegister(..) => (0, 0, "ANDs the second register into the first"),
+        Op::OrRegister(..) => (0, 0, "ORs the second register into the first"),
+        Op::XorRegister(..) => (0, 0, "XORs the second register into the first"),
+        Op::NotRegister(_) => (0, 0, "Inverts every bit of a register"),
+        Op::Dup => (1, 2, "Pushes a copy of the top value"),
+        Op::Swap => (2, 2, "Exchanges the top two values"),
+        Op::Drop => (1, 0, "Discards the top value"),
+        Op::Over => (2, 3, "Pushes a copy of the value below the top"),
+        Op::PushFlags => (0, 1, "Pushes the FLAGS register"),
+        Op::PopFlags => (1, 0, "Pops into the FLAGS register"),
+        Op::Jump(_) => (0, 0, "Continues at the address in the argument"),
+        Op::JumpLong(_) => (
+            0,
+            0,
+            "Continues at the 16-bit address in the next two bytes",
+        ),
+        Op::JumpRegister(_) => (0, 0, "Continues at the address in a register"),
+        Op::JumpZero(_) => (0, 0, "Jumps to the argument if the zero flag is set"),
+        Op::JumpNotZero(_) => (0, 0, "Jumps to the argument if the zero flag is clear"),
+        Op::JumpCarry(_) => (0, 0, "Jumps to the argument if the carry flag is set"),
+        Op::JumpNotCarry(_) => (0, 0, "Jumps to the argument if the carry flag is clear"),
+        Op::JumpLess(_) => (
+            0,
+            0,
+            "Jumps to the argument if the last subtraction's first operand was signed less",
+        ),
+        Op::JumpGreater(_) => (
+            0,
+            0,
+            "Jumps to the argument if the last subtraction's first operand was signed greater",
+        ),
+        Op::JumpLessEqual(_) => (
+            0,
+            0,
+            "Jumps to the argument if the last subtraction's first operand was signed less or equal",
+        ),
+        Op::JumpGreaterEqual(_) => (
+            0,
+            0,
+            "Jumps to the argument if the last subtraction's first operand was signed greater or equal",
+        ),
+        Op::Branch(_) => (
+            0,
+            0,
+            "Continues at PC plus the signed offset in the argument",
+        ),
+        Op::BranchZero(_) => (0, 0, "Branches by the argument if the zero flag is set"),
+        Op::BranchNotZero(_) => (0, 0, "Branches by the argument if the zero flag is clear"),
+        Op::BranchCarry(_) => (0, 0, "Branches by the argument if the carry flag is set"),
+        Op::BranchNotCarry(_) => (0, 0, "Branches by the argument if the carry flag is clear"),
+        Op::BranchLess(_) => (
+            0,
+            0,
+            "Branches by the argument if the last subtraction's first operand was signed less",
+        ),
+        Op::BranchGreater(_) => (
+            0,
+            0,
+            "Branches by the argument if the last subtraction's first operand was signed greater",
+        ),
+        Op::BranchLessEqual(_) => (
+            0,
+            0,
+            "Branches by the argument if the last subtraction's first operand was signed less or equal",
+        ),
+        Op::BranchGreaterEqual(_) => (
+            0,
+            0,
+            "Branches by the argument if the last subtraction's first operand was signed greater or equal",
+        ),
+        Op::CompareRegister(..) => (
+            0,
+            0,
+            "Sets the flags of subtracting the second register from the first, storing nothing",
+        ),
+        Op::MemCopy(..) => (
+            0,
+            0,
+            "Copies M bytes from the address in the second register to the address in the first",
+        ),
+        Op::MemSet(..) => (
+            0,
+            0,
+            "Fills M bytes at the address in the first register with the low byte of the second",
+        ),
+        Op::Test(..) => (
+            0,
+            0,
+            "Sets zero and negative from ANDing the two registers and clears carry and overflow, storing nothing",
+        ),
+        Op::MoveIfZero(..) => (
+            0,
+            0,
+            "Copies the second register into the first if the zero flag is set",
+        ),
+        Op::MoveIfNotZero(..) => (
+            0,
+            0,
+            "Copies the second register into the first if the zero flag is clear",
+        ),
+        Op::MoveRegister(..) => (0, 0, "Copies the second register into the first"),
+        Op::Load(_) => (0, 0, "Loads the word at the address in M into a register"),
+        Op::Store(_) => (0, 0, "Stores a register as a word at the address in M"),
+        Op::LoadIndirect(..) => (
+            0,
+            0,
+            "Loads the word at the address in the second register into the first",
+        ),
+        Op::StoreIndirect(..) => (
+            0,
+            0,
+            "Stores the first register as a word at the address in the second",
+        ),
+        Op::LoadOffset(..) => (
+            0,
+            0,
+            "Loads the word at the second register plus the 16-bit offset into the first",
+        ),
+        Op::StoreOffset(..) => (
+            0,
+            0,
+            "Stores the first register at the second register plus the 16-bit offset",
+        ),
+        Op::LoadImmediate(..) => (
+            0,
+            0,
+            "Loads the 16-bit value in the next two bytes into a register",
+        ),
+        Op::Signal(_) => (0, 0, "Calls the signal handler numbered by the argument"),
+        Op::Syscall(_) => (
+            0,
+            0,
+            "Calls the syscall handler numbered by the argument with A, B and C, result in A",
+        ),
+    };
+    let flags: &[&str] = match op {
+        Op::AddStack
+        | Op::AddRegister(..)
+        | Op::SubStack
+        | Op::SubRegister(..)
+        | Op::CompareRegister(..)
+        | Op::MulWide(..)
+        | Op::Test(..) => &ARITHMETIC_FLAGS,
+        _ => &[],
+    };
+    InstructionSpec {
+        mnemonic: op.mnemonic(),
+        opcode: op.value(),
+        operand: op.operand(),
+        bytes: op.size(),
+        pops,
+        pushes,
+        flags,
+        cycles: 1,
+        summary,
+    }
+}
+
+/// Every instruction, in opcode order.
+pub fn instructions() -> Vec<InstructionSpec> {
+    Op::examples().iter().map(describe).collect()
+}
+
+/// The instruction set as JSON: the instruction width, the registers in
+/// encoding order and every instruction.
+pub fn to_json() -> String {
+    let registers: Vec<String> = Register::ALL
+        .iter()
+        .map(|reg| format!("\"{}\"", reg))
+        .collect();
+    let instructions: Vec<String> = instructions()
+        .iter()
+        .map(|spec| {
+            let flags: Vec<String> = spec.flags.iter().map(|f| format!("\"{}\"", f)).collect();
+            format!(
+                "    {{\"mnemonic\": \"{}\", \"opcode\": {}, \"operand\": \"{}\", \"bytes\": {}, \"pops\": {}, \"pushes\": {}, \"flags\": [{}], \"cycles\": {}, \"summary\": \"{}\"}}",
+                spec.mnemonic,
+                spec.opcode,
+                spec.operand.name(),
+                spec.bytes,
+                spec.pops,
+                spec.pushes,
+                flags.join(", "),
+                spec.cycles,
+                spec.summary
+            )
+        })
+        .collect();
+    format!(
+        "{{\n  \"instruction_bytes\": 2,\n  \"registers\": [{}],\n  \"instructions\": [\n{}\n  ]\n}}\n",
+        registers.join(", "),
+        instructions.join(",\n")
+    )
+}
+
+/// The instructions a machine accepts.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct IsaProfile {
+    /// Opcodes outside the profile, none by default
+    excluded: BTreeSet<u8>,
+}
+
+impl IsaProfile {
+    /// Every instruction the VM implements.
+    pub fn full() -> Self {
+        Self::default()
+    }
+
+    /// Only the given instructions, by mnemonic in any case.
+    pub fn only(mnemonics: &[&str]) -> Result<Self, String> {
+        let mut allowed = BTreeSet::new();
+        for mnemonic in mnemonics {
+            allowed.insert(opcode_of(mnemonic)?);
+        }
+        Ok(Self {
+            excluded: instructions()
+                .iter()
+                .map(|spec| spec.opcode)
+                .filter(|opcode| !allowed.contains(opcode))
+                .collect(),
+        })
+    }
+
+    /// Parses a profile as the `vm` binary takes it: `base` for every
+    /// instruction, the only named profile so far, or a comma-separated
+    /// list of mnemonics.
+    pub fn parse(text: &str) -> Result<Self, String> {
+        match text {
+            "base" => Ok(Self::full()),
+            list => Self::only(&list.split(',').map(str::trim).collect::<Vec<_>>()),
+        }
+    }
+
+    /// Returns true if the profile accepts the operation.
+    pub fn allows(&self, op: &Op) -> bool {
+        !self.excluded.contains(&op.value())
+    }
+
+    /// Mnemonics of the instructions in the profile, in opcode order.
+    pub fn mnemonics(&self) -> Vec<&'static str> {
+        instructions()
+            .iter()
+            .filter(|spec| !self.excluded.contains(&spec.opcode))
+            .map(|spec| spec.mnemonic)
+            .collect()
+    }
+}
+
+/// Looks up an opcode by mnemonic.
+fn opcode_of(mnemonic: &str) -> Result<u8, String> {
+    instructions()
+        .iter()
+        .find(|spec| spec.mnemonic.eq_ignore_ascii_case(mnemonic))
+        .map(|spec| spec.opcode)
+        .ok_or_else(|| format!("unknown instruction in ISA profile - {}", mnemonic))
+}
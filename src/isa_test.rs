@@ -0,0 +1,106 @@
+//! Unit tests for the instruction set description.
+//!
+//! This file checks the description against the decoder, the assembler and
+//! the disassembler, so it cannot drift from the code.
+
+#[cfg(test)]
+mod tests {
+    use crate::asm::assemble_str;
+    use crate::asm::disassembler::{instruction_for, jump_target};
+    use crate::isa::{IsaProfile, Operand, describe, instructions, to_json};
+    use crate::{Machine, Op, Register};
+
+    #[test]
+    fn test_every_opcode_is_described() {
+        let described: Vec<u8> = instructions().iter().map(|spec| spec.opcode).collect();
+        // An opcode decodes, with some argument, exactly when it is described
+        for opcode in 0..=u8::MAX {
+            let decodes = (0..=u8::MAX).any(|arg| Op::from_bytes(&[opcode, arg, 0, 0]).is_ok());
+            assert_eq!(
+                decodes,
+                described.contains(&opcode),
+                "opcode 0x{:02X}",
+                opcode
+            );
+        }
+    }
+
+    #[test]
+    fn test_mnemonics_match_the_assembler() {
+        for op in Op::examples() {
+            let spec = describe(&op);
+            let operand = match spec.operand {
+                // The assembler takes jump targets as labels
+                Operand::Byte if jump_target(&op, 0).is_some() => " here",
+                Operand::RegisterPair
+                    if matches!(op, Op::LoadIndirect(..) | Op::StoreIndirect(..)) =>
+                {
+                    " B, [C]"
+                }
+                Operand::None => "",
+                Operand::Byte => " $01",
+                Operand::Register => " B",
+                Operand::RegisterPair => " B C",
+                Operand::Word if jump_target(&op, 0).is_some() => " here",
+                Operand::Word => " $1234",
+                Operand::RegisterWord => " B $1234",
+                Operand::RegisterPairWord => " B, [C+4]",
+            };
+            let source = format!("here:\n{}{}\n", spec.mnemonic, operand);
+            let bytecode = assemble_str(&source).unwrap();
+            assert_eq!(bytecode[0], spec.opcode, "{}", spec.mnemonic);
+
+            let op = Op::from_bytes(&bytecode).unwrap();
+            let text = instruction_for(&op, 0).to_string();
+            assert!(
+                text.starts_with(spec.mnemonic),
+                "{} vs {}",
+                text,
+                spec.mnemonic
+            );
+        }
+    }
+
+    #[test]
+    fn test_json_lists_everything() {
+        let json = to_json();
+        for spec in instructions() {
+            assert!(json.contains(&format!("\"mnemonic\": \"{}\"", spec.mnemonic)));
+        }
+        assert!(json.contains("\"registers\": [\"A\", \"B\""));
+        assert!(json.contains("\"R4\"]"));
+        assert!(json.contains(
+            "\"mnemonic\": \"LDI\", \"opcode\": 24, \"operand\": \"register_word\", \"bytes\": 4"
+        ));
+        assert!(json.contains("\"flags\": [\"zero\", \"carry\", \"negative\", \"overflow\"]"));
+    }
+
+    #[test]
+    fn test_profile_parsing() {
+        assert_eq!(IsaProfile::parse("base"), Ok(IsaProfile::full()));
+        let profile = IsaProfile::parse("push, pop,SIG").unwrap();
+        assert_eq!(profile.mnemonics(), vec!["PUSH", "POP", "SIG"]);
+        assert_eq!(
+            IsaProfile::parse("push,mul"),
+            Err("unknown instruction in ISA profile - mul".to_string())
+        );
+    }
+
+    #[test]
+    fn test_instructions_outside_the_profile_fault() {
+        let mut vm = Machine::new();
+        vm.isa = IsaProfile::only(&["PUSH", "POP"]).unwrap();
+        vm.load_program(&assemble_str("push %7\npop A\nadds\n").unwrap())
+            .unwrap();
+        vm.step().unwrap();
+        vm.step().unwrap();
+        assert_eq!(vm.get_register(Register::A), 7);
+
+        assert_eq!(
+            vm.step(),
+            Err("illegal instruction - ADDS (0x0F)".to_string())
+        );
+        // The faulting instruction is not executed
+        assert_eq!(vm.get_register(Register::PC), 4);
+    }
+}
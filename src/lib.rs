@@ -5,12 +5,27 @@
 //! - 8 16-bit registers
 //! - Simple instruction set
 
+/// Args module passes command-line arguments to programs
+pub mod args;
+
+/// Assembler module turns assembly source into bytecode
+pub mod asm;
+
+/// Asynchronous module runs the machine inside async applications, yielding between steps
+pub mod asynchronous;
+
 /// Macros module with code generation utilities
 pub mod macros;
 
+/// Logging module emits structured logs through `tracing` when that feature is enabled
+pub mod logging;
+
 /// Machine module provides the core VM implementation.
 pub mod machine;
 
+/// ISA module describes the instruction set for tools outside the crate
+pub mod isa;
+
 /// Memory module provides the memory system for the VM.
 pub mod memory;
 
@@ -20,14 +35,93 @@ pub mod registers;
 /// Opcodes module provides the register implementation
 pub mod opcodes;
 
+/// Program module provides the program image format shared by the assembler and loader
+pub mod program;
+
+/// Intel HEX module converts program images to and from Intel HEX text
+pub mod ihex;
+
+/// Fuzz module provides the entry points for the fuzz targets
+pub mod fuzz;
+
+/// Coredump module captures a failed machine for post-mortem debugging
+pub mod coredump;
+
+/// Devices module defines the memory-mapped framebuffer and keyboard
+pub mod devices;
+
+/// Diff module runs two machines in lockstep to find where they diverge
+pub mod diff;
+
+/// Snapshot module saves and restores the complete state of a machine
+pub mod snapshot;
+
+/// Syscalls module gives programs sandboxed access to the host environment and clock
+pub mod syscalls;
+
+/// Threaded module runs devices on their own threads, synchronized with the machine each step
+pub mod threaded;
+
+/// Timing module gives instructions cycle costs and paces runs to a clock rate
+pub mod timing;
+
+/// Trace module records executed steps for inspecting a run afterwards
+pub mod trace;
+
+/// Tracer module streams executed instructions to pluggable sinks while a machine runs
+pub mod tracer;
+
+/// Wasm module exposes the machine and assembler to JavaScript
+#[cfg(feature = "wasm")]
+pub mod wasm;
+
 /// Re-export key components for easier access
 pub use crate::machine::*;
 pub use crate::memory::*;
 pub use crate::opcodes::*;
+pub use crate::program::*;
 pub use crate::registers::*;
 
 // Include test modules
 #[cfg(test)]
+mod args_test;
+#[cfg(test)]
+mod asynchronous_test;
+#[cfg(test)]
+mod coredump_test;
+#[cfg(test)]
+mod devices_test;
+#[cfg(test)]
+mod diff_test;
+#[cfg(test)]
+mod fuzz_test;
+#[cfg(test)]
+mod ihex_test;
+#[cfg(test)]
+mod isa_test;
+#[cfg(test)]
 mod machine_test;
 #[cfg(test)]
 mod memory_test;
+#[cfg(test)]
+mod opcodes_test;
+#[cfg(test)]
+mod program_test;
+#[cfg(all(test, feature = "serde"))]
+mod serde_test;
+#[cfg(test)]
+mod snapshot_test;
+#[cfg(test)]
+mod syscalls_test;
+#[cfg(test)]
+mod test_support;
+#[cfg(test)]
+mod threaded_test;
+#[cfg(test)]
+mod timing_test;
+#[cfg(test)]
+mod trace_test;
+#[cfg(test)]
+mod tracer_test;
+#[cfg(all(test, feature = "wasm"))]
+mod wasm_test;
@@ -0,0 +1,153 @@
+//! Structured logging through the `tracing` crate, built with the `tracing`
+//! feature.
+//!
+//! The library emits events as a machine runs, and the binaries wrap each
+//! run in a span. Without the feature every event compiles to nothing, so
+//! the interpreter loop pays nothing for it. Levels:
+//!
+//! | Level   | Events                                          |
+//! | ------- | ----------------------------------------------- |
+//! | `WARN`  | Faults: instructions that failed                |
+//! | `INFO`  | Runs starting and stopping                      |
+//! | `DEBUG` | Signals, syscalls, key presses, beeps and device accesses |
+//! | `TRACE` | Every instruction executed                      |
+//!
+//! Logs are written to stderr, so they never mix with a program's output.
+//!
+//! Embedders get the library's events through whatever subscriber they
+//! install; every event has a target under `rustyvm::`, so they can be
+//! filtered per module. With the `log` feature the events are also emitted
+//! as `log` records while no `tracing` subscriber is installed.
+
+#[cfg(feature = "tracing")]
+use crate::devices;
+use crate::{Op, devices::Beep};
+
+/// A span covering one run of a program, closed when dropped.
+#[must_use = "the span closes as soon as it is dropped"]
+pub struct RunSpan {
+    #[cfg(feature = "tracing")]
+    _span: tracing::span::EnteredSpan,
+}
+
+/// Opens a span for a run of `program`; events until the span is dropped
+/// belong to it.
+#[cfg_attr(not(feature = "tracing"), allow(unused_variables))]
+pub fn run_span(program: &str) -> RunSpan {
+    let span = RunSpan {
+        #[cfg(feature = "tracing")]
+        _span: tracing::info_span!(target: "rustyvm::run", "run", program).entered(),
+    };
+    #[cfg(feature = "tracing")]
+    tracing::info!(target: "rustyvm::run", "run started");
+    span
+}
+
+/// Logs the end of a run at `INFO`.
+#[cfg_attr(not(feature = "tracing"), allow(unused_variables))]
+pub fn run_finished(steps: u64, outcome: &str) {
+    #[cfg(feature = "tracing")]
+    tracing::info!(target: "rustyvm::run", steps, outcome, "run finished");
+}
+
+#[cfg_attr(not(feature = "tracing"), allow(unused_variables))]
+pub(crate) fn step(pc: u16, op: &Op) {
+    #[cfg(feature = "tracing")]
+    tracing::trace!(target: "rustyvm::machine", pc, ?op, "step");
+}
+
+#[cfg_attr(not(feature = "tracing"), allow(unused_variables))]
+pub(crate) fn signal(signal: u8) {
+    #[cfg(feature = "tracing")]
+    tracing::debug!(target: "rustyvm::machine", signal, "signal");
+}
+
+#[cfg_attr(not(feature = "tracing"), allow(unused_variables))]
+pub(crate) fn syscall(number: u8, a: u16, b: u16, c: u16) {
+    #[cfg(feature = "tracing")]
+    tracing::debug!(target: "rustyvm::machine", number, a, b, c, "syscall");
+}
+
+#[cfg_attr(not(feature = "tracing"), allow(unused_variables))]
+pub(crate) fn fault(pc: u16, error: &str) {
+    #[cfg(feature = "tracing")]
+    tracing::warn!(target: "rustyvm::machine", pc, error, "fault");
+}
+
+/// Logs a stack read that touched a memory-mapped device.
+#[cfg_attr(not(feature = "tracing"), allow(unused_variables))]
+pub(crate) fn device_read(addr: u16, value: u16) {
+    #[cfg(feature = "tracing")]
+    if let Some(device) = device_at_word(addr) {
+        tracing::debug!(target: "rustyvm::devices", device, addr, value, "read");
+    }
+}
+
+/// Logs a stack write that touched a memory-mapped device.
+#[cfg_attr(not(feature = "tracing"), allow(unused_variables))]
+pub(crate) fn device_write(addr: u16, value: u16) {
+    #[cfg(feature = "tracing")]
+    if let Some(device) = device_at_word(addr) {
+        tracing::debug!(target: "rustyvm::devices", device, addr, value, "write");
+    }
+}
+
+/// Names the device under either byte of the word at `addr`.
+#[cfg(feature = "tracing")]
+fn device_at_word(addr: u16) -> Option<&'static str> {
+    devices::device_at(addr).or_else(|| devices::device_at(addr.wrapping_add(1)))
+}
+
+#[cfg_attr(not(feature = "tracing"), allow(unused_variables))]
+pub(crate) fn key_press(code: u8) {
+    #[cfg(feature = "tracing")]
+    tracing::debug!(target: "rustyvm::devices", code, "key press");
+}
+
+#[cfg_attr(not(feature = "tracing"), allow(unused_variables))]
+pub(crate) fn beep(beep: &Beep) {
+    #[cfg(feature = "tracing")]
+    tracing::debug!(
+        target: "rustyvm::devices",
+        frequency = beep.frequency,
+        duration_ms = beep.duration_ms,
+        "beep"
+    );
+}
+
+#[cfg_attr(not(feature = "tracing"), allow(unused_variables))]
+pub(crate) fn mail(message: u16) {
+    #[cfg(feature = "tracing")]
+    tracing::debug!(target: "rustyvm::devices", message, "mail delivered");
+}
+
+/// Sends logs to stderr, showing more for each `-v`: warnings with none,
+/// then `INFO`, `DEBUG` and `TRACE`. Fails for `-v` in a build without the
+/// `tracing` feature.
+#[cfg(feature = "tracing")]
+pub fn init(verbosity: u8) -> Result<(), String> {
+    use tracing::Level;
+
+    let level = match verbosity {
+        0 => Level::WARN,
+        1 => Level::INFO,
+        2 => Level::DEBUG,
+        _ => Level::TRACE,
+    };
+    tracing_subscriber::fmt()
+        .with_max_level(level)
+        .with_writer(std::io::stderr)
+        .try_init()
+        .map_err(|e| format!("failed to set up logging - {}", e))
+}
+
+#[cfg(not(feature = "tracing"))]
+pub fn init(verbosity: u8) -> Result<(), String> {
+    if verbosity > 0 {
+        return Err(
+            "this binary was built without the `tracing` feature - rebuild with `--features tracing`"
+                .to_string(),
+        );
+    }
+    Ok(())
+}
@@ -1,50 +1,361 @@
 //! VM core implementation for the 16-bit Virtual Machine.
 
-use std::collections::HashMap;
+use std::{
+    cell::RefCell,
+    collections::{BTreeMap, HashMap},
+    fmt,
+    io::{self, Read, Write},
+    rc::Rc,
+};
 
 use crate::{
-    Register, execute_instruction,
+    Flag, Op, Register, execute_instruction,
+    isa::{self, IsaProfile},
+    logging,
     memory::{Addressable, LinearMemory},
-    opcodes::parse_instructions,
+    program::{Executable, Program},
+    syscalls::FileTable,
+    timing::Timing,
 };
 
 /// Function type for signal handlers in the VM.
 /// Called when the VM executes a SIGNAL instruction.
 type SignalFunction = fn(&mut Machine) -> Result<(), String>;
 
+/// Function type for syscall handlers in the VM.
+/// Called when the VM executes a SYSCALL instruction, with the arguments
+/// read from the registers. The result is stored in A.
+pub type SyscallFunction = fn(&mut Machine, SyscallArgs) -> Result<u16, String>;
+
+/// Arguments of a syscall, read from A, B and C when it is made.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct SyscallArgs {
+    pub a: u16,
+    pub b: u16,
+    pub c: u16,
+}
+
+/// Lowest stack address, where SP starts. The stack grows upwards.
+pub const STACK_BASE: u16 = 0x1000;
+
+/// Layout of a machine built with [`Machine::with_config`]. The default is
+/// the machine [`Machine::new`] builds.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct MachineConfig {
+    /// Bytes of memory; addresses past 0xFFFF are never reached
+    pub memory_size: usize,
+    /// Lowest stack address, where SP starts. The stack grows upwards.
+    pub stack_base: u16,
+    /// Address the stack may not grow past, or `None` to let it run to the
+    /// end of memory
+    pub stack_limit: Option<u16>,
+    /// Where PC starts. Loading a program moves it to the program's entry
+    /// point.
+    pub initial_pc: u16,
+}
+
+impl Default for MachineConfig {
+    fn default() -> Self {
+        Self {
+            memory_size: 8 * 1024, // -> 8 KB
+            stack_base: STACK_BASE,
+            stack_limit: None,
+            initial_pc: 0,
+        }
+    }
+}
+
+/// Execution counters collected by [`Machine::step`] while profiling.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Profile {
+    /// Number of instructions executed; their cost in clock cycles is
+    /// [`Machine::cycles`]
+    pub steps: u64,
+    /// Executions per opcode
+    pub opcodes: BTreeMap<u8, u64>,
+    /// Executions per instruction address
+    pub addresses: BTreeMap<u16, u64>,
+}
+
+impl Profile {
+    /// Formats the executed addresses as coverage data, one `ADDR COUNT` line
+    /// per address in hex and decimal, sorted by address.
+    pub fn format_coverage(&self) -> String {
+        self.addresses
+            .iter()
+            .map(|(address, count)| format!("{:04X} {}\n", address, count))
+            .collect()
+    }
+
+    /// Reads coverage data written by [`Profile::format_coverage`].
+    pub fn parse_coverage(text: &str) -> Result<BTreeMap<u16, u64>, String> {
+        let mut coverage = BTreeMap::new();
+        for (idx, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() {
+                continue;
+            }
+            let (address, count) = line
+                .split_once(char::is_whitespace)
+                .ok_or_else(|| format!("line {}: expected `ADDR COUNT`", idx + 1))?;
+            let address = u16::from_str_radix(address, 16)
+                .map_err(|_| format!("line {}: invalid address `{}`", idx + 1, address))?;
+            let count = count
+                .trim()
+                .parse()
+                .map_err(|_| format!("line {}: invalid count `{}`", idx + 1, count.trim()))?;
+            coverage.insert(address, count);
+        }
+        Ok(coverage)
+    }
+}
+
+/// An output sink that keeps everything written to it, for GUIs, tests
+/// and the browser build. Clones share the same buffer, so one clone can
+/// be given to [`Machine::output`] and the other read after the run.
+#[derive(Debug, Clone, Default)]
+pub struct CapturedOutput {
+    buffer: Rc<RefCell<Vec<u8>>>,
+}
+
+impl CapturedOutput {
+    /// Returns the bytes written so far and empties the buffer.
+    pub fn take(&self) -> Vec<u8> {
+        self.buffer.take()
+    }
+}
+
+impl Write for CapturedOutput {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.buffer.borrow_mut().extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Why [`Machine::run`] or [`Machine::run_with_fuel`] returned.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum HaltReason {
+    /// The program halted the machine after this many steps
+    Halted { steps: u64 },
+    /// The step budget ran out before the program halted
+    OutOfFuel { steps: u64 },
+}
+
+impl HaltReason {
+    /// Number of instructions executed by the run.
+    pub fn steps(&self) -> u64 {
+        match self {
+            HaltReason::Halted { steps } | HaltReason::OutOfFuel { steps } => *steps,
+        }
+    }
+}
+
+/// An instruction that failed during [`Machine::run`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct VmError {
+    /// Address of the failing instruction
+    pub pc: u16,
+    /// Number of instructions executed, counting the failing one
+    pub steps: u64,
+    /// What went wrong, as returned by [`Machine::step`]
+    pub message: String,
+}
+
+impl fmt::Display for VmError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.message)
+    }
+}
+
+impl std::error::Error for VmError {}
+
+impl From<VmError> for String {
+    fn from(e: VmError) -> Self {
+        e.message
+    }
+}
+
+/// Callbacks around every instruction the machine executes, for tracers,
+/// coverage tools and profilers built outside the crate. Both methods do
+/// nothing unless overridden.
+///
+/// ```
+/// use std::{cell::Cell, rc::Rc};
+/// use rustyvm::{Hook, Machine, Op, halt};
+///
+/// struct CountPushes(Rc<Cell<u32>>);
+///
+/// impl Hook for CountPushes {
+///     fn before(&mut self, _: &Machine, _: u16, op: &Op) {
+///         if let Op::Push(_) = op {
+///             self.0.set(self.0.get() + 1);
+///         }
+///     }
+/// }
+///
+/// let pushes = Rc::new(Cell::new(0));
+/// // PUSH 1, PUSH 2, SIG $09
+/// let program = [0x01, 0x01, 0x01, 0x02, 0x09, 0x09];
+/// let mut vm = Machine::builder().handler(0x09, halt).program(&program).build().unwrap();
+/// vm.add_hook(CountPushes(pushes.clone()));
+/// vm.run().unwrap();
+/// assert_eq!(pushes.get(), 2);
+/// ```
+pub trait Hook {
+    /// Called once the instruction at `pc` has been decoded, before it
+    /// changes anything; PC still points at it. Instructions that fail to
+    /// decode or are not in the ISA profile never reach the hooks.
+    fn before(&mut self, machine: &Machine, pc: u16, op: &Op) {
+        let _ = (machine, pc, op);
+    }
+
+    /// Called after the instruction at `pc` has run, with its error if it
+    /// failed.
+    fn after(&mut self, machine: &Machine, pc: u16, op: &Op, error: Option<&str>) {
+        let _ = (machine, pc, op, error);
+    }
+}
+
 /// The main virtual machine structure.
 ///
 /// This struct represents the entire virtual machine, containing
 /// registers, memory, and state information.
 pub struct Machine {
     /// The VM's register set (13 registers, each 16 bits)
-    pub registers: [u16; 13],
+    pub registers: [u16; Register::COUNT],
     /// Keeps track whether the machine is in halt or not
     pub halt: bool,
     /// Keeps the cache of signal handler methods
     pub signal_handlers: HashMap<u8, SignalFunction>,
+    /// Handlers for the SYSCALL instruction, by number
+    pub syscall_handlers: HashMap<u8, SyscallFunction>,
     /// The VM's memory (dynamic dispatch allows for different implementations)
     pub memory: Box<dyn Addressable>,
+    /// Execution counters, collected only when set to `Some`
+    pub profile: Option<Profile>,
+    /// Where everything the machine prints goes: the state reports and
+    /// program output such as the `write` syscall. Stdout by default; a
+    /// [`CapturedOutput`] keeps it for reading back instead.
+    pub output: Box<dyn Write>,
+    /// Where the `read` syscall takes its bytes from. Stdin by default; any
+    /// reader, such as an `io::Cursor` over prepared bytes, can replace it
+    /// so interactive programs run the same way every time.
+    pub input: Box<dyn Read>,
+    /// Instructions the machine executes; anything else faults as illegal.
+    /// Every instruction by default.
+    pub isa: IsaProfile,
+    /// Files opened through the file syscalls, and the directories they
+    /// may be opened in. No directory is allowed by default.
+    pub files: FileTable,
+    /// Cycle cost of each instruction, see [`timing`](crate::timing)
+    pub timing: Timing,
+    /// Clock cycles executed so far, by the costs in `timing`
+    pub cycles: u64,
+    /// Lowest stack address, where SP started
+    pub stack_base: u16,
+    /// Address a push may not write past, if any
+    pub stack_limit: Option<u16>,
+    /// Where PC starts, and returns to on [`Machine::reset`]
+    pub initial_pc: u16,
+    /// Called around every instruction [`Machine::step`] executes, in the
+    /// order they were added
+    pub hooks: Vec<Box<dyn Hook>>,
+}
+
+impl Default for Machine {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Signal handler that halts the machine, which is what programs expect of
+/// `SIG $09`.
+pub fn halt(machine: &mut Machine) -> Result<(), String> {
+    machine.halt = true;
+    Ok(())
 }
 
 impl Machine {
+    /// Starts building a machine, see [`MachineBuilder`].
+    pub fn builder() -> MachineBuilder {
+        MachineBuilder::default()
+    }
+
     /// Creates a new virtual machine with initialized state.
     /// SP starts at 0x1000, PC at 0, all other registers at 0
     pub fn new() -> Self {
-        let memory_size = 8 * 1024; // -> 8 KB
+        Self::with_config(MachineConfig::default())
+    }
+
+    /// Creates a new virtual machine with the memory size and register
+    /// layout of `config`.
+    pub fn with_config(config: MachineConfig) -> Self {
+        Self::from_parts(Box::new(LinearMemory::new(config.memory_size)), config)
+    }
+
+    /// Creates a new virtual machine backed by the given memory.
+    /// Registers start out the same as with [`Machine::new`].
+    pub fn with_memory(memory: Box<dyn Addressable>) -> Self {
+        Self::from_parts(memory, MachineConfig::default())
+    }
+
+    /// Creates the machine around `memory`; the memory size in `config` is
+    /// not used.
+    fn from_parts(memory: Box<dyn Addressable>, config: MachineConfig) -> Self {
         let mut machine = Self {
-            registers: [0; 13],
+            registers: [0; Register::COUNT],
             halt: false,
             signal_handlers: HashMap::new(),
-            memory: Box::new(LinearMemory::new(memory_size)),
+            syscall_handlers: HashMap::new(),
+            memory,
+            profile: None,
+            output: Box::new(io::stdout()),
+            input: Box::new(io::stdin()),
+            isa: IsaProfile::full(),
+            files: FileTable::default(),
+            timing: Timing::default(),
+            cycles: 0,
+            stack_base: config.stack_base,
+            stack_limit: config.stack_limit,
+            initial_pc: config.initial_pc,
+            hooks: Vec::new(),
         };
+        machine.reset(false);
+        machine
+    }
+
+    /// Puts the machine back in the state it was built in, so it can run
+    /// another program. Registers return to zero except SP and PC, the halt
+    /// flag, cycle count and profile counters are cleared, and open files
+    /// are closed. Handlers, streams, the instruction profile, timing and the
+    /// allowed directories stay. Memory is zeroed only with `clear_memory`;
+    /// otherwise the next program loads over the last one.
+    pub fn reset(&mut self, clear_memory: bool) {
+        self.registers = [0; Register::COUNT];
         // Initialize SP to point to the beginning of stack area
-        // Starting at address 0x1000 gives plenty of room for both code and stack
-        machine.registers[Register::SP as usize] = 0x1000;
+        // The default of 0x1000 gives plenty of room for both code and stack
+        self.registers[Register::SP as usize] = self.stack_base;
 
-        // Initialize PC to 0 (program starts at the beginning of memory)
-        machine.registers[Register::PC as usize] = 0;
-        machine
+        // Initialize PC (by default programs start at the beginning of memory)
+        self.registers[Register::PC as usize] = self.initial_pc;
+
+        self.halt = false;
+        self.cycles = 0;
+        if let Some(profile) = self.profile.as_mut() {
+            *profile = Profile::default();
+        }
+        self.files.close_all();
+        if clear_memory {
+            self.memory.clear();
+        }
     }
 
     /// Gets the value of a specific register.
@@ -52,184 +363,501 @@ impl Machine {
         self.registers[r as usize]
     }
 
+    /// Whether a flag is set in the FLAGS register.
+    pub fn get_flag(&self, flag: Flag) -> bool {
+        self.registers[Register::FLAGS as usize] & flag.mask() != 0
+    }
+
+    /// Sets or clears a flag in the FLAGS register, leaving the other bits alone.
+    pub fn set_flag(&mut self, flag: Flag, set: bool) {
+        let flags = &mut self.registers[Register::FLAGS as usize];
+        if set {
+            *flags |= flag.mask();
+        } else {
+            *flags &= !flag.mask();
+        }
+    }
+
+    /// Adds a hook to be called around every instruction from the next step
+    /// on. Hooks stay through [`Machine::reset`].
+    pub fn add_hook(&mut self, hook: impl Hook + 'static) {
+        self.hooks.push(Box::new(hook));
+    }
+
     /// Defines a signal handler for a specific signal code.
     /// Called when the VM executes a SIGNAL instruction with the matching code.
     pub fn define_handler(&mut self, index: u8, f: SignalFunction) {
         self.signal_handlers.insert(index, f);
     }
 
+    /// Defines the handler for a syscall number.
+    /// Called when the VM executes a SYSCALL instruction with the matching
+    /// number, with A, B and C as its arguments; what it returns goes in A.
+    pub fn define_syscall(&mut self, number: u8, f: SyscallFunction) {
+        self.syscall_handlers.insert(number, f);
+    }
+
+    /// Loads a program image into memory at address 0.
+    /// Sets PC to the entry point recorded in the header (0 for raw binaries).
+    /// Returns the number of bytes and instructions loaded.
+    pub fn load_program(&mut self, bytes: &[u8]) -> Result<(usize, usize), String> {
+        self.load_program_at(bytes, 0, None)
+    }
+
+    /// Loads a program image into memory starting at `addr`.
+    /// Executable sections load at their own addresses, shifted by `addr`.
+    /// PC is set to `entry` when given, which must lie within the loaded image,
+    /// otherwise to the recorded entry point offset by `addr`.
+    /// Returns the number of bytes and instructions loaded.
+    pub fn load_program_at(
+        &mut self,
+        bytes: &[u8],
+        addr: u16,
+        entry: Option<u16>,
+    ) -> Result<(usize, usize), String> {
+        // Blocks to load as (offset from `addr`, bytes), and the entry offset
+        let executable;
+        let (blocks, default_entry): (Vec<(u16, &[u8])>, u16) = if Executable::is_executable(bytes)
+        {
+            executable = Executable::parse(bytes)?;
+            let blocks = executable
+                .sections
+                .iter()
+                .map(|s| (s.address, s.data.as_slice()))
+                .collect();
+            (blocks, executable.entry)
+        } else {
+            let program = Program::parse(bytes)?;
+            (vec![(0, program.code)], program.entry)
+        };
+
+        let mut loaded = (0, 0);
+        let mut ranges = Vec::with_capacity(blocks.len());
+        for (offset, data) in blocks {
+            let start = addr.checked_add(offset).ok_or(format!(
+                "section at 0x{:04X} does not fit in memory when loaded at 0x{:04X}",
+                offset, addr
+            ))?;
+            let (bytes, instructions) = self.memory.load_from_vec(data, start).ok_or(format!(
+                "program does not fit in memory - {} bytes at 0x{:04X}",
+                data.len(),
+                start
+            ))?;
+            loaded = (loaded.0 + bytes, loaded.1 + instructions);
+            ranges.push(start as usize..start as usize + data.len());
+        }
+
+        let entry = match entry {
+            Some(entry) => {
+                if !ranges.iter().any(|range| range.contains(&(entry as usize))) {
+                    let ranges: Vec<String> = ranges
+                        .iter()
+                        .map(|r| format!("0x{:04X}..0x{:04X}", r.start, r.end))
+                        .collect();
+                    return Err(format!(
+                        "entry point 0x{:04X} is outside the loaded image {}",
+                        entry,
+                        ranges.join(", ")
+                    ));
+                }
+                entry
+            }
+            None => addr.wrapping_add(default_entry),
+        };
+        self.registers[Register::PC as usize] = entry;
+        Ok(loaded)
+    }
+
     /// Pops a 16-bit value from the stack.
     /// First decrement SP by 2, then read the value at the new SP location.
     /// Restores SP on error.
     pub fn pop(&mut self) -> Result<u16, String> {
         // For pop, first decrement SP, then read
-        self.registers[Register::SP as usize] -= 2;
-        let sp = self.registers[Register::SP as usize];
+        let sp = self.registers[Register::SP as usize]
+            .checked_sub(2)
+            .ok_or("stack underflow - SP is below 0x0002")?;
+        self.registers[Register::SP as usize] = sp;
         if let Some(v) = self.memory.read2(sp) {
+            logging::device_read(sp, v);
             Ok(v)
         } else {
             // Restore SP on error
             self.registers[Register::SP as usize] += 2;
-            return Err(format!("memory read fault - 0x{:X}", sp));
+            Err(format!("memory read fault - 0x{:X}", sp))
         }
     }
 
     /// Pushes a 16-bit value onto the stack.
-    /// First write at current SP, then increment SP by 2
+    /// First write at current SP, then increment SP by 2. Fails without
+    /// writing when the value would reach past the stack limit or the end
+    /// of the address space.
     pub fn push(&mut self, v: u16) -> Result<(), String> {
         // For push, first write at current SP, then increment
         let sp = self.registers[Register::SP as usize];
+        let next = sp
+            .checked_add(2)
+            .ok_or_else(|| format!("stack overflow - SP=0x{:04X} reached the end of memory", sp))?;
+        if let Some(limit) = self.stack_limit
+            && sp as u32 + 2 > limit as u32
+        {
+            return Err(format!(
+                "stack overflow - SP=0x{:04X} reached the limit 0x{:04X}",
+                sp, limit
+            ));
+        }
         if !self.memory.write2(sp, v) {
             return Err(format!("memory write fault - 0x{:X}", sp));
         }
-        self.registers[Register::SP as usize] += 2;
+        logging::device_write(sp, v);
+        self.registers[Register::SP as usize] = next;
         Ok(())
     }
 
-    /// Prints the current state of the VM to the console.
+    /// The values on the stack, from the first pushed up to the top, read
+    /// without popping them.
+    pub fn stack(&self) -> impl DoubleEndedIterator<Item = u16> + '_ {
+        let sp = self.registers[Register::SP as usize];
+        (self.stack_base..sp.saturating_sub(1))
+            .step_by(2)
+            .filter_map(|addr| self.memory.read2(addr))
+    }
+
+    /// The value `n` entries below the top of the stack, 0 being the top,
+    /// read without popping it. None if the stack is not that deep.
+    pub fn peek(&self, n: u16) -> Option<u16> {
+        let addr = n
+            .checked_add(1)
+            .and_then(|n| n.checked_mul(2))
+            .and_then(|offset| self.registers[Register::SP as usize].checked_sub(offset))
+            .filter(|addr| *addr >= self.stack_base)?;
+        self.memory.read2(addr)
+    }
+
+    /// Prints the current state of the VM to its output.
     /// Shows register values, stack pointer, and program counter.
-    pub fn print_final_state(&self) {
-        println!("-----------------------------------------------");
-        println!("----------------Final State--------------------");
-        println!("Final output:");
-        println!(
+    pub fn print_final_state(&mut self) -> io::Result<()> {
+        let out = &mut self.output;
+        writeln!(out, "-----------------------------------------------")?;
+        writeln!(out, "----------------Final State--------------------")?;
+        writeln!(out, "Final output:")?;
+        writeln!(
+            out,
             "\tRegister A: 0x{:04X} ({})",
             self.registers[Register::A as usize],
             self.registers[Register::A as usize]
-        );
-        println!("Registers:");
-        for (i, reg) in self.registers.iter().enumerate() {
-            let reg_name = match Register::from_u8(i as u8) {
-                Some(r) => format!("{:?}", r),
-                None => "Unknown".to_string(),
-            };
-            if reg_name == "SP" || reg_name == "PC" || reg_name == "FLAGS" {
+        )?;
+        writeln!(out, "Registers:")?;
+        for reg in Register::ALL {
+            if matches!(reg, Register::SP | Register::PC | Register::FLAGS) {
                 continue;
             }
-            println!("\tRegister {}: 0x{:04X} ({})", reg_name, reg, reg);
+            let value = self.registers[reg as usize];
+            writeln!(out, "\tRegister {}: 0x{:04X} ({})", reg, value, value)?;
         }
-        println!(
+        writeln!(
+            out,
             "\tStack Pointer (SP): 0x{:04X} ({})",
             self.registers[Register::SP as usize],
             self.registers[Register::SP as usize]
-        );
-        println!(
+        )?;
+        writeln!(
+            out,
             "\tProgram Counter (PC): 0x{:04X} ({})",
             self.registers[Register::PC as usize],
             self.registers[Register::PC as usize]
-        );
-        println!(
+        )?;
+        writeln!(
+            out,
             "\tFlags (8 bit): 0b{:08b} ({})",
             self.registers[Register::FLAGS as usize],
             self.registers[Register::FLAGS as usize],
-        );
-        println!("-----------------------------------------------");
+        )?;
+        writeln!(out, "-----------------------------------------------")
     }
 
-    pub fn print_intermediate_state(&self) {
+    /// Prints the registers, the top of the stack and the next instruction
+    /// to the VM's output.
+    pub fn print_intermediate_state(&mut self) -> io::Result<()> {
         let pc = self.registers[Register::PC as usize];
+        let next_op = self.decode_at(pc);
         let sp = self.registers[Register::SP as usize];
+        let stack_items: Vec<(u16, u16)> = (0..3)
+            .map_while(|n| Some((sp.checked_sub(2 * (n + 1))?, self.peek(n)?)))
+            .collect();
+        let out = &mut self.output;
         let flags = self.registers[Register::FLAGS as usize];
 
         // Print header with PC and SP info
-        println!(
+        writeln!(
+            out,
             "\n[State] PC=0x{:04X} | SP=0x{:04X} | FLAGS=0b{:08b}",
             pc, sp, flags
-        );
+        )?;
 
         // First row: A, B, C, M registers
-        print!("Regs: ");
+        write!(out, "Regs: ")?;
         for &idx in &[Register::A, Register::B, Register::C, Register::M] {
             let val = self.registers[idx as usize];
-            print!("{:?}=0x{:04X}({:<3}) ", idx, val, val);
+            write!(out, "{:?}=0x{:04X}({:<3}) ", idx, val, val)?;
         }
-        println!();
+        writeln!(out)?;
 
         // Second row: R0-R4 registers
-        print!("     ");
-        for idx in Register::R0 as usize..=Register::R4 as usize {
-            let val = self.registers[idx];
-            let name = Register::from_u8(idx as u8).unwrap();
-            print!("{:?}=0x{:04X}({:<3}) ", name, val, val);
-        }
-        println!();
-
-        // Try to display some stack items if available
-        if sp >= 0x1002 {
-            // At least one item on stack
-            let mut stack_items = Vec::new();
-            let mut addr = sp - 2;
-            // Show up to 3 items from the stack
-            for _ in 0..3 {
-                if addr < 0x1000 {
-                    break;
-                }
-                if let Some(val) = self.memory.read2(addr) {
-                    stack_items.push((addr, val));
-                    addr -= 2;
-                } else {
-                    break;
-                }
-            }
+        write!(out, "     ")?;
+        for reg in &Register::ALL[Register::R0 as usize..] {
+            let val = self.registers[*reg as usize];
+            write!(out, "{}=0x{:04X}({:<3}) ", reg, val, val)?;
+        }
+        writeln!(out)?;
 
-            if !stack_items.is_empty() {
-                print!("Stack: ");
-                for (addr, val) in stack_items {
-                    print!("[0x{:04X}]=0x{:04X}({}) ", addr, val, val);
-                }
-                println!();
+        // Show up to 3 items from the top of the stack
+        if !stack_items.is_empty() {
+            write!(out, "Stack: ")?;
+            for (addr, val) in stack_items {
+                write!(out, "[0x{:04X}]=0x{:04X}({}) ", addr, val, val)?;
             }
+            writeln!(out)?;
         }
 
         // Show next instruction if available
-        if let Some(opcode) = self.memory.read(pc) {
-            if let Some(arg) = self.memory.read(pc + 1) {
-                if let Ok(next_op) =
-                    crate::opcodes::parse_instructions((opcode as u16) | ((arg as u16) << 8))
-                {
-                    println!("Next: 0x{:04X} | {:?}", pc, next_op);
-                }
-            }
+        if let Ok(next_op) = next_op {
+            writeln!(out, "Next: 0x{:04X} | {:?}", pc, next_op)?;
         }
+        Ok(())
     }
 
     /// Executes a single instruction in the VM.
     ///
     /// 1. Reads instruction from memory at PC
-    /// 2. Increments PC by 2 (each instruction is 2 bytes)
+    /// 2. Increments PC past it (most instructions are 2 bytes, LDI is 4),
+    ///    failing if that would wrap around the address space
     /// 3. Parses and executes the operation
     pub fn step(&mut self) -> Result<(), String> {
         let pc = self.registers[Register::PC as usize];
+        let op = self
+            .decode_at(pc)
+            .inspect_err(|error| logging::fault(pc, error))?;
+        if !self.isa.allows(&op) {
+            let error = format!(
+                "illegal instruction - {} (0x{:02X})",
+                isa::describe(&op).mnemonic,
+                op.value()
+            );
+            logging::fault(pc, &error);
+            return Err(error);
+        }
+        let Some(next_pc) = pc.checked_add(op.size()) else {
+            let error = format!(
+                "PC overflow - {} at 0x{:04X} runs past the end of memory",
+                isa::describe(&op).mnemonic,
+                pc
+            );
+            logging::fault(pc, &error);
+            return Err(error);
+        };
+        logging::step(pc, &op);
+        // Hooks are taken out while they run so they can see the machine
+        let mut hooks = std::mem::take(&mut self.hooks);
+        for hook in hooks.iter_mut() {
+            hook.before(self, pc, &op);
+        }
+        self.cycles += self.timing.cost(&op) as u64;
 
-        // Read opcode and argument as separate bytes for debugging output
-        let opcode = self.memory.read(pc).unwrap_or(0);
-        let arg = self.memory.read(pc + 1).unwrap_or(0);
+        if let Some(profile) = self.profile.as_mut() {
+            profile.steps += 1;
+            *profile.opcodes.entry(op.value()).or_default() += 1;
+            *profile.addresses.entry(pc).or_default() += 1;
+        }
 
-        // Read the full 16-bit instruction (in little-endian format)
-        // This gives us a value where:
-        // - Lower 8 bits contain the opcode (memory[pc])
-        // - Upper 8 bits contain the argument (memory[pc+1])
+        // Move the Program Counter to the next instruction (2 bytes: 1 for
+        // opcode, 1 for argument, and 2 more for a 16-bit operand)
+        self.registers[Register::PC as usize] = next_pc;
 
-        let ins = self
-            .memory
-            .read2(pc)
-            .ok_or(format!("memory read fault at PC=0x{:04X}", pc))?;
+        let result = if hooks.is_empty() {
+            execute_instruction(self, op)
+        } else {
+            let result = execute_instruction(self, op.clone());
+            for hook in hooks.iter_mut() {
+                hook.after(self, pc, &op, result.as_ref().err().map(String::as_str));
+            }
+            result
+        };
+        // Hooks added while the instruction ran come after the existing ones
+        hooks.append(&mut self.hooks);
+        self.hooks = hooks;
+        result.inspect_err(|error| logging::fault(pc, error))
+    }
 
-        // Increment the Program Counter register by 2 to move to the next instruction
-        // (each instruction is 2 bytes: 1 for opcode, 1 for argument)
-        self.registers[Register::PC as usize] = pc + 2;
+    /// Executes instructions until the program halts the machine.
+    ///
+    /// A program that never halts keeps this running forever; use
+    /// [`Machine::run_with_fuel`] when the program is not trusted.
+    pub fn run(&mut self) -> Result<HaltReason, VmError> {
+        self.run_for(None)
+    }
 
-        let op = parse_instructions(ins)?;
+    /// Executes instructions until the program halts the machine or
+    /// `max_steps` instructions have run, whichever comes first.
+    pub fn run_with_fuel(&mut self, max_steps: u64) -> Result<HaltReason, VmError> {
+        self.run_for(Some(max_steps))
+    }
 
-        // Debug output - consider making this optional or moving to a debug method
-        println!(
-            "Instruction: opcode=0x{:02X}, arg=0x{:02X} @ PC={} => {op:?}, SP=0x{:04X}",
-            opcode,
-            arg,
-            pc,
-            self.registers[Register::SP as usize]
-        );
+    fn run_for(&mut self, fuel: Option<u64>) -> Result<HaltReason, VmError> {
+        let mut steps = 0;
+        while !self.halt {
+            if fuel.is_some_and(|max| steps >= max) {
+                return Ok(HaltReason::OutOfFuel { steps });
+            }
+            let pc = self.registers[Register::PC as usize];
+            steps += 1;
+            self.step()
+                .map_err(|message| VmError { pc, steps, message })?;
+        }
+        Ok(HaltReason::Halted { steps })
+    }
+
+    /// Decodes the instruction stored at `addr` without executing it.
+    pub fn decode_at(&self, addr: u16) -> Result<Op, String> {
+        // The opcode (memory[addr]) decides how many bytes follow it: the
+        // argument (memory[addr+1]) and, for LDI, a 16-bit operand
+        let read = |offset: u16| {
+            addr.checked_add(offset)
+                .and_then(|at| self.memory.read(at))
+                .ok_or(format!("memory read fault at PC=0x{:04X}", addr))
+        };
+        let opcode = read(0)?;
+        let size = Op::size_of(opcode).ok_or(format!("unknown op - 0x{:X}", opcode))?;
+        let bytes = (0..size).map(read).collect::<Result<Vec<u8>, _>>()?;
+        Op::from_bytes(&bytes)
+    }
+}
+
+/// Sets up a machine in one expression: its layout, handlers, streams and
+/// the program to load.
+///
+/// ```
+/// use rustyvm::{Machine, Register, halt};
+///
+/// // PUSH 7, POP A, SIG $09
+/// let program = [0x01, 0x07, 0x02, 0x00, 0x09, 0x09];
+/// let mut vm = Machine::builder()
+///     .memory(16 * 1024)
+///     .handler(0x09, halt)
+///     .program(&program)
+///     .build()
+///     .unwrap();
+/// vm.run().unwrap();
+/// assert_eq!(vm.get_register(Register::A), 7);
+/// ```
+#[derive(Default)]
+pub struct MachineBuilder {
+    config: MachineConfig,
+    memory: Option<Box<dyn Addressable>>,
+    handlers: Vec<(u8, SignalFunction)>,
+    syscalls: Vec<(u8, SyscallFunction)>,
+    hooks: Vec<Box<dyn Hook>>,
+    program: Option<(Vec<u8>, u16)>,
+    output: Option<Box<dyn Write>>,
+    input: Option<Box<dyn Read>>,
+}
+
+impl MachineBuilder {
+    /// Uses `config` for the layout, replacing anything set so far.
+    pub fn config(mut self, config: MachineConfig) -> Self {
+        self.config = config;
+        self
+    }
+
+    /// Bytes of memory, 8 KB unless set.
+    pub fn memory(mut self, size: usize) -> Self {
+        self.config.memory_size = size;
+        self
+    }
+
+    /// Backs the machine with `memory` instead of a linear memory of the
+    /// configured size.
+    pub fn backed_by(mut self, memory: Box<dyn Addressable>) -> Self {
+        self.memory = Some(memory);
+        self
+    }
+
+    /// Where SP starts, see [`MachineConfig::stack_base`].
+    pub fn stack_base(mut self, addr: u16) -> Self {
+        self.config.stack_base = addr;
+        self
+    }
+
+    /// Address the stack may not grow past, see [`MachineConfig::stack_limit`].
+    pub fn stack_limit(mut self, addr: u16) -> Self {
+        self.config.stack_limit = Some(addr);
+        self
+    }
 
-        execute_instruction(self, op)
+    /// Defines a signal handler, as [`Machine::define_handler`] does.
+    pub fn handler(mut self, index: u8, f: SignalFunction) -> Self {
+        self.handlers.push((index, f));
+        self
+    }
+
+    /// Defines a syscall handler, as [`Machine::define_syscall`] does.
+    pub fn syscall(mut self, number: u8, f: SyscallFunction) -> Self {
+        self.syscalls.push((number, f));
+        self
+    }
+
+    /// Adds a hook, as [`Machine::add_hook`] does.
+    pub fn hook(mut self, hook: impl Hook + 'static) -> Self {
+        self.hooks.push(Box::new(hook));
+        self
+    }
+
+    /// Loads a program image at address 0 when the machine is built, as
+    /// [`Machine::load_program`] does.
+    pub fn program(self, bytes: &[u8]) -> Self {
+        self.program_at(bytes, 0)
+    }
+
+    /// Loads a program image at `addr` when the machine is built, as
+    /// [`Machine::load_program_at`] does.
+    pub fn program_at(mut self, bytes: &[u8], addr: u16) -> Self {
+        self.program = Some((bytes.to_vec(), addr));
+        self
+    }
+
+    /// Sends the machine's output to `output` instead of stdout.
+    pub fn output(mut self, output: impl Write + 'static) -> Self {
+        self.output = Some(Box::new(output));
+        self
+    }
+
+    /// Reads the machine's input from `input` instead of stdin.
+    pub fn input(mut self, input: impl Read + 'static) -> Self {
+        self.input = Some(Box::new(input));
+        self
+    }
+
+    /// Creates the machine. Fails when the program does not load.
+    pub fn build(self) -> Result<Machine, String> {
+        let mut machine = match self.memory {
+            Some(memory) => Machine::from_parts(memory, self.config),
+            None => Machine::with_config(self.config),
+        };
+        for (index, f) in self.handlers {
+            machine.define_handler(index, f);
+        }
+        for (number, f) in self.syscalls {
+            machine.define_syscall(number, f);
+        }
+        machine.hooks = self.hooks;
+        if let Some(output) = self.output {
+            machine.output = output;
+        }
+        if let Some(input) = self.input {
+            machine.input = input;
+        }
+        if let Some((bytes, addr)) = self.program {
+            machine.load_program_at(&bytes, addr, None)?;
+        }
+        Ok(machine)
     }
 }
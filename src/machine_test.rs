@@ -6,6 +6,7 @@
 #[cfg(test)]
 mod tests {
     use super::super::*;
+    use crate::test_support::halting_machine;
 
     #[test]
     fn test_register_conversion() {
@@ -38,6 +39,24 @@ mod tests {
         assert!(Register::from_str("").is_err());
     }
 
+    #[test]
+    fn test_register_metadata() {
+        assert_eq!(Register::COUNT, 13);
+        for (idx, reg) in Register::ALL.iter().enumerate() {
+            assert_eq!(Register::from_u8(idx as u8), Some(*reg));
+            assert_eq!(Register::from_str(reg.name()), Ok(*reg));
+        }
+        assert_eq!(Register::FLAGS.to_string(), "FLAGS");
+        let special: Vec<Register> = Register::ALL
+            .into_iter()
+            .filter(|reg| reg.class() == RegisterClass::Special)
+            .collect();
+        assert_eq!(
+            special,
+            [Register::SP, Register::PC, Register::BP, Register::FLAGS]
+        );
+    }
+
     #[test]
     fn test_op_values() {
         assert_eq!(Op::Nop.value(), 0x00);
@@ -46,7 +65,10 @@ mod tests {
         assert_eq!(Op::PushRegister(Register::A).value(), 0x03);
         assert_eq!(Op::AddStack.value(), 0x0F);
         assert_eq!(Op::AddRegister(Register::A, Register::B).value(), 0x04);
+        assert_eq!(Op::MoveRegister(Register::A, Register::B).value(), 0x05);
+        assert_eq!(Op::SubRegister(Register::A, Register::B).value(), 0x06);
         assert_eq!(Op::Signal(0).value(), 0x09);
+        assert_eq!(Op::SubStack.value(), 0x0E);
 
         // Test Op::equals function
         assert!(Op::equals(0x00, Op::Nop));
@@ -55,6 +77,7 @@ mod tests {
         assert!(Op::equals(0x03, Op::PushRegister(Register::A)));
         assert!(Op::equals(0x0F, Op::AddStack));
         assert!(Op::equals(0x04, Op::AddRegister(Register::A, Register::B)));
+        assert!(Op::equals(0x05, Op::MoveRegister(Register::A, Register::B)));
         assert!(Op::equals(0x09, Op::Signal(0)));
 
         assert!(!Op::equals(0x01, Op::Nop));
@@ -135,6 +158,147 @@ mod tests {
         assert!(vm.signal_handlers.is_empty());
     }
 
+    #[test]
+    fn test_machine_with_config() {
+        let mut vm = Machine::with_config(MachineConfig {
+            memory_size: 256,
+            stack_base: 0xF0,
+            stack_limit: Some(0xF4),
+            initial_pc: 0x10,
+        });
+        assert_eq!(vm.memory.size(), 256);
+        assert_state!(vm, SP = 0xF0, PC = 0x10);
+
+        vm.push(1).unwrap();
+        vm.push(2).unwrap();
+        assert_eq!(
+            vm.push(3),
+            Err("stack overflow - SP=0x00F4 reached the limit 0x00F4".to_string())
+        );
+        assert_state!(vm, SP = 0xF4);
+        assert_eq!(vm.stack().collect::<Vec<_>>(), [1, 2]);
+        assert_eq!(vm.peek(1), Some(1));
+        assert_eq!(vm.peek(2), None);
+
+        // The default is the machine `new` builds
+        let vm = Machine::with_config(MachineConfig::default());
+        assert_eq!(vm.memory.size(), 8 * 1024);
+        assert_state!(vm, SP = STACK_BASE, PC = 0);
+    }
+
+    #[test]
+    fn test_machine_builder() {
+        let output = CapturedOutput::default();
+        let program: Vec<u8> = [Op::Syscall(0x01), Op::Signal(0x09)]
+            .iter()
+            .flat_map(Op::to_bytes)
+            .collect();
+        let mut vm = Machine::builder()
+            .memory(1024)
+            .stack_base(0x0200)
+            .handler(0x09, halt)
+            .syscall(0x01, |_, args| Ok(args.a + 1))
+            .output(output.clone())
+            .program_at(&program, 0x0100)
+            .build()
+            .expect("Failed to build machine");
+
+        assert_eq!(vm.memory.size(), 1024);
+        assert_state!(vm, SP = 0x0200, PC = 0x0100);
+        assert_eq!(vm.run(), Ok(HaltReason::Halted { steps: 2 }));
+        assert_state!(vm, A = 1);
+        vm.print_final_state().unwrap();
+        assert!(!output.take().is_empty());
+
+        // The program has to fit in the memory
+        let error = Machine::builder().memory(2).program(&program).build();
+        assert_eq!(
+            error.err(),
+            Some("program does not fit in memory - 4 bytes at 0x0000".to_string())
+        );
+    }
+
+    #[test]
+    fn test_reset_reuses_the_machine() {
+        let program: Vec<u8> = [Op::Push(7), Op::PopRegister(Register::B), Op::Signal(0x09)]
+            .iter()
+            .flat_map(Op::to_bytes)
+            .collect();
+        let mut vm = Machine::builder()
+            .stack_base(0x0100)
+            .handler(0x09, halt)
+            .build()
+            .unwrap();
+        vm.profile = Some(Profile::default());
+
+        for _ in 0..2 {
+            vm.load_program(&program).unwrap();
+            assert_eq!(vm.run(), Ok(HaltReason::Halted { steps: 3 }));
+            assert_state!(vm, B = 7, PC = 6, SP = 0x0100);
+            assert_eq!(vm.profile.as_ref().map(|p| p.steps), Some(3));
+
+            vm.reset(false);
+            assert!(!vm.halt);
+            assert_eq!(vm.cycles, 0);
+            assert_eq!(vm.profile, Some(Profile::default()));
+            assert_state!(vm, B = 0, PC = 0, SP = 0x0100);
+            // The handlers stay, and so does the program until it is cleared
+            assert!(vm.signal_handlers.contains_key(&0x09));
+            assert_eq!(vm.memory.read(0), Some(Op::Push(0).value()));
+        }
+
+        vm.reset(true);
+        assert!((0..vm.memory.size() as u16).all(|addr| vm.memory.read(addr) == Some(0)));
+    }
+
+    #[test]
+    fn test_hooks_see_every_instruction() {
+        use std::{cell::RefCell, rc::Rc};
+
+        /// Logs what each call saw: the PC argument, then the PC register
+        struct Log(Rc<RefCell<Vec<String>>>);
+
+        impl Hook for Log {
+            fn before(&mut self, machine: &Machine, pc: u16, _: &Op) {
+                let register = machine.get_register(Register::PC);
+                self.0
+                    .borrow_mut()
+                    .push(format!("before {} {}", pc, register));
+            }
+
+            fn after(&mut self, machine: &Machine, pc: u16, _: &Op, error: Option<&str>) {
+                let register = machine.get_register(Register::PC);
+                self.0
+                    .borrow_mut()
+                    .push(format!("after {} {} {:?}", pc, register, error));
+            }
+        }
+
+        let log = Rc::new(RefCell::new(Vec::new()));
+        let program: Vec<u8> = [Op::Jump(4), Op::Nop, Op::Signal(0x42)]
+            .iter()
+            .flat_map(Op::to_bytes)
+            .collect();
+        let mut vm = Machine::builder()
+            .hook(Log(log.clone()))
+            .program(&program)
+            .build()
+            .unwrap();
+
+        vm.step().unwrap();
+        assert!(vm.step().is_err());
+        assert_eq!(
+            *log.borrow(),
+            [
+                "before 0 0",
+                "after 0 4 None",
+                "before 4 4",
+                "after 4 6 Some(\"unknown signal - 0x42\")",
+            ]
+        );
+        assert_eq!(vm.hooks.len(), 1);
+    }
+
     #[test]
     fn test_push_pop() {
         let mut vm = Machine::new();
@@ -182,6 +346,57 @@ mod tests {
         assert!(vm.halt);
     }
 
+    #[test]
+    fn test_run_until_halt_or_out_of_fuel() {
+        let program: Vec<u8> = [Op::Nop, Op::Nop, Op::Signal(0x09)]
+            .iter()
+            .flat_map(Op::to_bytes)
+            .collect();
+        let mut vm = halting_machine(&program);
+        assert_eq!(vm.run(), Ok(HaltReason::Halted { steps: 3 }));
+
+        // A jump to itself never halts
+        let mut vm = Machine::new();
+        vm.load_program(&Op::Jump(0).to_bytes()).unwrap();
+        assert_eq!(
+            vm.run_with_fuel(100),
+            Ok(HaltReason::OutOfFuel { steps: 100 })
+        );
+
+        let mut vm = Machine::new();
+        vm.load_program(&[Op::Nop.to_bytes(), Op::Signal(0x42).to_bytes()].concat())
+            .unwrap();
+        assert_eq!(
+            vm.run_with_fuel(100),
+            Err(VmError {
+                pc: 2,
+                steps: 2,
+                message: "unknown signal - 0x42".to_string(),
+            })
+        );
+    }
+
+    #[test]
+    fn test_syscall_handler() {
+        let mut vm = Machine::new();
+        vm.define_syscall(0x01, |_, args| Ok(args.a * 100 + args.b * 10 + args.c));
+        vm.define_syscall(0x02, |_, args| Err(format!("bad argument {}", args.a)));
+        vm.registers[Register::A as usize] = 1;
+        vm.registers[Register::B as usize] = 2;
+        vm.registers[Register::C as usize] = 3;
+        let program: Vec<u8> = [Op::Syscall(0x01), Op::Syscall(0x02), Op::Syscall(0x03)]
+            .iter()
+            .flat_map(Op::to_bytes)
+            .collect();
+        vm.load_program(&program).unwrap();
+
+        vm.step().expect("Failed to execute SYSCALL");
+        assert_state!(vm, A = 123, B = 2, C = 3);
+        assert_eq!(vm.step(), Err("bad argument 123".to_string()));
+        vm.registers[Register::PC as usize] = 4;
+        assert_eq!(vm.step(), Err("unknown syscall - 0x3".to_string()));
+    }
+
     #[test]
     fn test_step_push_pop() {
         let mut vm = Machine::new();
@@ -235,6 +450,386 @@ mod tests {
         assert_eq!(vm.registers[Register::PC as usize], 8);
     }
 
+    #[test]
+    fn test_step_sub_stack() {
+        let mut vm = Machine::new();
+
+        // Program: PUSH 30, PUSH 10, SUBSTACK, POP A, PUSH 10, PUSH 30, SUBSTACK, POP B
+        let program: Vec<u8> = [
+            Op::Push(30),
+            Op::Push(10),
+            Op::SubStack,
+            Op::PopRegister(Register::A),
+            Op::Push(10),
+            Op::Push(30),
+            Op::SubStack,
+            Op::PopRegister(Register::B),
+        ]
+        .iter()
+        .flat_map(Op::to_bytes)
+        .collect();
+        vm.load_program(&program).unwrap();
+        for _ in 0..8 {
+            vm.step().expect("Failed to execute instruction");
+        }
+
+        // The top value is subtracted from the one below it
+        assert_eq!(vm.registers[Register::A as usize], 20);
+        // And wraps around below zero
+        assert_eq!(vm.registers[Register::B as usize], 10u16.wrapping_sub(30));
+        assert_eq!(vm.registers[Register::SP as usize], 0x1000);
+    }
+
+    #[test]
+    fn test_step_sub_register() {
+        let mut vm = Machine::new();
+        vm.registers[Register::A as usize] = 50;
+        vm.registers[Register::B as usize] = 8;
+        vm.load_program(&Op::SubRegister(Register::A, Register::B).to_bytes())
+            .unwrap();
+
+        vm.step().expect("Failed to execute SUBREGISTER");
+
+        assert_eq!(vm.registers[Register::A as usize], 42);
+        assert_eq!(vm.registers[Register::B as usize], 8);
+    }
+
+    #[test]
+    fn test_arithmetic_flags() {
+        let run = |a: u16, b: u16, op: Op| {
+            let mut vm = Machine::new();
+            vm.registers[Register::A as usize] = a;
+            vm.registers[Register::B as usize] = b;
+            vm.load_program(&op.to_bytes()).unwrap();
+            vm.step().expect("Failed to execute instruction");
+            [Flag::Zero, Flag::Carry, Flag::Negative, Flag::Overflow].map(|flag| vm.get_flag(flag))
+        };
+        let add = Op::AddRegister(Register::A, Register::B);
+        let sub = Op::SubRegister(Register::A, Register::B);
+
+        // [zero, carry, negative, overflow]
+        assert_eq!(run(1, 2, add.clone()), [false, false, false, false]);
+        assert_eq!(run(0xFFFF, 1, add.clone()), [true, true, false, false]);
+        assert_eq!(run(0x7FFF, 1, add), [false, false, true, true]);
+        assert_eq!(run(5, 5, sub.clone()), [true, false, false, false]);
+        assert_eq!(run(3, 5, sub.clone()), [false, true, true, false]);
+        assert_eq!(run(0x8000, 1, sub), [false, false, false, true]);
+    }
+
+    #[test]
+    fn test_conditional_jumps() {
+        // Compares A with B by subtracting, then jumps to 0x40 or falls through
+        let taken = |a: u16, b: u16, jump: Op| {
+            let mut vm = Machine::new();
+            vm.registers[Register::A as usize] = a;
+            vm.registers[Register::B as usize] = b;
+            let mut program = Op::SubRegister(Register::A, Register::B)
+                .to_bytes()
+                .to_vec();
+            program.extend(jump.to_bytes());
+            vm.load_program(&program).unwrap();
+            vm.step().expect("Failed to execute SUBR");
+            vm.step().expect("Failed to execute jump");
+            vm.get_register(Register::PC) == 0x40
+        };
+
+        assert!(taken(4, 4, Op::JumpZero(0x40)));
+        assert!(!taken(4, 3, Op::JumpZero(0x40)));
+        assert!(taken(4, 3, Op::JumpNotZero(0x40)));
+        assert!(taken(3, 4, Op::JumpCarry(0x40)));
+        assert!(!taken(4, 3, Op::JumpCarry(0x40)));
+        assert!(taken(4, 3, Op::JumpNotCarry(0x40)));
+        // -1 < 1 as signed values, though not unsigned
+        assert!(taken(0xFFFF, 1, Op::JumpLess(0x40)));
+        assert!(!taken(1, 0xFFFF, Op::JumpLess(0x40)));
+        assert!(taken(1, 0xFFFF, Op::JumpGreater(0x40)));
+        assert!(!taken(4, 4, Op::JumpGreater(0x40)));
+        // Overflowing subtractions still compare correctly
+        assert!(taken(0x8000, 1, Op::JumpLess(0x40)));
+        assert!(taken(0x7FFF, 0xFFFF, Op::JumpGreater(0x40)));
+        assert!(taken(4, 4, Op::JumpLessEqual(0x40)));
+        assert!(taken(0xFFFF, 1, Op::JumpLessEqual(0x40)));
+        assert!(!taken(1, 0xFFFF, Op::JumpLessEqual(0x40)));
+        assert!(taken(4, 4, Op::JumpGreaterEqual(0x40)));
+        assert!(!taken(0x8000, 1, Op::JumpGreaterEqual(0x40)));
+    }
+
+    #[test]
+    fn test_set_flag() {
+        let mut vm = Machine::new();
+        vm.set_flag(Flag::Carry, true);
+        vm.set_flag(Flag::Overflow, true);
+        assert_eq!(vm.get_register(Register::FLAGS), 0b1010);
+        assert!(vm.get_flag(Flag::Carry));
+        assert!(!vm.get_flag(Flag::Zero));
+
+        vm.set_flag(Flag::Carry, false);
+        vm.set_flag(Flag::Overflow, true);
+        assert_eq!(vm.get_register(Register::FLAGS), 0b1000);
+    }
+
+    #[test]
+    fn test_step_multiply_wide() {
+        let mut vm = Machine::new();
+        vm.registers[Register::A as usize] = 0xFFFF;
+        vm.registers[Register::B as usize] = 0x1234;
+        vm.registers[Register::C as usize] = 300;
+        vm.registers[Register::R0 as usize] = 7;
+        let program: Vec<u8> = [
+            Op::MulWide(Register::A, Register::B),
+            Op::MulWide(Register::C, Register::R0),
+            // Into M itself, the low word wins
+            Op::MulWide(Register::M, Register::R0),
+        ]
+        .iter()
+        .flat_map(Op::to_bytes)
+        .collect();
+        vm.load_program(&program).unwrap();
+
+        vm.step().expect("Failed to execute MULW");
+        // 0xFFFF * 0x1234 = 0x1233_EDCC
+        assert_state!(vm, A = 0xEDCC, M = 0x1233, B = 0x1234);
+        // The product needed the high word
+        assert_eq!(vm.get_register(Register::FLAGS), 0b1110);
+        vm.step().expect("Failed to execute MULW");
+        assert_state!(vm, C = 2100, M = 0);
+        assert_eq!(vm.get_register(Register::FLAGS), 0);
+        vm.registers[Register::M as usize] = 0x4000;
+        vm.step().expect("Failed to execute MULW");
+        assert_state!(vm, M = 0xC000);
+    }
+
+    #[test]
+    fn test_compare_sets_flags_only() {
+        let mut vm = Machine::new();
+        // -5 compared with 3
+        vm.registers[Register::A as usize] = -5i16 as u16;
+        vm.registers[Register::B as usize] = 3;
+        vm.load_program(&Op::CompareRegister(Register::A, Register::B).to_bytes())
+            .unwrap();
+        vm.step().expect("Failed to execute CMP");
+
+        assert_eq!(vm.get_register(Register::A), -5i16 as u16);
+        assert!(vm.get_flag(Flag::Negative));
+        assert!(!vm.get_flag(Flag::Overflow));
+        assert!(!vm.get_flag(Flag::Zero));
+        // Unsigned, 0xFFFB is not below 3
+        assert!(!vm.get_flag(Flag::Carry));
+    }
+
+    #[test]
+    fn test_test_sets_flags_only() {
+        let mut vm = Machine::new();
+        vm.registers[Register::A as usize] = 0x80F0;
+        vm.registers[Register::B as usize] = 0x800F;
+        vm.registers[Register::C as usize] = 0x000F;
+        // Left over from an earlier subtraction, and cleared by TEST
+        vm.registers[Register::FLAGS as usize] = Flag::Carry.mask() | Flag::Overflow.mask();
+        let program: Vec<u8> = [
+            Op::Test(Register::A, Register::B),
+            Op::Test(Register::A, Register::C),
+        ]
+        .iter()
+        .flat_map(Op::to_bytes)
+        .collect();
+        vm.load_program(&program).unwrap();
+
+        vm.step().expect("Failed to execute TEST");
+        assert_eq!(vm.get_register(Register::A), 0x80F0);
+        assert!(vm.get_flag(Flag::Negative));
+        assert!(!vm.get_flag(Flag::Zero));
+        assert!(!vm.get_flag(Flag::Carry));
+        assert!(!vm.get_flag(Flag::Overflow));
+
+        vm.set_flag(Flag::Carry, true);
+        vm.step().expect("Failed to execute TEST");
+        assert!(vm.get_flag(Flag::Zero));
+        assert!(!vm.get_flag(Flag::Negative));
+        assert!(!vm.get_flag(Flag::Carry));
+    }
+
+    #[test]
+    fn test_step_conditional_move() {
+        let mut vm = Machine::new();
+        vm.registers[Register::B as usize] = 7;
+        vm.registers[Register::FLAGS as usize] = Flag::Zero.mask();
+        let program: Vec<u8> = [
+            Op::MoveIfNotZero(Register::A, Register::B),
+            Op::MoveIfZero(Register::C, Register::B),
+        ]
+        .iter()
+        .flat_map(Op::to_bytes)
+        .collect();
+        vm.load_program(&program).unwrap();
+
+        vm.step().expect("Failed to execute CMOVNZ");
+        assert_eq!(vm.get_register(Register::A), 0);
+        vm.step().expect("Failed to execute CMOVZ");
+        assert_eq!(vm.get_register(Register::C), 7);
+        // Moves leave the flags alone
+        assert_eq!(vm.get_register(Register::FLAGS), Flag::Zero.mask());
+    }
+
+    #[test]
+    fn test_step_load_immediate() {
+        let mut vm = Machine::new();
+        let mut program = Op::LoadImmediate(Register::R2, 0xBEEF).to_bytes();
+        program.extend(Op::LoadImmediate(Register::A, 1000).to_bytes());
+        vm.load_program(&program).unwrap();
+
+        vm.step().expect("Failed to execute LDI");
+        // PC moves past the whole four-byte instruction
+        assert_eq!(vm.get_register(Register::PC), 4);
+        assert_eq!(vm.get_register(Register::R2), 0xBEEF);
+        assert_eq!(vm.decode_at(4), Ok(Op::LoadImmediate(Register::A, 1000)));
+
+        vm.step().expect("Failed to execute LDI");
+        assert_eq!(vm.get_register(Register::PC), 8);
+        assert_eq!(vm.get_register(Register::A), 1000);
+    }
+
+    #[test]
+    fn test_step_load_store() {
+        let mut vm = Machine::new();
+        vm.registers[Register::M as usize] = 0x0800;
+        vm.registers[Register::A as usize] = 0xBEEF;
+        let program: Vec<u8> = [Op::Store(Register::A), Op::Load(Register::R3)]
+            .iter()
+            .flat_map(Op::to_bytes)
+            .collect();
+        vm.load_program(&program).unwrap();
+
+        vm.step().expect("Failed to execute STORE");
+        assert_eq!(vm.memory.read(0x0800), Some(0xEF));
+        assert_eq!(vm.memory.read(0x0801), Some(0xBE));
+
+        vm.step().expect("Failed to execute LOAD");
+        assert_eq!(vm.registers[Register::R3 as usize], 0xBEEF);
+
+        // Addresses outside memory fault
+        vm.registers[Register::M as usize] = 0x3000;
+        vm.registers[Register::PC as usize] = 0;
+        assert_eq!(vm.step(), Err("memory write fault - 0x3000".to_string()));
+    }
+
+    #[test]
+    fn test_step_indirect_and_offset_addressing() {
+        let mut vm = Machine::new();
+        vm.registers[Register::R0 as usize] = 0x0800;
+        vm.registers[Register::A as usize] = 0xBEEF;
+        let program: Vec<u8> = [
+            Op::StoreIndirect(Register::A, Register::R0),
+            Op::LoadOffset(Register::B, Register::R0, 0),
+            Op::StoreOffset(Register::B, Register::R0, 4),
+            Op::LoadIndirect(Register::C, Register::R0),
+            // 0xFFFC wraps to four bytes before the base
+            Op::LoadOffset(Register::R1, Register::R0, 0xFFFC),
+        ]
+        .iter()
+        .flat_map(Op::to_bytes)
+        .collect();
+        vm.load_program(&program).unwrap();
+
+        vm.step().expect("Failed to execute STORER");
+        assert_eq!(vm.memory.read2(0x0800), Some(0xBEEF));
+        vm.step().expect("Failed to execute LOADO");
+        assert_eq!(vm.registers[Register::B as usize], 0xBEEF);
+        vm.step().expect("Failed to execute STOREO");
+        assert_eq!(vm.memory.read2(0x0804), Some(0xBEEF));
+
+        vm.registers[Register::R0 as usize] = 0x0804;
+        vm.step().expect("Failed to execute LOADR");
+        assert_eq!(vm.registers[Register::C as usize], 0xBEEF);
+        vm.step().expect("Failed to execute LOADO");
+        assert_eq!(vm.registers[Register::R1 as usize], 0xBEEF);
+        assert_eq!(vm.registers[Register::PC as usize], 16);
+    }
+
+    #[test]
+    fn test_step_bitwise_stack() {
+        let mut vm = Machine::new();
+        let mut program = Vec::new();
+        for op in [Op::AndStack, Op::OrStack, Op::XorStack] {
+            program.extend(Op::Push(0b1100).to_bytes());
+            program.extend(Op::Push(0b1010).to_bytes());
+            program.extend(op.to_bytes());
+        }
+        program.extend(Op::Push(0x0F).to_bytes());
+        program.extend(Op::NotStack.to_bytes());
+        vm.load_program(&program).unwrap();
+        for _ in 0..program.len() / 2 {
+            vm.step().expect("Failed to execute instruction");
+        }
+
+        assert_eq!(
+            vm.stack().collect::<Vec<_>>(),
+            [0b1000, 0b1110, 0b0110, 0xFFF0]
+        );
+    }
+
+    #[test]
+    fn test_step_stack_manipulation() {
+        let mut vm = Machine::new();
+        let sp = vm.get_register(Register::SP);
+        let program: Vec<u8> = [
+            Op::Push(1),
+            Op::Push(2),
+            Op::Over,
+            Op::Swap,
+            Op::Dup,
+            Op::Drop,
+        ]
+        .iter()
+        .flat_map(Op::to_bytes)
+        .collect();
+        vm.load_program(&program).unwrap();
+        for _ in 0..2 {
+            vm.step().expect("Failed to execute PUSH");
+        }
+
+        // Each step's stack from the bottom, and how far SP moved
+        for (expected, moved) in [
+            (vec![1, 2, 1], 6),
+            (vec![1, 1, 2], 6),
+            (vec![1, 1, 2, 2], 8),
+            (vec![1, 1, 2], 6),
+        ] {
+            vm.step().expect("Failed to execute instruction");
+            assert_eq!(vm.stack().collect::<Vec<_>>(), expected);
+            assert_eq!(vm.get_register(Register::SP), sp + moved);
+        }
+    }
+
+    #[test]
+    fn test_step_bitwise_register() {
+        let mut vm = Machine::new();
+        vm.registers[Register::B as usize] = 0x0FF0;
+        let program: Vec<u8> = [
+            Op::PushRegister(Register::B),
+            Op::PopRegister(Register::A),
+            Op::PushRegister(Register::B),
+            Op::PopRegister(Register::C),
+            Op::Push(0x3C),
+            Op::PopRegister(Register::R0),
+            Op::AndRegister(Register::A, Register::R0),
+            Op::OrRegister(Register::B, Register::R0),
+            Op::XorRegister(Register::C, Register::R0),
+            Op::NotRegister(Register::R0),
+        ]
+        .iter()
+        .flat_map(Op::to_bytes)
+        .collect();
+        vm.load_program(&program).unwrap();
+        for _ in 0..10 {
+            vm.step().expect("Failed to execute instruction");
+        }
+
+        assert_eq!(vm.registers[Register::A as usize], 0x0030);
+        assert_eq!(vm.registers[Register::B as usize], 0x0FFC);
+        assert_eq!(vm.registers[Register::C as usize], 0x0FCC);
+        assert_eq!(vm.registers[Register::R0 as usize], 0xFFC3);
+    }
+
     #[test]
     fn test_get_register() {
         let mut vm = Machine::new();
@@ -266,4 +861,253 @@ mod tests {
         // In a more robust implementation, pop() would check if SP - 2 < 0x1000
         // before performing the operation.
     }
+
+    #[test]
+    fn test_end_of_the_address_space() {
+        let mut vm = Machine::with_config(MachineConfig {
+            memory_size: 0x10000,
+            ..MachineConfig::default()
+        });
+
+        // The last word is writable, but SP can't move past it
+        vm.registers[Register::SP as usize] = 0xFFFE;
+        assert_eq!(
+            vm.push(0x1234),
+            Err("stack overflow - SP=0xFFFE reached the end of memory".to_string())
+        );
+        assert_state!(vm, SP = 0xFFFE);
+        assert_eq!(vm.memory.read2(0xFFFE), Some(0));
+
+        // Nor can PC move past an instruction in the last word
+        vm.registers[Register::PC as usize] = 0xFFFE;
+        assert_eq!(
+            vm.step(),
+            Err("PC overflow - NOP at 0xFFFE runs past the end of memory".to_string())
+        );
+        assert_state!(vm, PC = 0xFFFE);
+        assert_eq!(vm.cycles, 0);
+    }
+
+    #[test]
+    fn test_load_program_raw() {
+        let mut vm = Machine::new();
+        let program = [Op::Push(0).value(), 0x42];
+
+        let (bytes, instructions) = vm.load_program(&program).expect("Failed to load program");
+        assert_eq!((bytes, instructions), (2, 1));
+        assert_eq!(vm.memory.read(1), Some(0x42));
+        assert_eq!(vm.get_register(Register::PC), 0);
+    }
+
+    #[test]
+    fn test_load_program_with_entry_header() {
+        let mut vm = Machine::new();
+        let code = [
+            Op::Push(0).value(),
+            1,
+            Op::Push(0).value(),
+            2,
+            Op::PopRegister(Register::A).value(),
+            Register::A as u8,
+        ];
+        let image = Program::encode(0x0002, &code);
+
+        let (bytes, _) = vm.load_program(&image).expect("Failed to load program");
+        assert_eq!(bytes, code.len());
+        assert_eq!(vm.get_register(Register::PC), 0x0002);
+
+        // Execution starts at the entry point, skipping the first PUSH
+        vm.step().expect("Failed to execute PUSH");
+        vm.step().expect("Failed to execute POP");
+        assert_eq!(vm.get_register(Register::A), 2);
+        assert_eq!(vm.get_register(Register::SP), 0x1000);
+    }
+
+    #[test]
+    fn test_load_program_truncated_header() {
+        let mut vm = Machine::new();
+        assert!(vm.load_program(&HEADER_MAGIC).is_err());
+    }
+
+    #[test]
+    fn test_load_program_at() {
+        let mut vm = Machine::new();
+        let code = [Op::Push(0).value(), 1, Op::Push(0).value(), 2];
+
+        vm.load_program_at(&code, 0x0100, None)
+            .expect("Failed to load program");
+        assert_eq!(vm.memory.read(0x0101), Some(1));
+        assert_eq!(vm.get_register(Register::PC), 0x0100);
+
+        // The header entry point is relative to the load address
+        let image = Program::encode(0x0002, &code);
+        vm.load_program_at(&image, 0x0100, None)
+            .expect("Failed to load program");
+        assert_eq!(vm.get_register(Register::PC), 0x0102);
+
+        vm.load_program_at(&code, 0x0100, Some(0x0102))
+            .expect("Failed to load program");
+        assert_eq!(vm.get_register(Register::PC), 0x0102);
+        vm.step().expect("Failed to execute PUSH");
+        assert_eq!(vm.pop(), Ok(2));
+    }
+
+    #[test]
+    fn test_load_program_at_rejects_bad_entry() {
+        let mut vm = Machine::new();
+        let code = [Op::Push(0).value(), 1];
+
+        assert!(vm.load_program_at(&code, 0x0100, Some(0x00FE)).is_err());
+        assert!(vm.load_program_at(&code, 0x0100, Some(0x0102)).is_err());
+        assert!(vm.load_program_at(&code, 0x1FFF, None).is_err());
+    }
+
+    #[test]
+    fn test_load_executable_sections() {
+        let executable = Executable {
+            entry: 0x0100,
+            sections: vec![
+                Section {
+                    address: 0x0100,
+                    data: vec![Op::Push(0).value(), 5],
+                },
+                Section {
+                    address: 0x0400,
+                    data: vec![0xAA, 0xBB],
+                },
+            ],
+            symbols: Default::default(),
+        };
+        let bytes = executable.encode().expect("Failed to encode");
+
+        let mut vm = Machine::new();
+        let (loaded, _) = vm.load_program(&bytes).expect("Failed to load program");
+        assert_eq!(loaded, 4);
+        assert_eq!(vm.get_register(Register::PC), 0x0100);
+        assert_eq!(vm.memory.read2(0x0400), Some(0xBBAA));
+
+        // An explicit entry point must fall inside one of the sections
+        assert!(vm.load_program_at(&bytes, 0, Some(0x0400)).is_ok());
+        assert!(vm.load_program_at(&bytes, 0, Some(0x0200)).is_err());
+
+        // Sections move together with the load address
+        vm.load_program_at(&bytes, 0x0010, None)
+            .expect("Failed to load program");
+        assert_eq!(vm.get_register(Register::PC), 0x0110);
+        assert_eq!(vm.memory.read2(0x0410), Some(0xBBAA));
+    }
+
+    #[test]
+    fn test_profile_counters() {
+        let mut vm = Machine::new();
+        vm.load_program(&[
+            Op::Push(0).value(),
+            1,
+            Op::Push(0).value(),
+            2,
+            Op::AddStack.value(),
+            0,
+        ])
+        .expect("Failed to load program");
+
+        // Nothing is counted until profiling is enabled
+        vm.step().expect("Failed to execute PUSH");
+        assert_eq!(vm.profile, None);
+
+        vm.profile = Some(Profile::default());
+        vm.step().expect("Failed to execute PUSH");
+        vm.step().expect("Failed to execute ADDS");
+
+        let profile = vm.profile.expect("Profile should be collected");
+        assert_eq!(profile.steps, 2);
+        assert_eq!(profile.opcodes[&Op::Push(0).value()], 1);
+        assert_eq!(profile.opcodes[&Op::AddStack.value()], 1);
+        assert_eq!(
+            profile.addresses.into_iter().collect::<Vec<_>>(),
+            vec![(2, 1), (4, 1)]
+        );
+    }
+
+    #[test]
+    fn test_coverage_round_trip() {
+        let profile = Profile {
+            steps: 3,
+            addresses: [(0x0002, 1), (0x0000, 2)].into(),
+            ..Default::default()
+        };
+
+        let text = profile.format_coverage();
+        assert_eq!(text, "0000 2\n0002 1\n");
+        assert_eq!(Profile::parse_coverage(&text), Ok(profile.addresses));
+        assert!(Profile::parse_coverage("0000\n").is_err());
+        assert!(Profile::parse_coverage("zz 1\n").is_err());
+    }
+
+    #[test]
+    fn test_decode_at() {
+        let mut vm = Machine::new();
+        vm.load_program(&[Op::Push(0).value(), 7, 0xFF, 0])
+            .expect("Failed to load program");
+
+        assert_eq!(vm.decode_at(0), Ok(Op::Push(7)));
+        assert!(vm.decode_at(2).is_err());
+        // Decoding never moves PC
+        assert_eq!(vm.get_register(Register::PC), 0);
+    }
+
+    #[test]
+    fn test_state_reports_go_to_output() {
+        let mut vm = Machine::new();
+        let output = CapturedOutput::default();
+        vm.output = Box::new(output.clone());
+        vm.registers[Register::A as usize] = 42;
+
+        vm.print_final_state().unwrap();
+        let report = String::from_utf8(output.take()).unwrap();
+        assert!(report.contains("\tRegister A: 0x002A (42)"), "{}", report);
+
+        vm.print_intermediate_state().unwrap();
+        let report = String::from_utf8(output.take()).unwrap();
+        assert!(report.starts_with("\n[State] PC=0x0000"), "{}", report);
+        assert!(output.take().is_empty());
+    }
+
+    #[test]
+    fn test_stack_inspection() {
+        let mut vm = Machine::new();
+        assert_eq!(vm.stack().count(), 0);
+        assert_eq!(vm.peek(0), None);
+
+        for value in [1, 2, 3] {
+            vm.push(value).unwrap();
+        }
+        assert_eq!(vm.stack().collect::<Vec<_>>(), [1, 2, 3]);
+        assert_eq!(vm.peek(0), Some(3));
+        assert_eq!(vm.peek(2), Some(1));
+        assert_eq!(vm.peek(3), None);
+        assert_eq!(vm.peek(u16::MAX), None);
+        // Reading never moves SP
+        assert_eq!(vm.get_register(Register::SP), STACK_BASE + 6);
+
+        let output = CapturedOutput::default();
+        vm.output = Box::new(output.clone());
+        vm.print_intermediate_state().unwrap();
+        let report = String::from_utf8(output.take()).unwrap();
+        assert!(
+            report.contains("Stack: [0x1004]=0x0003(3) [0x1002]=0x0002(2) [0x1000]=0x0001(1)"),
+            "{}",
+            report
+        );
+
+        // A stack at the bottom of memory has fewer entries to show
+        let mut vm = Machine::with_config(MachineConfig {
+            stack_base: 0,
+            ..MachineConfig::default()
+        });
+        vm.push(7).unwrap();
+        vm.output = Box::new(output.clone());
+        vm.print_intermediate_state().unwrap();
+        let report = String::from_utf8(output.take()).unwrap();
+        assert!(report.contains("Stack: [0x0000]=0x0007(7)"), "{}", report);
+    }
 }
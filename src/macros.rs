@@ -6,27 +6,44 @@
 /// - An enum with register variants
 /// - A from_u8 method to convert from numeric values
 /// - A from_str method to convert from string representations
+/// - `COUNT` and `ALL`, every register in declaration order
+/// - `name` and a `Display` impl printing it
+/// - `class`, a [`RegisterClass`](crate::RegisterClass) given after `=>`,
+///   general purpose when left out
+///
+/// Registers must be numbered from 0 in declaration order, so a register's
+/// number is also its index in `ALL` and in the machine's register file.
 ///
 /// # Example
 ///
 /// ```
+/// # use rustyvm::{RegisterClass, define_registers};
 /// define_registers! {
 ///     #[derive(Debug, PartialEq, Eq, Clone, Copy)]
 ///     #[repr(u8)]
 ///     pub enum Register {
 ///         A = 0x00,
-///         B = 0x01
+///         B = 0x01,
+///         PC = 0x02 => Special
 ///     }
 /// }
+///
+/// assert_eq!(Register::COUNT, 3);
+/// assert_eq!(Register::ALL[2], Register::PC);
+/// assert_eq!(Register::PC.class(), RegisterClass::Special);
+/// assert_eq!(Register::B.to_string(), "B");
 /// ```
 #[macro_export]
 macro_rules! define_registers {
+    (@class) => { $crate::RegisterClass::General };
+    (@class $class:ident) => { $crate::RegisterClass::$class };
+
     (
         $(#[$meta:meta])*
         $vis:vis enum $name:ident {
             $(
                 $(#[$variant_meta:meta])*
-                $variant:ident = $value:expr
+                $variant:ident = $value:expr $(=> $class:ident)?
             ),* $(,)?
         }
     ) => {
@@ -39,6 +56,12 @@ macro_rules! define_registers {
         }
 
         impl $name {
+            /// Number of registers.
+            $vis const COUNT: usize = [$(stringify!($variant)),*].len();
+
+            /// Every register, in declaration order.
+            $vis const ALL: [Self; Self::COUNT] = [$($name::$variant),*];
+
             /// Convert a numeric value to a register enum.
             $vis fn from_u8(v: u8) -> Option<Self> {
                 match v {
@@ -50,6 +73,7 @@ macro_rules! define_registers {
             }
 
             /// Convert a string representation to a register enum.
+            #[allow(clippy::should_implement_trait)]
             $vis fn from_str(s: &str) -> Result<Self, String> {
                 let s_upper = s.to_uppercase();
                 match s_upper.as_str() {
@@ -59,16 +83,57 @@ macro_rules! define_registers {
                     _ => Err(format!("Invalid register name: {}", s)),
                 }
             }
+
+            /// Name of the register as the assembler spells it.
+            $vis fn name(&self) -> &'static str {
+                match self {
+                    $($name::$variant => stringify!($variant),)*
+                }
+            }
+
+            /// Whether programs use the register freely or the machine
+            /// gives it a fixed role.
+            $vis fn class(&self) -> $crate::RegisterClass {
+                match self {
+                    $($name::$variant => $crate::define_registers!(@class $($class)?),)*
+                }
+            }
+        }
+
+        impl ::std::fmt::Display for $name {
+            fn fmt(&self, f: &mut ::std::fmt::Formatter<'_>) -> ::std::fmt::Result {
+                f.write_str(self.name())
+            }
         }
     };
 }
 
 /// A macro for generating instruction enums with helper methods.
 ///
-/// This macro generates:
-/// - An enum with instruction variants of different types
-/// - Methods to convert between instructions and their binary representation
-/// - Helper methods for working with instructions
+/// Each variant lists its operands by kind, its opcode and its mnemonic,
+/// and everything else is generated from that list:
+/// - The enum, with `byte` operands as `u8`, `reg` operands as `Register`
+///   and `word` operands as `u16`
+/// - `value` and `mnemonic` for the opcode and mnemonic of an instruction
+/// - `operand` for the shape of its operands, as an [`isa::Operand`](crate::isa::Operand)
+/// - `size` and `size_of` for the number of bytes an instruction takes
+/// - `to_u16` and `parse_instruction` to encode and decode the first two
+///   bytes of an instruction, and `to_bytes` and `from_bytes` for all of its
+///   bytes as they sit in memory
+/// - `examples`, one instruction per opcode with zero operands
+/// - `Display`, the mnemonic followed by its operands, and `FromStr`, which
+///   reads that form back
+///
+/// The argument byte holds a `byte` operand as is, a `reg` operand as its
+/// number, and two `reg` operands as two 4-bit numbers, the first in the
+/// upper half. Instructions without operands encode 0 there. A `word`
+/// operand comes last, alone or after one register or two, and follows the
+/// argument byte as two more bytes, little-endian, making the instruction
+/// four bytes long. The opcode alone decides the length.
+///
+/// As text, operands follow the mnemonic separated by spaces or commas.
+/// Bytes and words are written `$hex`, `%decimal` or plain decimal and
+/// displayed as `$hex`; registers by name. Mnemonics and registers are read in any case.
 ///
 /// # Dependencies
 ///
@@ -79,6 +144,7 @@ macro_rules! define_registers {
 /// # Example
 ///
 /// ```rust
+/// # use rustyvm::{define_instructions, define_registers};
 /// // First define your registers
 /// define_registers! {
 ///     #[derive(Debug, PartialEq, Eq, Clone, Copy)]
@@ -94,48 +160,264 @@ macro_rules! define_registers {
 /// // Then define your instructions
 /// define_instructions! {
 ///     #[derive(Debug, PartialEq, Eq, Clone, Copy)]
+///     #[repr(u8)]
 ///     pub enum Instruction {
-///         // No argument instructions
-///         Nop = 0x00,
+///         Nop = 0x00 => "NOP",
+///         Push(byte) = 0x01 => "PUSH",
+///         Pop(reg) = 0x02 => "POP",
+///         Add(reg, reg) = 0x04 => "ADD",
+///         Load(reg, word) = 0x05 => "LOAD",
+///         Call(word) = 0x06 => "CALL",
+///     }
+/// }
 ///
-///         // Numeric argument instructions
-///         Push(u8) = 0x01,
+/// let add = Instruction::Add(Register::B, Register::C);
+/// assert_eq!(add.to_u16(), 0x1204);
+/// assert_eq!(add.mnemonic(), "ADD");
+/// assert_eq!(Instruction::parse_instruction(0x1204), Ok(add));
+/// assert_eq!(add.to_bytes(), [0x04, 0x12]);
+/// assert_eq!(Instruction::examples().len(), 6);
 ///
-///         // Register argument instructions
-///         PopRegister(Register) = 0x02,
+/// let load = Instruction::Load(Register::D, 0x1234);
+/// assert_eq!(load.size(), 4);
+/// assert_eq!(load.to_bytes(), [0x05, 0x03, 0x34, 0x12]);
+/// assert_eq!(Instruction::from_bytes(&[0x05, 0x03, 0x34, 0x12]), Ok(load));
+/// assert_eq!(Instruction::Call(0x1FFE).to_bytes(), [0x06, 0x00, 0xFE, 0x1F]);
 ///
-///         // Two register arguments instructions
-///         AddRegister(Register, Register) = 0x04
-///     }
-/// }
+/// assert_eq!(Instruction::Push(7).to_string(), "PUSH $07");
+/// assert_eq!("add b, c".parse::<Instruction>(), Ok(add));
 /// ```
 #[macro_export]
 macro_rules! define_instructions {
-    // Main entry point that matches different instruction types
+    // Rust type of each operand kind
+    (@type byte) => { u8 };
+    (@type reg) => { Register };
+    (@type word) => { u16 };
+
+    // Shape of the argument byte for each list of operand kinds
+    (@operand []) => { $crate::isa::Operand::None };
+    (@operand [byte]) => { $crate::isa::Operand::Byte };
+    (@operand [reg]) => { $crate::isa::Operand::Register };
+    (@operand [reg, reg]) => { $crate::isa::Operand::RegisterPair };
+    (@operand [word]) => { $crate::isa::Operand::Word };
+    (@operand [reg, word]) => { $crate::isa::Operand::RegisterWord };
+    (@operand [reg, reg, word]) => { $crate::isa::Operand::RegisterPairWord };
+
+    // Bytes taken by each operand kind beyond the opcode and argument byte
+    (@extra byte) => { 0 };
+    (@extra reg) => { 0 };
+    (@extra word) => { 2 };
+
+    // The argument byte of `$ins`, known to be a `$variant`
+    (@encode $ins:ident, $name:ident, $variant:ident, []) => { 0u8 };
+    (@encode $ins:ident, $name:ident, $variant:ident, [byte]) => {{
+        let $name::$variant(value) = $ins else { unreachable!() };
+        *value
+    }};
+    (@encode $ins:ident, $name:ident, $variant:ident, [reg]) => {{
+        let $name::$variant(reg) = $ins else { unreachable!() };
+        *reg as u8
+    }};
+    (@encode $ins:ident, $name:ident, $variant:ident, [reg, reg]) => {{
+        let $name::$variant(reg1, reg2) = $ins else { unreachable!() };
+        ((*reg1 as u8 & 0x0F) << 4) | (*reg2 as u8 & 0x0F)
+    }};
+    (@encode $ins:ident, $name:ident, $variant:ident, [word]) => { 0u8 };
+    (@encode $ins:ident, $name:ident, $variant:ident, [reg, word]) => {{
+        let $name::$variant(reg, _) = $ins else { unreachable!() };
+        *reg as u8
+    }};
+    (@encode $ins:ident, $name:ident, $variant:ident, [reg, reg, word]) => {{
+        let $name::$variant(reg1, reg2, _) = $ins else { unreachable!() };
+        ((*reg1 as u8 & 0x0F) << 4) | (*reg2 as u8 & 0x0F)
+    }};
+
+    // The `word` operand of `$ins`, known to be a `$variant`, if it has one
+    (@word $ins:ident, $name:ident, $variant:ident, [word]) => {{
+        let $name::$variant(value) = $ins else { unreachable!() };
+        Some(*value)
+    }};
+    (@word $ins:ident, $name:ident, $variant:ident, [reg, word]) => {{
+        let $name::$variant(_, value) = $ins else { unreachable!() };
+        Some(*value)
+    }};
+    (@word $ins:ident, $name:ident, $variant:ident, [reg, reg, word]) => {{
+        let $name::$variant(_, _, value) = $ins else { unreachable!() };
+        Some(*value)
+    }};
+    (@word $ins:ident, $name:ident, $variant:ident, [$($kind:ident),*]) => { None };
+
+    // A `$variant` built from the argument byte `$arg` and the word `$word`
+    // that follows it
+    (@decode $arg:ident, $word:ident, $name:ident, $variant:ident, []) => { Ok($name::$variant) };
+    (@decode $arg:ident, $word:ident, $name:ident, $variant:ident, [byte]) => {
+        Ok($name::$variant($arg))
+    };
+    (@decode $arg:ident, $word:ident, $name:ident, $variant:ident, [reg]) => {
+        Register::from_u8($arg)
+            .ok_or(format!("unknown register - 0x{:X}", $arg))
+            .map($name::$variant)
+    };
+    (@decode $arg:ident, $word:ident, $name:ident, $variant:ident, [reg, reg]) => {{
+        let reg1 = ($arg >> 4) & 0x0F; // Upper 4 bits
+        let reg2 = $arg & 0x0F; // Lower 4 bits
+        match (Register::from_u8(reg1), Register::from_u8(reg2)) {
+            (Some(r1), Some(r2)) => Ok($name::$variant(r1, r2)),
+            (None, _) => Err(format!("unknown register - 0x{:X}", reg1)),
+            (_, None) => Err(format!("unknown register - 0x{:X}", reg2)),
+        }
+    }};
+    (@decode $arg:ident, $word:ident, $name:ident, $variant:ident, [word]) => {
+        Ok($name::$variant($word))
+    };
+    (@decode $arg:ident, $word:ident, $name:ident, $variant:ident, [reg, word]) => {
+        Register::from_u8($arg)
+            .ok_or(format!("unknown register - 0x{:X}", $arg))
+            .map(|reg| $name::$variant(reg, $word))
+    };
+    (@decode $arg:ident, $word:ident, $name:ident, $variant:ident, [reg, reg, word]) => {{
+        let reg1 = ($arg >> 4) & 0x0F; // Upper 4 bits
+        let reg2 = $arg & 0x0F; // Lower 4 bits
+        match (Register::from_u8(reg1), Register::from_u8(reg2)) {
+            (Some(r1), Some(r2)) => Ok($name::$variant(r1, r2, $word)),
+            (None, _) => Err(format!("unknown register - 0x{:X}", reg1)),
+            (_, None) => Err(format!("unknown register - 0x{:X}", reg2)),
+        }
+    }};
+
+    // A `$variant` with every operand 0
+    (@example $name:ident, $variant:ident, []) => { $name::$variant };
+    (@example $name:ident, $variant:ident, [$($kind:ident),+]) => {
+        $name::$variant($($crate::define_instructions!(@zero $kind)),+)
+    };
+    (@zero byte) => { 0 };
+    (@zero reg) => { Register::from_u8(0).expect("register 0 is defined") };
+    (@zero word) => { 0 };
+
+    // The mnemonic of `$ins`, known to be a `$variant`, then its operands
+    (@display $f:ident, $ins:ident, $name:ident, $variant:ident, []) => {
+        write!($f, "{}", $ins.mnemonic())
+    };
+    (@display $f:ident, $ins:ident, $name:ident, $variant:ident, [byte]) => {{
+        let $name::$variant(value) = $ins else { unreachable!() };
+        write!($f, "{} ${:02X}", $ins.mnemonic(), value)
+    }};
+    (@display $f:ident, $ins:ident, $name:ident, $variant:ident, [reg]) => {{
+        let $name::$variant(reg) = $ins else { unreachable!() };
+        write!($f, "{} {}", $ins.mnemonic(), reg)
+    }};
+    (@display $f:ident, $ins:ident, $name:ident, $variant:ident, [reg, reg]) => {{
+        let $name::$variant(reg1, reg2) = $ins else { unreachable!() };
+        write!($f, "{} {} {}", $ins.mnemonic(), reg1, reg2)
+    }};
+    (@display $f:ident, $ins:ident, $name:ident, $variant:ident, [word]) => {{
+        let $name::$variant(value) = $ins else { unreachable!() };
+        write!($f, "{} ${:04X}", $ins.mnemonic(), value)
+    }};
+    (@display $f:ident, $ins:ident, $name:ident, $variant:ident, [reg, word]) => {{
+        let $name::$variant(reg, value) = $ins else { unreachable!() };
+        write!($f, "{} {} ${:04X}", $ins.mnemonic(), reg, value)
+    }};
+    (@display $f:ident, $ins:ident, $name:ident, $variant:ident, [reg, reg, word]) => {{
+        let $name::$variant(reg1, reg2, value) = $ins else { unreachable!() };
+        write!($f, "{} {} {} ${:04X}", $ins.mnemonic(), reg1, reg2, value)
+    }};
+
+    // A `$variant` read from the operand words `$words`
+    (@parse $words:ident, $name:ident, $variant:ident, $mnemonic:literal, []) => {
+        match $words.len() {
+            0 => Ok($name::$variant),
+            found => Err(format!("{} takes 0 operand(s), found {}", $mnemonic, found)),
+        }
+    };
+    (@parse $words:ident, $name:ident, $variant:ident, $mnemonic:literal, [$($kind:ident),+]) => {{
+        let expected = [$(stringify!($kind)),+].len();
+        if $words.len() != expected {
+            return Err(format!(
+                "{} takes {} operand(s), found {}",
+                $mnemonic,
+                expected,
+                $words.len()
+            ));
+        }
+        let mut words = $words.iter();
+        Ok($name::$variant($(
+            $crate::define_instructions!(@parse_operand $kind, words.next().unwrap())?
+        ),+))
+    }};
+    (@parse_operand byte, $word:expr) => {{
+        let word: &str = $word;
+        match word.strip_prefix('$') {
+            Some(hex) => u8::from_str_radix(hex, 16),
+            None => word.strip_prefix('%').unwrap_or(word).parse::<u8>(),
+        }
+        .map_err(|_| format!("invalid byte - {}", word))
+    }};
+    (@parse_operand reg, $word:expr) => { Register::from_str($word) };
+    (@parse_operand word, $word:expr) => {{
+        let word: &str = $word;
+        match word.strip_prefix('$') {
+            Some(hex) => u16::from_str_radix(hex, 16),
+            None => word.strip_prefix('%').unwrap_or(word).parse::<u16>(),
+        }
+        .map_err(|_| format!("invalid word - {}", word))
+    }};
+
+    // Main entry point: the enum, then its methods
     (
         $(#[$meta:meta])*
         $vis:vis enum $name:ident {
-            $($rest:tt)*
+            $(
+                $(#[$variant_meta:meta])*
+                $variant:ident $(($($kind:ident),+))? = $value:literal => $mnemonic:literal
+            ),* $(,)?
         }
     ) => {
-        // First, define the enum with all variants
         $(#[$meta])*
         $vis enum $name {
-            $($rest)*
+            $(
+                $(#[$variant_meta])*
+                $variant $(($($crate::define_instructions!(@type $kind)),+))? = $value
+            ),*
         }
 
-        // Then implement methods for this enum
         impl $name {
             /// Gets the numeric opcode for the instruction
             $vis fn value(&self) -> u8 {
                 match self {
-                    $name::Nop => 0x00,
-                    $name::Push(_) => 0x01,
-                    $name::PopRegister(_) => 0x02,
-                    $name::PushRegister(_) => 0x03,
-                    $name::AddRegister(_, _) => 0x04,
-                    $name::AddStack => 0x0F,
-                    $name::Signal(_) => 0x09,
+                    $($name::$variant { .. } => $value,)*
+                }
+            }
+
+            /// Gets the assembler mnemonic for the instruction
+            $vis fn mnemonic(&self) -> &'static str {
+                match self {
+                    $($name::$variant { .. } => $mnemonic,)*
+                }
+            }
+
+            /// Gets the shape of the instruction's argument byte
+            $vis fn operand(&self) -> $crate::isa::Operand {
+                match self {
+                    $(
+                        $name::$variant { .. } => {
+                            $crate::define_instructions!(@operand [$($($kind),+)?])
+                        }
+                    )*
+                }
+            }
+
+            /// Number of bytes the instruction takes in memory
+            $vis fn size(&self) -> u16 {
+                Self::size_of(self.value()).expect("every opcode has a size")
+            }
+
+            /// Number of bytes taken by instructions with the opcode, `None`
+            /// for unknown opcodes
+            $vis fn size_of(opcode: u8) -> Option<u16> {
+                match opcode {
+                    $($value => Some(2 $($(+ $crate::define_instructions!(@extra $kind))+)?),)*
+                    _ => None,
                 }
             }
 
@@ -145,119 +427,148 @@ macro_rules! define_instructions {
             }
 
             /// Parse a 16-bit instruction into an Operation
+            /// The opcode is in the lower 8 bits and the argument in the upper 8 bits.
+            /// Instructions longer than two bytes need [`from_bytes`](Self::from_bytes).
             $vis fn parse_instruction(ins: u16) -> Result<Self, String> {
+                Self::decode(ins, None)
+            }
+
+            /// Decodes the first word of an instruction, and the word after
+            /// it for instructions that take one
+            fn decode(ins: u16, word: Option<u16>) -> Result<Self, String> {
                 let op = (ins & 0xFF) as u8;
                 let arg = Self::parse_instruction_arg(ins);
-
+                let size = Self::size_of(op).ok_or(format!("unknown op - 0x{:X}", op))?;
+                let word = match (size, word) {
+                    (2, _) => 0,
+                    (_, Some(word)) => word,
+                    (_, None) => {
+                        return Err(format!("truncated instruction - 0x{:X} takes {} bytes", op, size));
+                    }
+                };
                 match op {
-                    // No argument instructions
-                    0x00 => Ok($name::Nop),
-
-                    // Instructions with numeric argument
-                    0x01 => Ok($name::Push(arg)),
-                    0x09 => Ok($name::Signal(arg)),
-
-                    // Instructions with register argument
-                    0x02 => {
-                        Register::from_u8(arg)
-                            .ok_or(format!("Unknown register - 0x{:X}", arg))
-                            .map(|r| $name::PopRegister(r))
-                    },
-                    0x03 => {
-                        Register::from_u8(arg)
-                            .ok_or(format!("Unknown register - 0x{:X}", arg))
-                            .map(|r| $name::PushRegister(r))
-                    },
-
-                    // Two register argument instructions
-                    0x04 => {
-                        let reg1 = (arg >> 4) & 0x0F; // Upper 4 bits
-                        let reg2 = arg & 0x0F; // Lower 4 bits
-
-                        let r1 = Register::from_u8(reg1)
-                            .ok_or(format!("Unknown register - 0x{:X}", reg1))?;
-                        let r2 = Register::from_u8(reg2)
-                            .ok_or(format!("Unknown register - 0x{:X}", reg2))?;
-
-                        Ok($name::AddRegister(r1, r2))
-                    },
-
-                    // AddStack instruction
-                    0x0F => Ok($name::AddStack),
-
-                    // Unknown opcode
-                    _ => Err(format!("Unknown instruction - 0x{:X}", op)),
+                    $(
+                        $value => {
+                            $crate::define_instructions!(@decode arg, word, $name, $variant, [$($($kind),+)?])
+                        }
+                    )*
+                    _ => unreachable!(),
                 }
             }
 
             /// Convert the instruction back to its 16-bit binary representation
+            /// Instructions longer than two bytes give their first two here.
             $vis fn to_u16(&self) -> u16 {
-                match self {
-                    // No argument instructions
-                    $name::Nop => 0x00,
-
-                    // Numeric argument instructions
-                    $name::Push(arg) => {
-                        ((arg as u16) << 8) | 0x01
-                    },
-                    $name::Signal(arg) => {
-                        ((arg as u16) << 8) | 0x09
-                    },
-
-                    // Register argument instructions
-                    $name::PopRegister(reg) => {
-                        let reg_val = *reg as u8;
-                        ((reg_val as u16) << 8) | 0x02
-                    },
-                    $name::PushRegister(reg) => {
-                        let reg_val = *reg as u8;
-                        ((reg_val as u16) << 8) | 0x03
-                    },
-
-                    // Two register arguments instructions
-                    $name::AddRegister(reg1, reg2) => {
-                        let reg1_val = *reg1 as u8;
-                        let reg2_val = *reg2 as u8;
-                        let combined_arg = ((reg1_val & 0x0F) << 4) | (reg2_val & 0x0F);
-                        ((combined_arg as u16) << 8) | 0x04
-                    },
-
-                    // AddStack instruction
-                    $name::AddStack => 0x0F,
-                }
+                let arg = match self {
+                    $(
+                        $name::$variant { .. } => {
+                            $crate::define_instructions!(@encode self, $name, $variant, [$($($kind),+)?])
+                        }
+                    )*
+                };
+                ((arg as u16) << 8) | self.value() as u16
             }
 
-            /// Convert the instruction to a debug string representation
-            $vis fn to_string(&self) -> String {
+            /// The instruction as it sits in memory, opcode first
+            $vis fn to_bytes(&self) -> Vec<u8> {
+                let word: Option<u16> = match self {
+                    $(
+                        $name::$variant { .. } => {
+                            $crate::define_instructions!(@word self, $name, $variant, [$($($kind),+)?])
+                        }
+                    )*
+                };
+                let mut bytes = self.to_u16().to_le_bytes().to_vec();
+                bytes.extend(word.map(u16::to_le_bytes).into_iter().flatten());
+                bytes
+            }
+
+            /// Decodes the instruction at the start of `bytes`, which may
+            /// hold more after it
+            $vis fn from_bytes(bytes: &[u8]) -> Result<Self, String> {
+                let word_at = |at: usize| {
+                    bytes
+                        .get(at..at + 2)
+                        .map(|word| u16::from_le_bytes([word[0], word[1]]))
+                };
+                let Some(ins) = word_at(0) else {
+                    let op = *bytes.first().ok_or("truncated instruction - no bytes")?;
+                    let size = Self::size_of(op).ok_or(format!("unknown op - 0x{:X}", op))?;
+                    return Err(format!("truncated instruction - 0x{:X} takes {} bytes", op, size));
+                };
+                Self::decode(ins, word_at(2))
+            }
+
+            /// One instruction per opcode, in declaration order, with every
+            /// operand 0
+            $vis fn examples() -> Vec<Self> {
+                vec![$($crate::define_instructions!(@example $name, $variant, [$($($kind),+)?])),*]
+            }
+        }
+
+        /// The mnemonic followed by the operands.
+        impl ::std::fmt::Display for $name {
+            fn fmt(&self, f: &mut ::std::fmt::Formatter<'_>) -> ::std::fmt::Result {
                 match self {
-                    // No argument instructions
-                    $name::Nop => "Nop".to_string(),
-
-                    // Numeric argument instructions
-                    $name::Push(arg) => {
-                        format!("Push(0x{:02X})", arg)
-                    },
-                    $name::Signal(arg) => {
-                        format!("Signal(0x{:02X})", arg)
-                    },
-
-                    // Register argument instructions
-                    $name::PopRegister(reg) => {
-                        format!("PopRegister({:?})", reg)
-                    },
-                    $name::PushRegister(reg) => {
-                        format!("PushRegister({:?})", reg)
-                    },
-
-                    // Two register arguments instructions
-                    $name::AddRegister(reg1, reg2) => {
-                        format!("AddRegister({:?}, {:?})", reg1, reg2)
-                    },
-
-                    // AddStack instruction
-                    $name::AddStack => "AddStack".to_string(),
+                    $(
+                        $name::$variant { .. } => {
+                            $crate::define_instructions!(@display f, self, $name, $variant, [$($($kind),+)?])
+                        }
+                    )*
                 }
             }
         }
+
+        /// Reads an instruction in the form `Display` writes.
+        impl ::std::str::FromStr for $name {
+            type Err = String;
+
+            fn from_str(s: &str) -> Result<Self, String> {
+                let mut words = s
+                    .split(|c: char| c.is_whitespace() || c == ',')
+                    .filter(|word| !word.is_empty());
+                let mnemonic = words.next().ok_or("empty instruction")?;
+                let words: Vec<&str> = words.collect();
+                $(
+                    if mnemonic.eq_ignore_ascii_case($mnemonic) {
+                        return $crate::define_instructions!(@parse words, $name, $variant, $mnemonic, [$($($kind),+)?]);
+                    }
+                )*
+                Err(format!("unknown instruction - {}", mnemonic))
+            }
+        }
+    };
+}
+
+/// Asserts that a machine's registers and memory hold the given values,
+/// panicking with every mismatch at once.
+///
+/// Registers are named bare, memory as `[address] = [bytes]`:
+///
+/// ```
+/// # use rustyvm::{Machine, assert_state};
+/// let mut vm = Machine::new();
+/// vm.registers[0] = 30;
+/// vm.memory.write2(0x1000, 20);
+/// assert_state!(vm, A = 30, B = 0, [0x1000] = [20, 0]);
+/// ```
+#[macro_export]
+macro_rules! assert_state {
+    (@expect $expected:ident $(,)?) => {};
+    (@expect $expected:ident [$address:expr] = [$($byte:expr),* $(,)?] $(, $($rest:tt)*)?) => {
+        $expected.memory($address, &[$($byte),*]);
+        $crate::assert_state!(@expect $expected $($($rest)*)?);
+    };
+    (@expect $expected:ident $register:ident = $value:expr $(, $($rest:tt)*)?) => {
+        $expected.register($crate::Register::$register, $value);
+        $crate::assert_state!(@expect $expected $($($rest)*)?);
     };
+    ($vm:expr, $($rest:tt)*) => {{
+        let mut expected = $crate::diff::ExpectedState::new();
+        $crate::assert_state!(@expect expected $($rest)*);
+        let failures = expected.check(&$vm);
+        if !failures.is_empty() {
+            panic!("machine state differs:\n    {}", failures.join("\n    "));
+        }
+    }};
 }
@@ -5,8 +5,6 @@
 //! - Stack Memory: Starting at address 0x1000 (grows upward)
 //! - Memory Size: 8192 bytes (ends at 0x1FFF)
 
-use std::usize;
-
 /// Trait defining memory access operations for the VM.
 pub trait Addressable {
     /// Reads a single byte from memory at the specified address.
@@ -15,15 +13,18 @@ pub trait Addressable {
     /// Writes a single byte to memory at the specified address.
     fn write(&mut self, addr: u16, value: u8) -> bool;
 
+    /// Returns the number of addressable bytes, starting at address 0.
+    fn size(&self) -> usize;
+
     /// Reads a 16-bit word from memory using little-endian format.
     /// Lower byte at addr, upper byte at addr+1
     fn read2(&self, addr: u16) -> Option<u16> {
-        if let Some(lo) = self.read(addr) {
-            if let Some(hi) = self.read(addr + 1) {
-                // Combine bytes in little-endian format:
-                // Lower byte from addr, upper byte from addr+1
-                return Some((lo as u16) | ((hi as u16) << 8));
-            }
+        if let Some(lo) = self.read(addr)
+            && let Some(hi) = self.read(addr.checked_add(1)?)
+        {
+            // Combine bytes in little-endian format:
+            // Lower byte from addr, upper byte from addr+1
+            return Some((lo as u16) | ((hi as u16) << 8));
         }
         None
     }
@@ -37,26 +38,46 @@ pub trait Addressable {
 
         // Write bytes in little-endian format:
         // Lower byte at addr, upper byte at addr+1
-        self.write(addr, lo) && self.write(addr + 1, hi)
+        match addr.checked_add(1) {
+            Some(next) => self.write(addr, lo) && self.write(next, hi),
+            None => false,
+        }
     }
 
-    /// Copies a block of memory from one location to another.
+    /// Copies a block of memory from one location to another. Overlapping
+    /// blocks are copied as if through a temporary buffer. Fails, possibly
+    /// after writing part of the block, if either block leaves memory.
     fn copy(&mut self, from: u16, to: u16, n: usize) -> bool {
+        let mut bytes = Vec::with_capacity(n);
         for i in 0..n {
-            if let Some(x) = self.read(from + (i as u16)) {
-                if !self.write(to + (i as u16), x) {
-                    return false;
-                }
-            } else {
+            let Some(x) = block_address(from, i).and_then(|addr| self.read(addr)) else {
                 return false;
+            };
+            bytes.push(x);
+        }
+        for (i, x) in bytes.into_iter().enumerate() {
+            match block_address(to, i) {
+                Some(addr) if self.write(addr, x) => {}
+                _ => return false,
             }
         }
         true
     }
 
+    /// Writes `n` copies of `value` starting at `addr`. Fails, possibly
+    /// after writing part of the block, if the block leaves memory.
+    fn fill(&mut self, addr: u16, value: u8, n: usize) -> bool {
+        (0..n).all(|i| block_address(addr, i).is_some_and(|addr| self.write(addr, value)))
+    }
+
+    /// Sets every byte of memory to zero.
+    fn clear(&mut self) {
+        self.fill(0, 0, self.size().min(0x10000));
+    }
+
     /// Loads data from a vector into memory at the specified address.
     /// Returns the number of bytes and instructions loaded.
-    fn load_from_vec(&mut self, from: &Vec<u8>, addr: u16) -> Option<(usize, usize)> {
+    fn load_from_vec(&mut self, from: &[u8], addr: u16) -> Option<(usize, usize)> {
         let mut operations: usize = 0;
         for (i, b) in from.iter().enumerate() {
             if !self.write(addr + (i as u16), *b) {
@@ -69,6 +90,12 @@ pub trait Addressable {
     }
 }
 
+/// Address of byte `i` of a block starting at `start`, if it doesn't run past
+/// the end of the address space.
+fn block_address(start: u16, i: usize) -> Option<u16> {
+    u16::try_from(i).ok().and_then(|i| start.checked_add(i))
+}
+
 /// A flat, linear memory implementation for the VM.
 /// Provides contiguous memory with bounds-checking on all operations.
 pub struct LinearMemory {
@@ -110,4 +137,68 @@ impl Addressable for LinearMemory {
             false
         }
     }
+
+    fn size(&self) -> usize {
+        self.size
+    }
+
+    fn clear(&mut self) {
+        self.bytes.fill(0);
+    }
+}
+
+/// Size of one page of [`PagedMemory`] in bytes.
+pub const PAGE_SIZE: usize = 256;
+
+/// A memory implementation that allocates fixed-size pages on first write.
+/// Unwritten pages read as zero, so it behaves exactly like [`LinearMemory`]
+/// of the same size while only storing the pages a program touches.
+pub struct PagedMemory {
+    /// Pages by index, `None` until something is written to them
+    pages: Vec<Option<Box<[u8; PAGE_SIZE]>>>,
+    /// Total size of the memory in bytes
+    size: usize,
+}
+
+impl PagedMemory {
+    /// Creates a new paged memory instance with the specified size.
+    pub fn new(n: usize) -> Self {
+        Self {
+            pages: (0..n.div_ceil(PAGE_SIZE)).map(|_| None).collect(),
+            size: n,
+        }
+    }
+
+    /// Returns the number of pages that have been allocated.
+    pub fn allocated_pages(&self) -> usize {
+        self.pages.iter().filter(|page| page.is_some()).count()
+    }
+}
+
+impl Addressable for PagedMemory {
+    fn read(&self, addr: u16) -> Option<u8> {
+        if (addr as usize) >= self.size {
+            return None;
+        }
+        let (page, offset) = (addr as usize / PAGE_SIZE, addr as usize % PAGE_SIZE);
+        Some(self.pages[page].as_ref().map_or(0, |page| page[offset]))
+    }
+
+    fn write(&mut self, addr: u16, value: u8) -> bool {
+        if (addr as usize) >= self.size {
+            return false;
+        }
+        let (page, offset) = (addr as usize / PAGE_SIZE, addr as usize % PAGE_SIZE);
+        self.pages[page].get_or_insert_with(|| Box::new([0; PAGE_SIZE]))[offset] = value;
+        true
+    }
+
+    fn size(&self) -> usize {
+        self.size
+    }
+
+    /// Frees every page, since unwritten pages read as zero.
+    fn clear(&mut self) {
+        self.pages.iter_mut().for_each(|page| *page = None);
+    }
 }
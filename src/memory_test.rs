@@ -95,8 +95,32 @@ mod tests {
         assert_eq!(memory.read2(255), None); // Should fail - reads beyond bounds
     }
 
-    // The copy functionality relies on u8 addresses which can easily overflow
-    // We'll skip testing it directly for now
+    #[test]
+    fn test_copy_and_fill() {
+        let mut memory = LinearMemory::new(256);
+        memory.load_from_vec(&[1, 2, 3, 4], 10).unwrap();
+
+        assert!(memory.copy(10, 20, 4));
+        assert_eq!(memory.read2(20), Some(0x0201));
+        assert_eq!(memory.read2(22), Some(0x0403));
+
+        // Overlapping blocks copy the original bytes
+        assert!(memory.copy(10, 11, 4));
+        assert_eq!(memory.read2(10), Some(0x0101));
+        assert_eq!(memory.read2(12), Some(0x0302));
+        assert_eq!(memory.read(14), Some(4));
+
+        assert!(memory.fill(30, 0xAA, 3));
+        assert_eq!(memory.read2(30), Some(0xAAAA));
+        assert_eq!(memory.read(32), Some(0xAA));
+        assert_eq!(memory.read(33), Some(0));
+
+        // Blocks that leave memory or the address space fail
+        assert!(!memory.copy(250, 0, 10));
+        assert!(!memory.fill(250, 0, 10));
+        assert!(!memory.copy(0, 0xFFFF, 2));
+        assert!(memory.fill(0, 0, 0));
+    }
 
     #[test]
     fn test_load_from_vec() {
@@ -114,8 +138,8 @@ mod tests {
         assert_eq!(instructions, 3); // 3 instructions (2 bytes each)
 
         // Verify data was loaded correctly
-        for i in 0..6 {
-            assert_eq!(memory.read(100 + i as u16), Some(data[i]));
+        for (i, &byte) in data.iter().enumerate() {
+            assert_eq!(memory.read(100 + i as u16), Some(byte));
         }
 
         // Test loading data that would exceed memory bounds
@@ -138,4 +162,38 @@ mod tests {
         let memory = LinearMemory::new(256);
         takes_addressable(&memory);
     }
+
+    #[test]
+    fn test_paged_memory() {
+        let mut memory = PagedMemory::new(1000);
+        assert_eq!(memory.size(), 1000);
+        assert_eq!(memory.allocated_pages(), 0);
+
+        // Unwritten memory reads as zero without allocating
+        assert_eq!(memory.read(700), Some(0));
+        assert_eq!(memory.allocated_pages(), 0);
+
+        assert!(memory.write2(PAGE_SIZE as u16 - 1, 0xBEEF));
+        assert_eq!(memory.read2(PAGE_SIZE as u16 - 1), Some(0xBEEF));
+        assert_eq!(memory.allocated_pages(), 2);
+
+        // The last page is only partly addressable
+        assert!(memory.write(999, 1));
+        assert!(!memory.write(1000, 1));
+        assert_eq!(memory.read(1000), None);
+
+        // Clearing frees the pages rather than writing zeros into them
+        memory.clear();
+        assert_eq!(memory.allocated_pages(), 0);
+        assert_eq!(memory.read2(PAGE_SIZE as u16 - 1), Some(0));
+    }
+
+    #[test]
+    fn test_word_at_the_last_address_faults() {
+        // The second byte would be past 0xFFFF, so the access fails instead of wrapping
+        let mut memory = PagedMemory::new(0x10000);
+        assert_eq!(memory.read2(0xFFFF), None);
+        assert!(!memory.write2(0xFFFF, 0x1234));
+        assert!(memory.write2(0xFFFE, 0x1234));
+    }
 }
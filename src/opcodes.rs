@@ -1,41 +1,185 @@
-use crate::{Machine, Register};
+use crate::{Flag, Machine, Register, SyscallArgs, define_instructions, logging};
 
-/// Operations supported by the VM.
-///
-/// Each operation corresponds to a specific instruction opcode.
-/// The VM uses a 2-byte instruction format, where the first byte is the opcode
-/// and the second byte is an argument (when applicable).
-#[derive(Debug, PartialEq, Eq, Clone)]
-#[repr(u8)]
-pub enum Op {
-    /// No operation (opcode 0x00)
-    Nop = 0x00,
-    /// Push a value onto the stack (opcode 0x01)
-    /// Parameter: 8-bit value to push
-    Push(u8) = 0x01,
-    /// Pop a value from the stack into a register (opcode 0x02)
-    /// Parameter: destination register
-    PopRegister(Register) = 0x02,
-    /// Push a register value onto the stack (opcode 0x03)
-    /// Parameter: register to push
-    PushRegister(Register) = 0x03,
-    /// Add top two values on stack, push result (opcode 0x0F)
-    AddStack = 0x0F,
-    /// Add two registers, store result in first register (opcode 0x04)
-    /// Parameters: destination register, source register
-    AddRegister(Register, Register) = 0x04,
-    /// Signal returns the Signal (opcode 0x09)
-    /// Parameters: signal integer
-    Signal(u8) = 0x09,
+define_instructions! {
+    /// Operations supported by the VM.
+    ///
+    /// Each operation corresponds to a specific instruction opcode.
+    /// The VM uses a 2-byte instruction format, where the first byte is the opcode
+    /// and the second byte is an argument (when applicable). Instructions
+    /// with a 16-bit operand carry it in two more bytes.
+    #[derive(Debug, PartialEq, Eq, Clone)]
+    #[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+    #[repr(u8)]
+    pub enum Op {
+        /// No operation (opcode 0x00)
+        Nop = 0x00 => "NOP",
+        /// Push a value onto the stack (opcode 0x01)
+        /// Parameter: 8-bit value to push
+        Push(byte) = 0x01 => "PUSH",
+        /// Pop a value from the stack into a register (opcode 0x02)
+        /// Parameter: destination register
+        PopRegister(reg) = 0x02 => "POP",
+        /// Push a register value onto the stack (opcode 0x03)
+        /// Parameter: register to push
+        PushRegister(reg) = 0x03 => "PUSHR",
+        /// Add two registers, store result in first register (opcode 0x04)
+        /// Parameters: destination register, source register
+        AddRegister(reg, reg) = 0x04 => "ADDR",
+        /// Copy the second register into the first (opcode 0x05)
+        /// Parameters: destination register, source register
+        MoveRegister(reg, reg) = 0x05 => "MOV",
+        /// Subtract the second register from the first, store result in first register (opcode 0x06)
+        /// Parameters: destination register, source register
+        SubRegister(reg, reg) = 0x06 => "SUBR",
+        /// Load the word at the address in M into a register (opcode 0x07)
+        /// Parameter: destination register
+        Load(reg) = 0x07 => "LOAD",
+        /// Store a register at the address in M (opcode 0x08)
+        /// Parameter: source register
+        Store(reg) = 0x08 => "STORE",
+        /// Signal returns the Signal (opcode 0x09)
+        /// Parameters: signal integer
+        Signal(byte) = 0x09 => "SIG",
+        /// Load the word at the address in the second register into the first (opcode 0x0A)
+        /// Parameters: destination register, address register
+        LoadIndirect(reg, reg) = 0x0A => "LOADR",
+        /// Store the first register at the address in the second (opcode 0x0B)
+        /// Parameters: source register, address register
+        StoreIndirect(reg, reg) = 0x0B => "STORER",
+        /// Load the word at the second register plus an offset into the first (opcode 0x0C)
+        /// Parameters: destination register, address register, then the offset in the next two bytes
+        LoadOffset(reg, reg, word) = 0x0C => "LOADO",
+        /// Store the first register at the second register plus an offset (opcode 0x0D)
+        /// Parameters: source register, address register, then the offset in the next two bytes
+        StoreOffset(reg, reg, word) = 0x0D => "STOREO",
+        /// Pop the top value, subtract it from the one below, push result (opcode 0x0E)
+        SubStack = 0x0E => "SUBS",
+        /// Add top two values on stack, push result (opcode 0x0F)
+        AddStack = 0x0F => "ADDS",
+        /// Bitwise AND of the top two values on stack, push result (opcode 0x10)
+        AndStack = 0x10 => "ANDS",
+        /// Bitwise OR of the top two values on stack, push result (opcode 0x11)
+        OrStack = 0x11 => "ORS",
+        /// Bitwise XOR of the top two values on stack, push result (opcode 0x12)
+        XorStack = 0x12 => "XORS",
+        /// Invert every bit of the top value on stack (opcode 0x13)
+        NotStack = 0x13 => "NOTS",
+        /// Bitwise AND two registers, store result in first register (opcode 0x14)
+        /// Parameters: destination register, source register
+        AndRegister(reg, reg) = 0x14 => "ANDR",
+        /// Bitwise OR two registers, store result in first register (opcode 0x15)
+        /// Parameters: destination register, source register
+        OrRegister(reg, reg) = 0x15 => "ORR",
+        /// Bitwise XOR two registers, store result in first register (opcode 0x16)
+        /// Parameters: destination register, source register
+        XorRegister(reg, reg) = 0x16 => "XORR",
+        /// Invert every bit of a register (opcode 0x17)
+        /// Parameter: register to invert
+        NotRegister(reg) = 0x17 => "NOTR",
+        /// Load a 16-bit value into a register (opcode 0x18)
+        /// Parameters: destination register, then the value in the next two bytes
+        LoadImmediate(reg, word) = 0x18 => "LDI",
+        /// Push a copy of the top value on stack (opcode 0x19)
+        Dup = 0x19 => "DUP",
+        /// Exchange the top two values on stack (opcode 0x1A)
+        Swap = 0x1A => "SWAP",
+        /// Discard the top value on stack (opcode 0x1B)
+        Drop = 0x1B => "DROP",
+        /// Push a copy of the value below the top (opcode 0x1C)
+        Over = 0x1C => "OVER",
+        /// Set the flags of subtracting the second register from the first, storing nothing (opcode 0x1D)
+        /// Parameters: the two registers to compare
+        CompareRegister(reg, reg) = 0x1D => "CMP",
+        /// Multiply two registers into 32 bits, low word in the first, high word in M, and set the flags (opcode 0x1E)
+        /// Parameters: destination register, source register
+        MulWide(reg, reg) = 0x1E => "MULW",
+        /// Call the syscall handler numbered by the argument, with A, B and C as arguments (opcode 0x1F)
+        /// Parameter: syscall number
+        Syscall(byte) = 0x1F => "SYSCALL",
+        /// Continue at the address in the argument (opcode 0x20)
+        /// Parameter: 8-bit target address
+        Jump(byte) = 0x20 => "JMP",
+        /// Jump if the last result was zero (opcode 0x21)
+        /// Parameter: 8-bit target address
+        JumpZero(byte) = 0x21 => "JZ",
+        /// Jump if the last result was not zero (opcode 0x22)
+        /// Parameter: 8-bit target address
+        JumpNotZero(byte) = 0x22 => "JNZ",
+        /// Jump if the last addition carried or subtraction borrowed (opcode 0x23)
+        /// Parameter: 8-bit target address
+        JumpCarry(byte) = 0x23 => "JC",
+        /// Jump if the last addition did not carry or subtraction did not borrow (opcode 0x24)
+        /// Parameter: 8-bit target address
+        JumpNotCarry(byte) = 0x24 => "JNC",
+        /// Jump if the last subtraction's first operand was less, as signed values (opcode 0x25)
+        /// Parameter: 8-bit target address
+        JumpLess(byte) = 0x25 => "JLT",
+        /// Jump if the last subtraction's first operand was greater, as signed values (opcode 0x26)
+        /// Parameter: 8-bit target address
+        JumpGreater(byte) = 0x26 => "JGT",
+        /// Jump if the last subtraction's first operand was less or equal, as signed values (opcode 0x27)
+        /// Parameter: 8-bit target address
+        JumpLessEqual(byte) = 0x27 => "JLE",
+        /// Jump if the last subtraction's first operand was greater or equal, as signed values (opcode 0x28)
+        /// Parameter: 8-bit target address
+        JumpGreaterEqual(byte) = 0x28 => "JGE",
+        /// Continue at the address in a register (opcode 0x29)
+        /// Parameter: register holding the target address
+        JumpRegister(reg) = 0x29 => "JMPR",
+        /// Continue at the 16-bit address in the next two bytes (opcode 0x2A)
+        /// Parameter: 16-bit target address
+        JumpLong(word) = 0x2A => "JMPL",
+        /// Push the FLAGS register (opcode 0x30)
+        PushFlags = 0x30 => "PUSHF",
+        /// Pop the top value into the FLAGS register (opcode 0x31)
+        PopFlags = 0x31 => "POPF",
+        /// Copy M bytes from the address in the second register to the address in the first (opcode 0x32)
+        /// Parameters: destination address register, source address register
+        MemCopy(reg, reg) = 0x32 => "MEMCPY",
+        /// Fill M bytes at the address in the first register with the low byte of the second (opcode 0x33)
+        /// Parameters: destination address register, value register
+        MemSet(reg, reg) = 0x33 => "MEMSET",
+        /// Set the zero and negative flags of ANDing two registers and clear carry and overflow, storing nothing (opcode 0x34)
+        /// Parameters: the two registers to test
+        Test(reg, reg) = 0x34 => "TEST",
+        /// Copy the second register into the first if the last result was zero (opcode 0x35)
+        /// Parameters: destination register, source register
+        MoveIfZero(reg, reg) = 0x35 => "CMOVZ",
+        /// Copy the second register into the first if the last result was not zero (opcode 0x36)
+        /// Parameters: destination register, source register
+        MoveIfNotZero(reg, reg) = 0x36 => "CMOVNZ",
+        /// Continue at PC plus the signed offset in the argument (opcode 0x40)
+        /// Parameter: 8-bit signed offset from the next instruction
+        Branch(byte) = 0x40 => "BRA",
+        /// Branch if the last result was zero (opcode 0x41)
+        /// Parameter: 8-bit signed offset from the next instruction
+        BranchZero(byte) = 0x41 => "BZ",
+        /// Branch if the last result was not zero (opcode 0x42)
+        /// Parameter: 8-bit signed offset from the next instruction
+        BranchNotZero(byte) = 0x42 => "BNZ",
+        /// Branch if the last addition carried or subtraction borrowed (opcode 0x43)
+        /// Parameter: 8-bit signed offset from the next instruction
+        BranchCarry(byte) = 0x43 => "BC",
+        /// Branch if the last addition did not carry or subtraction did not borrow (opcode 0x44)
+        /// Parameter: 8-bit signed offset from the next instruction
+        BranchNotCarry(byte) = 0x44 => "BNC",
+        /// Branch if the last subtraction's first operand was less, as signed values (opcode 0x45)
+        /// Parameter: 8-bit signed offset from the next instruction
+        BranchLess(byte) = 0x45 => "BLT",
+        /// Branch if the last subtraction's first operand was greater, as signed values (opcode 0x46)
+        /// Parameter: 8-bit signed offset from the next instruction
+        BranchGreater(byte) = 0x46 => "BGT",
+        /// Branch if the last subtraction's first operand was less or equal, as signed values (opcode 0x47)
+        /// Parameter: 8-bit signed offset from the next instruction
+        BranchLessEqual(byte) = 0x47 => "BLE",
+        /// Branch if the last subtraction's first operand was greater or equal, as signed values (opcode 0x48)
+        /// Parameter: 8-bit signed offset from the next instruction
+        BranchGreaterEqual(byte) = 0x48 => "BGE",
+    }
 }
 
 /// Implementation of operation-related functionality.
 impl Op {
-    /// Gets the numeric opcode value for this operation.
-    pub fn value(&self) -> u8 {
-        unsafe { *<*const _>::from(self).cast::<u8>() }
-    }
-
     /// Checks if a numeric opcode matches a specific operation.
     pub fn equals(x: u8, other: Self) -> bool {
         x == other.value()
@@ -45,42 +189,79 @@ impl Op {
 /// Parses a 16-bit instruction and extracts the 8-bit argument.
 /// Uses little-endian format with ARGUMENT in upper 8 bits and OPCODE in lower 8 bits
 pub fn parse_instructions_arg(ins: u16) -> u8 {
-    ((ins & 0xff00) >> 8) as u8
+    Op::parse_instruction_arg(ins)
 }
 
 /// Parses a 16-bit instruction into an operation.
 /// Extracts the opcode (lower 8 bits) and returns the corresponding operation.
 pub fn parse_instructions(ins: u16) -> Result<Op, String> {
-    let op = (ins & 0xff) as u8;
+    Op::parse_instruction(ins)
+}
+
+/// Loads the word at the address in `base` plus `offset` into `r`. The
+/// address wraps, so an offset of 0xFFFE reads the word before `base`.
+fn load(machine: &mut Machine, r: Register, base: Register, offset: u16) -> Result<(), String> {
+    let addr = machine.registers[base as usize].wrapping_add(offset);
+    machine.registers[r as usize] = machine
+        .memory
+        .read2(addr)
+        .ok_or(format!("memory read fault - 0x{:X}", addr))?;
+    Ok(())
+}
+
+/// Stores `r` as a word at the address in `base` plus `offset`, wrapping.
+fn store(machine: &mut Machine, r: Register, base: Register, offset: u16) -> Result<(), String> {
+    let addr = machine.registers[base as usize].wrapping_add(offset);
+    let value = machine.registers[r as usize];
+    if !machine.memory.write2(addr, value) {
+        return Err(format!("memory write fault - 0x{:X}", addr));
+    }
+    Ok(())
+}
 
+/// Sets the arithmetic flags from a result and whether it carried (or
+/// borrowed) and overflowed. Other FLAGS bits are left alone.
+fn set_arithmetic_flags(machine: &mut Machine, result: u16, carry: bool, overflow: bool) {
+    machine.set_flag(Flag::Zero, result == 0);
+    machine.set_flag(Flag::Carry, carry);
+    machine.set_flag(Flag::Negative, result & 0x8000 != 0);
+    machine.set_flag(Flag::Overflow, overflow);
+}
+
+/// Adds two values, wrapping, and sets the flags from the result.
+fn add(machine: &mut Machine, a: u16, b: u16) -> u16 {
+    let (result, carry) = a.overflowing_add(b);
+    let (_, overflow) = (a as i16).overflowing_add(b as i16);
+    set_arithmetic_flags(machine, result, carry, overflow);
+    result
+}
+
+/// Subtracts `b` from `a`, wrapping, and sets the flags from the result.
+/// Carry means the subtraction borrowed, that is `a < b` unsigned.
+fn sub(machine: &mut Machine, a: u16, b: u16) -> u16 {
+    let (result, borrow) = a.overflowing_sub(b);
+    let (_, overflow) = (a as i16).overflowing_sub(b as i16);
+    set_arithmetic_flags(machine, result, borrow, overflow);
+    result
+}
+
+/// Whether a conditional jump, branch or move is taken with the current flags. The signed
+/// comparisons read the flags of a subtraction `a - b`: less means the sign
+/// of the result disagrees with the overflow flag.
+fn condition_holds(machine: &Machine, op: &Op) -> bool {
+    let zero = machine.get_flag(Flag::Zero);
+    let carry = machine.get_flag(Flag::Carry);
+    let less = machine.get_flag(Flag::Negative) != machine.get_flag(Flag::Overflow);
     match op {
-        x if x == Op::Nop.value() => Ok(Op::Nop),
-        x if x == Op::Push(0).value() => Ok(Op::Push(parse_instructions_arg(ins))),
-        x if x == Op::PopRegister(Register::A).value() => {
-            let arg = parse_instructions_arg(ins);
-            Register::from_u8(arg)
-                .ok_or(format!("unknown register - 0x{:X}", arg))
-                .map(|r| Op::PopRegister(r))
-        }
-        x if x == Op::PushRegister(Register::A).value() => {
-            let arg = parse_instructions_arg(ins);
-            Register::from_u8(arg)
-                .ok_or(format!("unknown register - 0x{:X}", arg))
-                .map(|r| Op::PushRegister(r))
-        }
-        x if x == Op::AddRegister(Register::A, Register::A).value() => {
-            let arg = parse_instructions_arg(ins);
-            // The first byte is the opcode
-            // The second byte is divided into two 4 bit parts to store 2 register address
-            let reg1 = (arg >> 4) & 0x0F; // Upper 4 bits
-            let reg2 = arg & 0x0F; // Lower 4 bits
-            let r1 = Register::from_u8(reg1).ok_or(format!("unknown register - 0x{:X}", reg1))?;
-            let r2 = Register::from_u8(reg2).ok_or(format!("unknown register - 0x{:X}", reg2))?;
-            Ok(Op::AddRegister(r1, r2))
-        }
-        x if x == Op::AddStack.value() => Ok(Op::AddStack),
-        x if x == Op::Signal(0).value() => Ok(Op::Signal(parse_instructions_arg(ins))),
-        _ => Err(format!("unknown op - 0x{:X}", op)),
+        Op::JumpZero(_) | Op::BranchZero(_) | Op::MoveIfZero(..) => zero,
+        Op::JumpNotZero(_) | Op::BranchNotZero(_) | Op::MoveIfNotZero(..) => !zero,
+        Op::JumpCarry(_) | Op::BranchCarry(_) => carry,
+        Op::JumpNotCarry(_) | Op::BranchNotCarry(_) => !carry,
+        Op::JumpLess(_) | Op::BranchLess(_) => less,
+        Op::JumpGreater(_) | Op::BranchGreater(_) => !zero && !less,
+        Op::JumpLessEqual(_) | Op::BranchLessEqual(_) => zero || less,
+        Op::JumpGreaterEqual(_) | Op::BranchGreaterEqual(_) => !less,
+        _ => true,
     }
 }
 
@@ -103,20 +284,215 @@ pub fn execute_instruction(machine: &mut Machine, op: Op) -> Result<(), String>
         Op::AddStack => {
             let a = machine.pop()?;
             let b = machine.pop()?;
-            let result = a + b;
+            let result = add(machine, b, a);
             machine.push(result)?;
             Ok(())
         }
         Op::AddRegister(r1, r2) => {
-            machine.registers[r1 as usize] += machine.registers[r2 as usize];
+            let (a, b) = (
+                machine.registers[r1 as usize],
+                machine.registers[r2 as usize],
+            );
+            machine.registers[r1 as usize] = add(machine, a, b);
+            Ok(())
+        }
+        Op::SubStack => {
+            let a = machine.pop()?;
+            let b = machine.pop()?;
+            let result = sub(machine, b, a);
+            machine.push(result)
+        }
+        Op::SubRegister(r1, r2) => {
+            let (a, b) = (
+                machine.registers[r1 as usize],
+                machine.registers[r2 as usize],
+            );
+            machine.registers[r1 as usize] = sub(machine, a, b);
+            Ok(())
+        }
+        Op::CompareRegister(r1, r2) => {
+            let (a, b) = (
+                machine.registers[r1 as usize],
+                machine.registers[r2 as usize],
+            );
+            sub(machine, a, b);
+            Ok(())
+        }
+        Op::MoveIfZero(r1, r2) | Op::MoveIfNotZero(r1, r2) => {
+            if condition_holds(machine, &op) {
+                machine.registers[r1 as usize] = machine.registers[r2 as usize];
+            }
+            Ok(())
+        }
+        Op::Test(r1, r2) => {
+            let result = machine.registers[r1 as usize] & machine.registers[r2 as usize];
+            // An AND can't carry or overflow, so those flags are cleared
+            set_arithmetic_flags(machine, result, false, false);
+            Ok(())
+        }
+        Op::MulWide(r1, r2) => {
+            let product =
+                machine.registers[r1 as usize] as u32 * machine.registers[r2 as usize] as u32;
+            // Carry and overflow mean the product does not fit in the low word
+            let wide = product > 0xFFFF;
+            set_arithmetic_flags(machine, product as u16, wide, wide);
+            // M first, so the destination keeps the low word even when it is M
+            machine.registers[Register::M as usize] = (product >> 16) as u16;
+            machine.registers[r1 as usize] = product as u16;
+            Ok(())
+        }
+        Op::AndStack | Op::OrStack | Op::XorStack => {
+            let a = machine.pop()?;
+            let b = machine.pop()?;
+            machine.push(match op {
+                Op::AndStack => b & a,
+                Op::OrStack => b | a,
+                _ => b ^ a,
+            })
+        }
+        Op::NotStack => {
+            let a = machine.pop()?;
+            machine.push(!a)
+        }
+        Op::AndRegister(r1, r2) => {
+            machine.registers[r1 as usize] &= machine.registers[r2 as usize];
+            Ok(())
+        }
+        Op::OrRegister(r1, r2) => {
+            machine.registers[r1 as usize] |= machine.registers[r2 as usize];
+            Ok(())
+        }
+        Op::XorRegister(r1, r2) => {
+            machine.registers[r1 as usize] ^= machine.registers[r2 as usize];
+            Ok(())
+        }
+        Op::NotRegister(r) => {
+            machine.registers[r as usize] = !machine.registers[r as usize];
+            Ok(())
+        }
+        Op::MoveRegister(r1, r2) => {
+            machine.registers[r1 as usize] = machine.registers[r2 as usize];
+            Ok(())
+        }
+        Op::Load(r) => load(machine, r, Register::M, 0),
+        Op::Store(r) => store(machine, r, Register::M, 0),
+        Op::LoadIndirect(r, base) => load(machine, r, base, 0),
+        Op::StoreIndirect(r, base) => store(machine, r, base, 0),
+        Op::LoadOffset(r, base, offset) => load(machine, r, base, offset),
+        Op::StoreOffset(r, base, offset) => store(machine, r, base, offset),
+        Op::LoadImmediate(r, value) => {
+            machine.registers[r as usize] = value;
+            Ok(())
+        }
+        Op::Dup => {
+            let a = machine.pop()?;
+            machine.push(a)?;
+            machine.push(a)
+        }
+        Op::Swap => {
+            let a = machine.pop()?;
+            let b = machine.pop()?;
+            machine.push(a)?;
+            machine.push(b)
+        }
+        Op::Drop => machine.pop().map(|_| ()),
+        Op::PushFlags => machine.push(machine.registers[Register::FLAGS as usize]),
+        Op::PopFlags => {
+            machine.registers[Register::FLAGS as usize] = machine.pop()?;
+            Ok(())
+        }
+        Op::Over => {
+            let a = machine.pop()?;
+            let b = machine.pop()?;
+            machine.push(b)?;
+            machine.push(a)?;
+            machine.push(b)
+        }
+        Op::MemCopy(to, from) => {
+            let (to, from) = (
+                machine.registers[to as usize],
+                machine.registers[from as usize],
+            );
+            let n = machine.registers[Register::M as usize];
+            if !machine.memory.copy(from, to, n as usize) {
+                return Err(format!(
+                    "memory copy fault - {} bytes from 0x{:X} to 0x{:X}",
+                    n, from, to
+                ));
+            }
+            Ok(())
+        }
+        Op::MemSet(to, value) => {
+            let to = machine.registers[to as usize];
+            let value = machine.registers[value as usize] as u8;
+            let n = machine.registers[Register::M as usize];
+            if !machine.memory.fill(to, value, n as usize) {
+                return Err(format!("memory fill fault - {} bytes at 0x{:X}", n, to));
+            }
+            Ok(())
+        }
+        Op::Jump(address) => {
+            machine.registers[Register::PC as usize] = address as u16;
+            Ok(())
+        }
+        Op::JumpLong(address) => {
+            machine.registers[Register::PC as usize] = address;
+            Ok(())
+        }
+        Op::JumpRegister(r) => {
+            machine.registers[Register::PC as usize] = machine.registers[r as usize];
+            Ok(())
+        }
+        Op::JumpZero(address)
+        | Op::JumpNotZero(address)
+        | Op::JumpCarry(address)
+        | Op::JumpNotCarry(address)
+        | Op::JumpLess(address)
+        | Op::JumpGreater(address)
+        | Op::JumpLessEqual(address)
+        | Op::JumpGreaterEqual(address) => {
+            if condition_holds(machine, &op) {
+                machine.registers[Register::PC as usize] = address as u16;
+            }
+            Ok(())
+        }
+        Op::Branch(offset)
+        | Op::BranchZero(offset)
+        | Op::BranchNotZero(offset)
+        | Op::BranchCarry(offset)
+        | Op::BranchNotCarry(offset)
+        | Op::BranchLess(offset)
+        | Op::BranchGreater(offset)
+        | Op::BranchLessEqual(offset)
+        | Op::BranchGreaterEqual(offset) => {
+            // PC already points past the branch, which is what the offset counts from
+            if condition_holds(machine, &op) {
+                let pc = &mut machine.registers[Register::PC as usize];
+                *pc = pc.wrapping_add_signed(offset as i8 as i16);
+            }
             Ok(())
         }
         Op::Signal(s) => {
+            logging::signal(s);
             let sig_fn = machine
                 .signal_handlers
                 .get(&s)
                 .ok_or(format!("unknown signal - 0x{:X}", s))?;
             sig_fn(machine)
         }
+        Op::Syscall(number) => {
+            let args = SyscallArgs {
+                a: machine.registers[Register::A as usize],
+                b: machine.registers[Register::B as usize],
+                c: machine.registers[Register::C as usize],
+            };
+            logging::syscall(number, args.a, args.b, args.c);
+            let handler = machine
+                .syscall_handlers
+                .get(&number)
+                .ok_or(format!("unknown syscall - 0x{:X}", number))?;
+            machine.registers[Register::A as usize] = handler(machine, args)?;
+            Ok(())
+        }
     }
 }
@@ -0,0 +1,158 @@
+//! Unit tests for the instruction encoding generated by `define_instructions!`.
+
+#[cfg(test)]
+mod tests {
+    use crate::asm::assemble_str;
+    use crate::isa::Operand;
+    use crate::{Op, Register};
+
+    fn sample() -> Vec<Op> {
+        vec![
+            Op::Nop,
+            Op::Push(0xAB),
+            Op::PopRegister(Register::C),
+            Op::PushRegister(Register::R4),
+            Op::AddRegister(Register::B, Register::M),
+            Op::MoveRegister(Register::R0, Register::A),
+            Op::SubRegister(Register::R2, Register::SP),
+            Op::Signal(0xF0),
+            Op::SubStack,
+            Op::AddStack,
+            Op::XorStack,
+            Op::OrRegister(Register::C, Register::FLAGS),
+            Op::NotRegister(Register::R3),
+            Op::LoadImmediate(Register::R1, 0xBEEF),
+        ]
+    }
+
+    #[test]
+    fn test_every_op_round_trips() {
+        for op in sample().into_iter().chain(Op::examples()) {
+            if op.size() == 2 {
+                assert_eq!(Op::parse_instruction(op.to_u16()), Ok(op.clone()));
+            }
+            assert_eq!(op.to_bytes().len(), op.size() as usize);
+            assert_eq!(Op::from_bytes(&op.to_bytes()), Ok(op.clone()));
+        }
+    }
+
+    #[test]
+    fn test_encoding_packs_operands_into_the_argument_byte() {
+        assert_eq!(Op::Push(0x7F).to_u16(), 0x7F01);
+        assert_eq!(Op::PopRegister(Register::B).to_u16(), 0x0102);
+        assert_eq!(Op::AddRegister(Register::B, Register::C).to_u16(), 0x1204);
+        assert_eq!(Op::AddStack.to_u16(), 0x000F);
+    }
+
+    #[test]
+    fn test_wide_operands_follow_the_argument_byte() {
+        let ldi = Op::LoadImmediate(Register::C, 0x1234);
+        assert_eq!(ldi.to_bytes(), [0x18, 0x02, 0x34, 0x12]);
+        assert_eq!(Op::size_of(0x18), Some(4));
+        assert_eq!(Op::size_of(0x01), Some(2));
+        assert_eq!(Op::size_of(0xFF), None);
+
+        // Bytes after the instruction are left alone
+        assert_eq!(Op::from_bytes(&[0x18, 0x02, 0x34, 0x12, 0x00]), Ok(ldi));
+        assert_eq!(
+            Op::from_bytes(&[0x18, 0x02, 0x34]),
+            Err("truncated instruction - 0x18 takes 4 bytes".to_string())
+        );
+        assert_eq!(
+            Op::parse_instruction(0x0218),
+            Err("truncated instruction - 0x18 takes 4 bytes".to_string())
+        );
+    }
+
+    #[test]
+    fn test_register_pair_with_a_word() {
+        let load = Op::LoadOffset(Register::A, Register::M, 0xFFFE);
+        assert_eq!(load.to_bytes(), [0x0C, 0x03, 0xFE, 0xFF]);
+        assert_eq!(load.operand(), Operand::RegisterPairWord);
+        assert_eq!(Op::from_bytes(&load.to_bytes()), Ok(load.clone()));
+        assert_eq!(load.to_string(), "LOADO A M $FFFE");
+        assert_eq!("loado a, m, $fffe".parse::<Op>(), Ok(load));
+    }
+
+    #[test]
+    fn test_metadata_comes_from_the_variant_list() {
+        let opcodes: Vec<u8> = Op::examples().iter().map(Op::value).collect();
+        assert_eq!(
+            opcodes,
+            [
+                0x00, 0x01, 0x02, 0x03, 0x04, 0x05, 0x06, 0x07, 0x08, 0x09, 0x0A, 0x0B, 0x0C, 0x0D,
+                0x0E, 0x0F, 0x10, 0x11, 0x12, 0x13, 0x14, 0x15, 0x16, 0x17, 0x18, 0x19, 0x1A, 0x1B,
+                0x1C, 0x1D, 0x1E, 0x1F, 0x20, 0x21, 0x22, 0x23, 0x24, 0x25, 0x26, 0x27, 0x28, 0x29,
+                0x2A, 0x30, 0x31, 0x32, 0x33, 0x34, 0x35, 0x36, 0x40, 0x41, 0x42, 0x43, 0x44, 0x45,
+                0x46, 0x47, 0x48
+            ]
+        );
+        assert_eq!(Op::MoveRegister(Register::A, Register::A).mnemonic(), "MOV");
+        assert_eq!(Op::Signal(0).operand(), Operand::Byte);
+        assert_eq!(Op::PushRegister(Register::A).operand(), Operand::Register);
+        assert_eq!(Op::Nop.operand(), Operand::None);
+    }
+
+    #[test]
+    fn test_decoding_rejects_unknown_values() {
+        assert_eq!(
+            Op::parse_instruction(0x00FF),
+            Err("unknown op - 0xFF".to_string())
+        );
+        assert_eq!(
+            Op::parse_instruction(0x0D02),
+            Err("unknown register - 0xD".to_string())
+        );
+        assert_eq!(
+            Op::parse_instruction(0x1F04),
+            Err("unknown register - 0xF".to_string())
+        );
+    }
+
+    #[test]
+    fn test_text_round_trips_through_display_and_the_assembler() {
+        for op in sample() {
+            let text = op.to_string();
+            assert_eq!(text.parse::<Op>(), Ok(op.clone()), "{}", text);
+            assert_eq!(assemble_str(&text).unwrap(), op.to_bytes(), "{}", text);
+        }
+        assert_eq!(
+            Op::AddRegister(Register::B, Register::M).to_string(),
+            "ADDR B M"
+        );
+        assert_eq!(Op::Signal(9).to_string(), "SIG $09");
+    }
+
+    #[test]
+    fn test_from_str_accepts_any_case_and_number_form() {
+        assert_eq!("push %7".parse::<Op>(), Ok(Op::Push(7)));
+        assert_eq!("PUSH 7".parse::<Op>(), Ok(Op::Push(7)));
+        assert_eq!(
+            "mov a, r1".parse::<Op>(),
+            Ok(Op::MoveRegister(Register::A, Register::R1))
+        );
+        assert_eq!("  adds ".parse::<Op>(), Ok(Op::AddStack));
+    }
+
+    #[test]
+    fn test_from_str_rejects_bad_text() {
+        assert_eq!("".parse::<Op>(), Err("empty instruction".to_string()));
+        assert_eq!(
+            "BOGUS end".parse::<Op>(),
+            Err("unknown instruction - BOGUS".to_string())
+        );
+        assert_eq!(
+            "ADDR A".parse::<Op>(),
+            Err("ADDR takes 2 operand(s), found 1".to_string())
+        );
+        assert_eq!(
+            "NOP A".parse::<Op>(),
+            Err("NOP takes 0 operand(s), found 1".to_string())
+        );
+        assert_eq!(
+            "PUSH $100".parse::<Op>(),
+            Err("invalid byte - $100".to_string())
+        );
+        assert!("POP X".parse::<Op>().is_err());
+    }
+}
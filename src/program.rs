@@ -0,0 +1,258 @@
+//! Program image format shared by the assembler and the VM loader.
+//!
+//! A program file is either a raw flat binary, loaded at address 0 and started
+//! at PC 0, or the same binary prefixed with a small header:
+//!
+//! | Offset | Size | Contents                          |
+//! | ------ | ---- | --------------------------------- |
+//! | 0      | 4    | Magic bytes `RVM\0`               |
+//! | 4      | 2    | Entry point (little-endian `u16`) |
+//!
+//! Programs that need more than one load address, or want to carry their
+//! symbols along, use the [`Executable`] container instead. All multi-byte
+//! fields are little-endian:
+//!
+//! | Size | Contents                                                  |
+//! | ---- | --------------------------------------------------------- |
+//! | 4    | Magic bytes `RVX\0`                                       |
+//! | 1    | Format version, currently 1                               |
+//! | 2    | Entry point                                               |
+//! | 1    | Number of sections                                        |
+//! | ...  | Per section: load address (2), length (2), then the bytes |
+//! | 2    | Number of symbols (0 when stripped)                       |
+//! | ...  | Per symbol: address (2), name length (1), then the name   |
+
+use std::collections::BTreeMap;
+
+/// Magic bytes identifying a program image with a header.
+pub const HEADER_MAGIC: [u8; 4] = *b"RVM\0";
+
+/// Total size of the program header in bytes.
+pub const HEADER_SIZE: usize = HEADER_MAGIC.len() + 2;
+
+/// A decoded program image, borrowing the code bytes from the input.
+#[derive(Debug, PartialEq, Eq)]
+pub struct Program<'a> {
+    /// Address execution starts at
+    pub entry: u16,
+    /// Code bytes to load at address 0
+    pub code: &'a [u8],
+}
+
+impl<'a> Program<'a> {
+    /// Decodes a program image, accepting both headered and raw binaries.
+    pub fn parse(bytes: &'a [u8]) -> Result<Self, String> {
+        if !bytes.starts_with(&HEADER_MAGIC) {
+            return Ok(Self {
+                entry: 0,
+                code: bytes,
+            });
+        }
+
+        if bytes.len() < HEADER_SIZE {
+            return Err(format!(
+                "truncated program header - expected {} bytes, found {}",
+                HEADER_SIZE,
+                bytes.len()
+            ));
+        }
+
+        let entry = u16::from_le_bytes([bytes[4], bytes[5]]);
+        Ok(Self {
+            entry,
+            code: &bytes[HEADER_SIZE..],
+        })
+    }
+
+    /// Encodes code bytes with a header recording the entry point.
+    pub fn encode(entry: u16, code: &[u8]) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(HEADER_SIZE + code.len());
+        bytes.extend(HEADER_MAGIC);
+        bytes.extend(entry.to_le_bytes());
+        bytes.extend(code);
+        bytes
+    }
+}
+
+/// Magic bytes identifying an executable container.
+pub const EXECUTABLE_MAGIC: [u8; 4] = *b"RVX\0";
+
+/// Executable container version written by [`Executable::encode`].
+pub const EXECUTABLE_VERSION: u8 = 1;
+
+/// A block of bytes loaded at a fixed address.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Section {
+    /// Address of the first byte
+    pub address: u16,
+    /// Bytes to load
+    pub data: Vec<u8>,
+}
+
+/// An executable container: entry point, sections with their load
+/// addresses and an optional symbol table.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Executable {
+    /// Address execution starts at
+    pub entry: u16,
+    /// Sections in the order they are loaded
+    pub sections: Vec<Section>,
+    /// Label addresses by name, empty when stripped
+    pub symbols: BTreeMap<String, u16>,
+}
+
+impl Executable {
+    /// Returns true if the bytes start with the executable magic.
+    pub fn is_executable(bytes: &[u8]) -> bool {
+        bytes.starts_with(&EXECUTABLE_MAGIC)
+    }
+
+    /// Encodes the executable into its binary form.
+    pub fn encode(&self) -> Result<Vec<u8>, String> {
+        let sections = u8::try_from(self.sections.len())
+            .map_err(|_| format!("too many sections - {}", self.sections.len()))?;
+        let symbols = u16::try_from(self.symbols.len())
+            .map_err(|_| format!("too many symbols - {}", self.symbols.len()))?;
+
+        let mut bytes = Vec::new();
+        bytes.extend(EXECUTABLE_MAGIC);
+        bytes.push(EXECUTABLE_VERSION);
+        bytes.extend(self.entry.to_le_bytes());
+        bytes.push(sections);
+        for section in &self.sections {
+            let len = u16::try_from(section.data.len())
+                .map_err(|_| format!("section at 0x{:04X} is too large", section.address))?;
+            bytes.extend(section.address.to_le_bytes());
+            bytes.extend(len.to_le_bytes());
+            bytes.extend(&section.data);
+        }
+        bytes.extend(symbols.to_le_bytes());
+        for (name, address) in &self.symbols {
+            let len = u8::try_from(name.len())
+                .map_err(|_| format!("symbol name is too long - {}", name))?;
+            bytes.extend(address.to_le_bytes());
+            bytes.push(len);
+            bytes.extend(name.as_bytes());
+        }
+        Ok(bytes)
+    }
+
+    /// Decodes an executable container.
+    pub fn parse(bytes: &[u8]) -> Result<Self, String> {
+        let mut reader = Reader::new(bytes, "executable");
+        if reader.take(EXECUTABLE_MAGIC.len())? != EXECUTABLE_MAGIC {
+            return Err("not an executable - bad magic".to_string());
+        }
+        let version = reader.take(1)?[0];
+        if version != EXECUTABLE_VERSION {
+            return Err(format!("unsupported executable version {}", version));
+        }
+
+        let entry = reader.u16()?;
+        let count = reader.take(1)?[0];
+        let mut sections = Vec::with_capacity(count as usize);
+        for _ in 0..count {
+            let address = reader.u16()?;
+            let len = reader.u16()?;
+            let data = reader.take(len as usize)?.to_vec();
+            if address as usize + data.len() > 0x10000 {
+                return Err(format!("section at 0x{:04X} runs past 0xFFFF", address));
+            }
+            sections.push(Section { address, data });
+        }
+
+        let mut symbols = BTreeMap::new();
+        for _ in 0..reader.u16()? {
+            let address = reader.u16()?;
+            let len = reader.take(1)?[0];
+            let name = std::str::from_utf8(reader.take(len as usize)?)
+                .map_err(|_| "symbol name is not valid UTF-8".to_string())?;
+            symbols.insert(name.to_string(), address);
+        }
+
+        if reader.pos != bytes.len() {
+            return Err(format!(
+                "{} trailing bytes after the executable",
+                bytes.len() - reader.pos
+            ));
+        }
+        Ok(Self {
+            entry,
+            sections,
+            symbols,
+        })
+    }
+
+    /// Flattens the sections into one image starting at the lowest section
+    /// address, with gaps filled with zeros. Returns the start address and a
+    /// headered program whose entry point is relative to it.
+    pub fn flatten(&self) -> (u16, Vec<u8>) {
+        let start = self.sections.iter().map(|s| s.address).min().unwrap_or(0);
+        let end = self
+            .sections
+            .iter()
+            .map(|s| s.address as usize + s.data.len())
+            .max()
+            .unwrap_or(start as usize);
+
+        let mut code = vec![0; end - start as usize];
+        for section in &self.sections {
+            let offset = (section.address - start) as usize;
+            code[offset..offset + section.data.len()].copy_from_slice(&section.data);
+        }
+        (
+            start,
+            Program::encode(self.entry.wrapping_sub(start), &code),
+        )
+    }
+}
+
+/// Reads little-endian fields from a binary format, failing on truncated input.
+pub(crate) struct Reader<'a> {
+    bytes: &'a [u8],
+    pos: usize,
+    /// Name of the format, for error messages
+    what: &'static str,
+}
+
+impl<'a> Reader<'a> {
+    pub(crate) fn new(bytes: &'a [u8], what: &'static str) -> Self {
+        Self {
+            bytes,
+            pos: 0,
+            what,
+        }
+    }
+
+    /// Returns true once every byte has been read.
+    pub(crate) fn is_empty(&self) -> bool {
+        self.pos >= self.bytes.len()
+    }
+
+    pub(crate) fn take(&mut self, len: usize) -> Result<&'a [u8], String> {
+        let bytes = self
+            .bytes
+            .get(self.pos..self.pos + len)
+            .ok_or_else(|| format!("truncated {} at byte {}", self.what, self.pos))?;
+        self.pos += len;
+        Ok(bytes)
+    }
+
+    /// Takes every byte not read yet.
+    pub(crate) fn rest(&mut self) -> &'a [u8] {
+        let bytes = self.bytes.get(self.pos..).unwrap_or_default();
+        self.pos = self.bytes.len();
+        bytes
+    }
+
+    pub(crate) fn u8(&mut self) -> Result<u8, String> {
+        Ok(self.take(1)?[0])
+    }
+
+    pub(crate) fn u16(&mut self) -> Result<u16, String> {
+        let bytes = self.take(2)?;
+        Ok(u16::from_le_bytes([bytes[0], bytes[1]]))
+    }
+}
@@ -0,0 +1,64 @@
+//! Unit tests for the program image formats.
+//!
+//! This file checks that executable containers round-trip, that malformed
+//! containers are rejected and that sections flatten into one image.
+
+#[cfg(test)]
+mod tests {
+    use super::super::*;
+
+    fn sample() -> Executable {
+        Executable {
+            entry: 0x0102,
+            sections: vec![
+                Section {
+                    address: 0x0100,
+                    data: vec![Op::Push(0).value(), 7, Op::Signal(0).value(), 9],
+                },
+                Section {
+                    address: 0x0200,
+                    data: vec![0xAA, 0xBB],
+                },
+            ],
+            symbols: [("main".to_string(), 0x0102)].into(),
+        }
+    }
+
+    #[test]
+    fn test_executable_round_trip() {
+        let bytes = sample().encode().expect("Failed to encode");
+        assert!(bytes.starts_with(&EXECUTABLE_MAGIC));
+        assert_eq!(bytes[4], EXECUTABLE_VERSION);
+        assert!(Executable::is_executable(&bytes));
+        assert_eq!(Executable::parse(&bytes), Ok(sample()));
+    }
+
+    #[test]
+    fn test_executable_errors() {
+        let bytes = sample().encode().expect("Failed to encode");
+
+        // Truncated in the middle of a section
+        assert!(Executable::parse(&bytes[..12]).is_err());
+        // Extra bytes after the symbol table
+        let mut trailing = bytes.clone();
+        trailing.push(0);
+        assert!(Executable::parse(&trailing).is_err());
+        // Unknown version
+        let mut version = bytes.clone();
+        version[4] = 99;
+        assert!(Executable::parse(&version).is_err());
+        // Plain program images are not executables
+        assert!(!Executable::is_executable(&Program::encode(0, &[0, 0])));
+    }
+
+    #[test]
+    fn test_executable_flatten() {
+        let (start, image) = sample().flatten();
+        assert_eq!(start, 0x0100);
+
+        let program = Program::parse(&image).expect("Failed to parse");
+        assert_eq!(program.entry, 0x0002);
+        assert_eq!(program.code.len(), 0x0102);
+        assert_eq!(&program.code[0x0100..], &[0xAA, 0xBB]);
+    }
+}
@@ -1,8 +1,20 @@
 use crate::define_registers;
 
+/// The role a register plays.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum RegisterClass {
+    /// Free for programs to use
+    General,
+    /// Given a fixed role by the machine: the stack, program counter,
+    /// frame pointer and flags
+    Special,
+}
+
 define_registers! {
     /// Register enum definition with 8 registers.
     #[derive(Debug, PartialEq, Eq, Clone, Copy)]
+    #[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
     #[repr(u8)]
     pub enum Register {
         /// General purpose register A (index 0)
@@ -14,13 +26,13 @@ define_registers! {
         /// Memory operations register (index 3)
         M = 0x03,
         /// Stack Pointer register - points to next available stack location (index 4)
-        SP = 0x04,
+        SP = 0x04 => Special,
         /// Program Counter register - points to next instruction (index 5)
-        PC = 0x05,
+        PC = 0x05 => Special,
         /// Base Pointer register - for stack frames (index 6)
-        BP = 0x06,
+        BP = 0x06 => Special,
         /// Status flags register (index 7)
-        FLAGS = 0x07,
+        FLAGS = 0x07 => Special,
         /// Extended register R0 (index 8)
         R0 = 0x08,
         /// Extended register R1 (index 9)
@@ -33,3 +45,28 @@ define_registers! {
         R4 = 0x0C,
     }
 }
+
+/// Condition bits of the FLAGS register, set by arithmetic and tested by
+/// conditional jumps.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[repr(u16)]
+pub enum Flag {
+    /// The result was zero (bit 0)
+    Zero = 0x01,
+    /// An addition carried out of bit 15, a subtraction borrowed, or a
+    /// product needed more than 16 bits (bit 1)
+    Carry = 0x02,
+    /// Bit 15 of the result is set (bit 2)
+    Negative = 0x04,
+    /// The result overflowed as a signed 16-bit value, or a product needed
+    /// more than 16 bits (bit 3)
+    Overflow = 0x08,
+}
+
+impl Flag {
+    /// The bit of the flag in the FLAGS register.
+    pub fn mask(self) -> u16 {
+        self as u16
+    }
+}
@@ -0,0 +1,108 @@
+//! Unit tests for the `serde` feature.
+//!
+//! This file checks the JSON shape of the types external tools read most,
+//! and that every serializable type comes back unchanged.
+
+#[cfg(test)]
+mod tests {
+    use serde::{Serialize, de::DeserializeOwned};
+    use serde_json::json;
+
+    use crate::asm::{self, warnings::Warning};
+    use crate::coredump::CoreDump;
+    use crate::diff::{Difference, Divergence, record_run};
+    use crate::test_support;
+    use crate::trace::Step;
+    use crate::{HaltReason, Machine, MachineConfig, Op, Register, VmError};
+
+    fn round_trip<T: Serialize + DeserializeOwned + PartialEq + std::fmt::Debug>(value: T) {
+        let text = serde_json::to_string(&value).expect("Failed to serialize");
+        let back: T = serde_json::from_str(&text).expect("Failed to deserialize");
+        assert_eq!(back, value, "{}", text);
+    }
+
+    fn halting_machine() -> Machine {
+        test_support::halting_machine(&asm::assemble_str("push %7\npop A\nsig $09\n").unwrap())
+    }
+
+    #[test]
+    fn test_instruction_shapes() {
+        assert_eq!(serde_json::to_value(Register::SP).unwrap(), json!("SP"));
+        assert_eq!(serde_json::to_value(Op::Nop).unwrap(), json!("Nop"));
+        assert_eq!(
+            serde_json::to_value(Op::MoveRegister(Register::PC, Register::A)).unwrap(),
+            json!({ "MoveRegister": ["PC", "A"] })
+        );
+        assert_eq!(
+            serde_json::to_value(Step {
+                pc: 2,
+                instruction: 0x0002,
+                word: None,
+                registers: vec![(Register::A, 7)],
+                writes: vec![],
+            })
+            .unwrap(),
+            json!({ "pc": 2, "instruction": 2, "registers": [["A", 7]], "writes": [] })
+        );
+    }
+
+    #[test]
+    fn test_round_trips() {
+        round_trip(Op::Signal(0x09));
+        round_trip(Op::AddRegister(Register::R0, Register::R4));
+        round_trip(Register::FLAGS);
+        round_trip(Warning::StackUnderflow {
+            address: 4,
+            needed: 2,
+            available: 1,
+        });
+        round_trip(Divergence {
+            step: 3,
+            pc: 4,
+            difference: Difference::Register {
+                register: Register::B,
+                left: 0,
+                right: 1,
+            },
+        });
+
+        let mut vm = halting_machine();
+        let (fingerprint, trace) = record_run(&mut vm, 100);
+        round_trip(fingerprint);
+        round_trip(trace);
+        round_trip(vm.snapshot());
+        round_trip(CoreDump::capture(&vm, 4, "boom"));
+    }
+
+    #[test]
+    fn test_machine_round_trips_through_its_snapshot() {
+        let mut vm = halting_machine();
+        vm.step().expect("Failed to step");
+
+        let value = serde_json::to_value(&vm).unwrap();
+        assert_eq!(value["registers"][Register::SP as usize], json!(0x1002));
+        assert_eq!(value["memory"].as_array().map(Vec::len), Some(8 * 1024));
+
+        let mut back: Machine = serde_json::from_value(value).unwrap();
+        assert_eq!(back.snapshot(), vm.snapshot());
+        // Handlers are not part of the state
+        back.define_handler(0x09, crate::halt);
+        assert_eq!(back.run(), Ok(HaltReason::Halted { steps: 2 }));
+        assert_eq!(back.get_register(Register::A), 7);
+
+        round_trip(HaltReason::OutOfFuel { steps: 10 });
+        round_trip(MachineConfig::default());
+        round_trip(VmError {
+            pc: 4,
+            steps: 3,
+            message: "unknown signal - 0x42".to_string(),
+        });
+    }
+
+    #[test]
+    fn test_assembler_errors_serialize() {
+        let error = asm::assemble_str("push %300\n").unwrap_err();
+        let value = serde_json::to_value(&error).unwrap();
+        assert_eq!(value["Parse"]["line"], json!(1));
+    }
+}
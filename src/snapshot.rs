@@ -0,0 +1,308 @@
+//! Machine snapshots: the complete state of a run, so it can be paused and
+//! resumed later, possibly by another process or another version of the
+//! crate.
+//!
+//! A snapshot holds the registers, the halt flag, every byte of memory and
+//! whatever state the host's devices chose to save. Signal handlers and
+//! profiling counters are not part of it; whoever resumes the machine
+//! registers its handlers again. All multi-byte fields are little-endian:
+//!
+//! | Size | Contents                                       |
+//! | ---- | ---------------------------------------------- |
+//! | 4    | Magic bytes `RVS\0`                            |
+//! | 1    | Format version, currently 2                    |
+//! | 1    | Oldest format version a reader must understand |
+//! | ...  | Blocks, each a 4-byte tag, a 4-byte length and that many bytes |
+//!
+//! The blocks are:
+//!
+//! | Tag    | Contents                                                     |
+//! | ------ | ------------------------------------------------------------ |
+//! | `CPU ` | Register count (1), the registers (2 each), halt flag (1)    |
+//! | `MEM ` | Memory size (4), then per page that is not all zeros: page number (2) and 256 bytes, the last page cut to the memory size |
+//! | `DEV ` | One per device: name length (1), the name, then its state    |
+//!
+//! Later versions may add blocks. A reader skips blocks it does not know
+//! whose tag starts with a lowercase letter, and refuses the others, so a
+//! writer marks which additions an older reader can safely ignore. A writer
+//! raises the second version byte only when older readers would get the
+//! state wrong even then.
+//!
+//! Version 1 snapshots, the registers, halt flag, memory size and memory
+//! written one after the other, can still be read.
+
+use std::collections::BTreeMap;
+
+use crate::{Machine, Register, program::Reader};
+
+/// Magic bytes at the start of a snapshot file.
+pub const SNAPSHOT_MAGIC: [u8; 4] = *b"RVS\0";
+
+/// Version of the snapshot format written by [`Snapshot::encode`].
+pub const SNAPSHOT_VERSION: u8 = 2;
+
+/// Oldest format version that can read what [`Snapshot::encode`] writes.
+pub const SNAPSHOT_COMPATIBLE_VERSION: u8 = 2;
+
+/// Bytes per page in the `MEM ` block.
+pub const SNAPSHOT_PAGE_SIZE: usize = 256;
+
+const CPU_BLOCK: [u8; 4] = *b"CPU ";
+const MEMORY_BLOCK: [u8; 4] = *b"MEM ";
+const DEVICE_BLOCK: [u8; 4] = *b"DEV ";
+
+/// The saved state of a machine.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Snapshot {
+    pub registers: [u16; Register::COUNT],
+    pub halt: bool,
+    /// Every byte of memory, starting at address 0
+    pub memory: Vec<u8>,
+    /// State of the host's devices by name, in whatever form each device
+    /// chooses. [`Machine::snapshot`] leaves it empty for the host to fill.
+    pub devices: BTreeMap<String, Vec<u8>>,
+}
+
+impl Snapshot {
+    /// Returns true if the bytes start with the snapshot magic.
+    pub fn is_snapshot(bytes: &[u8]) -> bool {
+        bytes.starts_with(&SNAPSHOT_MAGIC)
+    }
+
+    /// Encodes the snapshot in the format described in the module docs.
+    /// Fails if memory is larger than the 64K address space or a device
+    /// name is longer than 255 bytes.
+    pub fn encode(&self) -> Result<Vec<u8>, String> {
+        check_memory_size(self.memory.len())?;
+        let mut bytes = SNAPSHOT_MAGIC.to_vec();
+        bytes.push(SNAPSHOT_VERSION);
+        bytes.push(SNAPSHOT_COMPATIBLE_VERSION);
+
+        let mut cpu = vec![self.registers.len() as u8];
+        for value in self.registers {
+            cpu.extend(value.to_le_bytes());
+        }
+        cpu.push(self.halt as u8);
+        push_block(&mut bytes, CPU_BLOCK, &cpu);
+
+        let mut memory = (self.memory.len() as u32).to_le_bytes().to_vec();
+        for (index, page) in self.memory.chunks(SNAPSHOT_PAGE_SIZE).enumerate() {
+            if page.iter().any(|byte| *byte != 0) {
+                memory.extend((index as u16).to_le_bytes());
+                memory.extend(page);
+            }
+        }
+        push_block(&mut bytes, MEMORY_BLOCK, &memory);
+
+        for (name, state) in &self.devices {
+            let len = u8::try_from(name.len())
+                .map_err(|_| format!("device name is too long - {}", name))?;
+            let mut device = vec![len];
+            device.extend(name.as_bytes());
+            device.extend(state);
+            push_block(&mut bytes, DEVICE_BLOCK, &device);
+        }
+        Ok(bytes)
+    }
+
+    /// Decodes a snapshot file of any version this crate understands.
+    pub fn parse(bytes: &[u8]) -> Result<Self, String> {
+        let mut reader = Reader::new(bytes, "snapshot");
+        if reader.take(SNAPSHOT_MAGIC.len())? != SNAPSHOT_MAGIC {
+            return Err("not a snapshot - bad magic".to_string());
+        }
+        match reader.u8()? {
+            0 => Err("unsupported snapshot version 0".to_string()),
+            1 => parse_version_1(reader),
+            version => {
+                let compatible = reader.u8()?;
+                if compatible > SNAPSHOT_VERSION {
+                    return Err(format!(
+                        "unsupported snapshot version {} - it needs a reader for version {}, this one reads up to {}",
+                        version, compatible, SNAPSHOT_VERSION
+                    ));
+                }
+                parse_blocks(reader)
+            }
+        }
+    }
+}
+
+fn push_block(bytes: &mut Vec<u8>, tag: [u8; 4], payload: &[u8]) {
+    bytes.extend(tag);
+    bytes.extend((payload.len() as u32).to_le_bytes());
+    bytes.extend(payload);
+}
+
+fn parse_halt(flag: u8) -> Result<bool, String> {
+    match flag {
+        0 => Ok(false),
+        1 => Ok(true),
+        flag => Err(format!("invalid halt flag {} in snapshot", flag)),
+    }
+}
+
+fn parse_u32(reader: &mut Reader) -> Result<u32, String> {
+    Ok(u32::from_le_bytes(reader.take(4)?.try_into().unwrap()))
+}
+
+/// Memory is addressed with 16 bits, so anything larger can't be a machine's.
+fn check_memory_size(size: usize) -> Result<(), String> {
+    if size > 0x10000 {
+        return Err(format!(
+            "snapshot memory of {} bytes is larger than the 64K address space",
+            size
+        ));
+    }
+    Ok(())
+}
+
+fn parse_version_1(mut reader: Reader) -> Result<Snapshot, String> {
+    let mut registers = [0; Register::COUNT];
+    for value in registers.iter_mut() {
+        *value = reader.u16()?;
+    }
+    let halt = parse_halt(reader.u8()?)?;
+    let size = parse_u32(&mut reader)? as usize;
+    check_memory_size(size)?;
+    let memory = reader.take(size)?.to_vec();
+    if !reader.is_empty() {
+        return Err("trailing bytes after the snapshot memory".to_string());
+    }
+
+    Ok(Snapshot {
+        registers,
+        halt,
+        memory,
+        devices: BTreeMap::new(),
+    })
+}
+
+fn parse_blocks(mut reader: Reader) -> Result<Snapshot, String> {
+    let mut cpu = None;
+    let mut memory = None;
+    let mut devices = BTreeMap::new();
+
+    while !reader.is_empty() {
+        let tag: [u8; 4] = reader.take(4)?.try_into().unwrap();
+        let len = parse_u32(&mut reader)? as usize;
+        let mut block = Reader::new(reader.take(len)?, "snapshot block");
+        match tag {
+            CPU_BLOCK => {
+                let count = block.u8()? as usize;
+                let mut registers = [0; Register::COUNT];
+                if count != registers.len() {
+                    return Err(format!(
+                        "snapshot has {} registers, but the machine has {}",
+                        count,
+                        registers.len()
+                    ));
+                }
+                for value in registers.iter_mut() {
+                    *value = block.u16()?;
+                }
+                cpu = Some((registers, parse_halt(block.u8()?)?));
+            }
+            MEMORY_BLOCK => {
+                let size = parse_u32(&mut block)? as usize;
+                check_memory_size(size)?;
+                let mut bytes = vec![0; size];
+                while !block.is_empty() {
+                    let start = block.u16()? as usize * SNAPSHOT_PAGE_SIZE;
+                    if start >= size {
+                        return Err(format!("snapshot page at 0x{:X} is outside memory", start));
+                    }
+                    let end = (start + SNAPSHOT_PAGE_SIZE).min(size);
+                    bytes[start..end].copy_from_slice(block.take(end - start)?);
+                }
+                memory = Some(bytes);
+            }
+            DEVICE_BLOCK => {
+                let name_len = block.u8()? as usize;
+                let name = String::from_utf8(block.take(name_len)?.to_vec())
+                    .map_err(|_| "snapshot device name is not valid UTF-8")?;
+                devices.insert(name, block.rest().to_vec());
+            }
+            tag if tag[0].is_ascii_lowercase() => {}
+            tag => {
+                return Err(format!(
+                    "snapshot block `{}` is not supported by this version",
+                    String::from_utf8_lossy(&tag)
+                ));
+            }
+        }
+    }
+
+    let (registers, halt) = cpu.ok_or("snapshot has no registers")?;
+    let memory = memory.ok_or("snapshot has no memory")?;
+    Ok(Snapshot {
+        registers,
+        halt,
+        memory,
+        devices,
+    })
+}
+
+impl Machine {
+    /// Captures the registers, halt flag and memory of the machine.
+    pub fn snapshot(&self) -> Snapshot {
+        // Only 64K of a larger memory can be addressed
+        let memory = (0..self.memory.size().min(0x10000))
+            .map(|addr| self.memory.read(addr as u16).unwrap_or(0))
+            .collect();
+        Snapshot {
+            registers: self.registers,
+            halt: self.halt,
+            memory,
+            devices: BTreeMap::new(),
+        }
+    }
+
+    /// Puts the machine back into the state of a snapshot. The memory must
+    /// be the same size as when the snapshot was taken.
+    pub fn restore(&mut self, snapshot: &Snapshot) -> Result<(), String> {
+        if snapshot.memory.len() != self.memory.size() {
+            return Err(format!(
+                "snapshot has {} bytes of memory, but the machine has {}",
+                snapshot.memory.len(),
+                self.memory.size()
+            ));
+        }
+        for (addr, byte) in snapshot.memory.iter().enumerate() {
+            if !self.memory.write(addr as u16, *byte) {
+                return Err(format!("memory write fault - 0x{:X}", addr));
+            }
+        }
+        self.registers = snapshot.registers;
+        self.halt = snapshot.halt;
+        Ok(())
+    }
+}
+
+/// With the `serde` feature a machine serializes as its [`Snapshot`], so
+/// handlers, streams and settings are left out just as they are from a
+/// snapshot file.
+#[cfg(feature = "serde")]
+impl serde::Serialize for Machine {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serde::Serialize::serialize(&self.snapshot(), serializer)
+    }
+}
+
+/// Deserializes a [`Snapshot`] into a default machine with memory of the
+/// snapshot's size. Handlers have to be defined again before it runs on.
+#[cfg(feature = "serde")]
+impl<'de> serde::Deserialize<'de> for Machine {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let snapshot: Snapshot = serde::Deserialize::deserialize(deserializer)?;
+        let mut machine = Machine::with_config(crate::MachineConfig {
+            memory_size: snapshot.memory.len(),
+            ..Default::default()
+        });
+        machine
+            .restore(&snapshot)
+            .map_err(serde::de::Error::custom)?;
+        Ok(machine)
+    }
+}
@@ -0,0 +1,138 @@
+//! Unit tests for machine snapshots.
+//!
+//! This file pauses a program halfway, resumes it on a fresh machine from
+//! the encoded snapshot, and checks that it finishes as if never paused.
+
+#[cfg(test)]
+mod tests {
+    use crate::snapshot::{SNAPSHOT_MAGIC, Snapshot};
+    use crate::{Machine, Op, Register};
+
+    fn machine() -> Machine {
+        Machine::new().with_halt_handler()
+    }
+
+    fn program() -> Vec<u8> {
+        vec![
+            Op::Push(0).value(),
+            7,
+            Op::Push(0).value(),
+            5,
+            Op::AddStack.value(),
+            0,
+            Op::PopRegister(Register::A).value(),
+            Register::A as u8,
+            Op::Signal(0).value(),
+            0x09,
+        ]
+    }
+
+    #[test]
+    fn test_resume_from_snapshot() {
+        let mut vm = machine();
+        vm.load_program(&program()).expect("Failed to load program");
+        vm.step().expect("Failed to step");
+        vm.step().expect("Failed to step");
+
+        let bytes = vm.snapshot().encode().unwrap();
+        assert!(Snapshot::is_snapshot(&bytes));
+        let snapshot = Snapshot::parse(&bytes).expect("Failed to parse snapshot");
+        assert_eq!(snapshot, vm.snapshot());
+
+        let mut resumed = machine();
+        resumed.restore(&snapshot).expect("Failed to restore");
+        assert_eq!(resumed.get_register(Register::PC), 4);
+        assert_eq!(resumed.memory.read2(0x1002), Some(5));
+        while !resumed.halt {
+            resumed.step().expect("Failed to step");
+        }
+        assert_eq!(resumed.get_register(Register::A), 12);
+        assert!(resumed.snapshot().halt);
+    }
+
+    #[test]
+    fn test_bad_snapshots() {
+        let bytes = machine().snapshot().encode().unwrap();
+        assert!(Snapshot::parse(&bytes[..bytes.len() - 1]).is_err());
+        assert!(Snapshot::parse(b"RVM\0").is_err());
+
+        let mut small = Snapshot::parse(&bytes).expect("Failed to parse snapshot");
+        small.memory.truncate(16);
+        assert!(machine().restore(&small).is_err());
+
+        // A memory size past the address space is refused before allocating it
+        let mut huge = SNAPSHOT_MAGIC.to_vec();
+        huge.extend([2, 2]);
+        huge.extend(b"MEM ");
+        huge.extend(4u32.to_le_bytes());
+        huge.extend(u32::MAX.to_le_bytes());
+        assert_eq!(
+            Snapshot::parse(&huge),
+            Err(
+                "snapshot memory of 4294967295 bytes is larger than the 64K address space"
+                    .to_string()
+            )
+        );
+
+        // Device names are stored with a one-byte length
+        let mut long_name = machine().snapshot();
+        long_name.devices.insert("d".repeat(256), Vec::new());
+        assert!(long_name.encode().is_err());
+    }
+
+    #[test]
+    fn test_device_state_and_sparse_memory() {
+        let mut vm = machine();
+        vm.load_program(&program()).expect("Failed to load program");
+        let mut snapshot = vm.snapshot();
+        snapshot
+            .devices
+            .insert("keys".to_string(), b"ls\n".to_vec());
+
+        let bytes = snapshot.encode().unwrap();
+        // Only the page holding the program is stored
+        assert!(bytes.len() < 400, "{} bytes", bytes.len());
+        assert_eq!(Snapshot::parse(&bytes), Ok(snapshot));
+    }
+
+    #[test]
+    fn test_version_1_still_loads() {
+        let snapshot = machine().snapshot();
+        let mut bytes = SNAPSHOT_MAGIC.to_vec();
+        bytes.push(1);
+        for value in snapshot.registers {
+            bytes.extend(value.to_le_bytes());
+        }
+        bytes.push(0);
+        bytes.extend((snapshot.memory.len() as u32).to_le_bytes());
+        bytes.extend(&snapshot.memory);
+        assert_eq!(Snapshot::parse(&bytes), Ok(snapshot));
+    }
+
+    #[test]
+    fn test_newer_versions() {
+        let bytes = machine().snapshot().encode().unwrap();
+
+        // A later version with an extra block older readers may skip
+        let mut newer = bytes.clone();
+        newer[4] = 9;
+        newer.extend(b"note");
+        newer.extend(2u32.to_le_bytes());
+        newer.extend(b"hi");
+        assert_eq!(Snapshot::parse(&newer), Ok(machine().snapshot()));
+
+        // An extra block they must not skip
+        let mut required = bytes.clone();
+        required.extend(b"NOTE");
+        required.extend(0u32.to_le_bytes());
+        let err = Snapshot::parse(&required).unwrap_err();
+        assert!(err.contains("`NOTE` is not supported"), "{}", err);
+
+        // A version that older readers cannot read at all
+        let mut incompatible = bytes;
+        incompatible[4] = 9;
+        incompatible[5] = 9;
+        let err = Snapshot::parse(&incompatible).unwrap_err();
+        assert!(err.contains("unsupported snapshot version 9"), "{}", err);
+    }
+}
@@ -0,0 +1,386 @@
+//! Host syscalls: signals that let a program look at the world outside the VM.
+//!
+//! Each syscall is a signal, raised with `SIG $nn` like the halt signal.
+//! The `SYSCALL` instruction is separate, for embedders' own host calls;
+//! see [`Machine::define_syscall`].
+//! Arguments and results are passed in registers; 32-bit results are split
+//! with the low word in `A` and the high word in `B`.
+//!
+//! | Signal | Name     | Arguments                                  | Result                                |
+//! | ------ | -------- | ------------------------------------------ | ------------------------------------- |
+//! | `$10`  | `getenv` | `A` name (0-terminated), `B` buffer, `C` buffer size | `A` length of the value, `$FFFF` if unset |
+//! | `$11`  | `time`   | none                                       | Seconds since the Unix epoch          |
+//! | `$12`  | `ticks`  | none                                       | Milliseconds since the syscalls were installed |
+//! | `$13`  | `write`  | `A` buffer, `B` length                     | none                                  |
+//! | `$14`  | `read`   | `A` buffer, `B` buffer size                | `A` bytes read, 0 at end of input     |
+//! | `$15`  | `open`   | `A` path (0-terminated), `B` mode          | `A` handle, `$FFFF` if it cannot be opened |
+//! | `$16`  | `fread`  | `A` handle, `B` buffer, `C` buffer size    | `A` bytes read, 0 at end of file      |
+//! | `$17`  | `fwrite` | `A` handle, `B` buffer, `C` length         | none                                  |
+//! | `$18`  | `close`  | `A` handle                                 | none                                  |
+//!
+//! `getenv` copies at most `C` bytes of the value, without a terminator;
+//! a result larger than `C` means the value was cut short.
+//!
+//! `write` sends bytes to the machine's [`output`](Machine::output) and
+//! `read` takes them from its [`input`](Machine::input), stdout and stdin
+//! unless the host redirected them. `read` returns as soon as some input is
+//! available, so it may read less than the buffer holds.
+//!
+//! `open` takes mode 0 to read, 1 to write (creating or emptying the file)
+//! or 2 to append (creating it if needed). Handles index the machine's
+//! [`files`](Machine::files) and are reused after `close`.
+//!
+//! Runs are reproducible by default, so every syscall that reads the host
+//! is sandboxed: unless [`Permissions`] allows it, raising it fails the run
+//! with an error that names the missing permission. `write` and `read` need
+//! no permission, since they only reach the machine's own output and input.
+//! The file syscalls reach only the directories allowed in the machine's
+//! [`FileTable`], and fail the run for any path outside them.
+
+use std::{
+    env,
+    fs::{self, File, OpenOptions},
+    io::{Read, Write},
+    path::{Path, PathBuf},
+    sync::OnceLock,
+    time::{Instant, SystemTime, UNIX_EPOCH},
+};
+
+use crate::{Machine, Register};
+
+/// Signal for reading an environment variable.
+pub const SYS_GETENV: u8 = 0x10;
+
+/// Signal for reading the wall-clock time.
+pub const SYS_TIME: u8 = 0x11;
+
+/// Signal for reading the monotonic tick count.
+pub const SYS_TICKS: u8 = 0x12;
+
+/// Signal for writing bytes to the machine's output.
+pub const SYS_WRITE: u8 = 0x13;
+
+/// Signal for reading bytes from the machine's input.
+pub const SYS_READ: u8 = 0x14;
+
+/// Signal for opening a file.
+pub const SYS_OPEN: u8 = 0x15;
+
+/// Signal for reading from an open file.
+pub const SYS_FREAD: u8 = 0x16;
+
+/// Signal for writing to an open file.
+pub const SYS_FWRITE: u8 = 0x17;
+
+/// Signal for closing a file.
+pub const SYS_CLOSE: u8 = 0x18;
+
+/// Value returned by `getenv` for a variable that is not set.
+pub const ENV_UNSET: u16 = 0xFFFF;
+
+/// Value returned by `open` for a file that cannot be opened.
+pub const OPEN_FAILED: u16 = 0xFFFF;
+
+/// Start of the tick count, set by the first [`install`].
+static TICKS_START: OnceLock<Instant> = OnceLock::new();
+
+/// Which syscalls a program may use.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct Permissions {
+    /// Allows `getenv`
+    pub env: bool,
+    /// Allows `time` and `ticks`
+    pub time: bool,
+}
+
+/// Defines the syscall signals on a machine. Denied syscalls are still
+/// defined, so they fail with a clear error rather than as unknown signals.
+pub fn install(vm: &mut Machine, permissions: Permissions) {
+    TICKS_START.get_or_init(Instant::now);
+    vm.define_handler(SYS_WRITE, write);
+    vm.define_handler(SYS_READ, read);
+    vm.define_handler(SYS_OPEN, open);
+    vm.define_handler(SYS_FREAD, fread);
+    vm.define_handler(SYS_FWRITE, fwrite);
+    vm.define_handler(SYS_CLOSE, close);
+
+    if permissions.env {
+        vm.define_handler(SYS_GETENV, getenv);
+    } else {
+        vm.define_handler(SYS_GETENV, |_| {
+            Err(
+                "getenv is not allowed - environment access is disabled, see vm --allow-env"
+                    .to_string(),
+            )
+        });
+    }
+    if permissions.time {
+        vm.define_handler(SYS_TIME, time);
+        vm.define_handler(SYS_TICKS, ticks);
+    } else {
+        vm.define_handler(SYS_TIME, |_| {
+            Err("time is not allowed - clock access is disabled, see vm --allow-time".to_string())
+        });
+        vm.define_handler(SYS_TICKS, |_| {
+            Err("ticks is not allowed - clock access is disabled, see vm --allow-time".to_string())
+        });
+    }
+}
+
+fn getenv(vm: &mut Machine) -> Result<(), String> {
+    let name = read_string(vm, vm.get_register(Register::A))?;
+    let name = String::from_utf8(name).map_err(|_| "getenv: name is not valid UTF-8")?;
+
+    let Some(value) = env::var_os(&name) else {
+        vm.registers[Register::A as usize] = ENV_UNSET;
+        return Ok(());
+    };
+    let value = value.into_encoded_bytes();
+    let buffer = vm.get_register(Register::B);
+    let size = vm.get_register(Register::C) as usize;
+    for (offset, byte) in value.iter().take(size).enumerate() {
+        let addr = buffer.wrapping_add(offset as u16);
+        if !vm.memory.write(addr, *byte) {
+            return Err(format!("memory write fault - 0x{:X}", addr));
+        }
+    }
+    // Values too long to describe are reported as filling the whole range
+    vm.registers[Register::A as usize] = value.len().min(ENV_UNSET as usize - 1) as u16;
+    Ok(())
+}
+
+fn time(vm: &mut Machine) -> Result<(), String> {
+    let seconds = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_err(|_| "time: the host clock is before 1970")?
+        .as_secs();
+    set_u32(vm, seconds as u32);
+    Ok(())
+}
+
+fn ticks(vm: &mut Machine) -> Result<(), String> {
+    let start = TICKS_START.get_or_init(Instant::now);
+    set_u32(vm, start.elapsed().as_millis() as u32);
+    Ok(())
+}
+
+fn write(vm: &mut Machine) -> Result<(), String> {
+    let buffer = vm.get_register(Register::A);
+    let bytes = (0..vm.get_register(Register::B))
+        .map(|offset| {
+            let addr = buffer.wrapping_add(offset);
+            vm.memory
+                .read(addr)
+                .ok_or(format!("memory read fault - 0x{:X}", addr))
+        })
+        .collect::<Result<Vec<u8>, String>>()?;
+    vm.output
+        .write_all(&bytes)
+        .and_then(|_| vm.output.flush())
+        .map_err(|e| format!("write: failed to write output - {}", e))
+}
+
+fn read(vm: &mut Machine) -> Result<(), String> {
+    let buffer = vm.get_register(Register::A);
+    let mut bytes = vec![0; vm.get_register(Register::B) as usize];
+    let count = loop {
+        match vm.input.read(&mut bytes) {
+            Err(e) if e.kind() == std::io::ErrorKind::Interrupted => continue,
+            result => break result.map_err(|e| format!("read: failed to read input - {}", e))?,
+        }
+    };
+    for (offset, byte) in bytes[..count].iter().enumerate() {
+        let addr = buffer.wrapping_add(offset as u16);
+        if !vm.memory.write(addr, *byte) {
+            return Err(format!("memory write fault - 0x{:X}", addr));
+        }
+    }
+    vm.registers[Register::A as usize] = count as u16;
+    Ok(())
+}
+
+/// Files a program has open, and the directories it may open them in.
+#[derive(Debug, Default)]
+pub struct FileTable {
+    /// Canonical paths of the allowed directories
+    roots: Vec<PathBuf>,
+    /// Open files by handle, `None` for closed handles
+    handles: Vec<Option<File>>,
+}
+
+impl FileTable {
+    /// Allows files in `dir` and its subdirectories. Fails if `dir` is not
+    /// an existing directory.
+    pub fn allow(&mut self, dir: impl AsRef<Path>) -> Result<(), String> {
+        let dir = dir.as_ref();
+        let root = fs::canonicalize(dir)
+            .ok()
+            .filter(|root| root.is_dir())
+            .ok_or_else(|| format!("{} is not a directory", dir.display()))?;
+        self.roots.push(root);
+        Ok(())
+    }
+
+    /// The allowed directories, canonicalized.
+    pub fn roots(&self) -> &[PathBuf] {
+        &self.roots
+    }
+
+    /// Number of files open.
+    pub fn open_count(&self) -> usize {
+        self.handles.iter().flatten().count()
+    }
+
+    /// Closes every open file, keeping the allowed directories.
+    pub fn close_all(&mut self) {
+        self.handles.clear();
+    }
+
+    /// Resolves a path from the program against the allowed directories.
+    /// Returns `None` if it does not exist, or when creating, if its
+    /// directory does not. Creating through a dangling symlink is refused.
+    fn resolve(&self, path: &str, create: bool) -> Result<Option<PathBuf>, String> {
+        if self.roots.is_empty() {
+            return Err(
+                "open is not allowed - no directory is allowed, see vm --allow-path".to_string(),
+            );
+        }
+        let path = Path::new(path);
+        // A file being created may not exist yet, but its directory must
+        let resolved = match (fs::canonicalize(path), create) {
+            (Ok(resolved), _) => resolved,
+            (Err(_), true) => {
+                let parent = match path.parent() {
+                    Some(parent) if !parent.as_os_str().is_empty() => parent,
+                    _ => Path::new("."),
+                };
+                let (Ok(parent), Some(name)) = (fs::canonicalize(parent), path.file_name()) else {
+                    return Ok(None);
+                };
+                // A dangling symlink would be followed on create, wherever it points
+                if fs::symlink_metadata(path).is_ok_and(|meta| meta.is_symlink()) {
+                    return Err(format!(
+                        "open is not allowed - {} is a symlink to a missing file",
+                        path.display()
+                    ));
+                }
+                parent.join(name)
+            }
+            (Err(_), false) => return Ok(None),
+        };
+        if !self.roots.iter().any(|root| resolved.starts_with(root)) {
+            return Err(format!(
+                "open is not allowed - {} is outside the allowed directories",
+                path.display()
+            ));
+        }
+        Ok(Some(resolved))
+    }
+
+    /// Stores an open file under the lowest free handle.
+    fn insert(&mut self, file: File) -> u16 {
+        match self.handles.iter().position(Option::is_none) {
+            Some(handle) => {
+                self.handles[handle] = Some(file);
+                handle as u16
+            }
+            None => {
+                self.handles.push(Some(file));
+                (self.handles.len() - 1) as u16
+            }
+        }
+    }
+
+    fn get(&mut self, handle: u16) -> Result<&mut File, String> {
+        self.handles
+            .get_mut(handle as usize)
+            .and_then(Option::as_mut)
+            .ok_or_else(|| format!("bad file handle - {}", handle))
+    }
+}
+
+fn open(vm: &mut Machine) -> Result<(), String> {
+    let path = read_string(vm, vm.get_register(Register::A))?;
+    let path = String::from_utf8(path).map_err(|_| "open: path is not valid UTF-8")?;
+    let mut options = OpenOptions::new();
+    match vm.get_register(Register::B) {
+        0 => options.read(true),
+        1 => options.write(true).create(true).truncate(true),
+        2 => options.append(true).create(true),
+        mode => return Err(format!("open: unknown mode - {}", mode)),
+    };
+    let create = vm.get_register(Register::B) != 0;
+    // Files that cannot be opened are the program's to handle, escaping
+    // the allowed directories is not
+    let handle = match vm.files.resolve(&path, create)? {
+        Some(resolved) => match options.open(resolved) {
+            Ok(file) if (vm.files.handles.len() as u16) < OPEN_FAILED => vm.files.insert(file),
+            _ => OPEN_FAILED,
+        },
+        None => OPEN_FAILED,
+    };
+    vm.registers[Register::A as usize] = handle;
+    Ok(())
+}
+
+fn fread(vm: &mut Machine) -> Result<(), String> {
+    let buffer = vm.get_register(Register::B);
+    let mut bytes = vec![0; vm.get_register(Register::C) as usize];
+    let file = vm.files.get(vm.get_register(Register::A))?;
+    let count = file
+        .read(&mut bytes)
+        .map_err(|e| format!("fread: failed to read file - {}", e))?;
+    for (offset, byte) in bytes[..count].iter().enumerate() {
+        let addr = buffer.wrapping_add(offset as u16);
+        if !vm.memory.write(addr, *byte) {
+            return Err(format!("memory write fault - 0x{:X}", addr));
+        }
+    }
+    vm.registers[Register::A as usize] = count as u16;
+    Ok(())
+}
+
+fn fwrite(vm: &mut Machine) -> Result<(), String> {
+    let buffer = vm.get_register(Register::B);
+    let bytes = (0..vm.get_register(Register::C))
+        .map(|offset| {
+            let addr = buffer.wrapping_add(offset);
+            vm.memory
+                .read(addr)
+                .ok_or(format!("memory read fault - 0x{:X}", addr))
+        })
+        .collect::<Result<Vec<u8>, String>>()?;
+    vm.files
+        .get(vm.get_register(Register::A))?
+        .write_all(&bytes)
+        .map_err(|e| format!("fwrite: failed to write file - {}", e))
+}
+
+fn close(vm: &mut Machine) -> Result<(), String> {
+    let handle = vm.get_register(Register::A);
+    vm.files.get(handle)?;
+    vm.files.handles[handle as usize] = None;
+    Ok(())
+}
+
+/// Reads a 0-terminated string from memory, without the terminator.
+fn read_string(vm: &Machine, mut addr: u16) -> Result<Vec<u8>, String> {
+    let mut bytes = Vec::new();
+    loop {
+        let byte = vm
+            .memory
+            .read(addr)
+            .ok_or(format!("memory read fault - 0x{:X}", addr))?;
+        if byte == 0 {
+            return Ok(bytes);
+        }
+        bytes.push(byte);
+        addr = addr.wrapping_add(1);
+    }
+}
+
+/// Stores a 32-bit result, low word in `A` and high word in `B`.
+fn set_u32(vm: &mut Machine, value: u32) {
+    vm.registers[Register::A as usize] = value as u16;
+    vm.registers[Register::B as usize] = (value >> 16) as u16;
+}
@@ -0,0 +1,228 @@
+//! Unit tests for the host syscalls.
+//!
+//! This file raises each syscall from a one-instruction program, with and
+//! without the permission it needs.
+
+#[cfg(test)]
+mod tests {
+    use crate::syscalls::{
+        ENV_UNSET, OPEN_FAILED, Permissions, SYS_CLOSE, SYS_FREAD, SYS_FWRITE, SYS_GETENV,
+        SYS_OPEN, SYS_READ, SYS_TICKS, SYS_TIME, SYS_WRITE, install,
+    };
+    use crate::{CapturedOutput, Machine, Op, Register};
+
+    const ALLOW_ALL: Permissions = Permissions {
+        env: true,
+        time: true,
+    };
+
+    /// A machine about to raise `signal`, with the syscalls installed.
+    fn machine(signal: u8, permissions: Permissions) -> Machine {
+        let mut vm = Machine::new();
+        install(&mut vm, permissions);
+        vm.load_program(&[Op::Signal(0).value(), signal]).unwrap();
+        vm
+    }
+
+    fn write_string(vm: &mut Machine, addr: u16, text: &str) {
+        for (offset, byte) in text.bytes().chain([0]).enumerate() {
+            vm.memory.write(addr + offset as u16, byte);
+        }
+    }
+
+    fn read_bytes(vm: &Machine, addr: u16, len: u16) -> Vec<u8> {
+        (addr..addr + len)
+            .map(|a| vm.memory.read(a).unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn test_getenv() {
+        // Cargo sets this for every test run
+        let mut vm = machine(SYS_GETENV, ALLOW_ALL);
+        write_string(&mut vm, 0x0100, "CARGO_PKG_NAME");
+        vm.registers[Register::A as usize] = 0x0100;
+        vm.registers[Register::B as usize] = 0x0200;
+        vm.registers[Register::C as usize] = 32;
+        vm.step().unwrap();
+        assert_eq!(vm.get_register(Register::A), 7);
+        assert_eq!(read_bytes(&vm, 0x0200, 7), b"rustyvm");
+    }
+
+    #[test]
+    fn test_getenv_truncates_and_reports_unset() {
+        let mut vm = machine(SYS_GETENV, ALLOW_ALL);
+        write_string(&mut vm, 0x0100, "CARGO_PKG_NAME");
+        vm.registers[Register::A as usize] = 0x0100;
+        vm.registers[Register::B as usize] = 0x0200;
+        vm.registers[Register::C as usize] = 4;
+        vm.step().unwrap();
+        // The full length, with only the first 4 bytes copied
+        assert_eq!(vm.get_register(Register::A), 7);
+        assert_eq!(read_bytes(&vm, 0x0200, 5), b"rust\0");
+
+        let mut vm = machine(SYS_GETENV, ALLOW_ALL);
+        write_string(&mut vm, 0x0100, "RUSTYVM_SURELY_UNSET");
+        vm.registers[Register::A as usize] = 0x0100;
+        vm.step().unwrap();
+        assert_eq!(vm.get_register(Register::A), ENV_UNSET);
+    }
+
+    #[test]
+    fn test_time_and_ticks() {
+        let mut vm = machine(SYS_TIME, ALLOW_ALL);
+        vm.step().unwrap();
+        let seconds =
+            (vm.get_register(Register::B) as u32) << 16 | vm.get_register(Register::A) as u32;
+        // Later than 2024-01-01
+        assert!(seconds > 1_704_067_200, "{}", seconds);
+
+        let mut vm = machine(SYS_TICKS, ALLOW_ALL);
+        vm.step().unwrap();
+        let first = vm.get_register(Register::A);
+        vm.registers[Register::PC as usize] = 0;
+        vm.step().unwrap();
+        assert!(vm.get_register(Register::A) >= first);
+    }
+
+    #[test]
+    fn test_denied_syscalls() {
+        for (signal, message) in [
+            (SYS_GETENV, "environment access is disabled"),
+            (SYS_TIME, "clock access is disabled"),
+            (SYS_TICKS, "clock access is disabled"),
+        ] {
+            let mut vm = machine(signal, Permissions::default());
+            let err = vm.step().unwrap_err();
+            assert!(err.contains(message), "{}", err);
+        }
+    }
+
+    #[test]
+    fn test_write_needs_no_permission() {
+        let mut vm = machine(SYS_WRITE, Permissions::default());
+        let output = CapturedOutput::default();
+        vm.output = Box::new(output.clone());
+        write_string(&mut vm, 0x0100, "hello, world");
+        vm.registers[Register::A as usize] = 0x0100;
+        vm.registers[Register::B as usize] = 5;
+        vm.step().unwrap();
+        assert_eq!(output.take(), b"hello");
+
+        // Reads past the end of memory fail the step
+        vm.registers[Register::PC as usize] = 0;
+        vm.registers[Register::A as usize] = 0x1FFE;
+        assert!(vm.step().is_err());
+    }
+
+    #[test]
+    fn test_read_from_injected_input() {
+        let mut vm = machine(SYS_READ, Permissions::default());
+        vm.input = Box::new(std::io::Cursor::new(b"abcdef".to_vec()));
+        vm.registers[Register::A as usize] = 0x0100;
+        vm.registers[Register::B as usize] = 4;
+        vm.step().unwrap();
+        assert_eq!(vm.get_register(Register::A), 4);
+        assert_eq!(read_bytes(&vm, 0x0100, 5), b"abcd\0");
+
+        // The rest, then 0 at the end of the input
+        for expected in [2, 0] {
+            vm.registers[Register::PC as usize] = 0;
+            vm.registers[Register::A as usize] = 0x0100;
+            vm.step().unwrap();
+            assert_eq!(vm.get_register(Register::A), expected);
+        }
+        assert_eq!(read_bytes(&vm, 0x0100, 2), b"ef");
+    }
+
+    /// Raises `signal` again on a machine that has already run.
+    fn raise(vm: &mut Machine, signal: u8, a: u16, b: u16, c: u16) -> Result<(), String> {
+        vm.memory.write(0, Op::Signal(0).value());
+        vm.memory.write(1, signal);
+        vm.registers[Register::PC as usize] = 0;
+        vm.registers[Register::A as usize] = a;
+        vm.registers[Register::B as usize] = b;
+        vm.registers[Register::C as usize] = c;
+        vm.step()
+    }
+
+    #[test]
+    fn test_files_in_allowed_directory() {
+        let dir = std::env::temp_dir().join("rustyvm_file_syscalls");
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("out.txt");
+        let _ = std::fs::remove_file(&path);
+
+        let mut vm = machine(SYS_OPEN, Permissions::default());
+        vm.files.allow(&dir).unwrap();
+        write_string(&mut vm, 0x0100, path.to_str().unwrap());
+        write_string(&mut vm, 0x0300, "hello");
+
+        // Write, creating the file
+        raise(&mut vm, SYS_OPEN, 0x0100, 1, 0).unwrap();
+        let handle = vm.get_register(Register::A);
+        assert_eq!(handle, 0);
+        raise(&mut vm, SYS_FWRITE, handle, 0x0300, 5).unwrap();
+        raise(&mut vm, SYS_CLOSE, handle, 0, 0).unwrap();
+        assert_eq!(vm.files.open_count(), 0);
+        assert_eq!(std::fs::read(&path).unwrap(), b"hello");
+
+        // Read it back through a reused handle
+        raise(&mut vm, SYS_OPEN, 0x0100, 0, 0).unwrap();
+        assert_eq!(vm.get_register(Register::A), 0);
+        raise(&mut vm, SYS_FREAD, 0, 0x0400, 3).unwrap();
+        assert_eq!(vm.get_register(Register::A), 3);
+        assert_eq!(read_bytes(&vm, 0x0400, 3), b"hel");
+        raise(&mut vm, SYS_FREAD, 0, 0x0400, 16).unwrap();
+        assert_eq!(vm.get_register(Register::A), 2);
+        raise(&mut vm, SYS_FREAD, 0, 0x0400, 16).unwrap();
+        assert_eq!(vm.get_register(Register::A), 0);
+
+        // Missing files are reported to the program
+        write_string(&mut vm, 0x0100, dir.join("missing").to_str().unwrap());
+        raise(&mut vm, SYS_OPEN, 0x0100, 0, 0).unwrap();
+        assert_eq!(vm.get_register(Register::A), OPEN_FAILED);
+
+        // Bad handles fail the run
+        let err = raise(&mut vm, SYS_FREAD, 7, 0x0400, 1).unwrap_err();
+        assert_eq!(err, "bad file handle - 7");
+    }
+
+    #[test]
+    fn test_files_are_sandboxed() {
+        let dir = std::env::temp_dir().join("rustyvm_file_sandbox");
+        std::fs::create_dir_all(dir.join("inner")).unwrap();
+
+        let mut vm = machine(SYS_OPEN, Permissions::default());
+        write_string(&mut vm, 0x0100, dir.join("inner/../x").to_str().unwrap());
+        let err = raise(&mut vm, SYS_OPEN, 0x0100, 1, 0).unwrap_err();
+        assert!(err.contains("see vm --allow-path"), "{}", err);
+
+        // `..` cannot climb out of an allowed directory
+        vm.files.allow(dir.join("inner")).unwrap();
+        let err = raise(&mut vm, SYS_OPEN, 0x0100, 1, 0).unwrap_err();
+        assert!(err.contains("outside the allowed directories"), "{}", err);
+        assert!(!dir.join("x").exists());
+
+        assert!(vm.files.allow(dir.join("missing")).is_err());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_files_do_not_follow_dangling_symlinks() {
+        let dir = std::env::temp_dir().join("rustyvm_file_symlink");
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(dir.join("inner")).unwrap();
+        let link = dir.join("inner/link");
+        std::os::unix::fs::symlink(dir.join("escaped"), &link).unwrap();
+
+        let mut vm = machine(SYS_OPEN, Permissions::default());
+        vm.files.allow(dir.join("inner")).unwrap();
+        write_string(&mut vm, 0x0100, link.to_str().unwrap());
+        for mode in [1, 2] {
+            let err = raise(&mut vm, SYS_OPEN, 0x0100, mode, 0).unwrap_err();
+            assert!(err.contains("is a symlink to a missing file"), "{}", err);
+        }
+        assert!(!dir.join("escaped").exists());
+    }
+}
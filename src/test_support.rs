@@ -0,0 +1,35 @@
+//! Fixtures shared by the unit tests.
+//!
+//! Most tests run a short program that ends with `SIG $09`, which `vm`
+//! treats as halt. These build that machine once instead of in every file.
+
+use crate::{Machine, Op, Register, halt};
+
+impl Machine {
+    /// Installs [`halt`] on signal 0x09, as the `vm` binary does.
+    pub(crate) fn with_halt_handler(mut self) -> Machine {
+        self.define_handler(0x09, halt);
+        self
+    }
+}
+
+/// A machine with the halt handler, loaded with `program`.
+pub(crate) fn halting_machine(program: &[u8]) -> Machine {
+    let mut vm = Machine::new().with_halt_handler();
+    vm.load_program(program).expect("Failed to load program");
+    vm
+}
+
+/// PUSH 7, POP A, PUSH 9, SIG $09 - four steps, leaving A = 7 and 9 on the
+/// stack.
+pub(crate) fn push_pop_program() -> Vec<u8> {
+    [
+        Op::Push(7),
+        Op::PopRegister(Register::A),
+        Op::Push(9),
+        Op::Signal(0x09),
+    ]
+    .iter()
+    .flat_map(Op::to_bytes)
+    .collect()
+}
@@ -0,0 +1,178 @@
+//! Devices that run on their own threads.
+//!
+//! Some devices have to wait on the host: a serial port connected to a
+//! socket, a timer that sleeps. They cannot run inside [`Machine::step`],
+//! so each one gets a thread and a mailbox instead, and a [`Bus`] moves
+//! bytes between the mailboxes and memory at one synchronization point per
+//! step:
+//!
+//! 1. Bytes the program changed in a device's address range since the last
+//!    [`Bus::sync`] are sent to that device, in address order.
+//! 2. Bytes the device has written since then are stored in memory, in the
+//!    order it wrote them.
+//!
+//! So the program sees a device's writes between two instructions, never in
+//! the middle of one, and a device sees every change the program made even
+//! if it was busy at the time. A device that is slow to answer simply
+//! answers a few steps later; the machine never waits for it.
+//!
+//! ```
+//! use rustyvm::{Machine, threaded::Bus};
+//!
+//! // Answers every byte written to 0x0100 with the byte plus one at 0x0101
+//! let mut bus = Bus::new();
+//! bus.attach("echo", 0x0100..0x0102, |link| {
+//!     while let Some((addr, value)) = link.recv() {
+//!         if addr == 0x0100 {
+//!             link.write(0x0101, value + 1);
+//!         }
+//!     }
+//! });
+//!
+//! let mut vm = Machine::new();
+//! vm.memory.write(0x0100, 41);
+//! bus.sync(&mut vm).unwrap();
+//! # while vm.memory.read(0x0101) == Some(0) { bus.sync(&mut vm).unwrap(); }
+//! assert_eq!(vm.memory.read(0x0101), Some(42));
+//! bus.shutdown();
+//! ```
+
+use std::{
+    ops::Range,
+    sync::mpsc::{self, Receiver, RecvTimeoutError, Sender, TryRecvError},
+    thread::{self, JoinHandle},
+    time::Duration,
+};
+
+use crate::Machine;
+
+/// A byte of device memory: its address and value.
+pub type BusWrite = (u16, u8);
+
+/// The device's end of its mailbox, handed to the device thread.
+pub struct DeviceLink {
+    inbox: Receiver<BusWrite>,
+    outbox: Sender<BusWrite>,
+}
+
+impl DeviceLink {
+    /// Waits for the program to change a byte in the device's range.
+    /// Returns `None` once the bus has shut down, which is the device's cue
+    /// to stop.
+    pub fn recv(&self) -> Option<BusWrite> {
+        self.inbox.recv().ok()
+    }
+
+    /// Like [`recv`](Self::recv), but gives up after `timeout`. Returns
+    /// `Err(())` once the bus has shut down, `Ok(None)` on a timeout.
+    #[allow(clippy::result_unit_err)]
+    pub fn recv_timeout(&self, timeout: Duration) -> Result<Option<BusWrite>, ()> {
+        match self.inbox.recv_timeout(timeout) {
+            Ok(write) => Ok(Some(write)),
+            Err(RecvTimeoutError::Timeout) => Ok(None),
+            Err(RecvTimeoutError::Disconnected) => Err(()),
+        }
+    }
+
+    /// Writes a byte of device memory, stored at the next synchronization
+    /// point. Returns false once the bus has shut down.
+    pub fn write(&self, addr: u16, value: u8) -> bool {
+        self.outbox.send((addr, value)).is_ok()
+    }
+}
+
+/// The machine's end of one device's mailbox.
+struct Attached {
+    name: &'static str,
+    range: Range<u16>,
+    /// The device's memory as of the last sync, to spot the program's changes
+    shadow: Vec<u8>,
+    to_device: Sender<BusWrite>,
+    from_device: Receiver<BusWrite>,
+    thread: JoinHandle<()>,
+}
+
+/// The threaded devices attached to a machine.
+#[derive(Default)]
+pub struct Bus {
+    devices: Vec<Attached>,
+}
+
+impl Bus {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Starts `run` on a new thread as the device for the addresses in
+    /// `range`. The device's memory starts out as all zeros.
+    pub fn attach<F>(&mut self, name: &'static str, range: Range<u16>, run: F)
+    where
+        F: FnOnce(DeviceLink) + Send + 'static,
+    {
+        let (to_device, inbox) = mpsc::channel();
+        let (outbox, from_device) = mpsc::channel();
+        let thread = thread::Builder::new()
+            .name(format!("device {}", name))
+            .spawn(move || run(DeviceLink { inbox, outbox }))
+            .expect("failed to spawn a device thread");
+        self.devices.push(Attached {
+            name,
+            shadow: vec![0; range.len()],
+            range,
+            to_device,
+            from_device,
+            thread,
+        });
+    }
+
+    /// The synchronization point, called between steps: forwards the
+    /// program's changes to each device and stores what each device wrote.
+    /// Fails if a device writes outside its range or its thread has died.
+    pub fn sync(&mut self, vm: &mut Machine) -> Result<(), String> {
+        for device in &mut self.devices {
+            for (offset, addr) in device.range.clone().enumerate() {
+                let value = vm.memory.read(addr).unwrap_or(0);
+                if value != device.shadow[offset] {
+                    device.shadow[offset] = value;
+                    // A device that has stopped listening is caught below
+                    let _ = device.to_device.send((addr, value));
+                }
+            }
+
+            loop {
+                let (addr, value) = match device.from_device.try_recv() {
+                    Ok(write) => write,
+                    Err(TryRecvError::Empty) => break,
+                    Err(TryRecvError::Disconnected) if device.thread.is_finished() => {
+                        return Err(format!("device {} has stopped", device.name));
+                    }
+                    Err(TryRecvError::Disconnected) => break,
+                };
+                if !device.range.contains(&addr) {
+                    return Err(format!(
+                        "device {} wrote 0x{:04X}, outside its range 0x{:04X}..0x{:04X}",
+                        device.name, addr, device.range.start, device.range.end
+                    ));
+                }
+                if !vm.memory.write(addr, value) {
+                    return Err(format!("memory write fault - 0x{:X}", addr));
+                }
+                device.shadow[(addr - device.range.start) as usize] = value;
+            }
+        }
+        Ok(())
+    }
+
+    /// Closes every mailbox and waits for the device threads to finish.
+    /// A device blocked on the host is waited for until that call returns.
+    pub fn shutdown(self) {
+        let threads: Vec<_> = self
+            .devices
+            .into_iter()
+            .map(|device| device.thread)
+            .collect();
+        for thread in threads {
+            let _ = thread.join();
+        }
+    }
+}
@@ -0,0 +1,102 @@
+//! Unit tests for threaded devices.
+//!
+//! This file checks that bytes cross the bus in both directions at the
+//! synchronization point and that misbehaving devices are reported.
+
+#[cfg(test)]
+mod tests {
+    use std::sync::mpsc;
+
+    use crate::Machine;
+    use crate::threaded::Bus;
+
+    /// Syncs until `addr` holds something other than 0.
+    fn sync_until_set(bus: &mut Bus, vm: &mut Machine, addr: u16) -> u8 {
+        loop {
+            bus.sync(vm).unwrap();
+            match vm.memory.read(addr) {
+                Some(0) => std::thread::yield_now(),
+                Some(value) => return value,
+                None => panic!("0x{:04X} is outside memory", addr),
+            }
+        }
+    }
+
+    #[test]
+    fn test_program_writes_reach_the_device() {
+        let (seen_tx, seen_rx) = mpsc::channel();
+        let mut bus = Bus::new();
+        bus.attach("recorder", 0x0200..0x0204, move |link| {
+            while let Some(write) = link.recv() {
+                seen_tx.send(write).unwrap();
+            }
+        });
+
+        let mut vm = Machine::new();
+        vm.memory.write(0x0201, 7);
+        vm.memory.write(0x0203, 9);
+        // Outside the device's range, so never sent
+        vm.memory.write(0x0204, 1);
+        bus.sync(&mut vm).unwrap();
+        // Unchanged bytes are not sent again
+        bus.sync(&mut vm).unwrap();
+        bus.shutdown();
+
+        let seen: Vec<_> = seen_rx.iter().collect();
+        assert_eq!(seen, vec![(0x0201, 7), (0x0203, 9)]);
+    }
+
+    #[test]
+    fn test_device_writes_land_at_sync() {
+        let mut bus = Bus::new();
+        bus.attach("echo", 0x0300..0x0302, |link| {
+            while let Some((addr, value)) = link.recv() {
+                if addr == 0x0300 {
+                    link.write(0x0301, value.wrapping_mul(2));
+                }
+            }
+        });
+
+        let mut vm = Machine::new();
+        vm.memory.write(0x0300, 21);
+        assert_eq!(sync_until_set(&mut bus, &mut vm, 0x0301), 42);
+        bus.shutdown();
+    }
+
+    #[test]
+    fn test_device_writes_outside_its_range_fail() {
+        let mut bus = Bus::new();
+        bus.attach("stray", 0x0400..0x0401, |link| {
+            link.write(0x0500, 1);
+            // Stay alive so the bus sees the write rather than a dead device
+            while link.recv().is_some() {}
+        });
+
+        let mut vm = Machine::new();
+        let err = loop {
+            match bus.sync(&mut vm) {
+                Ok(()) => std::thread::yield_now(),
+                Err(e) => break e,
+            }
+        };
+        assert!(err.contains("outside its range"), "{}", err);
+        assert_eq!(vm.memory.read(0x0500), Some(0));
+        bus.shutdown();
+    }
+
+    #[test]
+    fn test_stopped_device_is_reported() {
+        let mut bus = Bus::new();
+        bus.attach("quitter", 0x0600..0x0601, |_| {});
+
+        let mut vm = Machine::new();
+        let err = loop {
+            match bus.sync(&mut vm) {
+                Ok(()) => std::thread::yield_now(),
+                Err(e) => break e,
+            }
+        };
+        assert_eq!(err, "device quitter has stopped");
+        bus.shutdown();
+    }
+}
@@ -0,0 +1,113 @@
+//! Instruction timing and real-time pacing.
+//!
+//! Every instruction costs a number of clock cycles, one each unless the
+//! machine's [`Timing`] says otherwise, and [`Machine::step`] adds the cost
+//! to [`Machine::cycles`]. On its own that only counts; a [`Pacer`] turns
+//! it into speed by sleeping whenever the machine runs ahead of its clock
+//! rate in host time, so a demo written for a 1 MHz machine does not run a
+//! hundred times too fast.
+//!
+//! ```
+//! use rustyvm::{Machine, timing::Timing};
+//!
+//! let mut vm = Machine::new();
+//! vm.timing = Timing::parse("ADDS=3,SIG=10").unwrap();
+//! ```
+
+use std::{
+    thread,
+    time::{Duration, Instant},
+};
+
+use crate::{Machine, Op, isa};
+
+/// Cycle cost of every opcode.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Timing {
+    costs: [u32; 256],
+}
+
+/// The costs listed by [`isa::instructions`].
+impl Default for Timing {
+    fn default() -> Self {
+        let mut costs = [1; 256];
+        for spec in isa::instructions() {
+            costs[spec.opcode as usize] = spec.cycles as u32;
+        }
+        Self { costs }
+    }
+}
+
+impl Timing {
+    /// Cycles the operation takes.
+    pub fn cost(&self, op: &Op) -> u32 {
+        self.costs[op.value() as usize]
+    }
+
+    /// Sets the cost of an instruction, by mnemonic in any case.
+    pub fn set(&mut self, mnemonic: &str, cycles: u32) -> Result<&mut Self, String> {
+        let spec = isa::instructions()
+            .into_iter()
+            .find(|spec| spec.mnemonic.eq_ignore_ascii_case(mnemonic))
+            .ok_or_else(|| format!("unknown instruction in timing - {}", mnemonic))?;
+        self.costs[spec.opcode as usize] = cycles;
+        Ok(self)
+    }
+
+    /// Parses comma-separated `MNEMONIC=CYCLES` overrides of the default
+    /// costs, as the `vm` binary takes them.
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let mut timing = Self::default();
+        for entry in text.split(',').map(str::trim) {
+            let (mnemonic, cycles) = entry
+                .split_once('=')
+                .ok_or_else(|| format!("expected `MNEMONIC=CYCLES`, found `{}`", entry))?;
+            let cycles = cycles
+                .trim()
+                .parse()
+                .map_err(|_| format!("invalid cycle count `{}`", cycles.trim()))?;
+            timing.set(mnemonic.trim(), cycles)?;
+        }
+        Ok(timing)
+    }
+}
+
+/// Keeps a machine from running faster than a clock rate.
+#[derive(Debug, Clone)]
+pub struct Pacer {
+    hz: u64,
+    /// Host time and machine cycles when pacing started
+    start: Instant,
+    start_cycles: u64,
+}
+
+/// Shortest sleep worth asking the host for.
+const MIN_SLEEP: Duration = Duration::from_millis(1);
+
+impl Pacer {
+    /// Starts pacing `vm` at `hz` cycles per second from now.
+    pub fn new(vm: &Machine, hz: u64) -> Self {
+        Self {
+            hz: hz.max(1),
+            start: Instant::now(),
+            start_cycles: vm.cycles,
+        }
+    }
+
+    /// How far the machine is ahead of its clock rate, zero if it is not.
+    pub fn ahead(&self, vm: &Machine) -> Duration {
+        let cycles = vm.cycles.saturating_sub(self.start_cycles);
+        let due = Duration::from_nanos((cycles as u128 * 1_000_000_000 / self.hz as u128) as u64);
+        due.saturating_sub(self.start.elapsed())
+    }
+
+    /// Sleeps until host time catches up with the machine. Called between
+    /// steps; short gaps are left to build up so the host is not asked for
+    /// sleeps it cannot time.
+    pub fn pace(&self, vm: &Machine) {
+        let ahead = self.ahead(vm);
+        if ahead >= MIN_SLEEP {
+            thread::sleep(ahead);
+        }
+    }
+}
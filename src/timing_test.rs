@@ -0,0 +1,55 @@
+//! Unit tests for instruction timing and pacing.
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, Instant};
+
+    use crate::asm::assemble_str;
+    use crate::timing::{Pacer, Timing};
+    use crate::{Machine, Op};
+
+    fn machine(source: &str) -> Machine {
+        let mut vm = Machine::new();
+        vm.load_program(&assemble_str(source).unwrap()).unwrap();
+        vm
+    }
+
+    #[test]
+    fn test_default_costs_are_one_cycle() {
+        let mut vm = machine("push %1\npush %2\nadds\n");
+        for _ in 0..3 {
+            vm.step().unwrap();
+        }
+        assert_eq!(vm.cycles, 3);
+    }
+
+    #[test]
+    fn test_overridden_costs() {
+        let mut vm = machine("push %1\npush %2\nadds\n");
+        vm.timing = Timing::parse("adds=5, PUSH = 2").unwrap();
+        assert_eq!(vm.timing.cost(&Op::AddStack), 5);
+        for _ in 0..3 {
+            vm.step().unwrap();
+        }
+        assert_eq!(vm.cycles, 9);
+
+        assert!(Timing::parse("mul=3").is_err());
+        assert!(Timing::parse("adds").is_err());
+        assert!(Timing::parse("adds=x").is_err());
+    }
+
+    #[test]
+    fn test_pacer_holds_back_a_fast_machine() {
+        let mut vm = Machine::new();
+        let pacer = Pacer::new(&vm, 1_000);
+        assert_eq!(pacer.ahead(&vm), Duration::ZERO);
+
+        // 20 cycles at 1 kHz take 20 ms
+        vm.cycles += 20;
+        assert!(pacer.ahead(&vm) > Duration::from_millis(10));
+        let start = Instant::now();
+        pacer.pace(&vm);
+        assert!(start.elapsed() >= Duration::from_millis(10));
+        assert!(pacer.ahead(&vm) < Duration::from_millis(1));
+    }
+}
@@ -0,0 +1,396 @@
+//! Execution traces: a compact record of every step a program took, for
+//! inspecting a run after it finished.
+//!
+//! A [`Recorder`] is attached to a machine before it runs and notes, after
+//! each step, the instruction executed, the registers it changed and the
+//! memory bytes it wrote. The result is a [`Trace`], stored as:
+//!
+//! ```text
+//! "VMT\0"  version (u8)  initial registers (13 x u16)
+//! per step:  PC (u16)  instruction word (u16)  [16-bit operand (u16)]
+//!            register count (u8)  count x (register u8, new value u16)
+//!            write count (u16)    count x (address u16, new byte u8)
+//! ```
+//!
+//! All values are little-endian. The 16-bit operand is only there for
+//! four-byte instructions, as the opcode says; version 1 traces never have
+//! it. PC is only listed among the changed registers when the step did not
+//! simply move on to the next instruction.
+//!
+//! Traces can also be exported for standard tools: as JSON lines, one object
+//! per step, or in the Chrome trace event format read by `chrome://tracing`
+//! and Perfetto. The recording has no clock, so exported timestamps count
+//! steps, one microsecond each.
+
+use std::{cell::RefCell, rc::Rc};
+
+use crate::{
+    Addressable, LinearMemory, Machine, Op, Register, asm::disassembler, devices, program::Reader,
+};
+
+/// Magic bytes at the start of a trace file.
+pub const TRACE_MAGIC: [u8; 4] = *b"VMT\0";
+
+/// Version of the trace format written by [`Trace::encode`].
+pub const TRACE_VERSION: u8 = 2;
+
+/// One executed instruction and its effects.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Step {
+    /// Address of the instruction
+    pub pc: u16,
+    /// The instruction word, opcode in the low byte
+    pub instruction: u16,
+    /// The 16-bit value after the instruction word, for four-byte instructions
+    #[cfg_attr(
+        feature = "serde",
+        serde(default, skip_serializing_if = "Option::is_none")
+    )]
+    pub word: Option<u16>,
+    /// Registers that changed, with their new values
+    pub registers: Vec<(Register, u16)>,
+    /// Memory bytes written, with their new values, in order
+    pub writes: Vec<(u16, u8)>,
+}
+
+impl Step {
+    /// The decoded instruction.
+    pub fn op(&self) -> Result<Op, String> {
+        let mut bytes = self.instruction.to_le_bytes().to_vec();
+        bytes.extend(self.word.map(u16::to_le_bytes).into_iter().flatten());
+        Op::from_bytes(&bytes)
+    }
+
+    /// The instruction as assembly, or the decode error in angle brackets.
+    pub fn mnemonic(&self) -> String {
+        self.op()
+            .map(|op| disassembler::instruction_for(&op, self.pc).to_string())
+            .unwrap_or_else(|e| format!("<{}>", e))
+    }
+
+    /// Bytes the instruction takes in memory.
+    fn size(&self) -> u16 {
+        if self.word.is_some() { 4 } else { 2 }
+    }
+
+    /// Updates a register set with the changes made by this step.
+    fn apply(&self, registers: &mut [u16; Register::COUNT]) {
+        registers[Register::PC as usize] = self.pc.wrapping_add(self.size());
+        for (register, value) in &self.registers {
+            registers[*register as usize] = *value;
+        }
+    }
+}
+
+/// A recorded run.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Trace {
+    /// Registers before the first step
+    pub initial: [u16; Register::COUNT],
+    pub steps: Vec<Step>,
+}
+
+impl Trace {
+    /// Returns true if the bytes start with the trace magic.
+    pub fn is_trace(bytes: &[u8]) -> bool {
+        bytes.starts_with(&TRACE_MAGIC)
+    }
+
+    /// Encodes the trace in the binary format described in the module docs.
+    pub fn encode(&self) -> Vec<u8> {
+        let mut bytes = TRACE_MAGIC.to_vec();
+        bytes.push(TRACE_VERSION);
+        for value in self.initial {
+            bytes.extend(value.to_le_bytes());
+        }
+        for step in &self.steps {
+            bytes.extend(step.pc.to_le_bytes());
+            bytes.extend(step.instruction.to_le_bytes());
+            if let Some(word) = step.word {
+                bytes.extend(word.to_le_bytes());
+            }
+            bytes.push(step.registers.len() as u8);
+            for (register, value) in &step.registers {
+                bytes.push(*register as u8);
+                bytes.extend(value.to_le_bytes());
+            }
+            bytes.extend((step.writes.len() as u16).to_le_bytes());
+            for (address, value) in &step.writes {
+                bytes.extend(address.to_le_bytes());
+                bytes.push(*value);
+            }
+        }
+        bytes
+    }
+
+    /// Decodes a trace file.
+    pub fn parse(bytes: &[u8]) -> Result<Self, String> {
+        let mut reader = Reader::new(bytes, "trace");
+        if reader.take(TRACE_MAGIC.len())? != TRACE_MAGIC {
+            return Err("not a trace - bad magic".to_string());
+        }
+        let version = reader.u8()?;
+        if !(1..=TRACE_VERSION).contains(&version) {
+            return Err(format!("unsupported trace version {}", version));
+        }
+
+        let mut initial = [0; Register::COUNT];
+        for value in initial.iter_mut() {
+            *value = reader.u16()?;
+        }
+
+        let mut steps = Vec::new();
+        while !reader.is_empty() {
+            let pc = reader.u16()?;
+            let instruction = reader.u16()?;
+            let word = match Op::size_of(instruction as u8) {
+                Some(4) if version >= 2 => Some(reader.u16()?),
+                _ => None,
+            };
+            let mut registers = Vec::new();
+            for _ in 0..reader.u8()? {
+                let index = reader.u8()?;
+                let register = Register::from_u8(index)
+                    .ok_or_else(|| format!("unknown register {} in step {}", index, steps.len()))?;
+                registers.push((register, reader.u16()?));
+            }
+            let mut writes = Vec::new();
+            for _ in 0..reader.u16()? {
+                writes.push((reader.u16()?, reader.u8()?));
+            }
+            steps.push(Step {
+                pc,
+                instruction,
+                word,
+                registers,
+                writes,
+            });
+        }
+
+        Ok(Self { initial, steps })
+    }
+
+    /// Registers after the first `count` steps; 0 gives the initial registers.
+    pub fn registers_after(&self, count: usize) -> [u16; Register::COUNT] {
+        let mut registers = self.initial;
+        for step in self.steps.iter().take(count) {
+            step.apply(&mut registers);
+        }
+        registers
+    }
+
+    /// Indexes of the steps that wrote to `address`.
+    pub fn writes_to(&self, address: u16) -> impl Iterator<Item = usize> + '_ {
+        self.steps
+            .iter()
+            .enumerate()
+            .filter(move |(_, step)| step.writes.iter().any(|(a, _)| *a == address))
+            .map(|(idx, _)| idx)
+    }
+
+    /// Number of steps after which `register` first held `value`, 0 meaning
+    /// it held it before the first step.
+    pub fn first_holding(&self, register: Register, value: u16) -> Option<usize> {
+        if self.initial[register as usize] == value {
+            return Some(0);
+        }
+        let mut registers = self.initial;
+        for (idx, step) in self.steps.iter().enumerate() {
+            step.apply(&mut registers);
+            if registers[register as usize] == value {
+                return Some(idx + 1);
+            }
+        }
+        None
+    }
+
+    /// Exports the trace as JSON lines, one object per step:
+    ///
+    /// ```text
+    /// {"step":1,"pc":0,"instruction":"PUSH %7","registers":{"SP":4098},"writes":[[4096,7],[4097,0]]}
+    /// ```
+    pub fn to_json_lines(&self) -> String {
+        let mut out = String::new();
+        for (idx, step) in self.steps.iter().enumerate() {
+            let registers: Vec<String> = step
+                .registers
+                .iter()
+                .map(|(register, value)| format!("\"{:?}\":{}", register, value))
+                .collect();
+            let writes: Vec<String> = step
+                .writes
+                .iter()
+                .map(|(address, value)| format!("[{},{}]", address, value))
+                .collect();
+            out.push_str(&format!(
+                "{{\"step\":{},\"pc\":{},\"instruction\":{},\"registers\":{{{}}},\"writes\":[{}]}}\n",
+                idx + 1,
+                step.pc,
+                json_string(&step.mnemonic()),
+                registers.join(","),
+                writes.join(",")
+            ));
+        }
+        out
+    }
+
+    /// Exports the trace in the Chrome trace event format. Instructions are
+    /// slices on a `cpu` track; signals and writes to the memory-mapped
+    /// devices are instant events on their own tracks.
+    pub fn to_chrome_trace(&self) -> String {
+        const CPU: u8 = 1;
+        const SIGNALS: u8 = 2;
+        const DEVICES: u8 = 3;
+
+        let mut events: Vec<String> = [(CPU, "cpu"), (SIGNALS, "signals"), (DEVICES, "devices")]
+            .iter()
+            .map(|(tid, name)| {
+                format!(
+                    "{{\"name\":\"thread_name\",\"ph\":\"M\",\"pid\":1,\"tid\":{},\"args\":{{\"name\":\"{}\"}}}}",
+                    tid, name
+                )
+            })
+            .collect();
+        for (ts, step) in self.steps.iter().enumerate() {
+            events.push(format!(
+                "{{\"name\":{},\"ph\":\"X\",\"ts\":{},\"dur\":1,\"pid\":1,\"tid\":{},\"args\":{{\"pc\":\"0x{:04X}\"}}}}",
+                json_string(&step.mnemonic()),
+                ts,
+                CPU,
+                step.pc
+            ));
+            if let Ok(Op::Signal(signal)) = step.op() {
+                events.push(format!(
+                    "{{\"name\":\"SIG ${:02X}\",\"ph\":\"i\",\"s\":\"t\",\"ts\":{},\"pid\":1,\"tid\":{}}}",
+                    signal, ts, SIGNALS
+                ));
+            }
+            for (address, value) in &step.writes {
+                if let Some(device) = devices::device_at(*address) {
+                    events.push(format!(
+                        "{{\"name\":\"{}\",\"ph\":\"i\",\"s\":\"t\",\"ts\":{},\"pid\":1,\"tid\":{},\"args\":{{\"address\":\"0x{:04X}\",\"value\":{}}}}}",
+                        device, ts, DEVICES, address, value
+                    ));
+                }
+            }
+        }
+        format!("{{\"traceEvents\":[\n{}\n]}}\n", events.join(",\n"))
+    }
+}
+
+/// Quotes a string for JSON output, escaping quotes, backslashes and
+/// control characters.
+pub fn json_string(text: &str) -> String {
+    let mut quoted = String::from("\"");
+    for c in text.chars() {
+        match c {
+            '"' => quoted.push_str("\\\""),
+            '\\' => quoted.push_str("\\\\"),
+            '\n' => quoted.push_str("\\n"),
+            c if c.is_control() => quoted.push_str(&format!("\\u{:04x}", c as u32)),
+            c => quoted.push(c),
+        }
+    }
+    quoted.push('"');
+    quoted
+}
+
+/// Writes seen since the last step, or `None` once recording has finished.
+type SharedWrites = Rc<RefCell<Option<Vec<(u16, u8)>>>>;
+
+/// Memory that passes every access through and logs the writes.
+struct WriteLog {
+    inner: Box<dyn Addressable>,
+    writes: SharedWrites,
+}
+
+impl Addressable for WriteLog {
+    fn read(&self, addr: u16) -> Option<u8> {
+        self.inner.read(addr)
+    }
+
+    fn write(&mut self, addr: u16, value: u8) -> bool {
+        let written = self.inner.write(addr, value);
+        if written && let Some(writes) = self.writes.borrow_mut().as_mut() {
+            writes.push((addr, value));
+        }
+        written
+    }
+
+    fn size(&self) -> usize {
+        self.inner.size()
+    }
+}
+
+/// Builds a [`Trace`] while a machine runs. Call [`Recorder::record`] after
+/// every [`Machine::step`], with the PC the step started from.
+pub struct Recorder {
+    writes: SharedWrites,
+    registers: [u16; Register::COUNT],
+    trace: Trace,
+}
+
+impl Recorder {
+    /// Starts recording, wrapping the machine's memory to see its writes.
+    /// Attach after loading the program so the load isn't recorded.
+    pub fn attach(vm: &mut Machine) -> Self {
+        let writes = Rc::new(RefCell::new(Some(Vec::new())));
+        let inner = std::mem::replace(&mut vm.memory, Box::new(LinearMemory::new(0)));
+        vm.memory = Box::new(WriteLog {
+            inner,
+            writes: writes.clone(),
+        });
+        Self {
+            writes,
+            registers: vm.registers,
+            trace: Trace {
+                initial: vm.registers,
+                steps: Vec::new(),
+            },
+        }
+    }
+
+    /// Records the step just executed from `pc`, whether or not it succeeded.
+    pub fn record(&mut self, vm: &Machine, pc: u16) {
+        let instruction = vm.memory.read2(pc).unwrap_or(0);
+        let word = match Op::size_of(instruction as u8) {
+            Some(4) => Some(vm.memory.read2(pc.wrapping_add(2)).unwrap_or(0)),
+            _ => None,
+        };
+        let next = pc.wrapping_add(if word.is_some() { 4 } else { 2 });
+        let registers = self
+            .registers
+            .iter()
+            .zip(vm.registers.iter())
+            .enumerate()
+            .filter(|(idx, (old, new))| match *idx == Register::PC as usize {
+                // Moving on to the next instruction is implied
+                true => **new != next,
+                false => old != new,
+            })
+            .map(|(idx, (_, new))| (Register::ALL[idx], *new))
+            .collect();
+        self.trace.steps.push(Step {
+            pc,
+            instruction,
+            word,
+            registers,
+            writes: self
+                .writes
+                .borrow_mut()
+                .as_mut()
+                .map(std::mem::take)
+                .unwrap_or_default(),
+        });
+        self.registers = vm.registers;
+    }
+
+    /// Stops recording and returns the trace. The machine keeps its memory
+    /// and can go on running; its writes are just no longer logged.
+    pub fn finish(self) -> Trace {
+        self.writes.replace(None);
+        self.trace
+    }
+}
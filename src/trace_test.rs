@@ -0,0 +1,133 @@
+//! Unit tests for execution traces.
+//!
+//! This file records short programs and checks the steps, the binary
+//! encoding, and the queries the trace viewer is built on.
+
+#[cfg(test)]
+mod tests {
+    use crate::test_support::{halting_machine, push_pop_program};
+    use crate::trace::{Recorder, Trace, json_string};
+    use crate::{Machine, Op, Register};
+
+    fn record() -> Trace {
+        let mut vm = halting_machine(&push_pop_program());
+
+        let mut recorder = Recorder::attach(&mut vm);
+        while !vm.halt {
+            let pc = vm.get_register(Register::PC);
+            vm.step().expect("Failed to step");
+            recorder.record(&vm, pc);
+        }
+        recorder.finish()
+    }
+
+    #[test]
+    fn test_record_steps() {
+        let trace = record();
+        assert_eq!(trace.steps.len(), 4);
+
+        let push = &trace.steps[0];
+        assert_eq!(push.pc, 0);
+        assert_eq!(push.instruction, 0x0701);
+        assert_eq!(push.registers, vec![(Register::SP, 0x1002)]);
+        assert_eq!(push.writes, vec![(0x1000, 7), (0x1001, 0)]);
+
+        let pop = &trace.steps[1];
+        assert_eq!(
+            pop.registers,
+            vec![(Register::A, 7), (Register::SP, 0x1000)]
+        );
+        assert!(pop.writes.is_empty());
+    }
+
+    #[test]
+    fn test_encode_round_trip() {
+        let trace = record();
+        let bytes = trace.encode();
+        assert!(Trace::is_trace(&bytes));
+        assert_eq!(Trace::parse(&bytes), Ok(trace));
+        assert!(Trace::parse(&bytes[..bytes.len() - 1]).is_err());
+    }
+
+    #[test]
+    fn test_wide_instructions_keep_their_operand() {
+        let mut vm = Machine::new();
+        let program: Vec<u8> = [Op::LoadImmediate(Register::B, 0x1234), Op::JumpLong(0x0100)]
+            .iter()
+            .flat_map(Op::to_bytes)
+            .collect();
+        vm.load_program(&program).expect("Failed to load program");
+
+        let mut recorder = Recorder::attach(&mut vm);
+        for _ in 0..2 {
+            let pc = vm.get_register(Register::PC);
+            vm.step().expect("Failed to step");
+            recorder.record(&vm, pc);
+        }
+        let trace = recorder.finish();
+
+        let ldi = &trace.steps[0];
+        assert_eq!(ldi.word, Some(0x1234));
+        assert_eq!(ldi.mnemonic(), "LDI B %4660");
+        // Moving past all four bytes is implied, like any other step
+        assert_eq!(ldi.registers, vec![(Register::B, 0x1234)]);
+        assert_eq!(trace.steps[1].mnemonic(), "JMPL L0100");
+        assert_eq!(trace.steps[1].registers, vec![(Register::PC, 0x0100)]);
+        assert_eq!(trace.registers_after(1)[Register::PC as usize], 4);
+
+        assert_eq!(Trace::parse(&trace.encode()), Ok(trace));
+    }
+
+    #[test]
+    fn test_queries() {
+        let trace = record();
+        assert_eq!(trace.writes_to(0x1000).collect::<Vec<_>>(), vec![0, 2]);
+        assert_eq!(trace.writes_to(0x0000).count(), 0);
+
+        assert_eq!(trace.first_holding(Register::A, 0), Some(0));
+        assert_eq!(trace.first_holding(Register::A, 7), Some(2));
+        assert_eq!(trace.first_holding(Register::A, 9), None);
+
+        let registers = trace.registers_after(3);
+        assert_eq!(registers[Register::A as usize], 7);
+        assert_eq!(registers[Register::SP as usize], 0x1002);
+        assert_eq!(registers[Register::PC as usize], 6);
+    }
+
+    #[test]
+    fn test_export_json_lines() {
+        let lines = record().to_json_lines();
+        let lines: Vec<&str> = lines.lines().collect();
+        assert_eq!(lines.len(), 4);
+        assert_eq!(
+            lines[0],
+            r#"{"step":1,"pc":0,"instruction":"PUSH %7","registers":{"SP":4098},"writes":[[4096,7],[4097,0]]}"#
+        );
+        assert_eq!(
+            lines[3],
+            r#"{"step":4,"pc":6,"instruction":"SIG $09","registers":{},"writes":[]}"#
+        );
+    }
+
+    #[test]
+    fn test_json_string_escapes() {
+        assert_eq!(json_string("say \"hi\"\\\n\t"), r#""say \"hi\"\\\n\u0009""#);
+    }
+
+    #[test]
+    fn test_export_chrome_trace() {
+        let mut trace = record();
+        // A write to the keyboard byte shows up on the devices track
+        trace.steps[1].writes.push((0x1BFE, 0x61));
+
+        let json = trace.to_chrome_trace();
+        assert!(json.starts_with(r#"{"traceEvents":["#));
+        assert!(json.contains(r#"{"name":"POP A","ph":"X","ts":1,"dur":1,"pid":1,"tid":1,"#));
+        assert!(json.contains(r#"{"name":"SIG $09","ph":"i","s":"t","ts":3,"pid":1,"tid":2}"#));
+        assert!(json.contains(
+            r#"{"name":"keyboard","ph":"i","s":"t","ts":1,"pid":1,"tid":3,"args":{"address":"0x1BFE","value":97}}"#
+        ));
+        // Stack writes are not device activity
+        assert_eq!(json.matches(r#""tid":3,"args":{"address""#).count(), 1);
+    }
+}
@@ -0,0 +1,295 @@
+//! Live instruction tracing with pluggable sinks.
+//!
+//! A [`Tracer`] is told about every instruction as the machine executes it,
+//! with the registers as the instruction left them. Unlike a
+//! [`Recorder`](crate::trace::Recorder), which keeps the whole run for
+//! inspecting afterwards, a tracer decides on the spot what to keep:
+//!
+//! - [`NullTracer`] discards everything
+//! - [`TextTracer`] writes one line per instruction, e.g. to stdout
+//! - [`RingTracer`] keeps the last few steps in memory, to show what led up
+//!   to a fault
+//! - [`JsonLinesTracer`] writes one JSON object per instruction, e.g. to a file
+//!
+//! Tracers are added with [`Machine::add_tracer`]. The machine owns what it
+//! is given, so a sink the host wants to read back goes in an
+//! `Rc<RefCell<_>>`, which is a tracer too:
+//!
+//! ```
+//! use std::{cell::RefCell, rc::Rc};
+//! use rustyvm::{Machine, halt, tracer::RingTracer};
+//!
+//! // PUSH 1, PUSH 2, SIG $09
+//! let program = [0x01, 0x01, 0x01, 0x02, 0x09, 0x09];
+//! let mut vm = Machine::builder().handler(0x09, halt).program(&program).build().unwrap();
+//! let ring = Rc::new(RefCell::new(RingTracer::new(2)));
+//! vm.add_tracer(ring.clone());
+//! vm.run().unwrap();
+//!
+//! let pcs: Vec<u16> = ring.borrow().steps().map(|step| step.pc).collect();
+//! assert_eq!(pcs, [2, 4]);
+//! ```
+
+use std::{
+    cell::RefCell,
+    collections::VecDeque,
+    fs::File,
+    io::{self, BufWriter, Write},
+    path::Path,
+    rc::Rc,
+};
+
+use crate::{Hook, Machine, Op, Register, asm::disassembler, trace::json_string};
+
+/// Receives every instruction the machine executes.
+pub trait Tracer {
+    /// Called before the instruction at `pc` runs, with the registers as
+    /// they are, for tracers that show what each step changed.
+    fn before_step(&mut self, pc: u16, op: &Op, registers: &[u16; Register::COUNT]) {
+        let _ = (pc, op, registers);
+    }
+
+    /// Called after the instruction at `pc` has run, with the registers as
+    /// it left them. Instructions that fail are traced too.
+    fn on_step(&mut self, pc: u16, op: &Op, registers: &[u16; Register::COUNT]);
+}
+
+/// A shared tracer, so the host can keep a handle on one the machine owns.
+impl<T: Tracer> Tracer for Rc<RefCell<T>> {
+    fn before_step(&mut self, pc: u16, op: &Op, registers: &[u16; Register::COUNT]) {
+        self.borrow_mut().before_step(pc, op, registers);
+    }
+
+    fn on_step(&mut self, pc: u16, op: &Op, registers: &[u16; Register::COUNT]) {
+        self.borrow_mut().on_step(pc, op, registers);
+    }
+}
+
+/// Runs a tracer from the machine's hooks.
+struct TracerHook<T>(T);
+
+impl<T: Tracer> Hook for TracerHook<T> {
+    fn before(&mut self, machine: &Machine, pc: u16, op: &Op) {
+        self.0.before_step(pc, op, &machine.registers);
+    }
+
+    fn after(&mut self, machine: &Machine, pc: u16, op: &Op, _: Option<&str>) {
+        self.0.on_step(pc, op, &machine.registers);
+    }
+}
+
+impl Machine {
+    /// Adds a tracer to be told about every instruction from the next step
+    /// on. It runs as one of the machine's [`hooks`](Machine::hooks).
+    pub fn add_tracer(&mut self, tracer: impl Tracer + 'static) {
+        self.add_hook(TracerHook(tracer));
+    }
+}
+
+/// Discards every step; the tracer to use when one is required but nothing
+/// should be traced.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct NullTracer;
+
+impl Tracer for NullTracer {
+    fn on_step(&mut self, _: u16, _: &Op, _: &[u16; Register::COUNT]) {}
+}
+
+/// Writes each instruction as a line of text: its address, the instruction
+/// and the registers it changed but PC, e.g. `0004  ADDR A B         A=0x001E`.
+///
+/// Tracing has no way to report a failed write, so the first one stops the
+/// tracer and is kept for [`TextTracer::error`].
+pub struct TextTracer<W: Write> {
+    out: W,
+    before: [u16; Register::COUNT],
+    error: Option<String>,
+}
+
+impl TextTracer<io::Stdout> {
+    /// Traces to stdout.
+    pub fn stdout() -> Self {
+        Self::new(io::stdout())
+    }
+}
+
+impl<W: Write> TextTracer<W> {
+    pub fn new(out: W) -> Self {
+        Self {
+            out,
+            before: [0; Register::COUNT],
+            error: None,
+        }
+    }
+
+    /// The write error that stopped the tracer, if any.
+    pub fn error(&self) -> Option<&str> {
+        self.error.as_deref()
+    }
+
+    /// Flushes the writer, e.g. once the run is over.
+    pub fn flush(&mut self) -> io::Result<()> {
+        self.out.flush()
+    }
+
+    /// Takes the writer back, for reading a trace kept in memory.
+    pub fn into_inner(self) -> W {
+        self.out
+    }
+}
+
+impl<W: Write> Tracer for TextTracer<W> {
+    fn before_step(&mut self, _: u16, _: &Op, registers: &[u16; Register::COUNT]) {
+        self.before = *registers;
+    }
+
+    fn on_step(&mut self, pc: u16, op: &Op, registers: &[u16; Register::COUNT]) {
+        if self.error.is_some() {
+            return;
+        }
+        let changes: Vec<String> = Register::ALL
+            .iter()
+            .filter(|reg| **reg != Register::PC)
+            .filter(|reg| self.before[**reg as usize] != registers[**reg as usize])
+            .map(|reg| format!("{}=0x{:04X}", reg, registers[*reg as usize]))
+            .collect();
+
+        let instruction = disassembler::instruction_for(op, pc).to_string();
+        if let Err(e) = writeln!(
+            self.out,
+            "{:04X}  {:<16} {}",
+            pc,
+            instruction,
+            changes.join(" ")
+        ) {
+            self.error = Some(format!("failed to write trace - {}", e));
+        }
+    }
+}
+
+/// One step kept by a [`RingTracer`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TracedStep {
+    pub pc: u16,
+    pub op: Op,
+    /// The registers after the instruction ran
+    pub registers: [u16; Register::COUNT],
+}
+
+/// Keeps the most recent steps in memory, dropping the oldest once it holds
+/// `capacity` of them.
+#[derive(Debug, Clone)]
+pub struct RingTracer {
+    steps: VecDeque<TracedStep>,
+    capacity: usize,
+}
+
+impl RingTracer {
+    /// Creates a ring buffer holding up to `capacity` steps.
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            steps: VecDeque::with_capacity(capacity),
+            capacity,
+        }
+    }
+
+    /// The steps kept, oldest first.
+    pub fn steps(&self) -> impl DoubleEndedIterator<Item = &TracedStep> + '_ {
+        self.steps.iter()
+    }
+
+    /// Forgets the steps kept so far.
+    pub fn clear(&mut self) {
+        self.steps.clear();
+    }
+}
+
+impl Tracer for RingTracer {
+    fn on_step(&mut self, pc: u16, op: &Op, registers: &[u16; Register::COUNT]) {
+        if self.capacity == 0 {
+            return;
+        }
+        if self.steps.len() == self.capacity {
+            self.steps.pop_front();
+        }
+        self.steps.push_back(TracedStep {
+            pc,
+            op: op.clone(),
+            registers: *registers,
+        });
+    }
+}
+
+/// Writes each instruction as a line of JSON, in the shape of
+/// [`Trace::to_json_lines`](crate::trace::Trace::to_json_lines) but with
+/// every register:
+///
+/// ```text
+/// {"step":1,"pc":0,"instruction":"PUSH %1","registers":{"A":0,...,"R4":0}}
+/// ```
+///
+/// As with [`TextTracer`], the first failed write stops the tracer.
+pub struct JsonLinesTracer<W: Write> {
+    out: W,
+    steps: u64,
+    error: Option<String>,
+}
+
+impl JsonLinesTracer<BufWriter<File>> {
+    /// Traces to a new file at `path`, replacing any file already there.
+    pub fn create(path: impl AsRef<Path>) -> Result<Self, String> {
+        let path = path.as_ref();
+        let file = File::create(path)
+            .map_err(|e| format!("failed to create {}: {}", path.display(), e))?;
+        Ok(Self::new(BufWriter::new(file)))
+    }
+}
+
+impl<W: Write> JsonLinesTracer<W> {
+    pub fn new(out: W) -> Self {
+        Self {
+            out,
+            steps: 0,
+            error: None,
+        }
+    }
+
+    /// The write error that stopped the tracer, if any.
+    pub fn error(&self) -> Option<&str> {
+        self.error.as_deref()
+    }
+
+    /// Flushes the writer, e.g. once the run is over.
+    pub fn flush(&mut self) -> io::Result<()> {
+        self.out.flush()
+    }
+
+    /// Takes the writer back, for reading a trace kept in memory.
+    pub fn into_inner(self) -> W {
+        self.out
+    }
+}
+
+impl<W: Write> Tracer for JsonLinesTracer<W> {
+    fn on_step(&mut self, pc: u16, op: &Op, registers: &[u16; Register::COUNT]) {
+        if self.error.is_some() {
+            return;
+        }
+        self.steps += 1;
+        let registers: Vec<String> = Register::ALL
+            .iter()
+            .map(|reg| format!("\"{:?}\":{}", reg, registers[*reg as usize]))
+            .collect();
+        let instruction = disassembler::instruction_for(op, pc).to_string();
+        if let Err(e) = writeln!(
+            self.out,
+            "{{\"step\":{},\"pc\":{},\"instruction\":{},\"registers\":{{{}}}}}",
+            self.steps,
+            pc,
+            json_string(&instruction),
+            registers.join(",")
+        ) {
+            self.error = Some(format!("failed to write trace - {}", e));
+        }
+    }
+}
@@ -0,0 +1,74 @@
+//! Unit tests for live instruction tracing.
+//!
+//! This file runs a short program under each sink and checks what it kept.
+
+#[cfg(test)]
+mod tests {
+    use std::{cell::RefCell, rc::Rc};
+
+    use crate::test_support::halting_machine;
+    use crate::tracer::{JsonLinesTracer, NullTracer, RingTracer, TextTracer, Tracer};
+    use crate::{Machine, Op, Register};
+
+    /// PUSH 7, POP A, SIG $09
+    fn machine() -> Machine {
+        let program: Vec<u8> = [Op::Push(7), Op::PopRegister(Register::A), Op::Signal(0x09)]
+            .iter()
+            .flat_map(Op::to_bytes)
+            .collect();
+        halting_machine(&program)
+    }
+
+    /// Runs the program with `tracer` and hands it back.
+    fn traced<T: Tracer + 'static>(tracer: T) -> T {
+        let shared = Rc::new(RefCell::new(tracer));
+        let mut vm = machine();
+        vm.add_tracer(shared.clone());
+        vm.add_tracer(NullTracer);
+        vm.run().expect("Failed to run");
+        drop(vm);
+        Rc::into_inner(shared).unwrap().into_inner()
+    }
+
+    #[test]
+    fn test_text_tracer_lists_changed_registers() {
+        let out = traced(TextTracer::new(Vec::new())).into_inner();
+        let lines: Vec<String> = String::from_utf8(out)
+            .unwrap()
+            .lines()
+            .map(|line| line.trim_end().to_string())
+            .collect();
+
+        assert_eq!(lines.len(), 3);
+        assert_eq!(lines[0], "0000  PUSH %7          SP=0x1002");
+        assert_eq!(lines[1], "0002  POP A            A=0x0007 SP=0x1000");
+        assert_eq!(lines[2], "0004  SIG $09");
+    }
+
+    #[test]
+    fn test_ring_tracer_keeps_the_last_steps() {
+        let ring = traced(RingTracer::new(2));
+        let steps: Vec<_> = ring.steps().collect();
+
+        assert_eq!(steps.len(), 2);
+        assert_eq!(steps[0].pc, 2);
+        assert_eq!(steps[0].op, Op::PopRegister(Register::A));
+        assert_eq!(steps[0].registers[Register::A as usize], 7);
+        assert_eq!(steps[1].op, Op::Signal(0x09));
+
+        assert_eq!(traced(RingTracer::new(0)).steps().count(), 0);
+    }
+
+    #[test]
+    fn test_json_lines_tracer() {
+        let out = traced(JsonLinesTracer::new(Vec::new())).into_inner();
+        let text = String::from_utf8(out).unwrap();
+        let lines: Vec<&str> = text.lines().collect();
+
+        assert_eq!(lines.len(), 3);
+        assert!(lines[1].starts_with(
+            "{\"step\":2,\"pc\":2,\"instruction\":\"POP A\",\"registers\":{\"A\":7,\"B\":0,"
+        ));
+        assert!(lines[1].ends_with("\"R4\":0}}"));
+    }
+}
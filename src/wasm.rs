@@ -0,0 +1,127 @@
+//! JavaScript bindings for a browser playground, built with the `wasm` feature.
+//!
+//! Build with `wasm-pack build --target web -- --features wasm`. The page
+//! creates a [`Vm`], assembles source with [`assemble`], loads the result
+//! and then steps or runs it, reading the registers and memory back after
+//! each call to draw them. Errors arrive as JavaScript exceptions carrying
+//! the same messages the command-line tools print.
+//!
+//! ```js
+//! import init, { Vm, assemble } from "./pkg/rustyvm.js";
+//!
+//! await init();
+//! const vm = new Vm();
+//! vm.load(assemble("push %2\npush %3\nadds\npop A\nsig $09\n"));
+//! vm.run(1000);
+//! console.log(vm.registers()[0]); // 5
+//! ```
+
+use wasm_bindgen::prelude::*;
+
+use std::io;
+
+use crate::{CapturedOutput, Machine, asm, devices::KeyQueue, halt, syscalls};
+
+/// A machine with the halt signal installed, as the `vm` binary runs it.
+#[wasm_bindgen]
+pub struct Vm {
+    machine: Machine,
+    output: CapturedOutput,
+    keys: KeyQueue,
+}
+
+impl Default for Vm {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[wasm_bindgen]
+impl Vm {
+    /// Creates a machine with empty memory, the halt handler on signal 0x09
+    /// and the syscalls that do not need the host.
+    #[wasm_bindgen(constructor)]
+    pub fn new() -> Vm {
+        let mut machine = Machine::new();
+        machine.define_handler(0x09, halt);
+        syscalls::install(&mut machine, syscalls::Permissions::default());
+        let output = CapturedOutput::default();
+        machine.output = Box::new(output.clone());
+        // The page has no stdin, so `read` sees the end of input
+        machine.input = Box::new(io::empty());
+        Vm {
+            machine,
+            output,
+            keys: KeyQueue::default(),
+        }
+    }
+
+    /// Loads a program image, raw or with an entry point header, at
+    /// address 0 and points PC at its entry. Returns the number of
+    /// instructions loaded.
+    pub fn load(&mut self, program: &[u8]) -> Result<usize, String> {
+        let (_, instructions) = self.machine.load_program(program)?;
+        Ok(instructions)
+    }
+
+    /// Executes one instruction unless the machine has halted. Returns
+    /// true once it has.
+    pub fn step(&mut self) -> Result<bool, String> {
+        if !self.machine.halt {
+            self.keys.pump(&mut self.machine);
+            self.machine.step()?;
+        }
+        Ok(self.machine.halt)
+    }
+
+    /// Executes up to `max_steps` instructions, stopping early when the
+    /// machine halts. Returns the number executed, so the page can keep
+    /// calling it from an animation frame without freezing the tab.
+    pub fn run(&mut self, max_steps: u32) -> Result<u32, String> {
+        let mut steps = 0;
+        while steps < max_steps && !self.machine.halt {
+            self.keys.pump(&mut self.machine);
+            self.machine.step()?;
+            steps += 1;
+        }
+        Ok(steps)
+    }
+
+    /// Queues a key press, delivered once the program has acknowledged the
+    /// keys before it. Codes are those of the keyboard device: ASCII, or
+    /// 0x80 to 0x83 for the arrows.
+    pub fn press_key(&mut self, code: u8) {
+        self.keys.push(code);
+    }
+
+    /// Takes what the program has written since the last call, decoded as
+    /// UTF-8 with invalid bytes replaced.
+    pub fn output(&self) -> String {
+        String::from_utf8_lossy(&self.output.take()).into_owned()
+    }
+
+    /// Returns true once the program has halted.
+    pub fn halted(&self) -> bool {
+        self.machine.halt
+    }
+
+    /// The 13 registers in `Register` order, A first.
+    pub fn registers(&self) -> Vec<u16> {
+        self.machine.registers.to_vec()
+    }
+
+    /// Reads `len` bytes of memory starting at `start`, stopping at the end
+    /// of memory.
+    pub fn memory(&self, start: u16, len: u16) -> Vec<u8> {
+        (start..start.saturating_add(len))
+            .map_while(|addr| self.machine.memory.read(addr))
+            .collect()
+    }
+}
+
+/// Assembles source text into bytecode ready for [`Vm::load`]. The source
+/// cannot use `.include`, since the browser has no files to read.
+#[wasm_bindgen]
+pub fn assemble(source: &str) -> Result<Vec<u8>, String> {
+    asm::assemble_str(source).map_err(|e| e.to_string())
+}
@@ -0,0 +1,65 @@
+//! Unit tests for the JavaScript bindings.
+//!
+//! The bindings are plain Rust underneath, so this file calls them directly
+//! on the host, the way the playground page would.
+
+#[cfg(test)]
+mod tests {
+    use crate::Register;
+    use crate::wasm::{Vm, assemble};
+
+    const SOURCE: &str = "push %2\npush %3\nadds\npop A\nsig $09\n";
+
+    #[test]
+    fn test_assemble_load_and_run() {
+        let mut vm = Vm::new();
+        assert_eq!(vm.load(&assemble(SOURCE).unwrap()), Ok(5));
+        assert_eq!(vm.run(1000), Ok(5));
+        assert!(vm.halted());
+        assert_eq!(vm.registers()[Register::A as usize], 5);
+        // Running a halted machine does nothing
+        assert_eq!(vm.run(1000), Ok(0));
+    }
+
+    #[test]
+    fn test_step_and_read_memory() {
+        let mut vm = Vm::new();
+        vm.load(&assemble(SOURCE).unwrap()).unwrap();
+        assert_eq!(vm.step(), Ok(false));
+        assert_eq!(vm.registers()[Register::SP as usize], 0x1002);
+        assert_eq!(vm.memory(0x1000, 2), vec![2, 0]);
+        // Reads stop at the end of memory
+        assert_eq!(vm.memory(0x1FFE, 8).len(), 2);
+    }
+
+    #[test]
+    fn test_errors_are_messages() {
+        assert!(assemble("bogus A\n").is_err());
+        let mut vm = Vm::new();
+        vm.load(&assemble("sig $42\n").unwrap()).unwrap();
+        assert!(vm.step().is_err());
+    }
+
+    #[test]
+    fn test_output_is_captured() {
+        let source = "mov A, text\nmov B, 2\nsig $13\nsig $09\ntext:\n.db \"hi\"\n";
+        let mut vm = Vm::new();
+        vm.load(&assemble(source).unwrap()).unwrap();
+        vm.run(100).unwrap();
+        assert_eq!(vm.output(), "hi");
+        assert_eq!(vm.output(), "");
+    }
+
+    #[test]
+    fn test_keys_are_queued() {
+        let mut vm = Vm::new();
+        vm.load(&assemble("nop\nnop\n").unwrap()).unwrap();
+        vm.press_key(b'a');
+        vm.press_key(b'b');
+        vm.step().unwrap();
+        // The first key waits in the keyboard byte until acknowledged
+        assert_eq!(vm.memory(0x1BFE, 1), vec![b'a']);
+        vm.step().unwrap();
+        assert_eq!(vm.memory(0x1BFE, 1), vec![b'a']);
+    }
+}
@@ -0,0 +1,1063 @@
+use rustyvm::asm::warnings::Warning;
+use rustyvm::{Flag, Machine, Register, STACK_BASE, asm, halt};
+
+/// Runs a program until it raises the halt signal (0x09).
+fn run_until_halt(vm: &mut Machine) {
+    vm.define_handler(0x09, halt);
+    vm.run().expect("Failed to execute instruction");
+}
+
+#[test]
+fn test_assemble_str_and_run() {
+    let source = "
+        ; add two numbers
+        push %10
+        push %20
+        adds
+        pop A
+        sig $09
+    ";
+
+    let bytecode = asm::assemble_str(source).expect("Failed to assemble");
+    let mut vm = Machine::new();
+    vm.load_program(&bytecode).expect("Failed to load program");
+    run_until_halt(&mut vm);
+
+    assert_eq!(vm.get_register(Register::A), 30);
+}
+
+#[test]
+fn test_assemble_str_subtraction() {
+    let source = "
+        push %50
+        push %8
+        subs
+        pop A
+        push %2
+        pop B
+        subr A B
+        sig $09
+    ";
+
+    let bytecode = asm::assemble_str(source).expect("Failed to assemble");
+    let mut vm = Machine::new();
+    vm.load_program(&bytecode).expect("Failed to load program");
+    run_until_halt(&mut vm);
+
+    assert_eq!(vm.get_register(Register::A), 40);
+}
+
+#[test]
+fn test_assemble_str_bitwise() {
+    // The bare mnemonics pick the register form when registers follow
+    let source = "
+        push $F0
+        push $3C
+        and
+        not
+        pop A
+        push $0F
+        pop B
+        xor A, B
+        or B A
+        push $00
+        nots
+        push $FF
+        ands
+        pop C
+        sig $09
+    ";
+
+    let bytecode = asm::assemble_str(source).expect("Failed to assemble");
+    assert_eq!(
+        asm::assemble_str("ands\nnots\nandr A B\nnotr A\n").unwrap(),
+        asm::assemble_str("and\nnot\nand A B\nnot A\n").unwrap()
+    );
+    let mut vm = Machine::new();
+    vm.load_program(&bytecode).expect("Failed to load program");
+    run_until_halt(&mut vm);
+
+    assert_eq!(vm.get_register(Register::A), 0xFFC0);
+    assert_eq!(vm.get_register(Register::B), 0xFFCF);
+    assert_eq!(vm.get_register(Register::C), 0x00FF);
+}
+
+#[test]
+fn test_jump_forward_and_back() {
+    let source = "
+            jmp start
+        back:
+            push %5
+            pop B
+            jmp done
+        start:
+            push %1
+            pop A
+            jmp back
+            push %99
+            pop A
+        done:
+            sig $09
+    ";
+
+    let bytecode = asm::assemble_str(source).expect("Failed to assemble");
+    let mut vm = Machine::new();
+    vm.load_program(&bytecode).expect("Failed to load program");
+    run_until_halt(&mut vm);
+
+    assert_eq!(vm.get_register(Register::A), 1);
+    assert_eq!(vm.get_register(Register::B), 5);
+}
+
+#[test]
+fn test_conditional_jump_loop() {
+    // Sums 5 + 4 + 3 + 2 + 1 into A, counting B down to zero
+    let source = "
+            push %5
+            pop B
+            push %1
+            pop C
+        loop:
+            addr A B
+            subr B C
+            jnz loop
+            push %0
+            pop R0
+            subr R0 A
+            jlt negative
+            sig $09
+        negative:
+            push %1
+            pop R1
+            sig $09
+    ";
+
+    let bytecode = asm::assemble_str(source).expect("Failed to assemble");
+    let mut vm = Machine::new();
+    vm.load_program(&bytecode).expect("Failed to load program");
+    run_until_halt(&mut vm);
+
+    assert_eq!(vm.get_register(Register::A), 15);
+    assert_eq!(vm.get_register(Register::B), 0);
+    // 0 - 15 is negative, so the JLT was taken
+    assert_eq!(vm.get_register(Register::R1), 1);
+}
+
+#[test]
+fn test_load_immediate() {
+    let source = "
+            ldi A #1000
+            ldi B, $BEEF
+            jmp done
+            ldi A %1
+        done:
+            sig $09
+    ";
+
+    let bytecode = asm::assemble_str(source).expect("Failed to assemble");
+    assert_eq!(bytecode[..4], [0x18, 0x00, 0xE8, 0x03]);
+    let mut vm = Machine::new();
+    vm.load_program(&bytecode).expect("Failed to load program");
+    run_until_halt(&mut vm);
+
+    assert_eq!(vm.get_register(Register::A), 1000);
+    assert_eq!(vm.get_register(Register::B), 0xBEEF);
+    assert!(asm::assemble_str("ldi A #70000").is_err());
+}
+
+#[test]
+fn test_load_and_store_through_m() {
+    // Copies a word from `source` to `target`, then reads it back
+    let source = "
+            movi M, source
+            load A
+            movi M, target
+            store A
+            load B
+            sig $09
+        source:
+            .db $34, $12
+        target:
+            .db $00, $00
+    ";
+
+    let bytecode = asm::assemble_str(source).expect("Failed to assemble");
+    let mut vm = Machine::new();
+    vm.load_program(&bytecode).expect("Failed to load program");
+    run_until_halt(&mut vm);
+
+    assert_eq!(vm.get_register(Register::A), 0x1234);
+    assert_eq!(vm.get_register(Register::B), 0x1234);
+    let target = bytecode.len() as u16 - 2;
+    assert_eq!(vm.memory.read2(target), Some(0x1234));
+}
+
+#[test]
+fn test_indexed_addressing() {
+    // Sums a three-word array through R0, then writes the total into the
+    // second field of a two-word struct through M
+    let source = "
+            movi R0, array
+            load A, [R0]
+            load B, [R0 + 2]
+            addr A B
+            load B, [R0+4]
+            addr A B
+            movi M, record
+            store A, [M+1*2]
+            loadr C, [M]
+            movi M, end
+            loado R1, [M-2]
+            sig $09
+        array:
+            .db $01, $00, $02, $00, $03, $00
+        record:
+            .db $FF, $00, $00, $00
+        end:
+    ";
+
+    let bytecode = asm::assemble_str(source).expect("Failed to assemble");
+    let mut vm = Machine::new();
+    vm.load_program(&bytecode).expect("Failed to load program");
+    run_until_halt(&mut vm);
+
+    assert_eq!(vm.get_register(Register::A), 6);
+    assert_eq!(vm.get_register(Register::C), 0x00FF);
+    assert_eq!(vm.get_register(Register::R1), 6);
+}
+
+#[test]
+fn test_indexed_addressing_errors() {
+    for (source, message) in [
+        ("loadr A, [M+2]", "LOADR takes no offset"),
+        ("loado A", "Expected an address like [M+4]"),
+        ("load A, [Q+2]", "must start with a register"),
+        ("load A, [M+70000]", "does not fit in 16 bits"),
+    ] {
+        let error = asm::assemble_str(source).unwrap_err().to_string();
+        assert!(error.contains(message), "{}: {}", source, error);
+    }
+}
+
+#[test]
+fn test_block_copy_and_fill() {
+    let source = "
+        .entry main
+        msg:
+            .ascii \"hello\"
+        main:
+            movi R0, msg
+            ldi R1 #512
+            ldi M #5
+            memcpy R1 R0
+            ldi R1 #517
+            ldi R2 #$121
+            ldi M #3
+            memset R1 R2
+            sig $09
+    ";
+
+    let bytecode = asm::assemble_str(source).expect("Failed to assemble");
+    let mut vm = Machine::new();
+    vm.load_program(&bytecode).expect("Failed to load program");
+    run_until_halt(&mut vm);
+
+    let bytes: Vec<u8> = (512..521).map(|a| vm.memory.read(a).unwrap()).collect();
+    // Only the low byte of R2 is written
+    assert_eq!(bytes, b"hello!!!\0");
+}
+
+#[test]
+fn test_block_copy_out_of_memory() {
+    let bytecode =
+        asm::assemble_str("ldi R0 #8190\nldi M #4\nmemset R0 A").expect("Failed to assemble");
+    let mut vm = Machine::new();
+    vm.load_program(&bytecode).expect("Failed to load program");
+    vm.step().expect("Failed to execute LDI");
+    vm.step().expect("Failed to execute LDI");
+
+    assert_eq!(
+        vm.step(),
+        Err("memory fill fault - 4 bytes at 0x1FFE".to_string())
+    );
+}
+
+#[test]
+fn test_signed_compare() {
+    // Clamps A to the range -100..=100, reading negative literals as i16
+    let source = "
+            ldi A, #-250
+            ldi B, -100
+            cmp A B
+            jge high
+            mov A B
+        high:
+            ldi B, 100
+            cmp A B
+            jle done
+            mov A B
+        done:
+            sig $09
+    ";
+
+    let bytecode = asm::assemble_str(source).expect("Failed to assemble");
+    let mut vm = Machine::new();
+    vm.load_program(&bytecode).expect("Failed to load program");
+    run_until_halt(&mut vm);
+
+    assert_eq!(vm.get_register(Register::A) as i16, -100);
+    assert!(
+        asm::assemble_str("ldi A, #-40000")
+            .unwrap_err()
+            .to_string()
+            .contains("can't be below -32768")
+    );
+}
+
+#[test]
+fn test_bit_test_before_a_jump() {
+    // Counts the set bits of A by testing each one in turn
+    let source = "
+            ldi A #$A5
+            ldi B #1
+            ldi C #0
+            ldi R0 #1
+        loop:
+            test A B
+            jz clear
+            addr C R0
+        clear:
+            addr B B
+            jnz loop
+            sig $09
+    ";
+
+    let bytecode = asm::assemble_str(source).expect("Failed to assemble");
+    let mut vm = Machine::new();
+    vm.load_program(&bytecode).expect("Failed to load program");
+    run_until_halt(&mut vm);
+
+    assert_eq!(vm.get_register(Register::C), 4);
+    assert_eq!(vm.get_register(Register::A), 0xA5);
+}
+
+#[test]
+fn test_conditional_move_selects_without_branching() {
+    // A = B == C ? R0 : R1
+    let source = "
+            ldi R0 #10
+            ldi R1 #20
+            cmp B C
+            mov A R1
+            cmovz A R0
+            sig $09
+    ";
+
+    let bytecode = asm::assemble_str(source).expect("Failed to assemble");
+    for (c, expected) in [(5, 10), (6, 20)] {
+        let mut vm = Machine::new();
+        vm.load_program(&bytecode).expect("Failed to load program");
+        vm.registers[Register::B as usize] = 5;
+        vm.registers[Register::C as usize] = c;
+        run_until_halt(&mut vm);
+
+        assert_eq!(vm.get_register(Register::A), expected);
+    }
+}
+
+#[test]
+fn test_multiply_wide() {
+    // 1.5 * 2.25 in 8.8 fixed point: the product has 16 fraction bits
+    let source = "
+            ldi A, $0180
+            ldi B, $0240
+            mulw A B
+            sig $09
+    ";
+
+    let bytecode = asm::assemble_str(source).expect("Failed to assemble");
+    let mut vm = Machine::new();
+    vm.load_program(&bytecode).expect("Failed to load program");
+    run_until_halt(&mut vm);
+
+    // 3.375 is $0003_6000 with 16 fraction bits
+    assert_eq!(vm.get_register(Register::M), 0x0003);
+    assert_eq!(vm.get_register(Register::A), 0x6000);
+}
+
+#[test]
+fn test_flags_survive_a_subroutine() {
+    // Calls a routine that compares, changing the flags, but saves and
+    // restores them around it
+    let source = "
+            ldi A, 1
+            ldi B, 2
+            movi R4, back
+            cmp A B
+            pushr R4
+            jmp routine
+        back:
+            pushr FLAGS
+            pop R0
+            sig $09
+        routine:
+            pushf
+            cmp A A
+            popf
+            pop PC
+    ";
+
+    let bytecode = asm::assemble_str(source).expect("Failed to assemble");
+    let mut vm = Machine::new();
+    vm.load_program(&bytecode).expect("Failed to load program");
+    run_until_halt(&mut vm);
+
+    // 1 - 2 borrowed and is negative
+    assert_eq!(
+        vm.get_register(Register::R0),
+        Flag::Carry.mask() | Flag::Negative.mask()
+    );
+    assert_eq!(vm.get_register(Register::SP), STACK_BASE);
+}
+
+#[test]
+fn test_dispatch_table() {
+    // Jumps to entry B of a table of branches, one per case
+    let source = "
+            movi M, table
+            addr B B
+            addr M B
+            jmpr M
+        table:
+            bra case0
+            bra case1
+            bra case2
+        case0:
+            ldi A #100
+            sig $09
+        case1:
+            ldi A #101
+            sig $09
+        case2:
+            ldi A #102
+            sig $09
+    ";
+
+    let bytecode = asm::assemble_str(source).expect("Failed to assemble");
+    for case in 0..3 {
+        let mut vm = Machine::new();
+        vm.load_program(&bytecode).expect("Failed to load program");
+        vm.registers[Register::B as usize] = case;
+        run_until_halt(&mut vm);
+
+        assert_eq!(vm.get_register(Register::A), 100 + case);
+    }
+}
+
+#[test]
+fn test_jump_target_out_of_range() {
+    let source = "
+        jmp far
+        .fill %300, $00
+        far:
+            sig $09
+    ";
+
+    let error = asm::assemble_str(source).unwrap_err().to_string();
+    assert!(
+        error.contains("Jump target out of range: far is at 0x012E"),
+        "{}",
+        error
+    );
+}
+
+#[test]
+fn test_branches_past_the_first_256_bytes() {
+    // The same countdown as above, placed where JMP and JNZ can't reach
+    let source = "
+            .entry start
+            .fill %300, $00
+        start:
+            push %5
+            pop B
+            push %1
+            pop C
+        loop:
+            addr A B
+            subr B C
+            bnz loop
+            bra done
+            sig $01
+        done:
+            sig $09
+    ";
+
+    let bytecode = asm::assemble_str(source).expect("Failed to assemble");
+    let mut vm = Machine::new();
+    vm.load_program(&bytecode).expect("Failed to load program");
+    run_until_halt(&mut vm);
+
+    assert_eq!(vm.get_register(Register::A), 15);
+    assert_eq!(vm.get_register(Register::B), 0);
+    // Halted just past the final SIG at 318, having branched over `sig $01`
+    assert_eq!(vm.get_register(Register::PC), 320);
+}
+
+#[test]
+fn test_branch_target_out_of_range() {
+    let source = "
+        bz far
+        .fill %128, $00
+        far:
+            sig $09
+    ";
+
+    let error = asm::assemble_str(source).unwrap_err().to_string();
+    assert!(
+        error.contains("Branch target out of range: far is 128 bytes away"),
+        "{}",
+        error
+    );
+    // 127 bytes ahead is the furthest a branch reaches
+    assert!(asm::assemble_str(&source.replace("%128", "%127")).is_ok());
+}
+
+#[test]
+fn test_long_jump_reaches_any_address() {
+    let source = "
+            jmpl far
+            .fill %8000, $00
+        far:
+            ldi A #1
+            sig $09
+    ";
+
+    let bytecode = asm::assemble_str(source).expect("Failed to assemble");
+    // The 16-bit address follows the opcode and an unused argument byte
+    assert_eq!(&bytecode[..4], &[0x2A, 0x00, 0x44, 0x1F]);
+
+    let mut vm = Machine::new();
+    vm.load_program(&bytecode).expect("Failed to load program");
+    run_until_halt(&mut vm);
+
+    assert_eq!(vm.get_register(Register::A), 1);
+}
+
+#[test]
+fn test_assemble_str_with_entry_point() {
+    let source = "
+        .entry main
+        skipped:
+            push %1
+            pop B
+        main:
+            push %2
+            pop A
+            sig $09
+    ";
+
+    let bytecode = asm::assemble_str(source).expect("Failed to assemble");
+    let mut vm = Machine::new();
+    vm.load_program(&bytecode).expect("Failed to load program");
+    run_until_halt(&mut vm);
+
+    assert_eq!(vm.get_register(Register::A), 2);
+    assert_eq!(vm.get_register(Register::B), 0);
+}
+
+#[test]
+fn test_assemble_str_reports_errors() {
+    assert!(matches!(
+        asm::assemble_str("pop %1"),
+        Err(asm::AsmError::Parse(_))
+    ));
+    assert!(matches!(
+        asm::assemble_str(".entry missing\nnop"),
+        Err(asm::AsmError::Codegen { .. })
+    ));
+}
+
+#[test]
+fn test_lexer_errors_have_lines() {
+    for (source, message) in [
+        ("nop\npush %1x\n", "invalid number `%1x`"),
+        ("nop\npush 0xZZ\n", "invalid number `0xZZ`"),
+        ("nop\n.db \"open\n", "unterminated string `\"open`"),
+        ("nop\npush @\n", "unknown token `@`"),
+    ] {
+        let error = asm::assemble_str(source).unwrap_err();
+        assert_eq!(error.line(), Some(2), "{}", source);
+        assert!(error.to_string().contains(message), "{}", error);
+    }
+}
+
+#[test]
+fn test_codegen_errors_have_lines() {
+    for (source, line, message) in [
+        ("nop\njmp nowhere\n", 2, "Undefined label: nowhere"),
+        ("a:\n  nop\na:\n  nop\n", 3, "Duplicate label: a"),
+        (
+            "nop\njmp far\n.fill %300, $00\nfar:\n",
+            2,
+            "Jump target out of range",
+        ),
+        (
+            ".entry start\n.entry start\nstart:\n",
+            2,
+            "Duplicate .entry directive",
+        ),
+    ] {
+        let error = asm::assemble_str(source).unwrap_err();
+        assert_eq!(error.line(), Some(line), "{}", source);
+        assert!(error.to_string().contains(message), "{}", error);
+    }
+
+    // Lines survive the optimizer removing instructions before the error
+    let options = asm::AsmOptions {
+        optimize: true,
+        ..Default::default()
+    };
+    let error = asm::assemble("nop\npush %0\nadds\njmp nowhere\n", &options).unwrap_err();
+    assert_eq!(error.line(), Some(4));
+    assert_eq!(
+        error.to_string(),
+        "Error generating bytecode at line 4: Undefined label: nowhere"
+    );
+}
+
+#[test]
+fn test_oversize_programs_are_errors() {
+    let options = asm::AsmOptions {
+        check_stack: true,
+        ..Default::default()
+    };
+    for (source, line) in [
+        ("nop\n.fill $FFFF, $00\n", 2),
+        (".rept %32769\n  nop\n.endr\n", 2),
+        (".fill $FFFF, $00\n.db %0\nend:\n", 3),
+    ] {
+        let error = asm::assemble(source, &options).unwrap_err();
+        assert_eq!(error.line(), Some(line), "{}", source);
+        assert!(
+            error
+                .to_string()
+                .contains("program exceeds the 64 KiB address space"),
+            "{}",
+            error
+        );
+    }
+
+    // Exactly 64 KiB still fits
+    let assembly = asm::assemble(".fill $FFFF, $00\n.db %0\n", &options).unwrap();
+    assert_eq!(assembly.bytecode.len(), 0x10000);
+}
+
+#[test]
+fn test_errors_work_with_question_mark() {
+    fn assemble_boxed(source: &str) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
+        Ok(asm::assemble_str(source)?)
+    }
+
+    let error = assemble_boxed("nop\npop %1\n").unwrap_err();
+    let source = error.source().expect("Parse errors carry their cause");
+    assert!(source.to_string().starts_with("Error at line 2"));
+}
+
+#[test]
+fn test_include_errors_name_the_file() {
+    let root = std::env::temp_dir().join("rustyvm_include_errors");
+    std::fs::create_dir_all(&root).expect("Failed to create include dir");
+    std::fs::write(root.join("bad.inc"), "nop\nnop\npush %zz\n").expect("Failed to write include");
+
+    let options = asm::AsmOptions {
+        source_dir: Some(root.clone()),
+        ..Default::default()
+    };
+    let error = asm::assemble("nop\n.include \"bad.inc\"\n", &options).unwrap_err();
+    std::fs::remove_dir_all(&root).ok();
+    assert_eq!(error.line(), Some(3));
+    assert!(error.file().is_some_and(|file| file.ends_with("bad.inc")));
+}
+
+#[test]
+fn test_assemble_file_symbols() {
+    let path = std::env::temp_dir().join("rustyvm_assemble_file_symbols.asm");
+    std::fs::write(
+        &path,
+        "start:\n  nop\n  nop\nloop:\n  nop\n.inner:\n  sig $09\n",
+    )
+    .expect("Failed to write source file");
+
+    let assembly = asm::assemble_file(&path).expect("Failed to assemble");
+    std::fs::remove_file(&path).ok();
+
+    assert_eq!(assembly.bytecode.len(), 8);
+    assert_eq!(assembly.symbols.get("start"), Some(&0));
+    assert_eq!(assembly.symbols.get("loop"), Some(&4));
+    assert_eq!(assembly.symbols.get("loop.inner"), Some(&6));
+}
+
+#[test]
+fn test_assemble_file_missing() {
+    assert!(matches!(
+        asm::assemble_file("does/not/exist.asm"),
+        Err(asm::AsmError::Io(_))
+    ));
+}
+
+#[test]
+fn test_pseudo_mov_immediate() {
+    let source = "
+        mov A, #300
+        mov B, $2A
+        MOV C, #0
+        mov R0, #65535
+        halt
+    ";
+
+    let bytecode = asm::assemble_str(source).expect("Failed to assemble");
+    let mut vm = Machine::new();
+    vm.load_program(&bytecode).expect("Failed to load program");
+    run_until_halt(&mut vm);
+
+    assert_eq!(vm.get_register(Register::A), 300);
+    assert_eq!(vm.get_register(Register::B), 0x2A);
+    assert_eq!(vm.get_register(Register::C), 0);
+    assert_eq!(vm.get_register(Register::R0), 65535);
+    // Expansions leave the stack balanced
+    assert_eq!(vm.get_register(Register::SP), 0x1000);
+}
+
+#[test]
+fn test_pseudo_clr_and_incsp() {
+    let source = "
+        push %5
+        pop A
+        clr A
+        incsp %6
+        halt
+    ";
+
+    let bytecode = asm::assemble_str(source).expect("Failed to assemble");
+    let mut vm = Machine::new();
+    vm.load_program(&bytecode).expect("Failed to load program");
+    run_until_halt(&mut vm);
+
+    assert_eq!(vm.get_register(Register::A), 0);
+    assert_eq!(vm.get_register(Register::SP), 0x1006);
+}
+
+#[test]
+fn test_pseudo_incsp_rejects_wide_count() {
+    assert!(matches!(
+        asm::assemble_str("incsp %256"),
+        Err(asm::AsmError::Parse(_))
+    ));
+}
+
+#[test]
+fn test_fill_and_rept() {
+    let source = "
+        .rept %3
+            push %2
+        .endr
+        .rept %2
+            adds
+        .endr
+        pop A
+        halt
+        .fill %4, $AA
+    ";
+
+    let bytecode = asm::assemble_str(source).expect("Failed to assemble");
+    assert_eq!(bytecode.len(), 7 * 2 + 4);
+    assert_eq!(&bytecode[14..], &[0xAA; 4]);
+
+    let mut vm = Machine::new();
+    vm.load_program(&bytecode).expect("Failed to load program");
+    run_until_halt(&mut vm);
+    assert_eq!(vm.get_register(Register::A), 6);
+}
+
+#[test]
+fn test_out_of_range_immediates() {
+    let err = match asm::assemble_str("nop\n\n  push %300\n") {
+        Err(asm::AsmError::Parse(e)) => e,
+        other => panic!("Expected a parse error, got {:?}", other),
+    };
+    assert_eq!(err.line, Some(3));
+    let message = err.to_string();
+    assert!(message.contains("value 300 does not fit in 8 bits"));
+    assert!(message.contains("MOV <reg>, #300"));
+
+    assert!(asm::assemble_str("sig $100").is_err());
+    assert!(asm::assemble_str("mov A, #65536").is_err());
+    assert!(asm::assemble_str("push %255\nsig $FF\nmov A, #65535").is_ok());
+}
+
+#[test]
+fn test_error_line_inside_rept() {
+    let source = ".rept %2\n  nop\n  pop %1\n.endr\n";
+    match asm::assemble_str(source) {
+        Err(asm::AsmError::Parse(e)) => assert_eq!(e.line, Some(3)),
+        other => panic!("Expected a parse error, got {:?}", other),
+    }
+}
+
+#[test]
+fn test_data_label_addresses() {
+    let source = "
+        movi A, message
+        push16 message
+        pop B
+        halt
+    message:
+        .ascii \"Hi\"
+        .db $00
+    ";
+
+    let bytecode = asm::assemble_str(source).expect("Failed to assemble");
+    let mut vm = Machine::new();
+    vm.load_program(&bytecode).expect("Failed to load program");
+    run_until_halt(&mut vm);
+
+    // Two address loads, PUSHR M, POP B and SIG come before the data
+    let message = 28 * 2 + 2 * 3;
+    assert_eq!(vm.get_register(Register::A), message);
+    assert_eq!(vm.get_register(Register::B), message);
+    assert_eq!(vm.get_register(Register::M), message);
+    assert_eq!(&bytecode[message as usize..], b"Hi\0");
+}
+
+#[test]
+fn test_raw_byte_directive() {
+    let source = "
+        push %7
+        pop A
+        .byte 0x05 0x10 ; MOV B, A
+        halt
+    ";
+
+    let bytecode = asm::assemble_str(source).expect("Failed to assemble");
+    assert_eq!(&bytecode[4..6], &[0x05, 0x10]);
+
+    let mut vm = Machine::new();
+    vm.load_program(&bytecode).expect("Failed to load program");
+    run_until_halt(&mut vm);
+    assert_eq!(vm.get_register(Register::B), 7);
+
+    assert!(asm::assemble_str(".byte 0x100").is_err());
+}
+
+#[test]
+fn test_include_search_path() {
+    let root = std::env::temp_dir().join("rustyvm_include_search_path");
+    let lib = root.join("lib");
+    std::fs::create_dir_all(&lib).expect("Failed to create include dir");
+    std::fs::write(
+        lib.join("set_a.inc"),
+        "push %7\npop A\n.include \"end.inc\"\n",
+    )
+    .expect("Failed to write include file");
+    std::fs::write(lib.join("end.inc"), "halt\n").expect("Failed to write include file");
+    std::fs::write(root.join("loop.inc"), ".include \"loop.inc\"\n")
+        .expect("Failed to write include file");
+
+    let options = asm::AsmOptions {
+        source_dir: Some(root.clone()),
+        include_dirs: vec![lib],
+        ..Default::default()
+    };
+    let assembly = asm::assemble(".include \"set_a.inc\"\n", &options).expect("Failed to assemble");
+    let mut vm = Machine::new();
+    vm.load_program(&assembly.bytecode)
+        .expect("Failed to load program");
+    run_until_halt(&mut vm);
+    assert_eq!(vm.get_register(Register::A), 7);
+
+    assert!(matches!(
+        asm::assemble(".include \"missing.inc\"\n", &options),
+        Err(asm::AsmError::Io(_))
+    ));
+    assert!(matches!(
+        asm::assemble(".include \"loop.inc\"\n", &options),
+        Err(asm::AsmError::Io(_))
+    ));
+    std::fs::remove_dir_all(&root).ok();
+}
+
+#[test]
+fn test_defines() {
+    let source = "
+        .if FAST
+            mov A, #SCALE*2
+        .else
+            mov A, #SCALE
+        .endif
+        halt
+    SCALE:
+    ";
+    let mut options = asm::AsmOptions::default();
+    options.defines.insert("FAST".to_string(), 1);
+    options.defines.insert("SCALE".to_string(), 300);
+
+    let assembly = asm::assemble(source, &options).expect("Failed to assemble");
+    assert!(
+        assembly
+            .warnings
+            .contains(&Warning::LabelShadowsConstant("SCALE".to_string()))
+    );
+
+    let mut vm = Machine::new();
+    vm.load_program(&assembly.bytecode)
+        .expect("Failed to load program");
+    run_until_halt(&mut vm);
+    assert_eq!(vm.get_register(Register::A), 600);
+}
+
+#[test]
+fn test_assemble_multiple_files() {
+    let dir = std::env::temp_dir().join("rustyvm_assemble_multiple_files");
+    std::fs::create_dir_all(&dir).expect("Failed to create source dir");
+    let main = dir.join("main.asm");
+    let data = dir.join("data.asm");
+    let broken = dir.join("broken.asm");
+    std::fs::write(&main, "start:\n  movi A, table\n  halt\n").expect("Failed to write source");
+    std::fs::write(&data, "table:\n  .db %1, %2\n").expect("Failed to write source");
+    std::fs::write(&broken, "nop\npop %1\n").expect("Failed to write source");
+
+    let assembly = asm::assemble_files(&[&main, &data], &asm::AsmOptions::default())
+        .expect("Failed to assemble");
+    assert_eq!(assembly.symbols.get("start"), Some(&0));
+    assert_eq!(assembly.symbols.get("table"), Some(&30));
+    assert_eq!(
+        asm::format_symbols(&assembly.symbols),
+        "0000 start\n001E table\n"
+    );
+
+    let mut vm = Machine::new();
+    vm.load_program(&assembly.bytecode)
+        .expect("Failed to load program");
+    run_until_halt(&mut vm);
+    assert_eq!(vm.get_register(Register::A), 30);
+
+    match asm::assemble_files(&[&main, &broken], &asm::AsmOptions::default()) {
+        Err(asm::AsmError::Parse(e)) => {
+            assert_eq!(e.line, Some(2));
+            assert_eq!(
+                e.file.as_deref(),
+                Some(broken.display().to_string().as_str())
+            );
+        }
+        other => panic!("Expected a parse error, got {:?}", other),
+    }
+    std::fs::remove_dir_all(&dir).ok();
+}
+
+#[test]
+fn test_pad_to_boundary() {
+    let source = "
+    start:
+        push %1
+        pop A
+        halt
+        .fill $10-$, $FF
+    table:
+        .db 1, 2, 3
+    table_end:
+        .fill table_end-table, 0
+    ";
+
+    let assembly = asm::assemble(source, &asm::AsmOptions::default()).expect("Failed to assemble");
+    assert_eq!(assembly.symbols.get("table"), Some(&0x10));
+    assert_eq!(assembly.bytecode.len(), 0x10 + 3 + 3);
+    assert_eq!(assembly.bytecode[6..0x10], [0xFF; 10]);
+    assert!(
+        !assembly
+            .warnings
+            .iter()
+            .any(|w| matches!(w, Warning::UnusedLabel(name) if name.starts_with("table")))
+    );
+}
+
+#[test]
+fn test_numeric_labels_in_repeats() {
+    let source = "
+        .rept %2
+        1:
+            movi A, 1b
+        .endr
+        halt
+    ";
+
+    let assembly = asm::assemble(source, &asm::AsmOptions::default()).expect("Failed to assemble");
+    let mut vm = Machine::new();
+    vm.load_program(&assembly.bytecode)
+        .expect("Failed to load program");
+    run_until_halt(&mut vm);
+    // Each repetition points at its own label
+    assert_eq!(vm.get_register(Register::A), 28);
+}
+
+#[test]
+fn test_symbols_round_trip() {
+    let assembly = asm::assemble(
+        "main:\n  nop\n.loop:\n  halt\n",
+        &asm::AsmOptions::default(),
+    )
+    .expect("Failed to assemble");
+    let text = asm::format_symbols(&assembly.symbols);
+    assert_eq!(asm::parse_symbols(&text), Ok(assembly.symbols));
+    assert!(asm::parse_symbols("zz main\n").is_err());
+    assert!(asm::parse_symbols("0004\n").is_err());
+
+    // Aliases share the file without being taken for labels
+    let assembly = asm::assemble(
+        "main:\n  nop\n.alias counter, C\n.alias total counter\n  pop total\n",
+        &asm::AsmOptions::default(),
+    )
+    .expect("Failed to assemble");
+    assert_eq!(
+        assembly.aliases[&2],
+        vec![
+            ("counter".to_string(), "C".to_string()),
+            ("total".to_string(), "C".to_string())
+        ]
+    );
+    let text = asm::format_symbols(&assembly.symbols) + &asm::format_aliases(&assembly.aliases);
+    assert_eq!(asm::parse_symbols(&text), Ok(assembly.symbols));
+    assert_eq!(asm::parse_aliases(&text), Ok(assembly.aliases));
+    assert!(asm::parse_aliases("0002 .alias counter\n").is_err());
+}
+
+#[test]
+fn test_intel_hex_image_runs_at_its_address() {
+    let bytecode = asm::assemble_str("push %42\npop A\nsig $09\n").expect("Failed to assemble");
+    let text = rustyvm::ihex::encode(0x0100, &bytecode, Some(0x0100));
+
+    let image = rustyvm::ihex::decode(&text).expect("Failed to decode");
+    assert_eq!(image.bytes, bytecode);
+
+    let mut vm = Machine::new();
+    vm.load_program_at(&image.bytes, image.address, image.entry)
+        .expect("Failed to load program");
+    run_until_halt(&mut vm);
+
+    assert_eq!(vm.get_register(Register::A), 42);
+}
+
+#[test]
+fn test_parse_define() {
+    let mut defines = asm::Constants::new();
+    assert_eq!(
+        asm::parse_define("DEBUG", &defines),
+        Ok(("DEBUG".to_string(), 1))
+    );
+    defines.insert("BASE".to_string(), 0x1000);
+    assert_eq!(
+        asm::parse_define("TOP=BASE+0x20", &defines),
+        Ok(("TOP".to_string(), 0x1020))
+    );
+    assert!(asm::parse_define("1X=2", &defines).is_err());
+    assert!(asm::parse_define("BIG=0x10000", &defines).is_err());
+}
@@ -1,4 +1,4 @@
-use rustyvm::{Machine, Op, Register};
+use rustyvm::{Machine, Op, Register, asm::ProgramBuilder, halt};
 
 #[test]
 fn test_push_pop_register() {
@@ -124,10 +124,7 @@ fn test_program_from_register_asm() {
     ];
 
     // Set up a signal handler for the halt signal
-    vm.define_handler(0x09, |vm| {
-        vm.halt = true;
-        Ok(())
-    });
+    vm.define_handler(0x09, halt);
 
     // Load program into memory
     for (i, &byte) in program.iter().enumerate() {
@@ -135,9 +132,7 @@ fn test_program_from_register_asm() {
     }
 
     // Run the program until it halts
-    while !vm.halt {
-        vm.step().expect("Failed to execute instruction");
-    }
+    vm.run().expect("Failed to execute instruction");
 
     // Check if registers contain expected values
     assert_eq!(vm.get_register(Register::A), 10);
@@ -191,10 +186,7 @@ fn test_program_add_asm() {
     ];
 
     // Set up a signal handler for the halt signal
-    vm.define_handler(0x09, |vm| {
-        vm.halt = true;
-        Ok(())
-    });
+    vm.define_handler(0x09, halt);
 
     // Load program into memory
     for (i, &byte) in program.iter().enumerate() {
@@ -202,9 +194,7 @@ fn test_program_add_asm() {
     }
 
     // Run the program until it halts
-    while !vm.halt {
-        vm.step().expect("Failed to execute instruction");
-    }
+    vm.run().expect("Failed to execute instruction");
 
     // Check if registers contain expected values
     assert_eq!(
@@ -276,9 +266,8 @@ fn test_stack_operation_sequence() {
 
     // Check that one value (5) remains on the stack
     assert_eq!(vm.get_register(Register::SP), 0x1002);
-    vm.registers[Register::SP as usize] -= 2; // Temporarily adjust SP to read the value
-    assert_eq!(vm.memory.read2(vm.get_register(Register::SP)).unwrap(), 5);
-    vm.registers[Register::SP as usize] += 2; // Restore SP
+    assert_eq!(vm.peek(0), Some(5));
+    assert_eq!(vm.stack().collect::<Vec<_>>(), [5]);
 }
 
 #[test]
@@ -306,15 +295,10 @@ fn test_memory_load_from_vec() {
     assert_eq!(instructions, 3);
 
     // Register halt signal handler
-    vm.define_handler(0x09, |vm| {
-        vm.halt = true;
-        Ok(())
-    });
+    vm.define_handler(0x09, halt);
 
     // Run program until halt
-    while !vm.halt {
-        vm.step().expect("Failed to execute instruction");
-    }
+    vm.run().expect("Failed to execute instruction");
 
     // Verify register value
     assert_eq!(vm.get_register(Register::A), 42);
@@ -338,3 +322,54 @@ fn test_load_16bit_values() {
     assert_eq!(vm.memory.read2(0x100).unwrap(), 0x1234);
     assert_eq!(vm.memory.read2(0x102).unwrap(), 0xABCD);
 }
+
+#[test]
+fn test_move_register() {
+    let mut vm = Machine::new();
+
+    // Program:
+    // PUSH #42
+    // POP A
+    // MOV R3 A
+    let program = [
+        Op::Push(0).value(),
+        42,
+        Op::PopRegister(Register::A).value(),
+        Register::A as u8,
+        Op::MoveRegister(Register::A, Register::A).value(),
+        (Register::R3 as u8) << 4 | Register::A as u8,
+    ];
+
+    for (i, &byte) in program.iter().enumerate() {
+        vm.memory.write(i as u16, byte);
+    }
+
+    for _ in 0..3 {
+        vm.step().expect("Failed to execute instruction");
+    }
+
+    // The source register keeps its value
+    assert_eq!(vm.get_register(Register::A), 42);
+    assert_eq!(vm.get_register(Register::R3), 42);
+}
+
+#[test]
+fn test_built_program() {
+    let mut vm = Machine::new();
+    let program = ProgramBuilder::new()
+        .push(10)
+        .push(20)
+        .adds()
+        .pop(Register::A)
+        .mov(Register::R3, Register::A)
+        .assemble()
+        .expect("Failed to assemble");
+    vm.load_program(&program).expect("Failed to load");
+
+    for _ in 0..5 {
+        vm.step().expect("Failed to execute instruction");
+    }
+
+    assert_eq!(vm.get_register(Register::A), 30);
+    assert_eq!(vm.get_register(Register::R3), 30);
+}